@@ -0,0 +1,152 @@
+//! Listing of the script event types and idle actions for the `actions` subcommand.
+//!
+//! The listing is generated from the JSON schemas of the script types themselves, so every field,
+//! default and constraint the parser accepts shows up without a separate registry to keep in
+//! sync. With `--json` the schemas are printed as is for external script editors.
+use crate::event::BotEvent;
+use crate::idle::IdleAction;
+
+use anyhow::Result;
+use serde_json::{json, Map, Value};
+
+/// Returns a short description of a field's type.
+fn type_name(schema: &Value) -> String {
+    if let Some(reference) = schema.get("$ref").and_then(Value::as_str) {
+        return reference
+            .rsplit('/')
+            .next()
+            .unwrap_or(reference)
+            .to_string();
+    }
+    if let Some(options) = schema.get("anyOf").and_then(Value::as_array) {
+        let names: Vec<String> = options
+            .iter()
+            .map(type_name)
+            .filter(|name| name != "null")
+            .collect();
+        return names.join(" | ");
+    }
+    match schema.get("type").and_then(Value::as_str) {
+        Some("array") => match schema.get("items") {
+            Some(items) => format!("[{}]", type_name(items)),
+            None => "array".to_string(),
+        },
+        Some(name) => name.to_string(),
+        None => "any".to_string(),
+    }
+}
+
+/// Returns the default and range constraints of a field, or whether it's required.
+fn constraints(schema: &Value, required: bool) -> String {
+    let mut parts = Vec::new();
+    if required {
+        parts.push("required".to_string());
+    }
+    if let Some(default) = schema.get("default") {
+        parts.push(format!("default {}", default));
+    }
+    // Unsigned integers get a minimum of 0 from their type alone, which isn't worth listing
+    if let Some(min) = schema.get("minimum").filter(|min| min.as_u64() != Some(0)) {
+        parts.push(format!("min {}", min));
+    }
+    if let Some(max) = schema.get("maximum") {
+        parts.push(format!("max {}", max));
+    }
+    parts.join(", ")
+}
+
+/// Returns the first line of a schema's description.
+pub fn summary(schema: &Value) -> &str {
+    schema
+        .get("description")
+        .and_then(Value::as_str)
+        .and_then(|description| description.lines().next())
+        .unwrap_or_default()
+}
+
+/// Prints the properties of an object schema, skipping the `tag` field.
+fn print_fields(object: &Value, defs: &Map<String, Value>, tag: &str, indent: usize) {
+    // Variants that wrap a struct refer to its definition instead of listing the fields
+    let object = match object.get("$ref").and_then(Value::as_str) {
+        Some(reference) => defs
+            .get(reference.rsplit('/').next().unwrap_or(reference))
+            .unwrap_or(object),
+        None => object,
+    };
+    let required: Vec<&str> = object
+        .get("required")
+        .and_then(Value::as_array)
+        .map(|names| names.iter().filter_map(Value::as_str).collect())
+        .unwrap_or_default();
+
+    if let Some(properties) = object.get("properties").and_then(Value::as_object) {
+        for (name, field) in properties.iter().filter(|(name, _)| *name != tag) {
+            let line = format!(
+                "{:indent$}{:<18} {:<22} {:<24} {}",
+                "",
+                name,
+                type_name(field),
+                constraints(field, required.contains(&name.as_str())),
+                summary(field),
+            );
+            println!("{}", line.trim_end());
+        }
+    }
+    // Flattened enums such as the color target allow one of several sets of fields
+    if let Some(options) = object.get("anyOf").and_then(Value::as_array) {
+        let alternatives: Vec<String> = options
+            .iter()
+            .filter_map(|option| option.get("required").and_then(Value::as_array))
+            .map(|names| {
+                names
+                    .iter()
+                    .filter_map(Value::as_str)
+                    .collect::<Vec<_>>()
+                    .join(" + ")
+            })
+            .collect();
+        println!("{:indent$}one of: {}", "", alternatives.join(" | "));
+    }
+}
+
+/// Prints every variant of an internally tagged enum schema with its fields.
+fn print_variants(schema: &Value, tag: &str) {
+    let empty = Map::new();
+    let defs = schema
+        .get("$defs")
+        .and_then(Value::as_object)
+        .unwrap_or(&empty);
+    for variant in schema
+        .get("oneOf")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+    {
+        let name = variant
+            .pointer(&format!("/properties/{}/const", tag))
+            .and_then(Value::as_str)
+            .unwrap_or_default();
+        println!("  {:<16} {}", name, summary(variant));
+        print_fields(variant, defs, tag, 4);
+    }
+}
+
+/// Prints the event types and idle actions scripts can use, as JSON schemas when `json` is set.
+pub fn run(json: bool) -> Result<()> {
+    let events = serde_json::to_value(schemars::schema_for!(BotEvent))?;
+    let idle_actions = serde_json::to_value(schemars::schema_for!(IdleAction))?;
+
+    if json {
+        let schemas = json!({ "events": events, "idle_actions": idle_actions });
+        println!("{}", serde_json::to_string_pretty(&schemas)?);
+        return Ok(());
+    }
+
+    println!("Fields of every event:");
+    print_fields(&events, &Map::new(), "", 2);
+    println!("\nEvent types:");
+    print_variants(&events, "type");
+    println!("\nIdle actions:");
+    print_variants(&idle_actions, "action");
+    Ok(())
+}
@@ -0,0 +1,70 @@
+//! Preconditions that are checked before an event runs.
+//!
+//! Long scripts drift out of sync with the game when a click misses or a dialog pops up. An event
+//! can assert the state it expects, such as the game window having focus or the bank being open,
+//! and a failed assertion then stops, skips or flags the event instead of clicking blindly.
+use crate::signature::Signature;
+use crate::window;
+
+use anyhow::{Context, Result};
+use schemars::JsonSchema;
+use serde::Deserialize;
+use std::fmt::Display;
+
+/// A single precondition of an event.
+#[derive(Debug, Deserialize, JsonSchema)]
+#[serde(tag = "assert", rename_all = "snake_case")]
+pub enum Assertion {
+    /// The focused window's title contains `title`.
+    WindowFocused { title: String },
+    /// The signature is on screen.
+    Present { signature: Signature },
+    /// The signature is not on screen.
+    Absent { signature: Signature },
+}
+
+impl Assertion {
+    /// Checks the assertion's parameters.
+    pub fn validate(&self) -> Result<()> {
+        match self {
+            Assertion::WindowFocused { .. } => Ok(()),
+            Assertion::Present { signature } | Assertion::Absent { signature } => {
+                signature.validate()
+            }
+        }
+    }
+
+    /// Reports whether the assertion currently holds.
+    pub fn holds(&self) -> Result<bool> {
+        match self {
+            Assertion::WindowFocused { title } => Ok(window::active_window_title()
+                .context("Failed to read the focused window")?
+                .is_some_and(|active| active.contains(title.as_str()))),
+            Assertion::Present { signature } => signature.is_present(),
+            Assertion::Absent { signature } => Ok(!signature.is_present()?),
+        }
+    }
+}
+
+impl Display for Assertion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Assertion::WindowFocused { title } => write!(f, "window '{}' focused", title),
+            Assertion::Present { .. } => write!(f, "signature present"),
+            Assertion::Absent { .. } => write!(f, "signature absent"),
+        }
+    }
+}
+
+/// What to do when one of an event's assertions fails.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum FailurePolicy {
+    /// Stop the run with an error.
+    #[default]
+    Abort,
+    /// Skip the event and continue with the next one.
+    Skip,
+    /// Log a warning and run the event anyway.
+    Warn,
+}
@@ -0,0 +1,273 @@
+//! Search for any of a set of templates at slightly varying scales, behind the `template` feature.
+//!
+//! An atlas is a list of template images, such as every item icon of an inventory, where
+//! directories stand for the images they contain. Each template is matched at scales within the
+//! tolerance around its original size, since UI scaling and window sizes rarely reproduce a
+//! screenshot pixel for pixel, and the closest match of any template wins. The event reports
+//! which template matched where, so one event can click an item wherever it sits.
+use crate::filter::{self, Filter};
+use crate::fixtures;
+use crate::profile::{self, Phase};
+use crate::vision::{self, Region, TargetHit, MAX_TEMPLATE_PIXEL_VARIANCE};
+use crate::windmouse::Point;
+
+use anyhow::{ensure, Context, Result};
+use image::GrayImage;
+use imageproc::template_matching::{find_extremes, MatchTemplateMethod};
+use log::debug;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+/// Difference between neighboring scales a template is tried at.
+const SCALE_STEP: f64 = 0.05;
+
+fn default_scale_tolerance() -> f64 {
+    0.1
+}
+
+/// Templates searched for at once.
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct AtlasSearch {
+    /// Template images, or directories whose PNG images are all used as templates.
+    pub templates: Vec<PathBuf>,
+    /// Preprocessing filters applied to the captured frame and templates before matching.
+    #[serde(default)]
+    pub filters: Vec<Filter>,
+    /// Screen area searched, the whole screen when omitted.
+    #[serde(default)]
+    pub search_region: Option<Region>,
+    /// Largest relative difference between a template's size and its size on screen.
+    #[serde(default = "default_scale_tolerance")]
+    #[schemars(range(min = 0.0, max = 0.5))]
+    pub scale_tolerance: f64,
+}
+
+/// Template that matched and where.
+#[derive(Debug)]
+pub struct AtlasHit {
+    /// Path of the template that matched.
+    pub template: PathBuf,
+    /// Scale the template matched at.
+    pub scale: f64,
+    /// Point to click within the match.
+    pub hit: TargetHit,
+}
+
+/// Match of a template at one scale, in the coordinates of the searched frame.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Candidate {
+    template: usize,
+    scale: f64,
+    origin: (u32, u32),
+    size: (u32, u32),
+    /// Mean squared difference per pixel, lower is closer.
+    score: f32,
+}
+
+impl AtlasSearch {
+    /// Checks that there are templates and the scale tolerance is usable.
+    pub fn validate(&self) -> Result<()> {
+        ensure!(
+            !self.templates.is_empty(),
+            "An atlas needs at least one template"
+        );
+        ensure!(
+            (0.0..=0.5).contains(&self.scale_tolerance),
+            "scale_tolerance must be between 0 and 0.5, got {}",
+            self.scale_tolerance
+        );
+        if let Some(region) = &self.search_region {
+            ensure!(
+                region.width > 0 && region.height > 0,
+                "The search region must not be empty"
+            );
+        }
+        Ok(())
+    }
+
+    /// Returns the template image files, with directories expanded to their PNG images in name
+    /// order.
+    pub fn template_paths(&self) -> Result<Vec<PathBuf>> {
+        let mut paths = Vec::new();
+        for path in &self.templates {
+            if !path.is_dir() {
+                paths.push(path.clone());
+                continue;
+            }
+            let mut images: Vec<PathBuf> = std::fs::read_dir(path)
+                .context(format!("Failed to read atlas directory {}", path.display()))?
+                .map(|entry| entry.map(|entry| entry.path()))
+                .collect::<std::io::Result<_>>()?;
+            images.retain(|image| {
+                image
+                    .extension()
+                    .is_some_and(|extension| extension.eq_ignore_ascii_case("png"))
+            });
+            ensure!(
+                !images.is_empty(),
+                "Atlas directory {} has no PNG images",
+                path.display()
+            );
+            images.sort();
+            paths.extend(images);
+        }
+        Ok(paths)
+    }
+
+    /// Captures the screen and returns the closest match of any template, `None` when none of
+    /// them is on screen.
+    pub fn locate(&self) -> Result<Option<AtlasHit>> {
+        let paths = self.template_paths()?;
+        let templates = paths
+            .iter()
+            .map(|path| vision::load_template(path, &self.filters))
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut frame = vision::capture_screen_rgba()?;
+        fixtures::record(&frame, self.search_region.as_ref());
+        let _phase = profile::phase(Phase::Scan);
+        filter::apply_all(&mut frame, &self.filters);
+        let (frame, origin) = vision::crop_to_region(frame, self.search_region.as_ref());
+        let frame = image::DynamicImage::ImageRgba8(frame).to_luma8();
+
+        let Some(best) = best_match(&frame, &templates, self.scale_tolerance) else {
+            debug!("None of the {} atlas templates is on screen", paths.len());
+            return Ok(None);
+        };
+        let template = paths[best.template].clone();
+        debug!(
+            "Atlas template {} matched at scale {:.2} with score {:.1}",
+            template.display(),
+            best.scale,
+            best.score
+        );
+        let corner = Point::new(
+            origin.x + i32::try_from(best.origin.0)?,
+            origin.y + i32::try_from(best.origin.1)?,
+        );
+        Ok(Some(AtlasHit {
+            template,
+            scale: best.scale,
+            hit: TargetHit {
+                point: vision::get_rand_point_in_rect(corner, best.size.0, best.size.1)?,
+                area: u64::from(best.size.0) * u64::from(best.size.1),
+            },
+        }))
+    }
+}
+
+/// Returns the scales a template is tried at, from smallest to largest, always including 1.
+fn scales(tolerance: f64) -> Vec<f64> {
+    let steps = (tolerance / SCALE_STEP).ceil() as i32;
+    if steps == 0 {
+        return vec![1.0];
+    }
+    (-steps..=steps)
+        .map(|i| 1.0 + tolerance * f64::from(i) / f64::from(steps))
+        .collect()
+}
+
+/// Matches every template at every scale against the frame and returns the closest match within
+/// the confidence threshold.
+fn best_match(frame: &GrayImage, templates: &[GrayImage], tolerance: f64) -> Option<Candidate> {
+    let mut best: Option<Candidate> = None;
+    for (index, template) in templates.iter().enumerate() {
+        for scale in scales(tolerance) {
+            let width = (f64::from(template.width()) * scale).round() as u32;
+            let height = (f64::from(template.height()) * scale).round() as u32;
+            if width == 0 || height == 0 || width > frame.width() || height > frame.height() {
+                continue;
+            }
+            let scaled = if width == template.width() && height == template.height() {
+                template.clone()
+            } else {
+                image::imageops::resize(
+                    template,
+                    width,
+                    height,
+                    image::imageops::FilterType::Triangle,
+                )
+            };
+            let result = imageproc::template_matching::match_template_parallel(
+                frame,
+                &scaled,
+                MatchTemplateMethod::SumOfSquaredErrors,
+            );
+            let extremes = find_extremes(&result);
+            let score = extremes.min_value / (width * height) as f32;
+            if score > MAX_TEMPLATE_PIXEL_VARIANCE.powi(2)
+                || best.is_some_and(|best| best.score <= score)
+            {
+                continue;
+            }
+            best = Some(Candidate {
+                template: index,
+                scale,
+                origin: extremes.min_value_location,
+                size: (width, height),
+                score,
+            });
+        }
+    }
+    best
+}
+
+/// Returns the display name of a template for logs and reports.
+pub fn template_name(path: &Path) -> String {
+    path.file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| path.display().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Luma;
+
+    /// Icon with a distinct pattern so that it only matches itself.
+    fn icon(seed: u32) -> GrayImage {
+        GrayImage::from_fn(20, 20, |x, y| {
+            Luma([((x * 7 + y * 13 + seed * 31) % 5 * 60) as u8])
+        })
+    }
+
+    #[test]
+    fn scales_cover_the_tolerance() {
+        assert_eq!(scales(0.0), vec![1.0]);
+        let scales = scales(0.1);
+        assert_eq!(scales.len(), 5);
+        assert!((scales[0] - 0.9).abs() < 1e-9 && (scales[4] - 1.1).abs() < 1e-9);
+        assert!(scales.contains(&1.0));
+    }
+
+    #[test]
+    fn best_match_finds_the_matching_template() {
+        let mut frame = GrayImage::from_pixel(120, 80, Luma([128]));
+        image::imageops::replace(&mut frame, &icon(2), 70, 30);
+        let templates = [icon(1), icon(2), icon(3)];
+
+        let best = best_match(&frame, &templates, 0.1).unwrap();
+        assert_eq!(best.template, 1);
+        assert_eq!(best.origin, (70, 30));
+        assert_eq!(best.size, (20, 20));
+
+        assert!(best_match(&frame, &templates[..1], 0.1).is_none());
+    }
+
+    #[test]
+    fn best_match_tolerates_scaling() {
+        let large = image::imageops::resize(
+            &GrayImage::from_fn(20, 20, |x, _| Luma([if x < 10 { 30 } else { 220 }])),
+            22,
+            22,
+            image::imageops::FilterType::Triangle,
+        );
+        let mut frame = GrayImage::from_pixel(100, 60, Luma([128]));
+        image::imageops::replace(&mut frame, &large, 40, 20);
+        let template = GrayImage::from_fn(20, 20, |x, _| Luma([if x < 10 { 30 } else { 220 }]));
+
+        let best = best_match(&frame, &[template], 0.1).unwrap();
+        assert!((best.scale - 1.1).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,222 @@
+//! Search regions inferred from where color events found their targets before.
+//!
+//! Objects a script clicks usually stay in one part of the screen, yet an event without a search
+//! region scans all of it every time. With inference on, the area each event's targets were
+//! found in is tracked across runs of the same script. Once an event found its target a few
+//! times within a small part of the screen, it scans that area, padded, instead. A miss there
+//! falls back to the whole screen right away, and every so often the whole screen is scanned
+//! anyway, so targets that moved are found again and the area grows to include them.
+use crate::rundir::RunDir;
+use crate::vision::Region;
+
+use anyhow::{Context, Result};
+use log::debug;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Matches needed before an event's search is narrowed.
+const MIN_MATCHES: u64 = 5;
+/// Every this many lookups of an event scan the whole screen.
+const FULL_SCAN_EVERY: u64 = 20;
+/// Largest share of the screen a narrowed region may cover, beyond it narrowing isn't worth it.
+const MAX_SHARE: f64 = 0.5;
+/// Padding around the matched area as a fraction of its larger side.
+const PADDING_FRACTION: f64 = 0.25;
+/// Least padding in pixels around the matched area.
+const MIN_PADDING_PX: u32 = 32;
+
+/// Where an event found its targets so far.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+struct History {
+    /// Number of lookups that found the target.
+    matches: u64,
+    /// Smallest area containing every match.
+    bounds: Region,
+    /// Number of pixels of the whole screen scan the area is compared against.
+    screen_pixels: u64,
+}
+
+impl History {
+    /// Returns the padded area to search once there are enough matches in a small enough area.
+    fn region(&self) -> Option<Region> {
+        if self.matches < MIN_MATCHES {
+            return None;
+        }
+        let side = self.bounds.width.max(self.bounds.height);
+        let padding = ((f64::from(side) * PADDING_FRACTION) as u32).max(MIN_PADDING_PX);
+        let region = self.bounds.grown(padding);
+        let share = region.area() as f64 / self.screen_pixels.max(1) as f64;
+        (share <= MAX_SHARE).then_some(region)
+    }
+}
+
+/// Inferred areas of the events of the running script.
+struct Inference {
+    /// File the areas of every script are kept in.
+    path: PathBuf,
+    /// Key of the running script in the file.
+    script: String,
+    events: BTreeMap<String, History>,
+    /// Lookups per event during this run.
+    lookups: HashMap<String, u64>,
+}
+
+static INFERENCE: Mutex<Option<Inference>> = Mutex::new(None);
+
+/// Areas of every script's events by script path and event id, as stored in the file.
+type Stored = BTreeMap<String, BTreeMap<String, History>>;
+
+/// Returns the default file the inferred areas are kept in, next to the default run directory
+/// root.
+pub fn default_path() -> PathBuf {
+    RunDir::default_root().with_file_name("regions.json")
+}
+
+fn load(path: &Path) -> Result<Stored> {
+    if !path.exists() {
+        return Ok(Stored::new());
+    }
+    let file = File::open(path).context(format!("Failed to open {}", path.display()))?;
+    serde_json::from_reader(BufReader::new(file)).context(format!(
+        "Failed to parse inferred regions {}",
+        path.display()
+    ))
+}
+
+/// Starts inferring search regions for the events of `script`, picking up the areas recorded
+/// by earlier runs of it.
+pub fn start(script: &Path) -> Result<()> {
+    let path = default_path();
+    let script = std::fs::canonicalize(script)
+        .unwrap_or_else(|_| script.to_path_buf())
+        .display()
+        .to_string();
+    let events = load(&path)?.remove(&script).unwrap_or_default();
+    debug!(
+        "Loaded the match areas of {} events from {}",
+        events.len(),
+        path.display()
+    );
+    if let Ok(mut inference) = INFERENCE.lock() {
+        *inference = Some(Inference {
+            path,
+            script,
+            events,
+            lookups: HashMap::new(),
+        });
+    }
+    Ok(())
+}
+
+/// Stops inferring search regions.
+pub fn stop() {
+    if let Ok(mut inference) = INFERENCE.lock() {
+        *inference = None;
+    }
+}
+
+/// Returns the region the event `id` should search in this time, `None` to scan the whole
+/// screen.
+pub fn region(id: &str) -> Option<Region> {
+    let mut inference = INFERENCE.lock().ok()?;
+    let inference = inference.as_mut()?;
+    let lookups = inference.lookups.entry(id.to_string()).or_default();
+    *lookups += 1;
+    if *lookups % FULL_SCAN_EVERY == 0 {
+        return None;
+    }
+    inference.events.get(id)?.region()
+}
+
+/// Records that the event `id` found its target within `bounds`. `screen_pixels` is the size of
+/// the scanned screen when the whole screen was scanned.
+pub fn record(id: &str, bounds: Region, screen_pixels: Option<u64>) {
+    let Ok(mut inference) = INFERENCE.lock() else {
+        return;
+    };
+    let Some(inference) = inference.as_mut() else {
+        return;
+    };
+    let history = inference.events.entry(id.to_string()).or_insert(History {
+        matches: 0,
+        bounds,
+        screen_pixels: 0,
+    });
+    history.matches += 1;
+    history.bounds = history.bounds.union(&bounds);
+    if let Some(pixels) = screen_pixels {
+        history.screen_pixels = pixels;
+    }
+}
+
+/// Returns the regions events currently search in, keyed by event id.
+pub fn regions() -> BTreeMap<String, Region> {
+    let Ok(inference) = INFERENCE.lock() else {
+        return BTreeMap::new();
+    };
+    inference
+        .iter()
+        .flat_map(|inference| &inference.events)
+        .filter_map(|(id, history)| Some((id.clone(), history.region()?)))
+        .collect()
+}
+
+/// Writes the areas of the running script's events back to the file.
+pub fn save() -> Result<()> {
+    let Ok(inference) = INFERENCE.lock() else {
+        return Ok(());
+    };
+    let Some(inference) = inference.as_ref() else {
+        return Ok(());
+    };
+    let mut stored = load(&inference.path).unwrap_or_default();
+    stored.insert(inference.script.clone(), inference.events.clone());
+    if let Some(dir) = inference.path.parent() {
+        std::fs::create_dir_all(dir)
+            .context(format!("Failed to create directory {}", dir.display()))?;
+    }
+    let file = File::create(&inference.path)
+        .context(format!("Failed to create {}", inference.path.display()))?;
+    serde_json::to_writer_pretty(BufWriter::new(file), &stored)
+        .context(format!("Failed to write {}", inference.path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn narrows_to_padded_bounds_of_enough_matches_in_a_small_area() {
+        let mut history = History {
+            matches: 4,
+            bounds: Region {
+                x: 100,
+                y: 200,
+                width: 40,
+                height: 200,
+            },
+            screen_pixels: 1920 * 1080,
+        };
+        assert_eq!(history.region(), None);
+
+        history.matches = 5;
+        let region = history.region().unwrap();
+        assert_eq!(
+            region,
+            Region {
+                x: 50,
+                y: 150,
+                width: 140,
+                height: 300,
+            }
+        );
+
+        history.bounds.width = 1600;
+        history.bounds.height = 800;
+        assert_eq!(history.region(), None);
+    }
+}
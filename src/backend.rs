@@ -0,0 +1,707 @@
+//! Input backends used to deliver clicks and keypresses.
+//!
+//! Four backends are available: one that shells out to xdotool, a native one that talks to the
+//! display server directly through enigo, one that injects events below the display server
+//! through a uinput virtual device, and one that does the same through ydotool's daemon. The last
+//! two also work on Wayland. The preferred backend is probed at startup and the bot falls
+//! back to another one when it is unavailable. A dry-run backend only logs the actions it's
+//! given, for runs that must not touch the real mouse and keyboard. Builds with the `gamepad`
+//! feature can wrap the selected backend in one that adds a virtual game controller.
+use crate::dryrun;
+use crate::error::{self, ColorbotError};
+#[cfg(feature = "gamepad")]
+use crate::gamepad::{GamepadAxis, GamepadButton};
+use crate::process;
+use crate::uinput::UinputBackend;
+use crate::windmouse::Point;
+use crate::ydotool::YdotoolBackend;
+
+use anyhow::{anyhow, bail, Context, Result};
+use clap::ValueEnum;
+use enigo::{Button, Coordinate, Direction, Enigo, Keyboard, Mouse, Settings};
+use log::{debug, info, warn};
+use schemars::JsonSchema;
+use serde::Deserialize;
+use std::process::Command;
+use std::sync::Mutex;
+use std::time::Duration;
+use x11rb::protocol::xproto::ConnectionExt;
+
+/// Longest time a single xdotool invocation may take before it's killed.
+const XDOTOOL_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Selectable input backend kinds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum BackendKind {
+    /// Shell out to the xdotool binary for every action.
+    Xdotool,
+    /// Inject input directly through the display server.
+    Native,
+    /// Inject input at the evdev level through a uinput virtual device, works on X11 and Wayland.
+    Uinput,
+    /// Run ydotool for every action, works on X11 and Wayland through the ydotoold daemon.
+    Ydotool,
+}
+
+impl BackendKind {
+    /// Returns the backend to try when this one is unavailable.
+    fn fallback(self) -> Self {
+        match self {
+            BackendKind::Xdotool => BackendKind::Native,
+            BackendKind::Native => BackendKind::Xdotool,
+            BackendKind::Uinput => BackendKind::Native,
+            BackendKind::Ydotool => BackendKind::Uinput,
+        }
+    }
+
+    /// Whether the backend injects input below the display server, where it can't read the
+    /// cursor back and tracks the position it moved it to instead.
+    pub fn tracks_cursor(self) -> bool {
+        matches!(self, BackendKind::Uinput | BackendKind::Ydotool)
+    }
+}
+
+/// Where a backend injecting below the display server last moved the cursor, in X screen
+/// coordinates.
+static INJECTED_CURSOR: Mutex<Option<Point>> = Mutex::new(None);
+
+/// Returns where the selected backend last moved the cursor when it can't read the real
+/// position, see [`BackendKind::tracks_cursor`].
+pub fn injected_cursor() -> Option<Point> {
+    INJECTED_CURSOR.lock().ok().and_then(|cursor| *cursor)
+}
+
+/// Records where a backend injecting below the display server moved the cursor.
+pub(crate) fn set_injected_cursor(point: Point) {
+    if let Ok(mut cursor) = INJECTED_CURSOR.lock() {
+        *cursor = Some(point);
+    }
+}
+
+/// Physical layout of the primary and secondary mouse buttons.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ButtonLayout {
+    /// Read the layout from the X server's pointer mapping.
+    Auto,
+    /// Primary button is the physical left button.
+    RightHanded,
+    /// Primary and secondary buttons are swapped, as on left-handed setups.
+    LeftHanded,
+}
+
+impl ButtonLayout {
+    /// Returns whether the primary and secondary buttons are swapped.
+    ///
+    /// Auto detection assumes the standard layout when the pointer mapping can't be read.
+    pub fn is_swapped(self) -> bool {
+        match self {
+            ButtonLayout::RightHanded => false,
+            ButtonLayout::LeftHanded => true,
+            ButtonLayout::Auto => detect_swapped_buttons().unwrap_or_else(|e| {
+                warn!(
+                    "Failed to read the pointer mapping, assuming right-handed buttons: {:#}",
+                    e
+                );
+                false
+            }),
+        }
+    }
+}
+
+/// Logical mouse button, as named in scripts.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum MouseButton {
+    /// Primary button.
+    #[default]
+    Left,
+    /// Secondary button, usually opening context menus.
+    Right,
+    Middle,
+}
+
+impl MouseButton {
+    /// Returns the physical button that the pointer mapping turns into this logical one.
+    pub fn physical(self, swapped: bool) -> Self {
+        match (self, swapped) {
+            (MouseButton::Left, true) => MouseButton::Right,
+            (MouseButton::Right, true) => MouseButton::Left,
+            (button, _) => button,
+        }
+    }
+}
+
+impl std::fmt::Display for MouseButton {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MouseButton::Left => write!(f, "left"),
+            MouseButton::Right => write!(f, "right"),
+            MouseButton::Middle => write!(f, "middle"),
+        }
+    }
+}
+
+/// Reads the X server's pointer mapping and checks whether buttons 1 and 3 are swapped.
+fn detect_swapped_buttons() -> Result<bool> {
+    let (conn, _) = x11rb::connect(None).context("Failed to connect to the X server")?;
+    let map = conn.get_pointer_mapping()?.reply()?.map;
+    Ok(map.len() >= 3 && map[0] == 3 && map[2] == 1)
+}
+
+/// A single backend capability, as named in scripts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Capability {
+    Scroll,
+    Drag,
+    KeyChords,
+    RelativeMoves,
+    SubMsTiming,
+    Gamepad,
+}
+
+/// Actions and timing guarantees an input backend can provide.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Capabilities {
+    /// Mouse wheel scrolling.
+    pub scroll: bool,
+    /// Holding a mouse button down across a movement.
+    pub drag: bool,
+    /// Key combinations such as "ctrl+c".
+    pub key_chords: bool,
+    /// Cursor moves relative to the current position.
+    pub relative_moves: bool,
+    /// Sub-millisecond spacing between consecutive actions.
+    pub sub_ms_timing: bool,
+    /// Buttons and axes of a virtual game controller.
+    pub gamepad: bool,
+}
+
+impl FromIterator<Capability> for Capabilities {
+    fn from_iter<I: IntoIterator<Item = Capability>>(iter: I) -> Self {
+        let mut capabilities = Capabilities::default();
+        for capability in iter {
+            match capability {
+                Capability::Scroll => capabilities.scroll = true,
+                Capability::Drag => capabilities.drag = true,
+                Capability::KeyChords => capabilities.key_chords = true,
+                Capability::RelativeMoves => capabilities.relative_moves = true,
+                Capability::SubMsTiming => capabilities.sub_ms_timing = true,
+                Capability::Gamepad => capabilities.gamepad = true,
+            }
+        }
+        capabilities
+    }
+}
+
+impl Capabilities {
+    /// Returns the names of the capabilities in `required` that this set lacks.
+    pub fn missing(&self, required: &Capabilities) -> Vec<&'static str> {
+        [
+            (required.scroll && !self.scroll, "scroll"),
+            (required.drag && !self.drag, "drag"),
+            (required.key_chords && !self.key_chords, "key chords"),
+            (
+                required.relative_moves && !self.relative_moves,
+                "relative moves",
+            ),
+            (
+                required.sub_ms_timing && !self.sub_ms_timing,
+                "sub-ms timing",
+            ),
+            (required.gamepad && !self.gamepad, "gamepad"),
+        ]
+        .into_iter()
+        .filter_map(|(is_missing, name)| is_missing.then_some(name))
+        .collect()
+    }
+}
+
+/// Common interface for delivering clicks and keypresses.
+pub trait InputBackend {
+    /// Returns a human readable backend name for logging.
+    fn name(&self) -> &'static str;
+
+    /// Returns the actions this backend is able to perform.
+    fn capabilities(&self) -> Capabilities;
+
+    /// Moves the cursor to an absolute screen position in a single step.
+    fn move_cursor(&mut self, point: Point) -> Result<()>;
+
+    /// Clicks a logical mouse button at the current cursor position.
+    ///
+    /// Backends send the physical button that the pointer mapping turns into the logical one, so
+    /// left and right clicks keep working on left-handed setups.
+    fn click(&mut self, button: MouseButton) -> Result<()>;
+
+    /// Presses or releases a logical mouse button without clicking it, to drag with it held.
+    fn set_button(&mut self, button: MouseButton, pressed: bool) -> Result<()>;
+
+    /// Presses and releases the given key or key chord (xdotool format, e.g. "ctrl+c").
+    fn key(&mut self, keycode: &str) -> Result<()>;
+
+    /// Holds the given key down for `duration` before releasing it.
+    fn hold_key(&mut self, keycode: &str, duration: Duration) -> Result<()>;
+
+    /// Types the given text as-is, without interpreting key names.
+    ///
+    /// Errors must not quote the text since it may be a secret.
+    fn text(&mut self, text: &str) -> Result<()>;
+
+    /// Releases every modifier key and mouse button, in case an action was interrupted while
+    /// holding them.
+    fn release_all(&mut self) -> Result<()>;
+
+    /// Presses or releases a button of the virtual game controller.
+    #[cfg(feature = "gamepad")]
+    fn gamepad_button(&mut self, button: GamepadButton, _pressed: bool) -> Result<()> {
+        bail!(
+            "The {} backend has no virtual controller to press {} on",
+            self.name(),
+            button
+        )
+    }
+
+    /// Moves an axis of the virtual game controller to `value`, from -1 to 1 for sticks and 0 to 1
+    /// for triggers.
+    #[cfg(feature = "gamepad")]
+    fn gamepad_axis(&mut self, axis: GamepadAxis, _value: f64) -> Result<()> {
+        bail!(
+            "The {} backend has no virtual controller to move {} on",
+            self.name(),
+            axis
+        )
+    }
+}
+
+/// Releases the modifiers and mouse buttons through enigo.
+fn release_with_enigo(enigo: &mut Enigo) -> Result<()> {
+    use enigo::Key;
+
+    for key in [Key::Shift, Key::Control, Key::Alt, Key::Meta] {
+        enigo.key(key, Direction::Release)?;
+    }
+    for button in [Button::Left, Button::Right, Button::Middle] {
+        enigo.button(button, Direction::Release)?;
+    }
+    Ok(())
+}
+
+/// Backend that runs the xdotool binary for each action.
+///
+/// Cursor paths are emitted through enigo since spawning xdotool for every path step would be
+/// far too slow.
+pub struct XdotoolBackend {
+    enigo: Enigo,
+    swapped_buttons: bool,
+}
+
+impl XdotoolBackend {
+    /// Verifies that xdotool is installed and runnable.
+    fn probe(swapped_buttons: bool) -> Result<Self> {
+        let output = process::run(
+            Command::new("xdotool").arg("version"),
+            "xdotool",
+            XDOTOOL_TIMEOUT,
+        )
+        .map_err(|e| process::missing_tool(e, "xdotool"))?;
+        if !output.status.success() {
+            bail!("xdotool exited with {}", output.status);
+        }
+        let enigo = Enigo::new(&Settings::default()).context("failed to init enigo")?;
+
+        Ok(Self {
+            enigo,
+            swapped_buttons,
+        })
+    }
+
+    /// Executes xdotool with the given arguments.
+    fn run(&self, args: &[&str]) -> Result<()> {
+        let output = process::run(
+            Command::new("xdotool").args(args),
+            "xdotool",
+            XDOTOOL_TIMEOUT,
+        )
+        .context(format!("Failed to execute xdotool with args: {:?}", args))?;
+        if !output.status.success() {
+            bail!("xdotool with args {:?} exited with {}", args, output.status);
+        }
+        Ok(())
+    }
+}
+
+impl InputBackend for XdotoolBackend {
+    fn name(&self) -> &'static str {
+        "xdotool"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        // Every action spawns a process, so timing is only as precise as process startup
+        Capabilities {
+            scroll: true,
+            drag: true,
+            key_chords: true,
+            relative_moves: true,
+            sub_ms_timing: false,
+            gamepad: false,
+        }
+    }
+
+    fn move_cursor(&mut self, point: Point) -> Result<()> {
+        self.enigo
+            .move_mouse(point.x, point.y, Coordinate::Abs)
+            .context("Failed to move the cursor")
+    }
+
+    fn click(&mut self, button: MouseButton) -> Result<()> {
+        let physical = match button.physical(self.swapped_buttons) {
+            MouseButton::Left => "1",
+            MouseButton::Middle => "2",
+            MouseButton::Right => "3",
+        };
+        self.run(&["click", physical])
+            .context(format!("Failed to execute xdotool for {} click", button))
+    }
+
+    fn set_button(&mut self, button: MouseButton, pressed: bool) -> Result<()> {
+        let physical = match button.physical(self.swapped_buttons) {
+            MouseButton::Left => "1",
+            MouseButton::Middle => "2",
+            MouseButton::Right => "3",
+        };
+        let action = if pressed { "mousedown" } else { "mouseup" };
+        self.run(&[action, physical]).context(format!(
+            "Failed to execute xdotool {} for the {} button",
+            action, button
+        ))
+    }
+
+    fn key(&mut self, keycode: &str) -> Result<()> {
+        self.run(&["key", keycode])
+            .context(format!("Failed to execute xdotool for key '{}'", keycode))
+    }
+
+    fn hold_key(&mut self, keycode: &str, duration: Duration) -> Result<()> {
+        self.run(&["keydown", keycode]).context(format!(
+            "Failed to execute xdotool keydown for '{}'",
+            keycode
+        ))?;
+        std::thread::sleep(duration);
+        self.run(&["keyup", keycode])
+            .context(format!("Failed to execute xdotool keyup for '{}'", keycode))
+    }
+
+    fn text(&mut self, text: &str) -> Result<()> {
+        // The text may be a secret, so it's passed on stdin where other users can't read it
+        let output = process::run_with_stdin(
+            Command::new("xdotool").args(["type", "--delay", "0", "--file", "-"]),
+            "xdotool type",
+            XDOTOOL_TIMEOUT,
+            Some(text.as_bytes()),
+        )
+        .context("Failed to execute xdotool to type text")?;
+        if !output.status.success() {
+            bail!("xdotool type exited with {}", output.status);
+        }
+        Ok(())
+    }
+
+    fn release_all(&mut self) -> Result<()> {
+        release_with_enigo(&mut self.enigo)
+    }
+}
+
+/// Backend that injects input through enigo without spawning processes.
+pub struct NativeBackend {
+    enigo: Enigo,
+    swapped_buttons: bool,
+}
+
+impl NativeBackend {
+    /// Connects to the display server.
+    fn probe(swapped_buttons: bool) -> Result<Self> {
+        let enigo = Enigo::new(&Settings::default()).context("failed to init enigo")?;
+        Ok(Self {
+            enigo,
+            swapped_buttons,
+        })
+    }
+}
+
+/// Translates a single xdotool key name into an enigo key.
+fn parse_key(name: &str) -> Result<enigo::Key> {
+    use enigo::Key;
+
+    let mut chars = name.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        return Ok(Key::Unicode(c));
+    }
+
+    let key = match name.to_ascii_lowercase().as_str() {
+        "ctrl" | "control" | "control_l" | "control_r" => Key::Control,
+        "shift" | "shift_l" | "shift_r" => Key::Shift,
+        "alt" | "alt_l" | "alt_r" => Key::Alt,
+        "super" | "super_l" | "super_r" | "meta" => Key::Meta,
+        "escape" | "esc" => Key::Escape,
+        "return" | "enter" => Key::Return,
+        "space" => Key::Space,
+        "tab" => Key::Tab,
+        "backspace" => Key::Backspace,
+        "delete" => Key::Delete,
+        "insert" => Key::Insert,
+        "home" => Key::Home,
+        "end" => Key::End,
+        "prior" | "page_up" => Key::PageUp,
+        "next" | "page_down" => Key::PageDown,
+        "up" => Key::UpArrow,
+        "down" => Key::DownArrow,
+        "left" => Key::LeftArrow,
+        "right" => Key::RightArrow,
+        "f1" => Key::F1,
+        "f2" => Key::F2,
+        "f3" => Key::F3,
+        "f4" => Key::F4,
+        "f5" => Key::F5,
+        "f6" => Key::F6,
+        "f7" => Key::F7,
+        "f8" => Key::F8,
+        "f9" => Key::F9,
+        "f10" => Key::F10,
+        "f11" => Key::F11,
+        "f12" => Key::F12,
+        _ => bail!("Unsupported key name '{}' for the native backend", name),
+    };
+
+    Ok(key)
+}
+
+impl InputBackend for NativeBackend {
+    fn name(&self) -> &'static str {
+        "native"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            scroll: true,
+            drag: true,
+            key_chords: true,
+            relative_moves: true,
+            sub_ms_timing: true,
+            gamepad: false,
+        }
+    }
+
+    fn move_cursor(&mut self, point: Point) -> Result<()> {
+        self.enigo
+            .move_mouse(point.x, point.y, Coordinate::Abs)
+            .context("Failed to move the cursor natively")
+    }
+
+    fn click(&mut self, button: MouseButton) -> Result<()> {
+        let physical = match button.physical(self.swapped_buttons) {
+            MouseButton::Left => Button::Left,
+            MouseButton::Middle => Button::Middle,
+            MouseButton::Right => Button::Right,
+        };
+        self.enigo
+            .button(physical, Direction::Click)
+            .context(format!("Failed to perform native {} click", button))
+    }
+
+    fn set_button(&mut self, button: MouseButton, pressed: bool) -> Result<()> {
+        let physical = match button.physical(self.swapped_buttons) {
+            MouseButton::Left => Button::Left,
+            MouseButton::Middle => Button::Middle,
+            MouseButton::Right => Button::Right,
+        };
+        let direction = if pressed {
+            Direction::Press
+        } else {
+            Direction::Release
+        };
+        self.enigo
+            .button(physical, direction)
+            .context(format!("Failed to set the {} button natively", button))
+    }
+
+    fn key(&mut self, keycode: &str) -> Result<()> {
+        let keys = keycode
+            .split('+')
+            .map(parse_key)
+            .collect::<Result<Vec<_>>>()?;
+
+        // Hold modifiers in order, tap the last key, then release in reverse order
+        let (last, modifiers) = keys.split_last().context("Empty key chord")?;
+        for modifier in modifiers {
+            self.enigo.key(*modifier, Direction::Press)?;
+        }
+        self.enigo.key(*last, Direction::Click)?;
+        for modifier in modifiers.iter().rev() {
+            self.enigo.key(*modifier, Direction::Release)?;
+        }
+
+        Ok(())
+    }
+
+    fn hold_key(&mut self, keycode: &str, duration: Duration) -> Result<()> {
+        let key = parse_key(keycode)?;
+        self.enigo.key(key, Direction::Press)?;
+        std::thread::sleep(duration);
+        self.enigo.key(key, Direction::Release)?;
+        Ok(())
+    }
+
+    fn text(&mut self, text: &str) -> Result<()> {
+        self.enigo
+            .text(text)
+            .map_err(|_| anyhow!("Failed to type text natively"))
+    }
+
+    fn release_all(&mut self) -> Result<()> {
+        release_with_enigo(&mut self.enigo)
+    }
+}
+
+/// Backend that logs actions instead of performing them.
+///
+/// The cursor it moves is the simulated one of the dry run module.
+pub struct DryRunBackend;
+
+impl DryRunBackend {
+    /// Creates the backend with the simulated cursor at `cursor` in X screen coordinates.
+    pub fn new(cursor: Point) -> Self {
+        dryrun::start(cursor);
+        Self
+    }
+
+    /// Returns the simulated cursor position.
+    fn cursor(&self) -> Point {
+        dryrun::cursor().unwrap_or(Point::new(0, 0))
+    }
+}
+
+impl InputBackend for DryRunBackend {
+    fn name(&self) -> &'static str {
+        "dry-run"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            scroll: true,
+            drag: true,
+            key_chords: true,
+            relative_moves: true,
+            sub_ms_timing: true,
+            gamepad: cfg!(feature = "gamepad"),
+        }
+    }
+
+    fn move_cursor(&mut self, point: Point) -> Result<()> {
+        dryrun::move_cursor(point);
+        Ok(())
+    }
+
+    fn click(&mut self, button: MouseButton) -> Result<()> {
+        dryrun::end_stroke(true);
+        info!("Dry run: {} click at {}", button, self.cursor());
+        Ok(())
+    }
+
+    fn set_button(&mut self, button: MouseButton, pressed: bool) -> Result<()> {
+        dryrun::end_stroke(pressed);
+        let action = if pressed { "press" } else { "release" };
+        info!("Dry run: {} {} button at {}", action, button, self.cursor());
+        Ok(())
+    }
+
+    fn key(&mut self, _keycode: &str) -> Result<()> {
+        dryrun::end_stroke(false);
+        // The key may be a resolved secret, the event's own log names it redacted
+        info!("Dry run: press a key");
+        Ok(())
+    }
+
+    fn hold_key(&mut self, _keycode: &str, duration: Duration) -> Result<()> {
+        dryrun::end_stroke(false);
+        info!("Dry run: hold a key for {:?}", duration);
+        std::thread::sleep(duration);
+        Ok(())
+    }
+
+    fn text(&mut self, text: &str) -> Result<()> {
+        dryrun::end_stroke(false);
+        // The text may be a secret, so only its length is logged
+        debug!("Dry run: type {} characters", text.chars().count());
+        Ok(())
+    }
+
+    fn release_all(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    #[cfg(feature = "gamepad")]
+    fn gamepad_button(&mut self, button: GamepadButton, pressed: bool) -> Result<()> {
+        let action = if pressed { "press" } else { "release" };
+        info!("Dry run: {} gamepad button {}", action, button);
+        Ok(())
+    }
+
+    #[cfg(feature = "gamepad")]
+    fn gamepad_axis(&mut self, axis: GamepadAxis, value: f64) -> Result<()> {
+        debug!("Dry run: move gamepad axis {} to {:.2}", axis, value);
+        Ok(())
+    }
+}
+
+/// Probes a single backend kind.
+fn probe(kind: BackendKind, swapped_buttons: bool) -> Result<Box<dyn InputBackend>> {
+    match kind {
+        BackendKind::Xdotool => Ok(Box::new(XdotoolBackend::probe(swapped_buttons)?)),
+        BackendKind::Native => Ok(Box::new(NativeBackend::probe(swapped_buttons)?)),
+        BackendKind::Uinput => Ok(Box::new(UinputBackend::probe(swapped_buttons)?)),
+        BackendKind::Ydotool => Ok(Box::new(YdotoolBackend::probe(swapped_buttons)?)),
+    }
+}
+
+/// Creates the preferred input backend, falling back to the alternative when it is unavailable.
+///
+/// When neither can be used and the preferred one's program isn't installed, the missing program
+/// is reported as the failure.
+pub fn select_backend(
+    preferred: BackendKind,
+    buttons: ButtonLayout,
+) -> Result<Box<dyn InputBackend>> {
+    // A position tracked for a backend of an earlier run is stale
+    if let Ok(mut cursor) = INJECTED_CURSOR.lock() {
+        *cursor = None;
+    }
+    let swapped_buttons = buttons.is_swapped();
+    if swapped_buttons {
+        debug!("Mouse buttons are swapped, left clicks use the physical right button");
+    }
+
+    match probe(preferred, swapped_buttons) {
+        Ok(backend) => {
+            debug!("Using {} input backend", backend.name());
+            Ok(backend)
+        }
+        Err(e) => {
+            let fallback = preferred.fallback();
+            warn!(
+                "{:?} input backend unavailable ({:#}), falling back to {:?}",
+                preferred, e, fallback
+            );
+            probe(fallback, swapped_buttons).map_err(|fallback_error| match error::kind(&e) {
+                Some(ColorbotError::ToolMissing(program)) => {
+                    warn!(
+                        "{:?} input backend unavailable too ({:#})",
+                        fallback, fallback_error
+                    );
+                    ColorbotError::ToolMissing(program).into()
+                }
+                _ => ColorbotError::InputBackend(fallback_error).into(),
+            })
+        }
+    }
+}
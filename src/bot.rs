@@ -0,0 +1,276 @@
+//! Running a bot from another Rust program.
+//!
+//! A [`Bot`] is built from a script and the same settings `colorbot run` takes, then either runs
+//! on the calling thread or is spawned onto its own, returning a [`BotHandle`] to pause, resume
+//! or stop it and wait for its report. Stopping ends the run before its next event like its
+//! runtime ran out, the report is still written.
+//!
+//! The library doesn't set up logging, the run logs through whatever `log` backend the program
+//! installed. Much of a run's state is process-wide, so only one bot runs at a time. Failures are
+//! returned as a [`ColorbotError`], whose [`ColorbotError::kind`] tells them apart.
+use crate::config::BotConfig;
+use crate::controls::MouseSettings;
+use crate::error::ColorbotError;
+use crate::report::RunReport;
+use crate::rundir::RunDir;
+#[cfg(feature = "stats-db")]
+use crate::statsdb;
+use crate::{hotkeys, instance, runner, script};
+
+use anyhow::{anyhow, ensure, Context, Result};
+use log::debug;
+use std::ffi::OsString;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+/// Whether a bot is running in this process.
+static RUNNING: AtomicBool = AtomicBool::new(false);
+/// Whether this process claimed the display, the claim lasts until it exits.
+static DISPLAY_CLAIMED: AtomicBool = AtomicBool::new(false);
+
+/// Builder of a [`Bot`], see [`Bot::builder`].
+#[derive(Debug, Default)]
+pub struct BotBuilder {
+    script: Option<PathBuf>,
+    speed: Option<f64>,
+    runtime: Option<Duration>,
+    args: Vec<OsString>,
+    config: Option<BotConfig>,
+}
+
+impl BotBuilder {
+    /// Sets the script to run.
+    pub fn script(mut self, path: impl Into<PathBuf>) -> Self {
+        self.script = Some(path.into());
+        self
+    }
+
+    /// Sets the factor scaling how fast the cursor moves, as `--mouse-speed`.
+    pub fn speed(mut self, speed: f64) -> Self {
+        self.speed = Some(speed);
+        self
+    }
+
+    /// Sets how long the bot runs, as `--runtime`. Durations are rounded down to whole seconds.
+    pub fn runtime(mut self, runtime: Duration) -> Self {
+        self.runtime = Some(runtime);
+        self
+    }
+
+    /// Adds `colorbot run` options, such as `["--dry-run"]`, for settings without a method here.
+    pub fn args<I, T>(mut self, args: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<OsString>,
+    {
+        self.args.extend(args.into_iter().map(Into::into));
+        self
+    }
+
+    /// Starts from `config` instead of the defaults, for setting options through its fields.
+    ///
+    /// The script, speed and runtime set on the builder replace the config's, options can't be
+    /// added on top of it with [`BotBuilder::args`].
+    pub fn config(mut self, config: BotConfig) -> Self {
+        self.config = Some(config);
+        self
+    }
+
+    /// Checks the settings and loads the script, failing like `colorbot run` would on them.
+    pub fn build(self) -> Result<Bot, ColorbotError> {
+        let config = self.parse_config()?;
+        script::load(&config.script)?;
+        Ok(Bot { config })
+    }
+
+    fn parse_config(self) -> Result<BotConfig> {
+        let mut config = match self.config {
+            Some(config) => {
+                ensure!(
+                    self.args.is_empty(),
+                    "A bot takes either a config or options, not both"
+                );
+                config
+            }
+            None => {
+                let script = self.script.clone().context("A bot needs a script to run")?;
+                BotConfig::parse(script, self.args)?
+            }
+        };
+        if let Some(script) = self.script {
+            config.script = script;
+        }
+        if let Some(speed) = self.speed {
+            config.mouse_speed = speed;
+        }
+        if let Some(runtime) = self.runtime {
+            config.runtime = runtime.as_secs();
+        }
+        ensure!(
+            !config.detach,
+            "--detach can't be used by a bot running in another program"
+        );
+        MouseSettings::from_config(&config).validate()?;
+        Ok(config)
+    }
+}
+
+/// A bot ready to run a script.
+#[derive(Debug, Clone)]
+pub struct Bot {
+    config: BotConfig,
+}
+
+impl Bot {
+    /// Returns a builder for a bot.
+    pub fn builder() -> BotBuilder {
+        BotBuilder::default()
+    }
+
+    /// Returns the settings the bot runs with.
+    pub fn config(&self) -> &BotConfig {
+        &self.config
+    }
+
+    /// Runs the script until its runtime is up or the bot is stopped, returning the run report.
+    ///
+    /// Blocks the calling thread, other threads control the run through [`Bot::pause`],
+    /// [`Bot::resume`] and [`Bot::stop`].
+    pub fn run(&self) -> Result<RunReport, ColorbotError> {
+        if RUNNING.swap(true, Ordering::SeqCst) {
+            return Err(anyhow!("Another bot is already running in this process").into());
+        }
+        let outcome = self.run_once();
+        RUNNING.store(false, Ordering::SeqCst);
+        Ok(outcome?)
+    }
+
+    fn run_once(&self) -> Result<RunReport> {
+        let config = &self.config;
+        let root = config.run_root.clone().unwrap_or_else(RunDir::default_root);
+        let run_dir = RunDir::create(&root, config.keep_runs as usize, &config.script)?;
+        debug!("Run directory is {}", run_dir.path().display());
+        // Replayed runs and dry runs don't touch the real cursor
+        if config.frames.is_none() && !config.dry_run && !DISPLAY_CLAIMED.load(Ordering::SeqCst) {
+            instance::lock_display(config.force)?;
+            DISPLAY_CLAIMED.store(true, Ordering::SeqCst);
+        }
+
+        let outcome = runner::run_event_loop(config.clone(), &run_dir);
+        #[cfg(feature = "stats-db")]
+        record_stats(config, &run_dir);
+        outcome.context("Failed to run event loop")?;
+        Ok(RunReport::load(&RunReport::path(&run_dir)?)?)
+    }
+
+    /// Runs the script on a new thread, returning a handle to control and wait for the run.
+    pub fn spawn(self) -> Result<BotHandle, ColorbotError> {
+        // A stop left over from an earlier run would end this one right away
+        hotkeys::set_stopping(false);
+        let thread = std::thread::Builder::new()
+            .name("colorbot".to_string())
+            .spawn(move || self.run())
+            .context("Failed to start the bot thread")?;
+        Ok(BotHandle { thread })
+    }
+
+    /// Pauses the running bot before its next event.
+    pub fn pause(&self) {
+        hotkeys::set_paused(true);
+    }
+
+    /// Resumes the paused bot.
+    pub fn resume(&self) {
+        hotkeys::set_paused(false);
+    }
+
+    /// Stops the running bot before its next event, even while it's paused.
+    pub fn stop(&self) {
+        hotkeys::set_stopping(true);
+    }
+}
+
+/// Records the run in the statistics database, only warning on failure like `colorbot run`.
+#[cfg(feature = "stats-db")]
+fn record_stats(config: &BotConfig, run_dir: &RunDir) {
+    // Runs that failed before the event loop started have no report to record
+    if !RunReport::path(run_dir).is_ok_and(|path| path.exists()) {
+        return;
+    }
+    let db = config
+        .stats_db
+        .clone()
+        .unwrap_or_else(statsdb::default_path);
+    if let Err(e) = statsdb::record(&db, run_dir) {
+        log::warn!(
+            "Failed to record the run in the statistics database: {:#}",
+            e
+        );
+    }
+}
+
+/// Control over a bot running on its own thread, see [`Bot::spawn`].
+#[derive(Debug)]
+pub struct BotHandle {
+    thread: JoinHandle<Result<RunReport, ColorbotError>>,
+}
+
+impl BotHandle {
+    /// Pauses the bot before its next event.
+    pub fn pause(&self) {
+        hotkeys::set_paused(true);
+    }
+
+    /// Resumes the paused bot.
+    pub fn resume(&self) {
+        hotkeys::set_paused(false);
+    }
+
+    /// Stops the bot before its next event, even while it's paused. Use [`BotHandle::join`] to
+    /// wait for it.
+    pub fn stop(&self) {
+        hotkeys::set_stopping(true);
+    }
+
+    /// Whether the run ended.
+    pub fn is_finished(&self) -> bool {
+        self.thread.is_finished()
+    }
+
+    /// Waits for the run to end and returns its report.
+    pub fn join(self) -> Result<RunReport, ColorbotError> {
+        match self.thread.join() {
+            Ok(outcome) => outcome,
+            Err(_) => Err(anyhow!("The bot thread panicked").into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builder_takes_a_config_or_options() {
+        let mut config = BotConfig::new("a.json");
+        config.dry_run = true;
+        let parsed = Bot::builder()
+            .config(config.clone())
+            .script("b.json")
+            .speed(2.0)
+            .parse_config()
+            .unwrap();
+        assert_eq!(parsed.script, PathBuf::from("b.json"));
+        assert!(parsed.dry_run);
+        assert_eq!(parsed.mouse_speed, 2.0);
+
+        assert!(Bot::builder()
+            .config(config)
+            .args(["--dry-run"])
+            .parse_config()
+            .is_err());
+        assert!(Bot::builder().args(["--dry-run"]).parse_config().is_err());
+    }
+}
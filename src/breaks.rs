@@ -0,0 +1,184 @@
+//! Scheduled breaks that pause the event loop during long sessions.
+//!
+//! Breaks start at randomized intervals and last a randomized duration, either drawn from a
+//! range such as `3m..8m` or jittered around a single duration such as `45m`. A break either
+//! keeps the input completely still or runs an idle script picked at random from a pool.
+use crate::backend::InputBackend;
+use crate::controls::MouseSettings;
+use crate::idle::IdleScript;
+use crate::indicator;
+
+use anyhow::{bail, ensure, Context, Result};
+use log::debug;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+/// Fraction by which single break intervals and lengths are randomly stretched or shortened.
+const BREAK_JITTER: f64 = 0.2;
+
+/// Time between breaks or length of a break.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BreakSpan {
+    /// Roughly this long, stretched or shortened by the break jitter.
+    Around(Duration),
+    /// Anywhere between the two durations.
+    Between(Duration, Duration),
+}
+
+impl BreakSpan {
+    /// Draws a duration from the span.
+    fn sample(&self) -> Duration {
+        match *self {
+            Self::Around(duration) => {
+                duration.mul_f64(rand::random_range(1.0 - BREAK_JITTER..=1.0 + BREAK_JITTER))
+            }
+            Self::Between(min, max) => rand::random_range(min..=max),
+        }
+    }
+
+    /// Returns the longest duration the span can draw.
+    pub fn max(&self) -> Duration {
+        match *self {
+            Self::Around(duration) => duration.mul_f64(1.0 + BREAK_JITTER),
+            Self::Between(_, max) => max,
+        }
+    }
+}
+
+impl FromStr for BreakSpan {
+    type Err = anyhow::Error;
+
+    /// Parses `45m`, `1h30m`, `90s`, plain seconds such as `300`, or a range such as `3m..8m`.
+    fn from_str(text: &str) -> Result<Self> {
+        let span = match text.split_once("..") {
+            Some((min, max)) => {
+                let (min, max) = (parse_duration(min)?, parse_duration(max)?);
+                ensure!(min <= max, "The range {} starts after it ends", text);
+                Self::Between(min, max)
+            }
+            None => Self::Around(parse_duration(text)?),
+        };
+        ensure!(!span.max().is_zero(), "Breaks need a duration above 0");
+        Ok(span)
+    }
+}
+
+/// Parses a duration of hours, minutes and seconds such as `1h30m`, or plain seconds.
+fn parse_duration(text: &str) -> Result<Duration> {
+    let text = text.trim();
+    if let Ok(secs) = text.parse::<u64>() {
+        return Ok(Duration::from_secs(secs));
+    }
+    let mut secs = 0;
+    let mut rest = text;
+    while !rest.is_empty() {
+        let digits = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let value: u64 = rest[..digits].parse().context(format!(
+            "Invalid duration '{}', expected e.g. 45m or 1h30m",
+            text
+        ))?;
+        let unit = match rest[digits..].chars().next() {
+            Some('h') => 3600,
+            Some('m') => 60,
+            Some('s') => 1,
+            _ => bail!("Invalid duration '{}', units are h, m and s", text),
+        };
+        secs += value * unit;
+        rest = &rest[digits + 1..];
+    }
+    ensure!(!text.is_empty(), "Missing duration");
+    Ok(Duration::from_secs(secs))
+}
+
+/// Decides when breaks are due and runs them.
+pub struct BreakScheduler {
+    every: BreakSpan,
+    length: BreakSpan,
+    next_break: Instant,
+    idle_scripts: Vec<IdleScript>,
+}
+
+impl BreakScheduler {
+    /// Creates a scheduler taking a break of `length` after every `every` of activity.
+    pub fn new(every: BreakSpan, length: BreakSpan, idle_scripts: Vec<IdleScript>) -> Self {
+        Self {
+            every,
+            length,
+            next_break: Instant::now() + every.sample(),
+            idle_scripts,
+        }
+    }
+
+    /// Takes a break if one is due, returning whether it did.
+    pub fn take_break_if_due(
+        &mut self,
+        input: &mut dyn InputBackend,
+        mouse: &MouseSettings,
+    ) -> Result<bool> {
+        if Instant::now() < self.next_break {
+            return Ok(false);
+        }
+
+        let length = self.length.sample();
+        let deadline = Instant::now() + length;
+        if self.idle_scripts.is_empty() {
+            debug!("Taking a {:?} break", length);
+            indicator::set_paused(true);
+            std::thread::sleep(length);
+            indicator::set_paused(false);
+        } else {
+            let script = &self.idle_scripts[rand::random_range(0..self.idle_scripts.len())];
+            debug!(
+                "Taking a {:?} break with idle script '{}'",
+                length,
+                script.name()
+            );
+            script.run_until(input, mouse, deadline)?;
+        }
+
+        self.next_break = Instant::now() + self.every.sample();
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_durations_and_ranges() {
+        let minutes = |m: u64| Duration::from_secs(m * 60);
+        assert_eq!(
+            "45m".parse::<BreakSpan>().unwrap(),
+            BreakSpan::Around(minutes(45))
+        );
+        assert_eq!(
+            "1h30m".parse::<BreakSpan>().unwrap(),
+            BreakSpan::Around(minutes(90))
+        );
+        assert_eq!(
+            "300".parse::<BreakSpan>().unwrap(),
+            BreakSpan::Around(minutes(5))
+        );
+        assert_eq!(
+            "3m..8m".parse::<BreakSpan>().unwrap(),
+            BreakSpan::Between(minutes(3), minutes(8))
+        );
+        for invalid in ["", "0", "45x", "m", "8m..3m", "1h..", "0s..0s"] {
+            assert!(invalid.parse::<BreakSpan>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn samples_stay_within_the_span() {
+        let range = BreakSpan::Between(Duration::from_secs(180), Duration::from_secs(480));
+        let around = BreakSpan::Around(Duration::from_secs(100));
+        for _ in 0..100 {
+            assert!((180..=480).contains(&range.sample().as_secs()));
+            assert!(around.sample() <= around.max());
+            assert!(around.sample() >= Duration::from_secs(80));
+        }
+    }
+}
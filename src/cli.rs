@@ -0,0 +1,186 @@
+//! Command line of the colorbot binary.
+//!
+//! The binary only calls [`main`], the commands run the crate's modules directly, which aren't
+//! part of the library's API.
+use crate::config::{Cli, Command, Tool};
+use crate::report::RunReport;
+use crate::rundir::RunDir;
+use crate::runner::run_event_loop;
+#[cfg(feature = "stats-db")]
+use crate::statsdb;
+use crate::{
+    actions, compare, control, crash, detach, display, doctor, error, graph, hotkeys, init,
+    instance, lint, output, picker, record, scripttest, secrets, stats, targetcheck, tune,
+};
+
+use anyhow::{Context, Result};
+use clap::Parser;
+use log::{debug, warn};
+use std::{fs::File, path::Path, process::ExitCode, time::Duration};
+
+/// Initializes terminal logging and, when given, a debug-level log file.
+fn init_logger(debug: bool, log_file: Option<&Path>) -> Result<()> {
+    // Warnings are always shown so that fallbacks and degraded behavior are visible
+    let level = if debug {
+        simplelog::LevelFilter::Debug
+    } else {
+        simplelog::LevelFilter::Warn
+    };
+    let log_config = simplelog::ConfigBuilder::new()
+        .add_filter_allow_str("colorbot")
+        .build();
+
+    let mut loggers: Vec<Box<dyn simplelog::SharedLogger>> = vec![simplelog::TermLogger::new(
+        level,
+        log_config.clone(),
+        simplelog::TerminalMode::Mixed,
+        simplelog::ColorChoice::Auto,
+    )];
+    if let Some(path) = log_file {
+        let file = File::create(path).context("Failed to create log file")?;
+        loggers.push(simplelog::WriteLogger::new(
+            simplelog::LevelFilter::Debug,
+            log_config,
+            file,
+        ));
+    }
+
+    simplelog::CombinedLogger::init(loggers).context("Failed to initialize logger")
+}
+
+/// Runs the command and returns an exit code telling the kinds of failures apart.
+pub fn main() -> ExitCode {
+    match try_main() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {:?}", e);
+            ExitCode::from(error::exit_code(&e))
+        }
+    }
+}
+
+/// Parses arguments, initializes logging, and runs the bot.
+fn try_main() -> Result<()> {
+    let cli = Cli::parse();
+    output::set_format(cli.output);
+
+    match cli.command {
+        Command::Run(config) => {
+            let run_dir = match detach::inherited_run_dir() {
+                Some(run_dir) => run_dir,
+                None => {
+                    let root = config.run_root.clone().unwrap_or_else(RunDir::default_root);
+                    let run_dir = RunDir::create(&root, config.keep_runs as usize, &config.script)?;
+                    if config.detach {
+                        return detach::spawn(&config, &run_dir);
+                    }
+                    run_dir
+                }
+            };
+            init_logger(cli.debug, Some(&run_dir.log_path()))?;
+            debug!("Run directory is {}", run_dir.path().display());
+            crash::install_hook(&run_dir, &config);
+            // Replayed runs and dry runs don't touch the real cursor
+            if config.frames.is_none() && !config.dry_run {
+                instance::lock_display(config.force)?;
+            }
+            if config.frames.is_none() {
+                control::serve()?;
+            }
+
+            #[cfg(feature = "stats-db")]
+            let stats_db = config
+                .stats_db
+                .clone()
+                .unwrap_or_else(statsdb::default_path);
+            let stats_out = config.stats_out.clone();
+            let outcome = run_event_loop(*config, &run_dir);
+            // Runs that failed before the event loop started have no report to record
+            let report_path = RunReport::path(&run_dir)?;
+            #[cfg(feature = "stats-db")]
+            if report_path.exists() {
+                if let Err(e) = statsdb::record(&stats_db, &run_dir) {
+                    warn!(
+                        "Failed to record the run in the statistics database: {:#}",
+                        e
+                    );
+                }
+            }
+            if report_path.exists() {
+                if let Err(e) = stats::summarize(&report_path, stats_out.as_deref()) {
+                    warn!("Failed to summarize the run: {:#}", e);
+                }
+            }
+            outcome.context("Failed to run event loop")?
+        }
+        Command::Tune(tune) => {
+            init_logger(cli.debug, None)?;
+            if tune.bot.frames.is_none() {
+                instance::lock_display(tune.bot.force)?;
+            }
+            tune::run(*tune, run_event_loop)?
+        }
+        Command::Tool(tool) => {
+            init_logger(cli.debug, None)?;
+            run_tool(tool)?
+        }
+    }
+
+    Ok(())
+}
+
+/// Runs one of the interactive authoring tools.
+fn run_tool(tool: Tool) -> Result<()> {
+    match tool {
+        Tool::SelectRegion => picker::select_region()?,
+        Tool::PickPoint { window } => picker::pick_point(window.as_deref())?,
+        Tool::WatchColor { on_change } => picker::watch_color(on_change)?,
+        Tool::SampleTarget {
+            region,
+            seconds,
+            hsv,
+        } => picker::sample_target(region, Duration::from_secs(seconds), hsv)?,
+        Tool::Phash { region, algorithm } => picker::phash(region, algorithm)?,
+        Tool::Record {
+            out,
+            stop_key,
+            seconds,
+        } => record::run(&out, &stop_key, seconds.map(Duration::from_secs))?,
+        Tool::Keys {
+            list,
+            export,
+            import,
+            hotkeys,
+        } => hotkeys::run(
+            hotkeys.as_deref(),
+            list,
+            export.as_deref(),
+            import.as_deref(),
+        )?,
+        Tool::Displays => display::run()?,
+        Tool::Doctor => doctor::run()?,
+        Tool::Validate { script, display } => lint::run(&script, display)?,
+        Tool::VerifyTargets { script, screenshot } => {
+            targetcheck::run(&script, screenshot.as_deref())?
+        }
+        Tool::Ctl { action } => control::run(action)?,
+        Tool::Init {
+            path,
+            template,
+            force,
+        } => init::run(&path, template, force)?,
+        Tool::Test { script } => scripttest::run(&script)?,
+        Tool::Graph { script, dot } => graph::run(&script, dot)?,
+        Tool::Actions { json } => actions::run(json || output::is_json())?,
+        #[cfg(feature = "stats-db")]
+        Tool::Stats { script, db } => statsdb::run(db.as_deref(), script.as_deref())?,
+        Tool::Compare { a, b } => compare::run(&a, &b)?,
+        Tool::Secrets {
+            file,
+            keyring,
+            action,
+        } => secrets::manage(&file, keyring, action)?,
+    }
+
+    Ok(())
+}
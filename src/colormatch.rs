@@ -0,0 +1,246 @@
+//! Ways of comparing a pixel to the colors of an RGB or palette target.
+//!
+//! The default per-channel RGB tolerance is exact and fast but breaks as soon as lighting or a
+//! brightness setting changes the game's colors. Comparing in HSV with a wide value range
+//! tolerates brightness changes, and the CIEDE2000 color difference matches colors the way the
+//! eye judges them, so a single threshold works for dark and bright targets alike.
+use crate::vision::PixelColor;
+
+use anyhow::{ensure, Result};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::sync::OnceLock;
+
+fn default_hue() -> f64 {
+    10.0
+}
+
+fn default_sat() -> f64 {
+    0.2
+}
+
+fn default_val() -> f64 {
+    0.35
+}
+
+fn default_max_delta_e() -> f64 {
+    5.0
+}
+
+/// Saturation below which a target color counts as gray, so its hue is ignored.
+const GRAY_SATURATION: f64 = 0.1;
+
+/// How pixels are compared to the colors of an RGB or palette target.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(tag = "mode", rename_all = "snake_case", deny_unknown_fields)]
+pub enum ColorMatcher {
+    /// Every channel within the color tolerance of a target color.
+    #[default]
+    Rgb,
+    /// Hue in degrees, saturation and value in [0, 1] within the given distances of a target
+    /// color. The hue of gray targets is ignored.
+    Hsv {
+        #[serde(default = "default_hue")]
+        hue: f64,
+        #[serde(default = "default_sat")]
+        sat: f64,
+        #[serde(default = "default_val")]
+        val: f64,
+    },
+    /// CIEDE2000 color difference to a target color of at most `max_delta_e`, where about 2 is
+    /// barely noticeable.
+    Ciede2000 {
+        #[serde(default = "default_max_delta_e")]
+        max_delta_e: f64,
+    },
+}
+
+impl ColorMatcher {
+    /// Checks that the distances are within their valid ranges.
+    pub fn validate(&self) -> Result<()> {
+        match *self {
+            ColorMatcher::Rgb => {}
+            ColorMatcher::Hsv { hue, sat, val } => {
+                ensure!(
+                    (0.0..=180.0).contains(&hue),
+                    "Hue distance {} must lie within [0, 180]",
+                    hue
+                );
+                ensure!(
+                    (0.0..=1.0).contains(&sat) && (0.0..=1.0).contains(&val),
+                    "Saturation and value distances must lie within [0, 1]"
+                );
+            }
+            ColorMatcher::Ciede2000 { max_delta_e } => {
+                ensure!(
+                    max_delta_e > 0.0,
+                    "max_delta_e must be positive, got {}",
+                    max_delta_e
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks if `color` matches `target`, using `tolerance` for per-channel RGB matching.
+    pub fn matches(&self, color: &PixelColor, target: &PixelColor, tolerance: u8) -> bool {
+        match *self {
+            ColorMatcher::Rgb => color.is_match(target, tolerance),
+            ColorMatcher::Hsv { hue, sat, val } => {
+                let (color, target) = (color.to_hsv(), target.to_hsv());
+                let hue_distance = (color.h - target.h).abs();
+                let hue_ok =
+                    target.s < GRAY_SATURATION || hue_distance.min(360.0 - hue_distance) <= hue;
+                hue_ok && (color.s - target.s).abs() <= sat && (color.v - target.v).abs() <= val
+            }
+            ColorMatcher::Ciede2000 { max_delta_e } => {
+                ciede2000(to_lab(color), to_lab(target)) <= max_delta_e
+            }
+        }
+    }
+}
+
+/// Converts an sRGB channel value to linear light.
+fn linear(channel: u8) -> f64 {
+    static TABLE: OnceLock<[f64; 256]> = OnceLock::new();
+    TABLE.get_or_init(|| {
+        std::array::from_fn(|i| {
+            let c = i as f64 / 255.0;
+            if c <= 0.04045 {
+                c / 12.92
+            } else {
+                ((c + 0.055) / 1.055).powf(2.4)
+            }
+        })
+    })[usize::from(channel)]
+}
+
+/// Converts an sRGB color to CIELAB under the D65 white point.
+fn to_lab(color: &PixelColor) -> [f64; 3] {
+    let [r, g, b] = color.rgb().map(linear);
+    let x = (0.4124 * r + 0.3576 * g + 0.1805 * b) / 0.95047;
+    let y = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+    let z = (0.0193 * r + 0.1192 * g + 0.9505 * b) / 1.08883;
+    let f = |t: f64| {
+        if t > 216.0 / 24389.0 {
+            t.cbrt()
+        } else {
+            (24389.0 / 27.0 * t + 16.0) / 116.0
+        }
+    };
+    let (fx, fy, fz) = (f(x), f(y), f(z));
+    [116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz)]
+}
+
+/// Computes the CIEDE2000 color difference between two CIELAB colors.
+fn ciede2000([l1, a1, b1]: [f64; 3], [l2, a2, b2]: [f64; 3]) -> f64 {
+    let c_mean = (a1.hypot(b1) + a2.hypot(b2)) / 2.0;
+    let g = 0.5 * (1.0 - (c_mean.powi(7) / (c_mean.powi(7) + 25f64.powi(7))).sqrt());
+    let (a1, a2) = (a1 * (1.0 + g), a2 * (1.0 + g));
+    let (c1, c2) = (a1.hypot(b1), a2.hypot(b2));
+    let hue = |a: f64, b: f64| {
+        if a == 0.0 && b == 0.0 {
+            0.0
+        } else {
+            b.atan2(a).to_degrees().rem_euclid(360.0)
+        }
+    };
+    let (h1, h2) = (hue(a1, b1), hue(a2, b2));
+
+    let delta_l = l2 - l1;
+    let delta_c = c2 - c1;
+    let delta_h = if c1 * c2 == 0.0 {
+        0.0
+    } else if (h2 - h1).abs() <= 180.0 {
+        h2 - h1
+    } else if h2 <= h1 {
+        h2 - h1 + 360.0
+    } else {
+        h2 - h1 - 360.0
+    };
+    let delta_h = 2.0 * (c1 * c2).sqrt() * (delta_h / 2.0).to_radians().sin();
+
+    let l_mean = (l1 + l2) / 2.0;
+    let c_mean = (c1 + c2) / 2.0;
+    let h_mean = if c1 * c2 == 0.0 {
+        h1 + h2
+    } else if (h1 - h2).abs() <= 180.0 {
+        (h1 + h2) / 2.0
+    } else if h1 + h2 < 360.0 {
+        (h1 + h2 + 360.0) / 2.0
+    } else {
+        (h1 + h2 - 360.0) / 2.0
+    };
+
+    let t = 1.0 - 0.17 * (h_mean - 30.0).to_radians().cos()
+        + 0.24 * (2.0 * h_mean).to_radians().cos()
+        + 0.32 * (3.0 * h_mean + 6.0).to_radians().cos()
+        - 0.20 * (4.0 * h_mean - 63.0).to_radians().cos();
+    let delta_theta = 30.0 * (-((h_mean - 275.0) / 25.0).powi(2)).exp();
+    let r_c = 2.0 * (c_mean.powi(7) / (c_mean.powi(7) + 25f64.powi(7))).sqrt();
+    let s_l = 1.0 + 0.015 * (l_mean - 50.0).powi(2) / (20.0 + (l_mean - 50.0).powi(2)).sqrt();
+    let s_c = 1.0 + 0.045 * c_mean;
+    let s_h = 1.0 + 0.015 * c_mean * t;
+    let r_t = -(2.0 * delta_theta).to_radians().sin() * r_c;
+
+    let (l, c, h) = (delta_l / s_l, delta_c / s_c, delta_h / s_h);
+    (l * l + c * c + h * h + r_t * c * h).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ciede2000_matches_reference_values() {
+        // Pairs from Sharma, Wu and Dalal's CIEDE2000 test data
+        let pairs = [
+            ([50.0, 2.6772, -79.7751], [50.0, 0.0, -82.7485], 2.0425),
+            ([50.0, 3.1571, -77.2803], [50.0, 0.0, -82.7485], 2.8615),
+            ([50.0, 2.5, 0.0], [73.0, 25.0, -18.0], 27.1492),
+            ([50.0, 2.5, 0.0], [50.0, 0.0, -2.5], 4.3065),
+            (
+                [60.2574, -34.0099, 36.2677],
+                [60.4626, -34.1751, 39.4387],
+                1.2644,
+            ),
+        ];
+        for (lab1, lab2, expected) in pairs {
+            let delta_e = ciede2000(lab1, lab2);
+            assert!(
+                (delta_e - expected).abs() < 1e-4,
+                "{:?} vs {:?}: {} != {}",
+                lab1,
+                lab2,
+                delta_e,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn perceptual_modes_tolerate_darkening() {
+        let target = PixelColor::new(200, 40, 40);
+        let darker = PixelColor::new(150, 30, 30);
+        assert!(!ColorMatcher::Rgb.matches(&darker, &target, 10));
+        assert!(ColorMatcher::Hsv {
+            hue: default_hue(),
+            sat: default_sat(),
+            val: default_val()
+        }
+        .matches(&darker, &target, 0));
+        assert!(!ColorMatcher::Hsv {
+            hue: default_hue(),
+            sat: default_sat(),
+            val: default_val()
+        }
+        .matches(&PixelColor::new(40, 200, 40), &target, 0));
+
+        let close = PixelColor::new(204, 44, 38);
+        let ciede = ColorMatcher::Ciede2000 {
+            max_delta_e: default_max_delta_e(),
+        };
+        assert!(ciede.matches(&close, &target, 0));
+        assert!(!ciede.matches(&PixelColor::new(40, 40, 200), &target, 0));
+    }
+}
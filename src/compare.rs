@@ -0,0 +1,142 @@
+//! Side-by-side comparison of two run reports for the `compare` subcommand.
+//!
+//! Prints throughput, reliability and timing metrics of both runs with the relative change, so
+//! the effect of a script or parameter change can be judged from data.
+use crate::output;
+use crate::profile::{EventProfile, PhaseTimes};
+use crate::report::RunReport;
+
+use anyhow::Result;
+use std::collections::BTreeSet;
+use std::path::Path;
+
+/// Prints a single metric row with the relative change from `a` to `b`.
+fn row(name: &str, a: Option<f64>, b: Option<f64>) {
+    let fmt = |value: Option<f64>| value.map_or_else(|| "-".to_string(), |v| format!("{:.2}", v));
+    let change = match (a, b) {
+        (Some(a), Some(b)) if a != 0.0 => format!("{:+.1}%", (b - a) / a.abs() * 100.0),
+        _ => String::new(),
+    };
+    println!("{:<34} {:>12} {:>12} {:>9}", name, fmt(a), fmt(b), change);
+}
+
+/// Number of `count` per hour of the run's wall clock time.
+fn per_hour(count: u64, report: &RunReport) -> Option<f64> {
+    (report.elapsed_secs > 0.0).then(|| count as f64 * 3_600.0 / report.elapsed_secs)
+}
+
+/// Sum of all phases of a run, the total time of an event.
+fn phase_sum(times: &PhaseTimes) -> f64 {
+    times.capture_ms
+        + times.scan_ms
+        + times.cluster_ms
+        + times.plan_ms
+        + times.execute_ms
+        + times.sleep_ms
+}
+
+/// Prints the rows comparing one script event in both runs.
+fn compare_event(id: &str, a: &RunReport, b: &RunReport) {
+    let profile_a = a.phases.get(id);
+    let profile_b = b.phases.get(id);
+    let mean = |profile: Option<&EventProfile>, phase: fn(&PhaseTimes) -> f64| {
+        profile.map(|p| phase(&p.mean))
+    };
+
+    println!("\nevent '{}'", id);
+    row(
+        "  runs/hour",
+        profile_a.and_then(|p| per_hour(p.runs, a)),
+        profile_b.and_then(|p| per_hour(p.runs, b)),
+    );
+    row(
+        "  hit rate %",
+        a.targets
+            .get(id)
+            .and_then(|t| t.hit_rate())
+            .map(|r| r * 100.0),
+        b.targets
+            .get(id)
+            .and_then(|t| t.hit_rate())
+            .map(|r| r * 100.0),
+    );
+    row(
+        "  misclicks",
+        a.targets.get(id).map(|t| t.misclicks as f64),
+        b.targets.get(id).map(|t| t.misclicks as f64),
+    );
+    for (name, phase) in [
+        ("  mean total ms", phase_sum as fn(&PhaseTimes) -> f64),
+        ("  mean capture ms", |t| t.capture_ms),
+        ("  mean scan ms", |t| t.scan_ms),
+        ("  mean cluster ms", |t| t.cluster_ms),
+        ("  mean plan ms", |t| t.plan_ms),
+        ("  mean execute ms", |t| t.execute_ms),
+        ("  mean sleep ms", |t| t.sleep_ms),
+    ] {
+        row(name, mean(profile_a, phase), mean(profile_b, phase));
+    }
+}
+
+/// Loads both reports and prints their comparison.
+pub fn run(path_a: &Path, path_b: &Path) -> Result<()> {
+    let a = RunReport::load(path_a)?;
+    let b = RunReport::load(path_b)?;
+    if output::is_json() {
+        // Programs compare the full reports themselves
+        let reports = serde_json::json!({ "a": a, "b": b });
+        println!("{}", serde_json::to_string_pretty(&reports)?);
+        return Ok(());
+    }
+
+    println!("A: {} ({})", path_a.display(), a.script.display());
+    println!("B: {} ({})", path_b.display(), b.script.display());
+    println!("\n{:<34} {:>12} {:>12} {:>9}", "metric", "A", "B", "change");
+
+    row("elapsed secs", Some(a.elapsed_secs), Some(b.elapsed_secs));
+    row(
+        "iterations/hour",
+        per_hour(a.iterations, &a),
+        per_hour(b.iterations, &b),
+    );
+    row(
+        "stuck incidents",
+        Some(a.stuck_incidents as f64),
+        Some(b.stuck_incidents as f64),
+    );
+    row(
+        "rollbacks",
+        Some(a.rollbacks as f64),
+        Some(b.rollbacks as f64),
+    );
+    row("tempo", Some(a.tempo), Some(b.tempo));
+    row("cpu percent", a.cpu_percent, b.cpu_percent);
+    row(
+        "throttled secs",
+        Some(a.throttled_secs),
+        Some(b.throttled_secs),
+    );
+    row(
+        "path step jitter mean us",
+        Some(a.path_step_jitter.mean_us),
+        Some(b.path_step_jitter.mean_us),
+    );
+    row(
+        "path step jitter p99 us",
+        Some(a.path_step_jitter.p99_us as f64),
+        Some(b.path_step_jitter.p99_us as f64),
+    );
+
+    let ids: BTreeSet<&String> = a.phases.keys().chain(b.phases.keys()).collect();
+    for id in ids {
+        compare_event(id, &a, &b);
+    }
+
+    for (label, report) in [("A", &a), ("B", &b)] {
+        if let Some(error) = &report.error {
+            println!("\n{} failed: {}", label, error);
+        }
+    }
+
+    Ok(())
+}
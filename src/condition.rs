@@ -0,0 +1,460 @@
+//! Conditions that decide whether an event runs, based on the progress of the run.
+//!
+//! A condition is a small expression over built-in variables, for example `iteration % 10 == 0`
+//! or `remaining_secs < 300 && matches_found["bank"] > 0`. Numbers support arithmetic and
+//! comparisons, whose results combine with `&&`, `||` and `!`. Conditions are type checked when
+//! the script is loaded, so a typo fails before the run starts rather than hours into it.
+use crate::targets;
+
+use anyhow::{bail, ensure, Context, Result};
+use schemars::JsonSchema;
+use serde::Deserialize;
+use std::fmt::{self, Display};
+use std::time::Duration;
+
+/// Progress of the run that conditions are evaluated against.
+#[derive(Debug, Clone, Copy)]
+pub struct RunContext {
+    /// Number of the current pass over the script, starting at 1.
+    pub iteration: u64,
+    /// Time since the event loop started.
+    pub elapsed: Duration,
+    /// Time until the runtime is up.
+    pub remaining: Duration,
+}
+
+/// Built-in numeric variables.
+#[derive(Debug, Clone, Copy)]
+enum Variable {
+    Iteration,
+    ElapsedSecs,
+    RemainingSecs,
+}
+
+impl Variable {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "iteration" => Some(Variable::Iteration),
+            "elapsed_secs" => Some(Variable::ElapsedSecs),
+            "remaining_secs" => Some(Variable::RemainingSecs),
+            _ => None,
+        }
+    }
+
+    fn value(self, context: &RunContext) -> f64 {
+        match self {
+            Variable::Iteration => context.iteration as f64,
+            Variable::ElapsedSecs => context.elapsed.as_secs_f64(),
+            Variable::RemainingSecs => context.remaining.as_secs_f64(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    And,
+    Or,
+}
+
+/// Binary operators from the loosest to the tightest binding.
+const PRECEDENCE: &[&[BinaryOp]] = &[
+    &[BinaryOp::Or],
+    &[BinaryOp::And],
+    &[
+        BinaryOp::Eq,
+        BinaryOp::Ne,
+        BinaryOp::Lt,
+        BinaryOp::Le,
+        BinaryOp::Gt,
+        BinaryOp::Ge,
+    ],
+    &[BinaryOp::Add, BinaryOp::Sub],
+    &[BinaryOp::Mul, BinaryOp::Div, BinaryOp::Rem],
+];
+
+impl BinaryOp {
+    fn symbol(self) -> &'static str {
+        match self {
+            BinaryOp::Add => "+",
+            BinaryOp::Sub => "-",
+            BinaryOp::Mul => "*",
+            BinaryOp::Div => "/",
+            BinaryOp::Rem => "%",
+            BinaryOp::Eq => "==",
+            BinaryOp::Ne => "!=",
+            BinaryOp::Lt => "<",
+            BinaryOp::Le => "<=",
+            BinaryOp::Gt => ">",
+            BinaryOp::Ge => ">=",
+            BinaryOp::And => "&&",
+            BinaryOp::Or => "||",
+        }
+    }
+}
+
+/// Type of an expression.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Type {
+    Number,
+    Bool,
+}
+
+impl Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Type::Number => write!(f, "a number"),
+            Type::Bool => write!(f, "true or false"),
+        }
+    }
+}
+
+/// Value of an evaluated expression.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Value {
+    Number(f64),
+    Bool(bool),
+}
+
+#[derive(Debug)]
+enum Expr {
+    Number(f64),
+    Bool(bool),
+    Variable(Variable),
+    /// Number of times the event with the given id found its target.
+    MatchesFound(String),
+    Not(Box<Expr>),
+    Neg(Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    /// Checks that operands have the types their operators need, returning the expression's type.
+    fn check(&self) -> Result<Type> {
+        let expect = |expr: &Expr, expected: Type, what: &str| -> Result<()> {
+            let actual = expr.check()?;
+            ensure!(
+                actual == expected,
+                "{} needs {}, got {}",
+                what,
+                expected,
+                actual
+            );
+            Ok(())
+        };
+
+        match self {
+            Expr::Number(_) | Expr::Variable(_) | Expr::MatchesFound(_) => Ok(Type::Number),
+            Expr::Bool(_) => Ok(Type::Bool),
+            Expr::Not(operand) => {
+                expect(operand, Type::Bool, "`!`")?;
+                Ok(Type::Bool)
+            }
+            Expr::Neg(operand) => {
+                expect(operand, Type::Number, "`-`")?;
+                Ok(Type::Number)
+            }
+            Expr::Binary(op, lhs, rhs) => {
+                let what = format!("`{}`", op.symbol());
+                match op {
+                    BinaryOp::And | BinaryOp::Or => {
+                        expect(lhs, Type::Bool, &what)?;
+                        expect(rhs, Type::Bool, &what)?;
+                        Ok(Type::Bool)
+                    }
+                    BinaryOp::Eq | BinaryOp::Ne => {
+                        let ty = lhs.check()?;
+                        expect(rhs, ty, &what)?;
+                        Ok(Type::Bool)
+                    }
+                    BinaryOp::Lt | BinaryOp::Le | BinaryOp::Gt | BinaryOp::Ge => {
+                        expect(lhs, Type::Number, &what)?;
+                        expect(rhs, Type::Number, &what)?;
+                        Ok(Type::Bool)
+                    }
+                    BinaryOp::Add
+                    | BinaryOp::Sub
+                    | BinaryOp::Mul
+                    | BinaryOp::Div
+                    | BinaryOp::Rem => {
+                        expect(lhs, Type::Number, &what)?;
+                        expect(rhs, Type::Number, &what)?;
+                        Ok(Type::Number)
+                    }
+                }
+            }
+        }
+    }
+
+    fn evaluate(&self, context: &RunContext) -> Result<Value> {
+        let number = |expr: &Expr| match expr.evaluate(context)? {
+            Value::Number(n) => Ok(n),
+            Value::Bool(_) => bail!("Expected a number"),
+        };
+        let boolean = |expr: &Expr| match expr.evaluate(context)? {
+            Value::Bool(b) => Ok(b),
+            Value::Number(_) => bail!("Expected true or false"),
+        };
+
+        Ok(match self {
+            Expr::Number(n) => Value::Number(*n),
+            Expr::Bool(b) => Value::Bool(*b),
+            Expr::Variable(variable) => Value::Number(variable.value(context)),
+            Expr::MatchesFound(id) => Value::Number(targets::found(id) as f64),
+            Expr::Not(operand) => Value::Bool(!boolean(operand)?),
+            Expr::Neg(operand) => Value::Number(-number(operand)?),
+            // Short-circuit so the right side isn't evaluated needlessly
+            Expr::Binary(BinaryOp::And, lhs, rhs) => Value::Bool(boolean(lhs)? && boolean(rhs)?),
+            Expr::Binary(BinaryOp::Or, lhs, rhs) => Value::Bool(boolean(lhs)? || boolean(rhs)?),
+            Expr::Binary(op @ (BinaryOp::Eq | BinaryOp::Ne), lhs, rhs) => {
+                let equal = lhs.evaluate(context)? == rhs.evaluate(context)?;
+                Value::Bool(equal == (*op == BinaryOp::Eq))
+            }
+            Expr::Binary(op, lhs, rhs) => {
+                let (a, b) = (number(lhs)?, number(rhs)?);
+                match op {
+                    BinaryOp::Add => Value::Number(a + b),
+                    BinaryOp::Sub => Value::Number(a - b),
+                    BinaryOp::Mul => Value::Number(a * b),
+                    BinaryOp::Div => Value::Number(a / b),
+                    BinaryOp::Rem => Value::Number(a % b),
+                    BinaryOp::Lt => Value::Bool(a < b),
+                    BinaryOp::Le => Value::Bool(a <= b),
+                    BinaryOp::Gt => Value::Bool(a > b),
+                    BinaryOp::Ge => Value::Bool(a >= b),
+                    BinaryOp::Eq | BinaryOp::Ne | BinaryOp::And | BinaryOp::Or => {
+                        unreachable!("handled above")
+                    }
+                }
+            }
+        })
+    }
+
+    /// Adds the ids of the events this expression reads the match count of to `ids`.
+    fn collect_event_ids<'a>(&'a self, ids: &mut Vec<&'a str>) {
+        match self {
+            Expr::MatchesFound(id) => ids.push(id),
+            Expr::Not(operand) | Expr::Neg(operand) => operand.collect_event_ids(ids),
+            Expr::Binary(_, lhs, rhs) => {
+                lhs.collect_event_ids(ids);
+                rhs.collect_event_ids(ids);
+            }
+            Expr::Number(_) | Expr::Bool(_) | Expr::Variable(_) => {}
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Ident(String),
+    Str(String),
+    Punct(&'static str),
+}
+
+impl Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Number(n) => write!(f, "{}", n),
+            Token::Ident(name) => write!(f, "{}", name),
+            Token::Str(s) => write!(f, "\"{}\"", s),
+            Token::Punct(punct) => write!(f, "{}", punct),
+        }
+    }
+}
+
+/// Punctuation tokens, longer ones first so `<=` isn't read as `<` followed by `=`.
+const PUNCTUATION: &[&str] = &[
+    "==", "!=", "<=", ">=", "&&", "||", "<", ">", "+", "-", "*", "/", "%", "!", "(", ")", "[", "]",
+];
+
+fn tokenize(source: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut rest = source.trim_start();
+
+    while let Some(c) = rest.chars().next() {
+        if c.is_ascii_digit() || c == '.' {
+            let end = rest
+                .find(|c: char| !c.is_ascii_digit() && c != '.')
+                .unwrap_or(rest.len());
+            let number = &rest[..end];
+            tokens.push(Token::Number(
+                number
+                    .parse()
+                    .context(format!("Invalid number '{}'", number))?,
+            ));
+            rest = &rest[end..];
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let end = rest
+                .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                .unwrap_or(rest.len());
+            tokens.push(Token::Ident(rest[..end].to_string()));
+            rest = &rest[end..];
+        } else if c == '"' {
+            let Some(end) = rest[1..].find('"') else {
+                bail!("Unterminated string");
+            };
+            tokens.push(Token::Str(rest[1..=end].to_string()));
+            rest = &rest[end + 2..];
+        } else if let Some(punct) = PUNCTUATION.iter().find(|p| rest.starts_with(**p)) {
+            tokens.push(Token::Punct(punct));
+            rest = &rest[punct.len()..];
+        } else {
+            bail!("Unexpected character '{}'", c);
+        }
+        rest = rest.trim_start();
+    }
+
+    Ok(tokens)
+}
+
+/// Recursive descent parser over the tokens of a condition.
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn eat(&mut self, punct: &'static str) -> bool {
+        let matches = self.tokens.get(self.pos) == Some(&Token::Punct(punct));
+        if matches {
+            self.pos += 1;
+        }
+        matches
+    }
+
+    fn expect(&mut self, punct: &'static str) -> Result<()> {
+        ensure!(self.eat(punct), "Expected '{}'", punct);
+        Ok(())
+    }
+
+    /// Parses the binary operators of `level` and tighter binding ones.
+    fn binary(&mut self, level: usize) -> Result<Expr> {
+        let Some(ops) = PRECEDENCE.get(level) else {
+            return self.unary();
+        };
+
+        let mut lhs = self.binary(level + 1)?;
+        while let Some(op) = ops.iter().copied().find(|op| self.eat(op.symbol())) {
+            let rhs = self.binary(level + 1)?;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<Expr> {
+        if self.eat("!") {
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+        if self.eat("-") {
+            return Ok(Expr::Neg(Box::new(self.unary()?)));
+        }
+        self.atom()
+    }
+
+    fn atom(&mut self) -> Result<Expr> {
+        match self.next() {
+            Some(Token::Number(n)) => Ok(Expr::Number(n)),
+            Some(Token::Ident(name)) => match name.as_str() {
+                "true" => Ok(Expr::Bool(true)),
+                "false" => Ok(Expr::Bool(false)),
+                "matches_found" => {
+                    self.expect("[")?;
+                    let Some(Token::Str(id)) = self.next() else {
+                        bail!("matches_found needs an event id in quotes, like matches_found[\"bank\"]");
+                    };
+                    self.expect("]")?;
+                    Ok(Expr::MatchesFound(id))
+                }
+                _ => match Variable::from_name(&name) {
+                    Some(variable) => Ok(Expr::Variable(variable)),
+                    None => bail!(
+                        "Unknown variable '{}', expected iteration, elapsed_secs, remaining_secs \
+                         or matches_found",
+                        name
+                    ),
+                },
+            },
+            Some(Token::Punct("(")) => {
+                let expr = self.binary(0)?;
+                self.expect(")")?;
+                Ok(expr)
+            }
+            Some(token) => bail!("Unexpected '{}'", token),
+            None => bail!("Unexpected end of condition"),
+        }
+    }
+}
+
+/// A parsed and type checked condition.
+#[derive(Debug, Deserialize, JsonSchema)]
+#[serde(try_from = "String")]
+#[schemars(with = "String")]
+pub struct Condition {
+    source: String,
+    expr: Expr,
+}
+
+impl TryFrom<String> for Condition {
+    type Error = anyhow::Error;
+
+    fn try_from(source: String) -> Result<Self> {
+        let parse = || -> Result<Expr> {
+            let mut parser = Parser {
+                tokens: tokenize(&source)?,
+                pos: 0,
+            };
+            let expr = parser.binary(0)?;
+            if let Some(token) = parser.next() {
+                bail!("Unexpected '{}' after the end of the condition", token);
+            }
+            ensure!(
+                expr.check()? == Type::Bool,
+                "Condition must be true or false, like iteration % 10 == 0"
+            );
+            Ok(expr)
+        };
+        let expr = parse().context(format!("Invalid condition '{}'", source))?;
+
+        Ok(Self { source, expr })
+    }
+}
+
+impl Condition {
+    /// Evaluates the condition against the run's progress.
+    pub fn evaluate(&self, context: &RunContext) -> Result<bool> {
+        match self.expr.evaluate(context)? {
+            Value::Bool(b) => Ok(b),
+            Value::Number(_) => bail!("Condition '{}' is not true or false", self.source),
+        }
+    }
+
+    /// Returns the ids of the events whose match counts the condition reads.
+    pub fn event_ids(&self) -> Vec<&str> {
+        let mut ids = Vec::new();
+        self.expr.collect_event_ids(&mut ids);
+        ids
+    }
+}
+
+impl Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
@@ -1,8 +1,222 @@
-use clap::Parser;
+//! Settings of a bot run and the rest of the command line.
+//!
+//! [`BotConfig`] holds every setting of `colorbot run`, the types of its fields are exported
+//! from here.
+pub use crate::backend::{BackendKind, ButtonLayout};
+pub use crate::breaks::BreakSpan;
+use crate::control::CtlAction;
+pub use crate::indicator::Corner;
+use crate::init::Template;
+pub use crate::movement::MouseAlgorithm;
+use crate::output::OutputFormat;
+pub use crate::replay::FrameAdvance;
+pub use crate::runtime::{Cores, IoClass};
+use crate::secrets::SecretsAction;
+use crate::vision::HashAlgorithm;
+pub use crate::vision::{CaptureSource, Region};
 
-/// Command-line configuration for the bot runtime and script.
+use anyhow::{Context, Result};
+use clap::{Args, FromArgMatches, Parser, Subcommand};
+use std::ffi::OsString;
+use std::path::PathBuf;
+
+/// Top-level command-line interface.
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
+pub(crate) struct Cli {
+    /// Enable debug logging output to terminal.
+    #[arg(short = 'g', long, global = true, default_value_t = false)]
+    pub debug: bool,
+
+    /// Print the results of subcommands as text or as JSON for other programs.
+    #[arg(long, global = true, value_enum, default_value_t = OutputFormat::Text)]
+    pub output: OutputFormat,
+
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+/// Available subcommands.
+#[derive(Subcommand, Debug)]
+pub(crate) enum Command {
+    /// Run a bot script.
+    Run(Box<BotConfig>),
+    /// Run short trials of a bot script over a grid of settings and recommend the best ones.
+    Tune(Box<TuneConfig>),
+    #[command(flatten)]
+    Tool(Tool),
+}
+
+/// Interactive authoring tools.
+#[derive(Subcommand, Debug)]
+pub(crate) enum Tool {
+    /// Write a commented starter script to build on.
+    Init {
+        /// Path of the script to write.
+        #[arg(default_value = "script.json")]
+        path: std::path::PathBuf,
+
+        /// Kind of script to start from.
+        #[arg(short = 't', long, value_enum, default_value_t = Template::ColorClick)]
+        template: Template,
+
+        /// Replace the file when it exists.
+        #[arg(long, default_value_t = false)]
+        force: bool,
+    },
+    /// Drag a rectangle on a frozen screenshot and print it as region JSON.
+    SelectRegion,
+    /// Follow the cursor with a crosshair and print the clicked point as JSON.
+    PickPoint {
+        /// Report coordinates relative to the window whose title contains this text.
+        #[arg(short = 'w', long)]
+        window: Option<String>,
+    },
+    /// Continuously print the RGB and HSV color under the cursor.
+    WatchColor {
+        /// Only print when the color under the cursor changes.
+        #[arg(short = 'c', long, default_value_t = false)]
+        on_change: bool,
+    },
+    /// Observe a region and print a target covering the colors it displays.
+    SampleTarget {
+        /// Region to observe as 'x,y,width,height'.
+        #[arg(short = 'R', long)]
+        region: Region,
+
+        /// Duration in seconds to observe the region.
+        #[arg(short = 's', long, default_value_t = 3)]
+        seconds: u64,
+
+        /// Print an HSV range target instead of a color palette.
+        #[arg(long, default_value_t = false)]
+        hsv: bool,
+    },
+    /// Print a perceptual hash signature of a screen region, to recognize a screen by.
+    Phash {
+        /// Region to hash as 'x,y,width,height'.
+        #[arg(short = 'R', long)]
+        region: Region,
+
+        /// Hash algorithm.
+        #[arg(long, value_enum, default_value_t = HashAlgorithm::Difference)]
+        algorithm: HashAlgorithm,
+    },
+    /// Record real clicks and key presses with their timing into a bot script.
+    Record {
+        /// Path the recorded script is written to.
+        out: std::path::PathBuf,
+
+        /// Key that stops the recording, as an xdotool key name.
+        #[arg(long, default_value = "F12")]
+        stop_key: String,
+
+        /// Stop recording after this many seconds even without the stop key.
+        #[arg(short = 's', long)]
+        seconds: Option<u64>,
+    },
+    /// Show the pause and panic hotkeys, or import or export their bindings.
+    Keys {
+        /// Print the active bindings, the default when nothing is imported or exported.
+        #[arg(long, default_value_t = false)]
+        list: bool,
+
+        /// Write the active bindings to this file.
+        #[arg(long)]
+        export: Option<std::path::PathBuf>,
+
+        /// Read bindings from this file and make them the active ones.
+        #[arg(long)]
+        import: Option<std::path::PathBuf>,
+
+        /// Hotkeys file to use instead of $XDG_CONFIG_HOME/colorbot/hotkeys.json.
+        #[arg(long)]
+        hotkeys: Option<std::path::PathBuf>,
+    },
+    /// List the connected displays with their index, resolution and offset.
+    Displays,
+    /// Check the environment and print setup guidance for missing requirements.
+    Doctor,
+    /// Check a script for errors without running it and print every problem found.
+    Validate {
+        /// Path to the JSON bot script.
+        script: std::path::PathBuf,
+
+        /// Index of the display to check regions against, defaults to the whole screen.
+        #[arg(long)]
+        display: Option<usize>,
+    },
+    /// Check every color target and signature in a script against the screen and report which
+    /// currently match.
+    VerifyTargets {
+        /// Path to the JSON bot script.
+        script: std::path::PathBuf,
+
+        /// Full-screen screenshot to check against instead of the live screen.
+        #[arg(long)]
+        screenshot: Option<std::path::PathBuf>,
+    },
+    /// Run the test cases of a script against their fixture screenshots.
+    Test {
+        /// Path to the JSON bot script.
+        script: std::path::PathBuf,
+    },
+    /// Talk to the bot running on this display, e.g. to follow its decisions.
+    Ctl {
+        #[command(subcommand)]
+        action: CtlAction,
+    },
+    /// Print the control flow of a script: branches, repeat blocks, conditions and rollbacks.
+    Graph {
+        /// Path to the JSON bot script.
+        script: std::path::PathBuf,
+
+        /// Print Graphviz DOT, e.g. to pipe into `dot -Tsvg`.
+        #[arg(long, default_value_t = false)]
+        dot: bool,
+    },
+    /// List the event types and idle actions scripts can use with their fields, defaults and
+    /// constraints.
+    Actions {
+        /// Print the JSON schemas of events and idle actions instead.
+        #[arg(long, default_value_t = false)]
+        json: bool,
+    },
+    /// Summarize the runs recorded in the statistics database, per script or for one script by
+    /// week and version.
+    #[cfg(feature = "stats-db")]
+    Stats {
+        /// Show the trends of this script instead of a summary of all scripts.
+        script: Option<std::path::PathBuf>,
+
+        /// Statistics database to read, defaults to $XDG_STATE_HOME/colorbot/stats.db.
+        #[arg(long)]
+        db: Option<std::path::PathBuf>,
+    },
+    /// Compare two run reports side by side.
+    Compare {
+        /// Report of the baseline run.
+        a: std::path::PathBuf,
+
+        /// Report of the run to compare against the baseline.
+        b: std::path::PathBuf,
+    },
+    /// Manage an encrypted secrets file referenced by scripts.
+    Secrets {
+        /// Path to the secrets file, created on first use.
+        file: std::path::PathBuf,
+
+        /// Read the secrets password from the desktop keyring instead of prompting.
+        #[arg(long, default_value_t = false)]
+        keyring: bool,
+
+        #[command(subcommand)]
+        action: SecretsAction,
+    },
+}
+
+/// Command-line configuration for the bot runtime and script.
+#[derive(Args, Debug, Clone)]
 pub struct BotConfig {
     /// Path to the JSON bot script file containing event sequences.
     pub script: std::path::PathBuf,
@@ -11,9 +225,15 @@ pub struct BotConfig {
     #[arg(short = 'r', long, default_value_t = 3_600)]
     pub runtime: u64,
 
-    /// Enable debug logging output to terminal.
-    #[arg(short = 'g', long, default_value_t = false)]
-    pub debug: bool,
+    /// Keep running the script as loaded at startup instead of reloading it between iterations
+    /// when its file changes.
+    #[arg(long)]
+    pub no_reload: bool,
+
+    /// Begin the first iteration at this named checkpoint of the script instead of at its first
+    /// event.
+    #[arg(long)]
+    pub start_at_checkpoint: Option<String>,
 
     // Average additional delay in ms to add to each script event delay.
     #[arg(short = 'd', long, default_value_t = 500)]
@@ -22,4 +242,311 @@ pub struct BotConfig {
     /// Maximum additional delay in ms that can be added to each script event delay.
     #[arg(short = 'm', long, default_value_t = 1_000)]
     pub max_added_delay: u64,
+
+    /// Input latency in ms between issuing a mouse move and the cursor reflecting it.
+    ///
+    /// Measured at startup when omitted.
+    #[arg(short = 'l', long)]
+    pub input_latency: Option<u64>,
+
+    /// Targets smaller than this many pixels are approached with a fast move to their vicinity
+    /// followed by a short, slow move onto them.
+    #[arg(long, default_value_t = 400)]
+    pub small_target_area: u64,
+
+    /// Preferred input backend for clicks and keypresses.
+    ///
+    /// Falls back to another backend when the preferred one is unavailable.
+    #[arg(short = 'b', long, value_enum, default_value_t = BackendKind::Native)]
+    pub backend: BackendKind,
+
+    /// Index of the display to capture and click on, see `colorbot displays`.
+    ///
+    /// Defaults to the primary display.
+    #[arg(long)]
+    pub display: Option<usize>,
+
+    /// Where screen frames are captured from, the portal also works on Wayland.
+    #[arg(long, value_enum, default_value_t = CaptureSource::X11)]
+    pub capture: CaptureSource,
+
+    /// Mouse button layout, left clicks use whichever physical button is primary.
+    #[arg(long, value_enum, default_value_t = ButtonLayout::Auto)]
+    pub buttons: ButtonLayout,
+
+    /// Directory under which per-run directories for logs and artifacts are created.
+    ///
+    /// Defaults to $XDG_STATE_HOME/colorbot/runs.
+    #[arg(long)]
+    pub run_root: Option<std::path::PathBuf>,
+
+    /// Number of most recent run directories to keep, older ones are deleted.
+    #[arg(short = 'k', long, default_value_t = 10, value_parser = clap::value_parser!(u64).range(1..))]
+    pub keep_runs: u64,
+
+    /// Statistics database the run is recorded in.
+    ///
+    /// Defaults to $XDG_STATE_HOME/colorbot/stats.db.
+    #[cfg(feature = "stats-db")]
+    #[arg(long)]
+    pub stats_db: Option<std::path::PathBuf>,
+
+    /// Also write the end-of-run summary of every event as JSON to this file.
+    #[arg(long)]
+    pub stats_out: Option<std::path::PathBuf>,
+
+    /// Seconds to count down before the first event, to focus the game window.
+    ///
+    /// Afterwards the script's metadata window, or any window other than the terminal, must have
+    /// the focus.
+    #[arg(long, default_value_t = 0)]
+    pub countdown: u64,
+
+    /// Don't show the square telling whether the bot controls input, see `--indicator-corner`.
+    #[arg(long)]
+    pub no_indicator: bool,
+
+    /// Screen corner of the square that is green while the bot controls input and amber while
+    /// it's paused, so viewers of a stream or recording can tell the bot's input apart.
+    #[arg(long, value_enum, default_value_t = Corner::TopLeft)]
+    pub indicator_corner: Corner,
+
+    /// Run in the background, detached from the terminal.
+    ///
+    /// Terminal output goes to output.log in the run directory.
+    #[arg(long, default_value_t = false)]
+    pub detach: bool,
+
+    /// Run even when another colorbot process is already driving this display.
+    #[arg(long, default_value_t = false)]
+    pub force: bool,
+
+    /// JSON file with the pause and panic hotkey bindings.
+    ///
+    /// Defaults to $XDG_CONFIG_HOME/colorbot/hotkeys.json when it exists, see `colorbot keys`.
+    #[arg(long)]
+    pub hotkeys: Option<std::path::PathBuf>,
+
+    /// JSON file binding the actions that keypress events refer to to keys.
+    ///
+    /// Defaults to $XDG_CONFIG_HOME/colorbot/keybinds.json when it exists.
+    #[arg(long)]
+    pub keybinds: Option<std::path::PathBuf>,
+
+    /// Encrypted secrets file providing values for secret references in the script.
+    #[arg(long)]
+    pub secrets: Option<std::path::PathBuf>,
+
+    /// Read the secrets password from the desktop keyring instead of prompting.
+    #[arg(long, default_value_t = false)]
+    pub secrets_keyring: bool,
+
+    /// Take a break after this much activity, e.g. 45m for roughly 45 minutes or 30m..1h for
+    /// anywhere in between. Plain numbers are seconds.
+    #[arg(long)]
+    pub break_every: Option<BreakSpan>,
+
+    /// Length of each break, e.g. 5m or 3m..8m.
+    #[arg(long, default_value = "5m", requires = "break_every")]
+    pub break_for: BreakSpan,
+
+    /// Idle script to run during breaks instead of staying still, one is picked at random per
+    /// break. May be given multiple times.
+    #[arg(long, requires = "break_every")]
+    pub idle_script: Vec<std::path::PathBuf>,
+
+    /// Narrow the search of color events without a search region to the area their targets were
+    /// found in by earlier runs of the script, with a full-screen fallback.
+    #[arg(long)]
+    pub infer_regions: bool,
+
+    /// Run as fast as the machine allows: no cycle sleep, capture rate cap or CPU target. The
+    /// detection latency of each event is checked against --turbo-budget.
+    #[arg(long, conflicts_with_all = ["min_cycle_sleep", "max_capture_fps", "max_cpu_percent"])]
+    pub turbo: bool,
+
+    /// Longest mean time in ms an event may spend capturing, scanning, clustering and planning
+    /// in turbo mode before it's reported.
+    #[arg(long, default_value_t = 50.0, requires = "turbo")]
+    pub turbo_budget: f64,
+
+    /// Minimum sleep in ms after each executed event, keeps tight scripts from spinning a core.
+    #[arg(long, default_value_t = 10)]
+    pub min_cycle_sleep: u64,
+
+    /// Maximum number of screen captures per second, 0 disables the cap.
+    #[arg(long, default_value_t = 30)]
+    pub max_capture_fps: u32,
+
+    /// Soft target for CPU usage in percent of one core, events are spaced out further while
+    /// usage is above it.
+    #[arg(long)]
+    pub max_cpu_percent: Option<f64>,
+
+    /// Nice level of the bot from -20 to 19, such as 10 to leave the CPU to the game first.
+    /// Levels below 0 need root.
+    #[arg(long, allow_negative_numbers = true, value_parser = clap::value_parser!(i32).range(-20..=19))]
+    pub nice: Option<i32>,
+
+    /// IO scheduling class of the bot, idle only uses the disk when the game doesn't.
+    #[arg(long, value_enum)]
+    pub ionice: Option<IoClass>,
+
+    /// Cores to capture and scan the screen on, such as 2,3 or 4-7, leaving the others to the
+    /// game.
+    #[arg(long)]
+    pub pin_cores: Option<Cores>,
+
+    /// Declare the run stuck when no event completes for this many minutes, 0 disables the
+    /// watchdog.
+    #[arg(long, default_value_t = 10)]
+    pub watchdog_minutes: u64,
+
+    /// Declare the run stalled when this many events in a row leave the screen unchanged, 0
+    /// disables stall detection.
+    ///
+    /// A stalled run is handled like a stuck one.
+    #[arg(long, default_value_t = 0)]
+    pub stall_cycles: u32,
+
+    /// Screen region watched for changes by stall detection as 'x,y,width,height', defaults to
+    /// the whole screen.
+    #[arg(long)]
+    pub stall_region: Option<Region>,
+
+    /// Bot script to run when the run gets stuck before resuming, the run aborts without one.
+    #[arg(long)]
+    pub recovery_script: Option<std::path::PathBuf>,
+
+    /// Tempo factor applied to every delay and mouse movement duration, above 1 is slower.
+    ///
+    /// Sampled between --tempo-min and --tempo-max when omitted, so every session has a slightly
+    /// different overall pacing. Pass the tempo from a previous run's report to reproduce it.
+    #[arg(long, conflicts_with_all = ["tempo_min", "tempo_max"])]
+    pub tempo: Option<f64>,
+
+    /// Lower bound of the sampled session tempo.
+    #[arg(long, default_value_t = 0.9)]
+    pub tempo_min: f64,
+
+    /// Upper bound of the sampled session tempo.
+    #[arg(long, default_value_t = 1.15)]
+    pub tempo_max: f64,
+
+    /// Maximum per-channel difference for a pixel to match an RGB or palette color target.
+    #[arg(long, default_value_t = 3)]
+    pub color_tolerance: u8,
+
+    /// Largest per-channel color shift learned from a color signature that only matches with
+    /// shifted colors, e.g. after a brightness change. 0 disables learning.
+    #[arg(long, default_value_t = 24)]
+    pub max_color_offset: u8,
+
+    /// Factor scaling how far mouse paths stray from a straight line, above 1 is wobblier.
+    #[arg(long, default_value_t = 1.0)]
+    pub path_deviation: f64,
+
+    /// Factor scaling how fast the cursor moves, above 1 is faster.
+    #[arg(long, default_value_t = 1.0)]
+    pub mouse_speed: f64,
+
+    /// Algorithm generating mouse paths: windmouse physics or a smooth Bézier curve.
+    #[arg(long, value_enum, default_value_t = MouseAlgorithm::WindMouse)]
+    pub mouse_algo: MouseAlgorithm,
+
+    /// Peak sideways hand tremor added to mouse paths in pixels, 0 disables it.
+    #[arg(long, default_value_t = 0.8)]
+    pub tremor_amplitude: f64,
+
+    /// Frequency of the hand tremor in Hz.
+    #[arg(long, default_value_t = 10.0)]
+    pub tremor_frequency: f64,
+
+    /// Average number of small corrective kinks per second of mouse movement, 0 disables them.
+    #[arg(long, default_value_t = 1.5)]
+    pub micro_corrections: f64,
+
+    /// Strength of the slowdown and small wander at the end of mouse paths, 0 stops dead on the
+    /// final point.
+    #[arg(long, default_value_t = 1.0)]
+    pub homing: f64,
+
+    /// Probability that a longer mouse movement overshoots its destination by a few pixels and
+    /// corrects back onto it, 0 always lands directly.
+    #[arg(long, default_value_t = 0.2)]
+    pub overshoot: f64,
+
+    /// Run against the PNG screenshots in this directory, in file name order, instead of the
+    /// live screen. Input is only logged, and the run ends once every frame has been used.
+    #[arg(long)]
+    pub frames: Option<std::path::PathBuf>,
+
+    /// Save every frame the vision pipeline consumes into this directory, cropped to the scanned
+    /// region, as fixtures to replay with --frames.
+    #[arg(long)]
+    pub record_frames: Option<std::path::PathBuf>,
+
+    /// Run against the live screen without touching the mouse or keyboard. Mouse paths, clicks
+    /// and key presses are only written to the run log.
+    #[arg(long, conflicts_with = "frames")]
+    pub dry_run: bool,
+
+    /// Draw the mouse paths and clicks of a dry run or --frames run onto a screenshot saved as
+    /// this PNG at the end of the run.
+    #[arg(long)]
+    pub dry_run_paths: Option<std::path::PathBuf>,
+
+    /// When to move on to the next screenshot of --frames.
+    #[arg(long, value_enum, default_value_t = FrameAdvance::Event, requires = "frames")]
+    pub frame_advance: FrameAdvance,
+}
+
+impl BotConfig {
+    /// Returns the settings `colorbot run <script>` runs with when given no other options.
+    pub fn new(script: impl Into<PathBuf>) -> Self {
+        Self::parse(script.into(), Vec::new()).expect("every run option has a valid default")
+    }
+
+    /// Parses `colorbot run` options for `script`.
+    pub(crate) fn parse(script: PathBuf, args: Vec<OsString>) -> Result<Self> {
+        let mut command = Self::augment_args(clap::Command::new("colorbot-run"));
+        let matches = command
+            .try_get_matches_from_mut(
+                [OsString::from("colorbot-run"), script.into_os_string()]
+                    .into_iter()
+                    .chain(args),
+            )
+            .context("Invalid bot options")?;
+        Self::from_arg_matches(&matches).context("Invalid bot options")
+    }
+}
+
+/// Command-line configuration for a tuning session.
+///
+/// Every combination of the listed values is tried in a trial run of the script.
+#[derive(Args, Debug)]
+pub(crate) struct TuneConfig {
+    #[command(flatten)]
+    pub bot: BotConfig,
+
+    /// Duration in seconds of each trial, replaces --runtime.
+    #[arg(long, default_value_t = 120)]
+    pub trial_secs: u64,
+
+    /// Color tolerances to try, comma separated.
+    #[arg(long, value_delimiter = ',', default_values_t = [2, 3, 5])]
+    pub tolerances: Vec<u8>,
+
+    /// Session tempos to try, comma separated.
+    #[arg(long, value_delimiter = ',', default_values_t = [0.9, 1.0, 1.15])]
+    pub tempos: Vec<f64>,
+
+    /// Mouse path deviations to try, comma separated.
+    #[arg(long, value_delimiter = ',', default_values_t = [0.6, 1.0, 1.4])]
+    pub deviations: Vec<f64>,
+
+    /// Let the trials drive the real mouse and keyboard, needed unless --frames or --dry-run is
+    /// given.
+    #[arg(long)]
+    pub live: bool,
 }
@@ -0,0 +1,341 @@
+//! Control socket of a running bot, used by `colorbot ctl`.
+//!
+//! A run listens on an abstract Unix socket named after the display it drives, next to its
+//! instance lock, so `colorbot ctl` from any terminal reaches the bot on the current display,
+//! including a detached one. A client sends a single command line and reads the reply until the
+//! connection closes. `tail` replies with the run's decisions as JSON lines while they're made.
+//!
+//! `set`, `disable-event` and `enable-event` adjust the run while it's tuned. They're checked
+//! and queued right away, and the event loop applies them before the next event starts, so an
+//! event never sees its settings change halfway.
+use crate::config::BotConfig;
+use crate::decisions::{self, Decision};
+use crate::instance;
+use crate::output::{self, Render};
+use crate::vision;
+
+use anyhow::{bail, ensure, Context, Result};
+use clap::ValueEnum;
+use log::{debug, info, warn};
+use serde::Serialize;
+use std::collections::HashSet;
+use std::io::{BufRead, BufReader, Write};
+use std::os::linux::net::SocketAddrExt;
+use std::os::unix::net::{SocketAddr, UnixListener, UnixStream};
+use std::sync::{mpsc, Mutex};
+
+/// Commands for a running bot.
+#[derive(clap::Subcommand, Debug)]
+pub enum CtlAction {
+    /// Follow the decisions of the running bot: events started, matches found, targets chosen
+    /// and branches taken.
+    Tail,
+    /// Change a setting of the running bot from the next event on.
+    Set { setting: Setting, value: String },
+    /// Skip an event of the running bot until it's enabled again.
+    DisableEvent { id: String },
+    /// Run an event the bot was told to skip again.
+    EnableEvent { id: String },
+}
+
+/// Setting of a running bot that `colorbot ctl set` changes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Setting {
+    /// Factor scaling how fast the cursor moves, as `--mouse-speed`.
+    #[value(name = "mouse_speed")]
+    MouseSpeed,
+    /// Factor scaling how far mouse paths stray, as `--path-deviation`.
+    #[value(name = "path_deviation", alias = "mouse_deviation")]
+    PathDeviation,
+    /// Per-channel color difference a pixel may have to match, as `--color-tolerance`.
+    #[value(name = "tolerance", alias = "color_tolerance")]
+    Tolerance,
+    /// Average delay in ms added to event delays, as `--added-delay`.
+    #[value(name = "added_delay")]
+    AddedDelay,
+}
+
+impl Setting {
+    /// Returns the name the setting is given by.
+    fn name(&self) -> &'static str {
+        match self {
+            Self::MouseSpeed => "mouse_speed",
+            Self::PathDeviation => "path_deviation",
+            Self::Tolerance => "tolerance",
+            Self::AddedDelay => "added_delay",
+        }
+    }
+
+    /// Parses and checks a value of the setting.
+    fn parse(&self, text: &str) -> Result<f64> {
+        let value = match self {
+            Self::MouseSpeed | Self::PathDeviation => {
+                let value: f64 = text.parse().context("expected a number")?;
+                ensure!(value > 0.0, "{} must be positive", self.name());
+                value
+            }
+            Self::Tolerance => f64::from(
+                text.parse::<u8>()
+                    .context("expected a whole number from 0 to 255")?,
+            ),
+            Self::AddedDelay => text
+                .parse::<u64>()
+                .context("expected a whole number of ms")? as f64,
+        };
+        Ok(value)
+    }
+}
+
+/// Change to a running bot, applied between events.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Adjustment {
+    Set(Setting, f64),
+    DisableEvent(String),
+    EnableEvent(String),
+}
+
+/// Adjustments received and not applied yet, in order.
+static PENDING: Mutex<Vec<Adjustment>> = Mutex::new(Vec::new());
+/// Ids of the events of the running script, which events to disable are checked against.
+static EVENT_IDS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// Makes the events of the running script available to `disable-event` and `enable-event`.
+pub fn set_event_ids(ids: Vec<String>) {
+    if let Ok(mut event_ids) = EVENT_IDS.lock() {
+        *event_ids = ids;
+    }
+}
+
+/// Returns the adjustments received since the last call.
+pub fn take_adjustments() -> Vec<Adjustment> {
+    PENDING
+        .lock()
+        .map(|mut pending| std::mem::take(&mut *pending))
+        .unwrap_or_default()
+}
+
+/// Applies an adjustment to the run's configuration and set of disabled events.
+pub fn apply(adjustment: Adjustment, config: &mut BotConfig, disabled: &mut HashSet<String>) {
+    info!("Applying '{}' from colorbot ctl", adjustment);
+    match adjustment {
+        Adjustment::Set(Setting::MouseSpeed, value) => config.mouse_speed = value,
+        Adjustment::Set(Setting::PathDeviation, value) => config.path_deviation = value,
+        Adjustment::Set(Setting::Tolerance, value) => {
+            config.color_tolerance = value as u8;
+            vision::set_color_tolerance(config.color_tolerance);
+        }
+        Adjustment::Set(Setting::AddedDelay, value) => config.added_delay = value as u64,
+        Adjustment::DisableEvent(id) => {
+            disabled.insert(id);
+        }
+        Adjustment::EnableEvent(id) => {
+            disabled.remove(&id);
+        }
+    }
+}
+
+impl std::fmt::Display for Adjustment {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Set(setting, value) => write!(f, "set {} {}", setting.name(), value),
+            Self::DisableEvent(id) => write!(f, "disable-event {}", id),
+            Self::EnableEvent(id) => write!(f, "enable-event {}", id),
+        }
+    }
+}
+
+/// Parses an adjustment command line sent by a client.
+fn parse_adjustment(command: &str) -> Result<Adjustment> {
+    let (verb, rest) = command.split_once(' ').unwrap_or((command, ""));
+    let rest = rest.trim();
+    let adjustment = match verb {
+        "set" => {
+            let (name, value) = rest
+                .split_once(' ')
+                .context("set needs a setting and a value")?;
+            let setting = Setting::from_str(name, false)
+                .map_err(|_| anyhow::anyhow!("unknown setting '{}'", name))?;
+            let value = setting
+                .parse(value.trim())
+                .context(format!("invalid value for {}", setting.name()))?;
+            Adjustment::Set(setting, value)
+        }
+        "disable-event" | "enable-event" => {
+            let known = EVENT_IDS
+                .lock()
+                .map(|ids| ids.iter().any(|id| id == rest))
+                .unwrap_or(false);
+            ensure!(known, "the script has no event '{}'", rest);
+            if verb == "disable-event" {
+                Adjustment::DisableEvent(rest.to_string())
+            } else {
+                Adjustment::EnableEvent(rest.to_string())
+            }
+        }
+        _ => bail!("unknown command '{}'", command),
+    };
+    Ok(adjustment)
+}
+
+/// Returns the address of the control socket for the current display.
+fn address() -> Result<SocketAddr> {
+    let name = format!("{}-ctl", instance::lock_name());
+    SocketAddr::from_abstract_name(name.as_bytes()).context("Failed to build the control address")
+}
+
+/// Starts answering `colorbot ctl` for the rest of the process.
+///
+/// A run that can't claim the socket, such as a second one forced onto the same display, runs
+/// without it.
+pub fn serve() -> Result<()> {
+    decisions::start();
+    let listener = match UnixListener::bind_addr(&address()?) {
+        Ok(listener) => listener,
+        Err(e) => {
+            warn!(
+                "colorbot ctl can't reach this run, the control socket is taken: {}",
+                e
+            );
+            return Ok(());
+        }
+    };
+    std::thread::Builder::new()
+        .name("control".to_string())
+        .spawn(move || {
+            for stream in listener.incoming().flatten() {
+                // Each client may stay connected for the rest of the run
+                let _ = std::thread::Builder::new()
+                    .name("control-client".to_string())
+                    .spawn(move || {
+                        if let Err(e) = handle(stream) {
+                            debug!("Control client disconnected: {:#}", e);
+                        }
+                    });
+            }
+        })
+        .context("Failed to start the control socket thread")?;
+    Ok(())
+}
+
+/// Answers the command of a connected client.
+fn handle(stream: UnixStream) -> Result<()> {
+    let mut command = String::new();
+    BufReader::new(&stream).read_line(&mut command)?;
+    let mut stream = stream;
+    match command.trim() {
+        "tail" => {
+            let (sender, receiver) = mpsc::channel();
+            decisions::subscribe(sender);
+            for line in receiver {
+                writeln!(stream, "{}", line)?;
+            }
+            Ok(())
+        }
+        command => {
+            match parse_adjustment(command) {
+                Ok(adjustment) => {
+                    writeln!(stream, "ok: '{}' applies before the next event", adjustment)?;
+                    if let Ok(mut pending) = PENDING.lock() {
+                        pending.push(adjustment);
+                    }
+                }
+                Err(e) => writeln!(stream, "error: {:#}", e)?,
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Reply of the bot to an adjustment.
+#[derive(Serialize)]
+struct Reply {
+    message: String,
+}
+
+impl Render for Reply {
+    fn render(&self, out: &mut dyn Write) -> std::io::Result<()> {
+        writeln!(out, "{}", self.message)
+    }
+}
+
+/// Sends a command to the bot running on the current display.
+pub fn run(action: CtlAction) -> Result<()> {
+    let mut stream = UnixStream::connect_addr(&address()?)
+        .context("No colorbot run is listening on this display")?;
+    let command = match action {
+        CtlAction::Tail => {
+            writeln!(stream, "tail")?;
+            return tail(stream);
+        }
+        CtlAction::Set { setting, value } => format!("set {} {}", setting.name(), value),
+        CtlAction::DisableEvent { id } => format!("disable-event {}", id),
+        CtlAction::EnableEvent { id } => format!("enable-event {}", id),
+    };
+    ensure!(!command.contains('\n'), "Commands must fit on one line");
+    writeln!(stream, "{}", command)?;
+
+    let mut reply = String::new();
+    BufReader::new(stream)
+        .read_line(&mut reply)
+        .context("Lost the connection to the bot")?;
+    let reply = reply.trim_end();
+    if let Some(error) = reply.strip_prefix("error: ") {
+        bail!("The bot refused the command: {}", error);
+    }
+    output::print(&Reply {
+        message: reply.strip_prefix("ok: ").unwrap_or(reply).to_string(),
+    })
+}
+
+/// Prints the decisions the bot sends until it stops.
+fn tail(stream: UnixStream) -> Result<()> {
+    let mut out = std::io::stdout().lock();
+    for line in BufReader::new(stream).lines() {
+        let line = line.context("Lost the connection to the bot")?;
+        if let Some(error) = line.strip_prefix("error: ") {
+            bail!("The bot refused the command: {}", error);
+        }
+        if output::is_json() {
+            writeln!(out, "{}", line)?;
+        } else {
+            let decision: Decision =
+                serde_json::from_str(&line).context("The bot sent an unreadable decision")?;
+            writeln!(out, "{}", decision)?;
+        }
+        out.flush()?;
+    }
+    eprintln!("The run ended");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_adjustments() {
+        set_event_ids(vec!["hover skill".to_string()]);
+        assert_eq!(
+            parse_adjustment("set mouse_speed 5").unwrap(),
+            Adjustment::Set(Setting::MouseSpeed, 5.0)
+        );
+        assert_eq!(
+            parse_adjustment("set color_tolerance 8").unwrap(),
+            Adjustment::Set(Setting::Tolerance, 8.0)
+        );
+        assert_eq!(
+            parse_adjustment("disable-event hover skill").unwrap(),
+            Adjustment::DisableEvent("hover skill".to_string())
+        );
+        for invalid in [
+            "set mouse_speed 0",
+            "set tolerance 300",
+            "set speed 2",
+            "set mouse_speed",
+            "disable-event walk",
+            "pause",
+        ] {
+            assert!(parse_adjustment(invalid).is_err(), "{}", invalid);
+        }
+    }
+}
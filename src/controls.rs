@@ -1,64 +1,357 @@
 //! Mouse and keyboard control utilities for bot automation.
 //!
 //! This module provides functions for simulating human-like mouse movements and keyboard inputs
-//! using xdotool for input simulation and device_query for cursor position tracking.
+//! using the selected input backend for clicks and keys and device_query for cursor position
+//! tracking, or the position tracked by backends that inject input below the display server.
+use crate::backend::{self, InputBackend, MouseButton};
+use crate::config::BotConfig;
+use crate::decisions::{self, DecisionKind};
+use crate::delay::{DelayModel, ScriptDelay};
+use crate::display;
+use crate::dryrun;
+use crate::focus;
+use crate::homing::Homing;
+use crate::movement::MouseAlgorithm;
+use crate::overshoot::Overshoot;
+use crate::stats;
+use crate::tremor::Tremor;
+use crate::vision::TargetHit;
 use crate::windmouse::{Point, WindMouse};
 
-use anyhow::{Context, Result};
+use anyhow::{bail, ensure, Context, Result};
 use device_query::{DeviceQuery, DeviceState};
+use enigo::{Coordinate, Enigo, Mouse, Settings};
 use log::debug;
-use std::{process::Command, time::Duration};
-
-/// Executes xdotool with the given arguments.
-fn run_xdotool(args: &[&str]) -> Result<()> {
-    Command::new("xdotool")
-        .args(args)
-        .output()
-        .context(format!("Failed to execute xdotool with args: {:?}", args))?;
-    Ok(())
+use std::time::{Duration, Instant};
+
+/// Gets the current mouse cursor position on the selected display.
+pub fn get_mouse_pos() -> Point {
+    display::from_screen(screen_mouse_pos())
+}
+
+/// Gets the current mouse cursor position in X screen coordinates.
+///
+/// During a dry run this is the simulated cursor.
+fn screen_mouse_pos() -> Point {
+    dryrun::cursor().unwrap_or_else(get_screen_mouse_pos)
 }
 
-/// Gets the current mouse cursor position.
-fn get_mouse_pos() -> Point {
-    let device_state = DeviceState::new();
+/// Gets the position of the real mouse cursor in X screen coordinates.
+///
+/// Backends injecting below the display server report where they moved the cursor. Without one
+/// and without an X server to ask, the position is unknown and the origin is returned.
+pub fn get_screen_mouse_pos() -> Point {
+    if let Some(point) = backend::injected_cursor() {
+        return point;
+    }
+    let Some(device_state) = DeviceState::checked_new() else {
+        return Point::new(0, 0);
+    };
     let mouse_state = device_state.get_mouse();
 
     Point::new(mouse_state.coords.0, mouse_state.coords.1)
 }
 
-/// Moves the mouse cursor to the target position using human-like movement.
-pub fn move_mouse(target: Point) -> Result<()> {
+/// Measures the delay between issuing a mouse move and the cursor position reflecting it.
+///
+/// The cursor is nudged back and forth by one pixel several times and the median round trip is
+/// returned. The cursor is restored to its original position afterwards.
+pub fn measure_input_latency() -> Result<Duration> {
+    const SAMPLES: usize = 7;
+    const SAMPLE_TIMEOUT: Duration = Duration::from_millis(500);
+
+    let mut enigo = Enigo::new(&Settings::default()).context("failed to init enigo")?;
+    // enigo moves in X screen coordinates
+    let origin = screen_mouse_pos();
+    let mut latencies = Vec::with_capacity(SAMPLES);
+
+    for i in 0..SAMPLES {
+        // Alternate between the origin and a neighboring pixel so every sample is a real move
+        let target = if i % 2 == 0 {
+            Point::new(origin.x + 1, origin.y)
+        } else {
+            origin
+        };
+
+        let start = Instant::now();
+        enigo.move_mouse(target.x, target.y, Coordinate::Abs)?;
+        while screen_mouse_pos() != target {
+            if start.elapsed() > SAMPLE_TIMEOUT {
+                enigo.move_mouse(origin.x, origin.y, Coordinate::Abs)?;
+                bail!(
+                    "Cursor did not reach {} within {:?} of issuing the move",
+                    target,
+                    SAMPLE_TIMEOUT
+                );
+            }
+            std::thread::yield_now();
+        }
+        latencies.push(start.elapsed());
+    }
+
+    enigo.move_mouse(origin.x, origin.y, Coordinate::Abs)?;
+
+    latencies.sort();
+    Ok(latencies[SAMPLES / 2])
+}
+
+/// Tunables shared by every mouse movement.
+#[derive(Debug, Clone, Copy)]
+pub struct MouseSettings {
+    /// Delay before a move is reflected on screen. It paces the intermediate path steps and
+    /// extends the settle time before the caller clicks.
+    pub input_latency: Duration,
+    /// Targets smaller than this many pixels are approached in two stages.
+    pub small_target_area: u64,
+    /// Session tempo factor stretching movement durations and pauses.
+    pub tempo: f64,
+    /// Factor scaling how far cursor paths stray from a straight line.
+    pub path_deviation: f64,
+    /// Factor scaling how fast the cursor moves, independently of the session tempo.
+    pub speed: f64,
+    /// Hand tremor and micro-corrections added to cursor paths.
+    pub tremor: Tremor,
+    /// Deceleration and wander at the end of cursor paths.
+    pub homing: Homing,
+    /// Chance of overshooting the destination and correcting back onto it.
+    pub overshoot: Overshoot,
+    /// Algorithm that generates cursor paths.
+    pub algorithm: MouseAlgorithm,
+}
+
+impl MouseSettings {
+    /// Builds the settings from the command-line configuration.
+    pub fn from_config(config: &BotConfig) -> Self {
+        Self {
+            input_latency: Duration::from_millis(config.input_latency.unwrap_or_default()),
+            small_target_area: config.small_target_area,
+            tempo: config.tempo.unwrap_or(1.0),
+            path_deviation: config.path_deviation,
+            speed: config.mouse_speed,
+            tremor: Tremor {
+                amplitude: config.tremor_amplitude,
+                frequency: config.tremor_frequency,
+                correction_rate: config.micro_corrections,
+            },
+            homing: Homing {
+                intensity: config.homing,
+            },
+            overshoot: Overshoot {
+                probability: config.overshoot,
+            },
+            algorithm: config.mouse_algo,
+        }
+    }
+
+    /// Replaces the speed and path deviation with the ones an event sets.
+    pub fn with_overrides(mut self, speed: Option<f64>, deviation: Option<f64>) -> Self {
+        self.speed = speed.unwrap_or(self.speed);
+        self.path_deviation = deviation.unwrap_or(self.path_deviation);
+        self
+    }
+
+    /// Factor stretching the duration of cursor movements.
+    pub fn movement_tempo(&self) -> f64 {
+        self.tempo / self.speed
+    }
+
+    /// Checks that the path humanization parameters are usable.
+    pub fn validate(&self) -> Result<()> {
+        ensure!(
+            self.speed > 0.0,
+            "The mouse speed must be positive, got {}",
+            self.speed
+        );
+        self.tremor.validate().context("Invalid mouse tremor")?;
+        self.homing.validate().context("Invalid mouse homing")?;
+        self.overshoot.validate().context("Invalid mouse overshoot")
+    }
+}
+
+/// Pauses between a movement that missed the target and the correction onto it.
+fn correction_pause(settings: &MouseSettings) {
+    const CORRECTION_PAUSE_RNG_MS: std::ops::RangeInclusive<u64> = 40..=120;
+
+    std::thread::sleep(
+        Duration::from_millis(rand::random_range(CORRECTION_PAUSE_RNG_MS)).mul_f64(settings.tempo),
+    );
+}
+
+/// Sleeps long enough for the cursor to come to rest after a movement.
+fn settle(settings: &MouseSettings) {
     const MOUSE_SETTLE_DELAY_RNG_MS: std::ops::RangeInclusive<u64> = 50..=150;
 
+    std::thread::sleep(
+        settings.input_latency
+            + Duration::from_millis(rand::random_range(MOUSE_SETTLE_DELAY_RNG_MS))
+                .mul_f64(settings.tempo),
+    );
+}
+
+/// Moves the mouse cursor to the target position using human-like movement.
+///
+/// Longer movements occasionally overshoot the target and correct back onto it.
+pub fn move_mouse(
+    input: &mut dyn InputBackend,
+    target: Point,
+    settings: &MouseSettings,
+) -> Result<()> {
     let start_pos = get_mouse_pos();
-    let mut wind_mouse = WindMouse::new().context("failed to construct wind mouse object")?;
+    let mut wind_mouse = WindMouse::new()
+        .with_poll_interval(settings.input_latency)
+        .with_tempo(settings.movement_tempo())
+        .with_deviation(settings.path_deviation)
+        .with_algorithm(settings.algorithm)
+        .with_tremor(settings.tremor)
+        .with_homing(settings.homing);
+
+    if let Some(past) = settings.overshoot.point(start_pos, target) {
+        debug!(
+            "Moving mouse from {} to {} overshooting to {}",
+            start_pos, target, past
+        );
+        wind_mouse
+            .move_to(input, start_pos, past)
+            .context("mouse move failed")?;
+        correction_pause(settings);
+        wind_mouse
+            .move_precisely_to(input, get_mouse_pos(), target)
+            .context("mouse correction failed")?;
+    } else {
+        debug!("Moving mouse from {} to {}", start_pos, target);
+        wind_mouse
+            .move_to(input, start_pos, target)
+            .context("mouse move failed")?;
+    }
+
+    settle(settings);
+
+    Ok(())
+}
+
+/// Moves the mouse onto a located target, taking the target's size into account.
+///
+/// Following Fitts's law, small targets get a fast movement that stops short of the target and a
+/// second short, slow movement onto it instead of one fast curve that tends to overshoot.
+pub fn move_to_target(
+    input: &mut dyn InputBackend,
+    hit: TargetHit,
+    settings: &MouseSettings,
+) -> Result<()> {
+    const APPROACH_GAP_PX: std::ops::RangeInclusive<f64> = 20.0..=45.0;
+
+    decisions::record(DecisionKind::TargetChosen {
+        point: hit.point,
+        area: hit.area,
+    });
+    let start_pos = get_mouse_pos();
+    let target = hit.point;
+    let distance = f64::hypot(
+        f64::from(target.x - start_pos.x),
+        f64::from(target.y - start_pos.y),
+    );
+    if hit.area >= settings.small_target_area || distance <= *APPROACH_GAP_PX.end() {
+        return move_mouse(input, target, settings);
+    }
+
+    // Stop short of the target along the approach direction with a little sideways scatter
+    let gap = rand::random_range(APPROACH_GAP_PX);
+    let (dx, dy) = (
+        f64::from(target.x - start_pos.x) / distance,
+        f64::from(target.y - start_pos.y) / distance,
+    );
+    let scatter = rand::random_range(-0.3..=0.3) * gap;
+    let vicinity = Point::new(
+        target.x - (dx * gap - dy * scatter).round() as i32,
+        target.y - (dy * gap + dx * scatter).round() as i32,
+    );
 
-    debug!("Moving mouse from {} to {}", start_pos, target);
+    debug!(
+        "Approaching {}px target at {} in two stages via {}",
+        hit.area, target, vicinity
+    );
+    let mut wind_mouse = WindMouse::new()
+        .with_poll_interval(settings.input_latency)
+        .with_tempo(settings.movement_tempo())
+        .with_deviation(settings.path_deviation)
+        .with_algorithm(settings.algorithm)
+        .with_tremor(settings.tremor)
+        .with_homing(settings.homing);
     wind_mouse
-        .move_to(start_pos, target)
-        .context("mouse move failed")?;
+        .move_to(input, start_pos, vicinity)
+        .context("mouse approach failed")?;
+    correction_pause(settings);
+    wind_mouse
+        .move_precisely_to(input, get_mouse_pos(), target)
+        .context("mouse correction failed")?;
 
-    std::thread::sleep(Duration::from_millis(rand::random_range(
-        MOUSE_SETTLE_DELAY_RNG_MS,
-    )));
+    settle(settings);
 
     Ok(())
 }
 
 /// Performs a left mouse button click.
-pub fn left_click() -> Result<()> {
-    run_xdotool(&["click", "1"]).context("Failed to execute xdotool for left click")?;
+pub fn left_click(input: &mut dyn InputBackend) -> Result<()> {
+    input.click(MouseButton::Left)?;
+    stats::record_click();
+    Ok(())
+}
+
+/// Clicks `button` `clicks` times in a row, fast enough to count as a double or triple click.
+pub fn click(input: &mut dyn InputBackend, button: MouseButton, clicks: u32) -> Result<()> {
+    const CLICK_GAP_RNG_MS: std::ops::RangeInclusive<u64> = 60..=120;
+
+    for i in 0..clicks {
+        if i > 0 {
+            std::thread::sleep(Duration::from_millis(rand::random_range(CLICK_GAP_RNG_MS)));
+        }
+        input.click(button)?;
+        stats::record_click();
+    }
     Ok(())
 }
 
 /// Presses and releases the specified key with a random delay.
-pub fn toggle_key(keycode: &str) -> Result<()> {
+pub fn toggle_key(input: &mut dyn InputBackend, keycode: &str) -> Result<()> {
     const KEY_DELAY_RNG_MS: std::ops::RangeInclusive<u64> = 100..=150;
 
-    run_xdotool(&["key", keycode])
-        .context(format!("Failed to execute xdotool for key '{}'", keycode))?;
+    focus::wait_for_target()?;
+    input.key(keycode)?;
 
     std::thread::sleep(Duration::from_millis(rand::random_range(KEY_DELAY_RNG_MS)));
 
     Ok(())
 }
+
+/// Types text one character at a time with humanized gaps between keystrokes.
+///
+/// The gaps are drawn from `keystroke_delay` when given, otherwise from a model of a steady
+/// typist with the occasional hesitation. The focus is checked before every character.
+pub fn type_text(
+    input: &mut dyn InputBackend,
+    text: &str,
+    keystroke_delay: Option<&ScriptDelay>,
+) -> Result<()> {
+    const MIN_KEYSTROKE_GAP: Duration = Duration::from_millis(40);
+    const MAX_KEYSTROKE_GAP: Duration = Duration::from_millis(400);
+
+    // Mean gap of roughly 120 ms with the occasional longer hesitation
+    let model = DelayModel::new(MIN_KEYSTROKE_GAP)
+        .with_short_gamma(2.0, 40.0)
+        .with_max_delay(MAX_KEYSTROKE_GAP);
+    let mut rng = rand::rng();
+
+    let mut buf = [0; 4];
+    for c in text.chars() {
+        // The focus may move mid-string, the rest of a password mustn't land in a terminal
+        focus::wait_for_target()?;
+        input.text(c.encode_utf8(&mut buf))?;
+        let gap = match keystroke_delay {
+            Some(delay) => delay.sample()?,
+            None => model.next_delay(&mut rng)?,
+        };
+        std::thread::sleep(gap);
+    }
+
+    Ok(())
+}
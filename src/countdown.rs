@@ -0,0 +1,65 @@
+//! Countdown between launching a run and its first event.
+//!
+//! Runs are usually started from a terminal, which keeps the focus until the user switches to
+//! the game. The countdown gives them time to do so, then checks that the game window, or at
+//! least some window other than the terminal, has the focus so the first key presses don't end
+//! up in the terminal.
+use crate::window;
+
+use anyhow::{ensure, Result};
+use log::warn;
+use std::io::Write;
+use std::time::Duration;
+
+/// Counts down `secs` seconds on the terminal and checks which window has the focus afterwards.
+///
+/// When `expected` is given, the focused window's title must contain it. Otherwise the window
+/// that had the focus when the countdown started must have lost it.
+pub fn run(secs: u64, expected: Option<&str>) -> Result<()> {
+    if secs == 0 {
+        return Ok(());
+    }
+    let launched_from = window::active_window_title().ok().flatten();
+
+    for remaining in (1..=secs).rev() {
+        eprint!(
+            "\rStarting in {}s, focus {}  ",
+            remaining,
+            expected.map_or("the game window".to_string(), |title| format!(
+                "the '{}' window",
+                title
+            ))
+        );
+        let _ = std::io::stderr().flush();
+        std::thread::sleep(Duration::from_secs(1));
+    }
+    eprintln!();
+
+    let focused = match window::active_window_title() {
+        Ok(focused) => focused,
+        Err(e) => {
+            warn!(
+                "Failed to check the focused window, starting anyway: {:#}",
+                e
+            );
+            return Ok(());
+        }
+    };
+    let shown = focused.as_deref().unwrap_or("none");
+    match expected {
+        Some(title) => ensure!(
+            focused
+                .as_deref()
+                .is_some_and(|focused| focused.contains(title)),
+            "The '{}' window isn't focused after the countdown, the focused window is '{}'",
+            title,
+            shown
+        ),
+        None => ensure!(
+            launched_from.is_none() || focused != launched_from,
+            "The window colorbot was started from ('{}') still has the focus after the countdown",
+            shown
+        ),
+    }
+    Ok(())
+}
@@ -0,0 +1,148 @@
+//! Crash reports written when the bot panics.
+//!
+//! A panic hook writes the panic message, a backtrace, the event that was running, and a snapshot
+//! of the configuration to the run directory, along with a thumbnail of the last captured frame.
+//! Inputs held by the backend are released before the panic is allowed to end the process.
+use crate::backend::InputBackend;
+use crate::config::BotConfig;
+use crate::rundir::{ArtifactKind, RunDir};
+
+use anyhow::{Context, Result};
+use image::RgbaImage;
+use serde::Serialize;
+use std::backtrace::Backtrace;
+use std::fs::File;
+use std::io::BufWriter;
+use std::panic::{self, AssertUnwindSafe, PanicHookInfo};
+use std::path::PathBuf;
+use std::sync::{Mutex, MutexGuard, TryLockError};
+
+/// Width in pixels of the frame thumbnail kept for crash reports.
+const THUMBNAIL_WIDTH: u32 = 320;
+
+/// State captured while running that is included in a crash report.
+struct CrashContext {
+    current_event: Option<String>,
+    last_frame: Option<RgbaImage>,
+}
+
+static CONTEXT: Mutex<CrashContext> = Mutex::new(CrashContext {
+    current_event: None,
+    last_frame: None,
+});
+
+/// Locks the crash context without blocking, since the panic may have happened while holding it.
+fn lock_context() -> Option<MutexGuard<'static, CrashContext>> {
+    match CONTEXT.try_lock() {
+        Ok(guard) => Some(guard),
+        Err(TryLockError::Poisoned(poisoned)) => Some(poisoned.into_inner()),
+        Err(TryLockError::WouldBlock) => None,
+    }
+}
+
+/// Records the id of the event that's about to run.
+pub fn set_current_event(id: Option<&str>) {
+    if let Some(mut context) = lock_context() {
+        context.current_event = id.map(str::to_string);
+    }
+}
+
+/// Keeps a thumbnail of a captured frame for the crash report.
+pub fn record_frame(frame: &RgbaImage) {
+    let (width, height) = frame.dimensions();
+    if width == 0 {
+        return;
+    }
+    let thumb_height = (u64::from(height) * u64::from(THUMBNAIL_WIDTH) / u64::from(width)).max(1);
+    let thumbnail = image::imageops::thumbnail(
+        frame,
+        THUMBNAIL_WIDTH.min(width),
+        u32::try_from(thumb_height).unwrap_or(height),
+    );
+    if let Some(mut context) = lock_context() {
+        context.last_frame = Some(thumbnail);
+    }
+}
+
+/// Contents of the crash report.
+#[derive(Debug, Serialize)]
+struct CrashReport {
+    message: String,
+    location: Option<String>,
+    event: Option<String>,
+    config: String,
+    backtrace: String,
+    frame: Option<PathBuf>,
+}
+
+/// Writes the crash report and frame thumbnail, returning the report's path.
+fn write_report(run_dir: &RunDir, config: &str, info: &PanicHookInfo) -> Result<PathBuf> {
+    let message = match info.payload().downcast_ref::<&str>() {
+        Some(message) => message.to_string(),
+        None => info
+            .payload()
+            .downcast_ref::<String>()
+            .cloned()
+            .unwrap_or_else(|| "Unknown panic payload".to_string()),
+    };
+
+    let (event, last_frame) = match lock_context() {
+        Some(mut context) => (context.current_event.clone(), context.last_frame.take()),
+        None => (None, None),
+    };
+
+    let frame = match last_frame {
+        Some(thumbnail) => {
+            let path = run_dir.artifact_path(ArtifactKind::Crash, "last-frame.png")?;
+            thumbnail
+                .save(&path)
+                .context("Failed to save the last frame thumbnail")?;
+            Some(path)
+        }
+        None => None,
+    };
+
+    let report = CrashReport {
+        message,
+        location: info.location().map(ToString::to_string),
+        event,
+        config: config.to_string(),
+        backtrace: Backtrace::force_capture().to_string(),
+        frame,
+    };
+    let path = run_dir.artifact_path(ArtifactKind::Crash, "crash.json")?;
+    let file = File::create(&path).context("Failed to create crash report")?;
+    serde_json::to_writer_pretty(BufWriter::new(file), &report)
+        .context("Failed to write crash report")?;
+    Ok(path)
+}
+
+/// Installs a panic hook that writes a crash report into the run directory.
+pub fn install_hook(run_dir: &RunDir, config: &BotConfig) {
+    let run_dir = run_dir.clone();
+    let config = format!("{:#?}", config);
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        match write_report(&run_dir, &config, info) {
+            Ok(path) => eprintln!("Wrote crash report to {}", path.display()),
+            Err(e) => eprintln!("Failed to write crash report: {:#}", e),
+        }
+        default_hook(info);
+    }));
+}
+
+/// Runs `f`, releasing held inputs before a panic in it continues unwinding.
+pub fn release_inputs_on_panic<T>(
+    input: &mut dyn InputBackend,
+    f: impl FnOnce(&mut dyn InputBackend) -> T,
+) -> T {
+    match panic::catch_unwind(AssertUnwindSafe(|| f(&mut *input))) {
+        Ok(value) => value,
+        Err(payload) => {
+            if let Err(e) = input.release_all() {
+                eprintln!("Failed to release held inputs: {:#}", e);
+            }
+            panic::resume_unwind(payload)
+        }
+    }
+}
@@ -0,0 +1,121 @@
+//! Structured log of the decisions a run makes, streamed to `colorbot ctl tail`.
+//!
+//! The debug log explains everything, but it's verbose and a terminal following it can receive
+//! the keys the bot types. Decisions are the few facts needed to follow a run: which event
+//! started, how many pixels matched its target, where the cursor was sent and which branch was
+//! taken. They are only serialized while a client is following them, so a run nobody watches
+//! pays almost nothing for them.
+use crate::windmouse::Point;
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::sync::mpsc::Sender;
+use std::sync::{Mutex, OnceLock};
+use std::time::Instant;
+
+/// Something the run decided or observed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "decision", rename_all = "snake_case")]
+pub enum DecisionKind {
+    /// The event passed its condition and starts executing.
+    EventStarted { iteration: u64 },
+    /// A scan of the screen for the event's target finished.
+    MatchesFound { matches: usize },
+    /// The cursor is sent to a point on the located target.
+    TargetChosen { point: Point, area: u64 },
+    /// The event branches to another event instead of continuing with the next one.
+    BranchTaken { to: String, found: bool },
+    /// The event failed.
+    EventFailed { error: String },
+}
+
+/// Decision along with when and in which event it was made.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Decision {
+    /// Seconds since the run started.
+    pub elapsed_secs: f64,
+    /// Id of the event that made the decision, empty outside of events.
+    pub event: String,
+    #[serde(flatten)]
+    pub kind: DecisionKind,
+}
+
+impl fmt::Display for Decision {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:>9.3}s {:<20} ", self.elapsed_secs, self.event)?;
+        match &self.kind {
+            DecisionKind::EventStarted { iteration } => {
+                write!(f, "started in iteration {}", iteration)
+            }
+            DecisionKind::MatchesFound { matches } => write!(f, "{} pixels match", matches),
+            DecisionKind::TargetChosen { point, area } => {
+                write!(f, "moving to {} on a {}px target", point, area)
+            }
+            DecisionKind::BranchTaken { to, found: true } => {
+                write!(f, "found its target, continuing at '{}'", to)
+            }
+            DecisionKind::BranchTaken { to, found: false } => {
+                write!(f, "missed its target, continuing at '{}'", to)
+            }
+            DecisionKind::EventFailed { error } => write!(f, "failed: {}", error),
+        }
+    }
+}
+
+static STARTED: OnceLock<Instant> = OnceLock::new();
+/// Id of the event being executed.
+static CURRENT_EVENT: Mutex<String> = Mutex::new(String::new());
+/// Channels of the clients following the log, each one a JSON line per decision.
+static SUBSCRIBERS: Mutex<Vec<Sender<String>>> = Mutex::new(Vec::new());
+
+/// Marks the start of the run, which decision times count from.
+pub fn start() {
+    STARTED.get_or_init(Instant::now);
+}
+
+/// Sends the decisions made from now on to `subscriber` until its receiver is dropped.
+pub fn subscribe(subscriber: Sender<String>) {
+    if let Ok(mut subscribers) = SUBSCRIBERS.lock() {
+        subscribers.push(subscriber);
+    }
+}
+
+/// Marks the start of an event, whose id the following decisions carry.
+pub fn begin_event(id: &str, iteration: u64) {
+    if let Ok(mut current) = CURRENT_EVENT.lock() {
+        current.clear();
+        current.push_str(id);
+    }
+    record(DecisionKind::EventStarted { iteration });
+}
+
+/// Returns the id of the event being executed.
+pub fn current_event() -> String {
+    CURRENT_EVENT
+        .lock()
+        .map(|current| current.clone())
+        .unwrap_or_default()
+}
+
+/// Sends a decision of the current event to every client following the log.
+pub fn record(kind: DecisionKind) {
+    let Ok(mut subscribers) = SUBSCRIBERS.lock() else {
+        return;
+    };
+    if subscribers.is_empty() {
+        return;
+    }
+    let decision = Decision {
+        elapsed_secs: STARTED.get_or_init(Instant::now).elapsed().as_secs_f64(),
+        event: CURRENT_EVENT
+            .lock()
+            .map(|current| current.clone())
+            .unwrap_or_default(),
+        kind,
+    };
+    let Ok(line) = serde_json::to_string(&decision) else {
+        return;
+    };
+    // Clients that disconnected dropped their receiver
+    subscribers.retain(|subscriber| subscriber.send(line.clone()).is_ok());
+}
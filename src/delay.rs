@@ -1,7 +1,12 @@
 //! A flexible delay generator with a minimum guaranteed delay, variable extra delay
-use anyhow::Result;
+use crate::timing::DelayDistribution;
+
+use anyhow::{bail, ensure, Result};
 use rand::Rng;
 use rand_distr::{Distribution, Gamma};
+use schemars::JsonSchema;
+use serde::Deserialize;
+use std::collections::BTreeMap;
 use std::time::Duration;
 
 /// Delay model descriptor.
@@ -62,7 +67,7 @@ impl DelayModel {
     ///
     /// # Example
     ///
-    /// ```
+    /// ```ignore
     /// use std::time::Duration;
     ///
     /// let model = DelayModel::new(Duration::from_millis(800));
@@ -126,3 +131,76 @@ impl DelayModel {
         }
     }
 }
+
+/// Base delay of a script event in milliseconds, as written in the script.
+#[derive(Debug, Clone, PartialEq, Deserialize, JsonSchema)]
+#[serde(untagged)]
+pub enum ScriptDelay {
+    /// Fixed delay.
+    Fixed(u64),
+    /// Delay drawn uniformly from an inclusive `[min, max]` range.
+    Range([u64; 2]),
+    /// Delay drawn from a distribution, such as a long-tailed log-normal one.
+    Distribution(DelayDistribution),
+    /// Name of a delay profile from the script's `delays` table.
+    Profile(String),
+}
+
+impl ScriptDelay {
+    /// Checks that a range isn't inverted and that a distribution's parameters are valid.
+    pub fn validate(&self) -> Result<()> {
+        match self {
+            ScriptDelay::Range([min, max]) => ensure!(
+                min <= max,
+                "Delay range [{}, {}] has its minimum above its maximum",
+                min,
+                max
+            ),
+            ScriptDelay::Distribution(distribution) => distribution.validate()?,
+            ScriptDelay::Fixed(_) | ScriptDelay::Profile(_) => {}
+        }
+        Ok(())
+    }
+
+    /// Replaces a profile reference with the profile's delay.
+    pub fn resolve(&mut self, profiles: &BTreeMap<String, ScriptDelay>) -> Result<()> {
+        if let ScriptDelay::Profile(name) = self {
+            match profiles.get(name.as_str()) {
+                Some(ScriptDelay::Profile(_)) => {
+                    bail!("Delay profile '{}' refers to another profile", name)
+                }
+                Some(delay) => *self = delay.clone(),
+                None => bail!("Undefined delay profile '{}'", name),
+            }
+        }
+        Ok(())
+    }
+
+    /// Draws a base delay.
+    pub fn sample(&self) -> Result<Duration> {
+        match self {
+            ScriptDelay::Fixed(ms) => Ok(Duration::from_millis(*ms)),
+            ScriptDelay::Range([min, max]) => {
+                Ok(Duration::from_millis(rand::random_range(*min..=*max)))
+            }
+            ScriptDelay::Distribution(distribution) => distribution.sample(&mut rand::rng()),
+            ScriptDelay::Profile(name) => bail!("Delay profile '{}' was never resolved", name),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delays_stay_within_the_bounds() {
+        let model =
+            DelayModel::new(Duration::from_millis(800)).with_max_delay(Duration::from_millis(1200));
+        let mut rng = rand::rng();
+        for _ in 0..100 {
+            let delay = model.next_delay(&mut rng).unwrap();
+            assert!((Duration::from_millis(800)..=Duration::from_millis(1200)).contains(&delay));
+        }
+    }
+}
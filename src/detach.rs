@@ -0,0 +1,57 @@
+//! Running the bot in the background, detached from the terminal it was started from.
+//!
+//! A detached run is a copy of the same command started in its own process group with stdin
+//! closed and its output going to a file in the run directory. Key events the bot sends can't
+//! echo into the launching terminal, and the run survives the terminal being closed. The parent
+//! creates the run directory and hands it to the copy through the environment, so it can tell
+//! the user where the logs are before it exits.
+use crate::config::BotConfig;
+use crate::rundir::RunDir;
+
+use anyhow::{ensure, Context, Result};
+use std::fs::File;
+use std::os::unix::process::CommandExt;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+/// Environment variable holding the run directory of a detached run.
+const RUN_DIR_VAR: &str = "COLORBOT_DETACHED_RUN_DIR";
+
+/// Returns the run directory handed down by the parent when this process is a detached run.
+///
+/// The variable is removed, so processes started by the run don't take it for a detached run.
+pub fn inherited_run_dir() -> Option<RunDir> {
+    let path = std::env::var_os(RUN_DIR_VAR)?;
+    std::env::remove_var(RUN_DIR_VAR);
+    Some(RunDir::existing(PathBuf::from(path)))
+}
+
+/// Starts the current command again in the background with `run_dir` as its run directory.
+pub fn spawn(config: &BotConfig, run_dir: &RunDir) -> Result<()> {
+    // A password prompt would stop the background process group as soon as it reads the terminal
+    ensure!(
+        config.secrets.is_none() || config.secrets_keyring,
+        "A detached run can't prompt for the secrets password, pass --secrets-keyring"
+    );
+
+    let output_path = run_dir.output_path();
+    let output = File::create(&output_path)
+        .context(format!("Failed to create {}", output_path.display()))?;
+    let exe = std::env::current_exe().context("Failed to find the colorbot executable")?;
+    let child = Command::new(exe)
+        .args(std::env::args_os().skip(1))
+        .env(RUN_DIR_VAR, run_dir.path())
+        .stdin(Stdio::null())
+        .stdout(output.try_clone()?)
+        .stderr(output)
+        .process_group(0)
+        .spawn()
+        .context("Failed to start the detached run")?;
+
+    eprintln!(
+        "Running in the background as pid {}, see {}",
+        child.id(),
+        run_dir.path().display()
+    );
+    Ok(())
+}
@@ -0,0 +1,62 @@
+//! Screenshots saved to the run directory when a target looks misconfigured.
+//!
+//! Misconfigured targets don't always fail loudly. A color that matches the sky still produces a
+//! click, just not on anything useful. Code that notices such a target saves what it was looking
+//! at here, once per target and run, so the warning it logs can point at the evidence.
+use crate::rundir::{ArtifactKind, RunDir};
+
+use anyhow::{Context, Result};
+use image::RgbaImage;
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+/// Run directory screenshots are saved to and the names already saved.
+struct Diagnostics {
+    run_dir: Option<RunDir>,
+    saved: BTreeSet<String>,
+}
+
+static DIAGNOSTICS: Mutex<Diagnostics> = Mutex::new(Diagnostics {
+    run_dir: None,
+    saved: BTreeSet::new(),
+});
+
+/// Saves screenshots into `run_dir` for the rest of the run.
+pub fn start(run_dir: &RunDir) {
+    if let Ok(mut diagnostics) = DIAGNOSTICS.lock() {
+        diagnostics.run_dir = Some(run_dir.clone());
+        diagnostics.saved.clear();
+    }
+}
+
+/// Saves `frame` as `<name>.png`, returning its path. Characters other than letters, digits,
+/// `-` and `_` in the name are replaced.
+///
+/// Returns the path saved earlier when a screenshot of the same name exists, and `None` outside
+/// of a run.
+pub fn save(name: &str, frame: &RgbaImage) -> Result<Option<PathBuf>> {
+    let Ok(mut diagnostics) = DIAGNOSTICS.lock() else {
+        return Ok(None);
+    };
+    let Some(run_dir) = &diagnostics.run_dir else {
+        return Ok(None);
+    };
+    let name: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    let path = run_dir.artifact_path(ArtifactKind::Diagnostics, &format!("{}.png", name))?;
+    if diagnostics.saved.insert(name) {
+        frame
+            .save(&path)
+            .context(format!("Failed to save {}", path.display()))?;
+    }
+    Ok(Some(path))
+}
@@ -0,0 +1,250 @@
+//! Selection of the monitor a run captures and clicks on.
+//!
+//! Script coordinates are relative to the top-left corner of the captured display. With several
+//! monitors, `--display` picks one by its index in `colorbot displays`. Only that monitor is
+//! captured, and points are shifted by its offset within the X screen before the cursor moves
+//! there and back when the cursor position is read, so a script works on any monitor unchanged.
+//!
+//! A script can also be relative to a window instead, see [`follow_window`]. Captures are then
+//! cropped to the window and points shifted by its position, which is read again before every
+//! event, so the script keeps working wherever the window is moved.
+use crate::output::{self, Render};
+use crate::portal;
+use crate::vision::Region;
+use crate::windmouse::Point;
+use crate::window::{self, WindowInfo};
+
+use anyhow::{ensure, Context, Result};
+use log::{debug, info};
+use serde::Serialize;
+use std::io::Write;
+use std::sync::Mutex;
+use x11rb::connection::Connection;
+use x11rb::protocol::randr::ConnectionExt as _;
+use x11rb::protocol::xproto::ConnectionExt as _;
+
+/// Monitor of the X screen.
+#[derive(Debug, Clone, Serialize)]
+pub struct Monitor {
+    /// Position in the list of monitors, which is also the order the capturer lists them in.
+    pub index: usize,
+    /// Output name such as `DP-1`.
+    pub name: String,
+    /// Top-left corner within the X screen.
+    pub origin: Point,
+    pub width: u32,
+    pub height: u32,
+    /// Whether this is the primary monitor, which is captured without `--display`.
+    pub primary: bool,
+}
+
+/// Lists the active monitors of every X screen.
+pub fn list() -> Result<Vec<Monitor>> {
+    let (conn, _) = x11rb::connect(None).context("Failed to connect to the X server")?;
+    let mut monitors = Vec::new();
+    for screen in &conn.setup().roots {
+        let reply = conn
+            .randr_get_monitors(screen.root, true)?
+            .reply()
+            .context("Failed to list the monitors, the X server needs RandR 1.5")?;
+        for info in reply.monitors {
+            let name = conn.get_atom_name(info.name)?.reply()?.name;
+            monitors.push(Monitor {
+                index: monitors.len(),
+                name: String::from_utf8_lossy(&name).into_owned(),
+                origin: Point::new(i32::from(info.x), i32::from(info.y)),
+                width: u32::from(info.width),
+                height: u32::from(info.height),
+                primary: info.primary,
+            });
+        }
+    }
+    Ok(monitors)
+}
+
+/// Monitor chosen with `--display`, `None` for the primary one at the screen's origin.
+static SELECTED: Mutex<Option<Monitor>> = Mutex::new(None);
+
+/// Selects the monitor with the given index, or the primary one when `None`.
+pub fn select(index: Option<usize>) -> Result<()> {
+    let monitor = match index {
+        Some(index) => {
+            let monitors = list()?;
+            let count = monitors.len();
+            let monitor = monitors.into_iter().nth(index).context(format!(
+                "There's no display {}, {} displays are connected (see `colorbot displays`)",
+                index, count
+            ))?;
+            debug!(
+                "Using display {} ({}, {}x{} at {})",
+                index, monitor.name, monitor.width, monitor.height, monitor.origin
+            );
+            Some(monitor)
+        }
+        None => None,
+    };
+    if let Ok(mut selected) = SELECTED.lock() {
+        *selected = monitor;
+    }
+    Ok(())
+}
+
+/// Returns the size of the whole screen in pixels: the monitor shared through the portal while
+/// one is, the X screen otherwise.
+pub fn screen_size() -> Result<(u32, u32)> {
+    if let Some(size) = portal::stream_size() {
+        return Ok(size);
+    }
+    let (width, height) = window::screen_size()?;
+    Ok((u32::from(width), u32::from(height)))
+}
+
+/// Returns the monitor chosen with `--display`.
+pub fn selected() -> Option<Monitor> {
+    SELECTED.lock().ok().and_then(|selected| selected.clone())
+}
+
+/// Window script coordinates are relative to, `None` for the display itself.
+static WINDOW: Mutex<Option<WindowInfo>> = Mutex::new(None);
+
+/// Makes script coordinates relative to `window`, or to the display again when `None`.
+///
+/// Fails when the window isn't entirely on the captured display.
+pub fn follow_window(window: Option<WindowInfo>) -> Result<()> {
+    if let Some(window) = &window {
+        check_on_display(window)?;
+        debug!(
+            "Coordinates are relative to the window '{}' at {}",
+            window.title, window.origin
+        );
+    }
+    if let Ok(mut followed) = WINDOW.lock() {
+        *followed = window;
+    }
+    Ok(())
+}
+
+/// Returns the window script coordinates are relative to.
+pub fn followed_window() -> Option<WindowInfo> {
+    WINDOW.lock().ok().and_then(|window| window.clone())
+}
+
+/// Reads the position of the followed window again, failing when it was closed or moved off
+/// the captured display.
+pub fn refresh_window() -> Result<()> {
+    let Some(window) = followed_window() else {
+        return Ok(());
+    };
+    let moved = window::locate(&window)?;
+    if (moved.origin, moved.width, moved.height) == (window.origin, window.width, window.height) {
+        return Ok(());
+    }
+    check_on_display(&moved)?;
+    info!(
+        "The window '{}' moved to {} and is {}x{}",
+        moved.title, moved.origin, moved.width, moved.height
+    );
+    if let Ok(mut followed) = WINDOW.lock() {
+        *followed = Some(moved);
+    }
+    Ok(())
+}
+
+/// Checks that the window lies within the captured display, since the part outside it can't be
+/// captured.
+fn check_on_display(window: &WindowInfo) -> Result<()> {
+    let area = window_area_of(window);
+    let (width, height) = match selected() {
+        Some(monitor) => (monitor.width, monitor.height),
+        None => screen_size()?,
+    };
+    let fits = |start: i32, length: u32, limit: u32| {
+        start >= 0 && i64::from(start) + i64::from(length) <= i64::from(limit)
+    };
+    ensure!(
+        fits(area.x, area.width, width) && fits(area.y, area.height, height),
+        "The window '{}' must be entirely on the captured display",
+        window.title
+    );
+    Ok(())
+}
+
+/// Returns the offset of the selected monitor within the X screen.
+fn monitor_offset() -> Point {
+    SELECTED
+        .lock()
+        .ok()
+        .and_then(|selected| selected.as_ref().map(|monitor| monitor.origin))
+        .unwrap_or(Point::new(0, 0))
+}
+
+/// Returns the area of the window within captures of the selected display.
+fn window_area_of(window: &WindowInfo) -> Region {
+    let monitor = monitor_offset();
+    Region {
+        x: window.origin.x - monitor.x,
+        y: window.origin.y - monitor.y,
+        width: window.width,
+        height: window.height,
+    }
+}
+
+/// Returns the area captures are cropped to, the followed window within the display.
+pub fn window_area() -> Option<Region> {
+    followed_window().map(|window| window_area_of(&window))
+}
+
+/// Returns the offset of script coordinates within the X screen.
+fn offset() -> Point {
+    match followed_window() {
+        Some(window) => window.origin,
+        None => monitor_offset(),
+    }
+}
+
+/// Translates a point on the selected display, or in the followed window, into X screen
+/// coordinates.
+pub fn to_screen(point: Point) -> Point {
+    let offset = offset();
+    Point::new(point.x + offset.x, point.y + offset.y)
+}
+
+/// Translates a point in X screen coordinates onto the selected display, or into the followed
+/// window.
+pub fn from_screen(point: Point) -> Point {
+    let offset = offset();
+    Point::new(point.x - offset.x, point.y - offset.y)
+}
+
+/// Connected displays, as printed by `colorbot displays`.
+#[derive(Serialize)]
+struct Displays {
+    displays: Vec<Monitor>,
+}
+
+impl Render for Displays {
+    fn render(&self, out: &mut dyn Write) -> std::io::Result<()> {
+        writeln!(
+            out,
+            "{:<6} {:<12} {:>11} {:>12}",
+            "index", "name", "resolution", "offset"
+        )?;
+        for monitor in &self.displays {
+            writeln!(
+                out,
+                "{:<6} {:<12} {:>11} {:>12} {}",
+                monitor.index,
+                monitor.name,
+                format!("{}x{}", monitor.width, monitor.height),
+                format!("{},{}", monitor.origin.x, monitor.origin.y),
+                if monitor.primary { "primary" } else { "" }
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Prints the connected displays with their index, resolution and offset.
+pub fn run() -> Result<()> {
+    output::print(&Displays { displays: list()? })
+}
@@ -0,0 +1,239 @@
+//! Environment diagnostics for the `doctor` subcommand.
+//!
+//! Checks the display session, screen capture and each input backend's requirements, and prints
+//! setup guidance for anything that's missing.
+use crate::output::{self, Render};
+use crate::portal;
+use crate::process;
+use crate::runtime;
+use crate::uinput::UINPUT_PATH;
+
+use anyhow::{bail, Result};
+use serde::Serialize;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::process::Command;
+use std::time::Duration;
+
+/// udev rule granting the `input` group access to the uinput device.
+const UINPUT_UDEV_RULE: &str =
+    r#"KERNEL=="uinput", GROUP="input", MODE="0660", OPTIONS+="static_node=uinput""#;
+
+/// Outcome of a single check.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum Status {
+    Ok,
+    Warn,
+    Fail,
+}
+
+/// Result of a single check with a human readable explanation.
+#[derive(Serialize)]
+struct Check {
+    name: &'static str,
+    status: Status,
+    detail: String,
+}
+
+impl Check {
+    fn new(name: &'static str, status: Status, detail: impl Into<String>) -> Self {
+        Self {
+            name,
+            status,
+            detail: detail.into(),
+        }
+    }
+}
+
+/// Reports which kind of display session is running.
+fn check_session() -> Check {
+    let wayland = std::env::var_os("WAYLAND_DISPLAY").is_some();
+    let x11 = std::env::var_os("DISPLAY").is_some();
+    match (wayland, x11) {
+        (true, true) => Check::new(
+            "session",
+            Status::Warn,
+            "Wayland with XWayland, X11 capture and the xdotool and native backends only see X11 windows, use --capture portal and --backend uinput or ydotool for Wayland windows",
+        ),
+        (true, false) => Check::new(
+            "session",
+            Status::Warn,
+            "Wayland without XWayland, run with --capture portal and --backend uinput or ydotool",
+        ),
+        (false, true) => Check::new("session", Status::Ok, "X11"),
+        (false, false) => Check::new("session", Status::Fail, "No display found, DISPLAY is unset"),
+    }
+}
+
+/// Checks that the X server accepts connections, which is only required outside of Wayland.
+fn check_x_server() -> Check {
+    let wayland = std::env::var_os("WAYLAND_DISPLAY").is_some();
+    match x11rb::connect(None) {
+        Ok(_) => Check::new("x server", Status::Ok, "Connected"),
+        Err(e) => Check::new(
+            "x server",
+            if wayland { Status::Warn } else { Status::Fail },
+            format!("Failed to connect: {}", e),
+        ),
+    }
+}
+
+/// Checks that the screencast portal is reachable for portal capture.
+fn check_portal() -> Check {
+    match portal::screencast_version() {
+        Ok(version) => Check::new(
+            "screencast portal",
+            Status::Ok,
+            format!("Version {}", version),
+        ),
+        Err(e) => Check::new(
+            "screencast portal",
+            Status::Warn,
+            format!(
+                "Unavailable ({:#}), only needed for --capture portal. Install \
+                 xdg-desktop-portal and the portal backend for your desktop",
+                e
+            ),
+        ),
+    }
+}
+
+/// Checks that GStreamer can read the portal's PipeWire stream for portal capture.
+fn check_gstreamer() -> Check {
+    match portal::check_gstreamer() {
+        Ok(()) => Check::new("gstreamer", Status::Ok, "pipewiresrc found"),
+        Err(e) => Check::new(
+            "gstreamer",
+            Status::Warn,
+            format!("{:#}, only needed for --capture portal", e),
+        ),
+    }
+}
+
+/// Checks that ydotool reaches its daemon for the ydotool backend.
+fn check_ydotool() -> Check {
+    // A move by zero pixels fails without the daemon and leaves the cursor where it is
+    match process::run(
+        Command::new("ydotool").args(["mousemove", "-x", "0", "-y", "0"]),
+        "ydotool",
+        Duration::from_secs(5),
+    ) {
+        Ok(output) if output.status.success() => {
+            Check::new("ydotool", Status::Ok, "Reaches ydotoold")
+        }
+        Ok(_) => Check::new(
+            "ydotool",
+            Status::Warn,
+            "Installed but ydotoold isn't running, start it (often 'systemctl --user start \
+             ydotool') when selecting the ydotool backend",
+        ),
+        Err(_) => Check::new(
+            "ydotool",
+            Status::Warn,
+            "Not installed, only needed when selecting the ydotool backend",
+        ),
+    }
+}
+
+/// Checks that xdotool is installed for the xdotool backend.
+fn check_xdotool() -> Check {
+    match process::run(
+        Command::new("xdotool").arg("version"),
+        "xdotool",
+        Duration::from_secs(5),
+    ) {
+        Ok(output) if output.status.success() => Check::new(
+            "xdotool",
+            Status::Ok,
+            String::from_utf8_lossy(&output.stdout).trim().to_string(),
+        ),
+        _ => Check::new(
+            "xdotool",
+            Status::Warn,
+            "Not installed, only needed when selecting the xdotool backend",
+        ),
+    }
+}
+
+/// Checks that the uinput device exists and is writable for the uinput backend.
+fn check_uinput() -> Check {
+    match OpenOptions::new().read(true).write(true).open(UINPUT_PATH) {
+        Ok(_) => Check::new("uinput", Status::Ok, format!("{} is writable", UINPUT_PATH)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Check::new(
+            "uinput",
+            Status::Warn,
+            format!(
+                "{} does not exist, load the module with 'sudo modprobe uinput'",
+                UINPUT_PATH
+            ),
+        ),
+        Err(e) => Check::new(
+            "uinput",
+            Status::Warn,
+            format!(
+                "{} is not writable ({}). To use the uinput backend, add the udev rule\n\
+                 \x20   {}\n\
+                 to /etc/udev/rules.d/99-colorbot-uinput.rules, add yourself to the input group \
+                 with 'sudo usermod -aG input $USER', then reload with \
+                 'sudo udevadm control --reload && sudo udevadm trigger' and log in again",
+                UINPUT_PATH, e, UINPUT_UDEV_RULE
+            ),
+        ),
+    }
+}
+
+/// Checks that the bot's priority can be read and how far it may go.
+fn check_priority() -> Check {
+    match runtime::check() {
+        Ok(detail) => Check::new("priority", Status::Ok, detail),
+        Err(e) => Check::new(
+            "priority",
+            Status::Warn,
+            format!("{:#}, only needed for --nice, --ionice and --pin-cores", e),
+        ),
+    }
+}
+
+/// Results of every check.
+#[derive(Serialize)]
+struct Report {
+    checks: Vec<Check>,
+    /// Number of required checks that failed.
+    failed: usize,
+}
+
+impl Render for Report {
+    fn render(&self, out: &mut dyn Write) -> std::io::Result<()> {
+        for check in &self.checks {
+            let label = match check.status {
+                Status::Ok => "ok",
+                Status::Warn => "warn",
+                Status::Fail => "FAIL",
+            };
+            writeln!(out, "[{:>4}] {}: {}", label, check.name, check.detail)?;
+        }
+        Ok(())
+    }
+}
+
+/// Runs every check, prints the results and fails when a required check fails.
+pub fn run() -> Result<()> {
+    let checks = vec![
+        check_session(),
+        check_x_server(),
+        check_portal(),
+        check_gstreamer(),
+        check_xdotool(),
+        check_uinput(),
+        check_ydotool(),
+        check_priority(),
+    ];
+    let failed = checks.iter().filter(|c| c.status == Status::Fail).count();
+    output::print(&Report { checks, failed })?;
+
+    if failed > 0 {
+        bail!("{} required check(s) failed", failed);
+    }
+    Ok(())
+}
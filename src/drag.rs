@@ -0,0 +1,134 @@
+//! Drag and drop between two targets with the mouse button held.
+//!
+//! A drag moves onto its start like a click would, presses the button, carries the cursor along a
+//! cubic Bézier curve to the end and releases the button there. The curve eases in and out
+//! whatever `--mouse-algo` is, since a hand carrying an item starts and stops more carefully than
+//! it moves between clicks. Each end is either a fixed point
+//! or a color target located when the event runs.
+use crate::backend::{InputBackend, MouseButton};
+use crate::colormatch::ColorMatcher;
+use crate::controls::{self, MouseSettings};
+use crate::display;
+use crate::filter::Filter;
+use crate::forbidden;
+use crate::movement::{Bezier, MovementStrategy};
+use crate::timing;
+use crate::vision::{self, ColorTarget, ResultMode, ScanOptions, TargetHit};
+use crate::windmouse::Point;
+
+use anyhow::{ensure, Context, Result};
+use log::debug;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use std::time::Duration;
+
+/// Time between two cursor updates of a drag.
+const STEP_INTERVAL: Duration = Duration::from_millis(12);
+
+/// Pause after pressing the button and before releasing it, so the target registers the drag.
+const HOLD_RNG_MS: std::ops::RangeInclusive<u64> = 80..=160;
+
+/// Where a drag starts or ends.
+#[derive(Debug, Deserialize, JsonSchema)]
+#[serde(untagged)]
+pub enum DragEnd {
+    /// Fixed point on the display.
+    Point { point: Point },
+    /// Color target searched for on screen when the event runs.
+    Color {
+        /// Colors that make up the target.
+        #[serde(flatten)]
+        target: ColorTarget,
+        /// Preprocessing filters applied to the captured frame before matching.
+        #[serde(default)]
+        filters: Vec<Filter>,
+        /// How the captured frame is scanned for matching pixels.
+        #[serde(flatten)]
+        scan: Box<ScanOptions>,
+    },
+}
+
+impl DragEnd {
+    /// Checks that a color target can be searched for.
+    pub fn validate(&self) -> Result<()> {
+        let DragEnd::Color {
+            target,
+            filters,
+            scan,
+        } = self
+        else {
+            return Ok(());
+        };
+        target.validate().context("Invalid color target")?;
+        scan.validate().context("Invalid scan options")?;
+        ensure!(
+            scan.match_mode == ColorMatcher::Rgb || !matches!(target, ColorTarget::Hsv { .. }),
+            "match_mode only applies to RGB and palette targets"
+        );
+        ensure!(
+            scan.result_mode != ResultMode::CountOnly,
+            "count_only can't pick a point to drag from or to"
+        );
+        for filter in filters {
+            filter.validate().context("Invalid filter")?;
+        }
+        Ok(())
+    }
+
+    /// Returns the point to press or release the button at.
+    pub fn locate(&self) -> Result<TargetHit> {
+        match self {
+            // A fixed point is reached in a single movement, like a large target
+            DragEnd::Point { point } => Ok(TargetHit {
+                point: *point,
+                area: u64::MAX,
+            }),
+            DragEnd::Color {
+                target,
+                filters,
+                scan,
+            } => Ok(vision::find_point_in_shape(target, filters, scan)?),
+        }
+    }
+}
+
+/// Holds `button` down from the located start to the located end of a drag.
+///
+/// The button is released even when moving to the end fails, so it isn't left held.
+pub fn drag(
+    input: &mut dyn InputBackend,
+    from: TargetHit,
+    to: TargetHit,
+    button: MouseButton,
+    settings: &MouseSettings,
+) -> Result<()> {
+    let hold = || Duration::from_millis(rand::random_range(HOLD_RNG_MS)).mul_f64(settings.tempo);
+
+    controls::move_to_target(input, from, settings)?;
+    debug!("Dragging with the {} button to {}", button, to.point);
+    input.set_button(button, true)?;
+    std::thread::sleep(hold());
+    let carried = carry(input, to.point, settings);
+    if carried.is_ok() {
+        std::thread::sleep(hold());
+    }
+    input.set_button(button, false)?;
+    carried
+}
+
+/// Moves the cursor along a curve onto `dest`.
+fn carry(input: &mut dyn InputBackend, dest: Point, settings: &MouseSettings) -> Result<()> {
+    let step = STEP_INTERVAL.mul_f64(settings.movement_tempo());
+    // Drags always follow an eased curve, whatever algorithm other movements use
+    let curve = Bezier::new(settings.path_deviation);
+    let path = forbidden::route(controls::get_mouse_pos(), dest, &mut |from, to| {
+        curve.path(from, to)
+    })?;
+    let path = settings.tremor.apply(&path, step);
+    let precise_timing = input.capabilities().sub_ms_timing;
+    for point in path.into_iter().skip(1) {
+        timing::path_step_sleep(step, precise_timing);
+        input.move_cursor(display::to_screen(point))?;
+    }
+    Ok(())
+}
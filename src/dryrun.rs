@@ -0,0 +1,154 @@
+//! Simulated cursor and recorded mouse paths of runs whose input is only logged.
+//!
+//! A dry run never moves the real cursor, so the cursor the bot believes in is kept here
+//! instead: every path starts where the previous one ended, as it would in a live run. The paths
+//! are cut into strokes at every click, button or key press, logged, and can be drawn onto a
+//! screenshot to see where the bot would have moved and clicked.
+use crate::display;
+use crate::vision;
+use crate::windmouse::Point;
+
+use anyhow::{Context, Result};
+use image::{Rgba, RgbaImage};
+use imageproc::drawing::{draw_hollow_circle_mut, draw_line_segment_mut};
+use log::{debug, info};
+use std::path::Path;
+use std::sync::Mutex;
+
+/// Cursor movements between two presses, in X screen coordinates.
+#[derive(Debug, Default)]
+struct Stroke {
+    points: Vec<Point>,
+    /// Whether the stroke ended with a click or button press where it stopped.
+    clicked: bool,
+}
+
+/// State of the simulated input.
+struct Simulation {
+    cursor: Point,
+    current: Stroke,
+    strokes: Vec<Stroke>,
+}
+
+static SIMULATION: Mutex<Option<Simulation>> = Mutex::new(None);
+
+const PATH_COLOR: Rgba<u8> = Rgba([255, 200, 0, 255]);
+const CLICK_COLOR: Rgba<u8> = Rgba([255, 40, 40, 255]);
+const CLICK_RADIUS: i32 = 6;
+
+/// Starts simulating the cursor at `cursor`, forgetting earlier strokes.
+pub fn start(cursor: Point) {
+    if let Ok(mut simulation) = SIMULATION.lock() {
+        *simulation = Some(Simulation {
+            cursor,
+            current: Stroke::default(),
+            strokes: Vec::new(),
+        });
+    }
+}
+
+/// Returns the simulated cursor position in X screen coordinates while simulating.
+pub fn cursor() -> Option<Point> {
+    SIMULATION
+        .lock()
+        .ok()
+        .and_then(|simulation| simulation.as_ref().map(|simulation| simulation.cursor))
+}
+
+/// Moves the simulated cursor.
+pub fn move_cursor(point: Point) {
+    if let Ok(mut simulation) = SIMULATION.lock() {
+        if let Some(simulation) = simulation.as_mut() {
+            if simulation.current.points.is_empty() {
+                simulation.current.points.push(simulation.cursor);
+            }
+            simulation.current.points.push(point);
+            simulation.cursor = point;
+        }
+    }
+}
+
+/// Ends the stroke the cursor moved along since the last press, logging it.
+pub fn end_stroke(clicked: bool) {
+    let Ok(mut simulation) = SIMULATION.lock() else {
+        return;
+    };
+    let Some(simulation) = simulation.as_mut() else {
+        return;
+    };
+    let mut stroke = std::mem::take(&mut simulation.current);
+    if let (Some(first), Some(last)) = (stroke.points.first(), stroke.points.last()) {
+        info!(
+            "Dry run: move from {} to {} over {} steps",
+            first,
+            last,
+            stroke.points.len() - 1
+        );
+    } else if clicked {
+        stroke.points.push(simulation.cursor);
+    } else {
+        return;
+    }
+    stroke.clicked = clicked;
+    simulation.strokes.push(stroke);
+}
+
+/// Draws the recorded strokes onto a capture of the screen and saves it as a PNG.
+///
+/// Clicks are circled. When the screen can't be captured the strokes are drawn onto a black
+/// canvas instead.
+pub fn save_paths(path: &Path) -> Result<()> {
+    end_stroke(false);
+    let strokes: Vec<(Vec<Point>, bool)> = {
+        let simulation = SIMULATION
+            .lock()
+            .map_err(|_| anyhow::anyhow!("The dry run state is poisoned"))?;
+        let Some(simulation) = simulation.as_ref() else {
+            return Ok(());
+        };
+        simulation
+            .strokes
+            .iter()
+            .map(|stroke| {
+                let points = stroke
+                    .points
+                    .iter()
+                    .map(|point| display::from_screen(*point))
+                    .collect();
+                (points, stroke.clicked)
+            })
+            .collect()
+    };
+
+    let mut canvas = match vision::capture_screen_rgba() {
+        Ok(frame) => frame,
+        Err(e) => {
+            debug!("Drawing dry run paths without a screenshot: {:#}", e);
+            let points = strokes.iter().flat_map(|(points, _)| points);
+            let (width, height) = points.fold((1, 1), |(w, h), point| {
+                (
+                    w.max(point.x + CLICK_RADIUS + 1),
+                    h.max(point.y + CLICK_RADIUS + 1),
+                )
+            });
+            RgbaImage::new(width as u32, height as u32)
+        }
+    };
+    for (points, clicked) in &strokes {
+        for pair in points.windows(2) {
+            draw_line_segment_mut(
+                &mut canvas,
+                (pair[0].x as f32, pair[0].y as f32),
+                (pair[1].x as f32, pair[1].y as f32),
+                PATH_COLOR,
+            );
+        }
+        if let (true, Some(end)) = (*clicked, points.last()) {
+            draw_hollow_circle_mut(&mut canvas, (end.x, end.y), CLICK_RADIUS, CLICK_COLOR);
+        }
+    }
+    canvas.save(path).context(format!(
+        "Failed to save dry run paths to {}",
+        path.display()
+    ))
+}
@@ -0,0 +1,120 @@
+//! Kinds of failures that callers can tell apart.
+//!
+//! Errors are passed up with anyhow inside the crate, which keeps adding context cheap. Failures
+//! worth reacting to differently, such as a missing input backend as opposed to a broken script,
+//! are raised as a [`ColorbotError`] where they happen. Its variant can be recovered from any
+//! error chain, and the process exit code tells the kinds apart for scripts that run colorbot.
+//! Every public function of the library that can fail returns a [`ColorbotError`], with failures
+//! of no specific kind under [`ColorbotError::Other`].
+use std::path::PathBuf;
+use thiserror::Error;
+
+/// Failure that callers may want to handle differently from others.
+#[derive(Debug, Error)]
+pub enum ColorbotError {
+    /// The screen couldn't be captured.
+    #[error("Failed to capture the screen")]
+    CaptureFailed(#[source] anyhow::Error),
+    /// A bot script isn't valid JSON or doesn't have the expected structure.
+    #[error("Failed to parse bot script {}", .0.display())]
+    ScriptParse(PathBuf, #[source] anyhow::Error),
+    /// None of the input backends can be used on this system.
+    #[error("No usable input backend found")]
+    InputBackend(#[source] anyhow::Error),
+    /// A program the selected input backend runs, such as xdotool, isn't installed and the
+    /// fallback backend can't be used either.
+    #[error("{0} is not installed or not in PATH")]
+    ToolMissing(&'static str),
+    /// No pixel on screen matches a color target.
+    #[error("No pixels found matching the target color")]
+    ColorNotFound,
+    /// A template image isn't on screen.
+    #[error("No match found for template image {}", .0.display())]
+    ImageNotFound(PathBuf),
+    /// A color target matches so much of the screen that it's likely a background color.
+    #[error("The target color matches {0:.0}% of the scanned pixels, likely a background color")]
+    TargetTooCommon(f64),
+    /// Any other failure. A kind raised below the context it carries is still found by
+    /// [`ColorbotError::kind`].
+    #[error(transparent)]
+    Other(anyhow::Error),
+}
+
+impl ColorbotError {
+    /// Returns the specific kind of this failure, looking into [`ColorbotError::Other`], or `None`
+    /// for failures without one.
+    pub fn kind(&self) -> Option<&ColorbotError> {
+        match self {
+            ColorbotError::Other(error) => kind(error),
+            kind => Some(kind),
+        }
+    }
+
+    /// Returns the process exit code for this kind of failure.
+    pub fn exit_code(&self) -> u8 {
+        match self.kind() {
+            Some(ColorbotError::ScriptParse(..)) => 2,
+            Some(ColorbotError::InputBackend(_) | ColorbotError::ToolMissing(_)) => 3,
+            Some(ColorbotError::CaptureFailed(_)) => 4,
+            Some(
+                ColorbotError::ColorNotFound
+                | ColorbotError::ImageNotFound(_)
+                | ColorbotError::TargetTooCommon(_),
+            ) => 5,
+            Some(ColorbotError::Other(_)) | None => 1,
+        }
+    }
+}
+
+impl From<anyhow::Error> for ColorbotError {
+    /// Unwraps an error raised as a [`ColorbotError`] when no context was added on top of it, so
+    /// nothing is lost, and wraps every other error into [`ColorbotError::Other`].
+    fn from(error: anyhow::Error) -> Self {
+        if !error
+            .chain()
+            .next()
+            .is_some_and(|e| e.is::<ColorbotError>())
+        {
+            return ColorbotError::Other(error);
+        }
+        error.downcast().unwrap_or_else(ColorbotError::Other)
+    }
+}
+
+/// Returns the kind of a failure, `None` for failures without a specific kind.
+pub(crate) fn kind(error: &anyhow::Error) -> Option<&ColorbotError> {
+    error.downcast_ref().and_then(ColorbotError::kind)
+}
+
+/// Returns the process exit code for a failure, 1 for failures without a specific kind.
+pub(crate) fn exit_code(error: &anyhow::Error) -> u8 {
+    kind(error).map_or(1, ColorbotError::exit_code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::{anyhow, Context};
+
+    #[test]
+    fn kinds_survive_context_and_conversion() {
+        let missing: anyhow::Error = ColorbotError::ToolMissing("xdotool").into();
+        assert!(matches!(
+            ColorbotError::from(missing),
+            ColorbotError::ToolMissing("xdotool")
+        ));
+
+        let wrapped = Err::<(), _>(ColorbotError::ColorNotFound)
+            .context("Event 'fish' failed")
+            .unwrap_err();
+        let error = ColorbotError::from(wrapped);
+        assert!(matches!(error, ColorbotError::Other(_)));
+        assert!(matches!(error.kind(), Some(ColorbotError::ColorNotFound)));
+        assert_eq!(error.exit_code(), 5);
+        assert_eq!(exit_code(&anyhow::Error::from(error)), 5);
+
+        let plain = ColorbotError::from(anyhow!("Something else"));
+        assert!(plain.kind().is_none());
+        assert_eq!(plain.exit_code(), 1);
+    }
+}
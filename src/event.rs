@@ -3,19 +3,42 @@
 //! This module defines the core event types (keypresses, color detection, and image template
 //! recognition) that can be deserialized from bot scripts and executed with randomized delays for
 //! human-like automation.
+use crate::assertion::{Assertion, FailurePolicy};
+#[cfg(feature = "template")]
+use crate::atlas::{self, AtlasSearch};
+use crate::backend::{Capabilities, InputBackend, MouseButton};
+use crate::colormatch::ColorMatcher;
+use crate::condition::{Condition, RunContext};
 use crate::config::BotConfig;
-use crate::delay::DelayModel;
-use crate::vision::PixelColor;
+use crate::controls::MouseSettings;
+use crate::decisions::{self, DecisionKind};
+use crate::delay::{DelayModel, ScriptDelay};
+use crate::drag::{self, DragEnd};
+use crate::error::ColorbotError;
+use crate::filter::Filter;
+#[cfg(feature = "gamepad")]
+use crate::gamepad::{self, GamepadAxis, GamepadButton};
+use crate::profile::{self, Phase};
+use crate::relogin::Relogin;
+use crate::retry::RetryPolicy;
+use crate::rhythm::Rhythm;
+use crate::secrets::{ScriptString, SecretStore};
+use crate::targets;
+use crate::theme;
+use crate::verify::{self, EffectCheck, HoverCheck};
+use crate::vision::{ColorTarget, ResultMode, ScanOptions};
 use crate::{controls, vision};
 
-use anyhow::{Context, Result};
-use log::debug;
+use anyhow::{anyhow, bail, ensure, Context, Result};
+use log::{debug, warn};
+use schemars::JsonSchema;
 use serde::Deserialize;
+use std::collections::BTreeMap;
 use std::path::PathBuf;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 /// Represents different types of bot events that can be executed.
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, JsonSchema)]
 pub struct BotEvent {
     /// Event identifier for logging.
     pub id: String,
@@ -24,8 +47,42 @@ pub struct BotEvent {
     #[serde(default = "default_count")]
     pub count: u32,
 
-    /// Base delay in milliseconds before executing the event.
-    pub delay: u64,
+    /// Base delay in milliseconds after executing the event: a number, a `[min, max]` range, a
+    /// distribution, or the name of a delay profile.
+    #[serde(alias = "delay_dist", alias = "delay_rng")]
+    pub delay: ScriptDelay,
+
+    /// Condition on the run's progress that must hold for the event to run.
+    #[serde(default)]
+    pub when: Option<Condition>,
+
+    /// Preconditions checked before the event runs.
+    #[serde(default)]
+    pub assert: Vec<Assertion>,
+
+    /// What to do when one of the preconditions doesn't hold.
+    #[serde(default)]
+    pub on_assert_fail: FailurePolicy,
+
+    /// Event to continue at after the target was found and clicked.
+    #[serde(default)]
+    pub if_found: Option<String>,
+
+    /// Event to continue at when the target isn't on screen, instead of failing the run.
+    #[serde(default)]
+    pub if_not_found: Option<String>,
+
+    /// Events that must have run earlier in the pass over the script whenever this one runs.
+    #[serde(default)]
+    pub requires: Vec<String>,
+
+    /// Mouse speed factor of this event's movements instead of `--mouse-speed`.
+    #[serde(default)]
+    pub mouse_speed: Option<f64>,
+
+    /// Path deviation factor of this event's movements instead of `--path-deviation`.
+    #[serde(default)]
+    pub mouse_deviation: Option<f64>,
 
     /// The specific event type and its parameters.
     #[serde(flatten)]
@@ -36,50 +93,635 @@ fn default_count() -> u32 {
     1
 }
 
+fn default_max_candidates() -> usize {
+    5
+}
+
+fn default_clicks() -> u32 {
+    1
+}
+
+fn default_wait_timeout() -> u64 {
+    10000
+}
+
+/// Time between screen checks of a wait_for_color event.
+const WAIT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
 /// The specific type of bot event.
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, JsonSchema)]
 #[serde(tag = "type")]
 pub enum BotEventType {
     /// Keyboard key press event.
-    #[serde(rename = "keypress")]
+    #[serde(rename = "keypress", alias = "key")]
     KeyPress {
-        /// Key to press (xdotool format).
-        keycode: String,
+        /// Key to press (xdotool format), either inline or a secret reference.
+        #[serde(alias = "key")]
+        keycode: ScriptString,
+    },
+    /// Types a string one character at a time, such as a bank PIN or a chat command.
+    #[serde(rename = "type_text")]
+    TypeText {
+        /// Text to type, either inline or a secret reference. A trailing `\n` presses Enter.
+        text: ScriptString,
+        /// Delay in ms between keystrokes: a number, a `[min, max]` range, a distribution, or
+        /// the name of a delay profile. Defaults to a steady typist's rhythm.
+        #[serde(default)]
+        keystroke_delay: Option<ScriptDelay>,
     },
     /// Color-based pixel detection and click event.
     #[serde(rename = "color")]
     Color {
-        /// Target RGB color values [r, g, b].
-        rgb: [u8; 3],
+        /// Colors that make up the target.
+        #[serde(flatten)]
+        target: ColorTarget,
+        /// Colors used instead of `target` while the named script theme is active.
+        #[serde(default)]
+        variants: BTreeMap<String, ColorTarget>,
+        /// Preprocessing filters applied to the captured frame before matching.
+        #[serde(default)]
+        filters: Vec<Filter>,
+        /// How the captured frame is scanned for matching pixels.
+        #[serde(flatten)]
+        scan: ScanOptions,
+        /// How often the screen is searched again when the target isn't found.
+        #[serde(flatten)]
+        retry: RetryPolicy,
+        /// Tooltip that must appear while hovering a candidate before it's clicked.
+        #[serde(default)]
+        verify_hover: Option<Box<HoverCheck>>,
+        /// Maximum number of clusters tried when verification fails.
+        #[serde(default = "default_max_candidates")]
+        #[schemars(range(min = 1))]
+        max_candidates: usize,
+        /// Mouse button that clicks the target.
+        #[serde(default)]
+        button: MouseButton,
+        /// Number of clicks in a row, 2 for a double click.
+        #[serde(default = "default_clicks")]
+        #[schemars(range(min = 1))]
+        clicks: u32,
+        /// Change around the clicked point that confirms the click had an effect.
+        #[serde(default)]
+        confirm_effect: Option<EffectCheck>,
     },
+    /// Template image detection and click event.
     #[serde(rename = "image")]
     Image {
         /// Path to the image file to search for on the screen.
         image_path: PathBuf,
+        /// Preprocessing filters applied to the captured frame and template before matching.
+        #[serde(default)]
+        filters: Vec<Filter>,
+        /// Mouse button that clicks the target.
+        #[serde(default)]
+        button: MouseButton,
+        /// Number of clicks in a row, 2 for a double click.
+        #[serde(default = "default_clicks")]
+        #[schemars(range(min = 1))]
+        clicks: u32,
+        /// Change around the clicked point that confirms the click had an effect.
+        #[serde(default)]
+        confirm_effect: Option<EffectCheck>,
+    },
+    /// Searches for any of a set of templates at slightly varying scales and clicks the closest
+    /// match.
+    #[cfg(feature = "template")]
+    #[serde(rename = "atlas")]
+    Atlas {
+        /// Templates and where to search for them.
+        #[serde(flatten)]
+        search: AtlasSearch,
+        /// Mouse button that clicks the target.
+        #[serde(default)]
+        button: MouseButton,
+        /// Number of clicks in a row, 2 for a double click.
+        #[serde(default = "default_clicks")]
+        #[schemars(range(min = 1))]
+        clicks: u32,
+        /// Change around the clicked point that confirms the click had an effect.
+        #[serde(default)]
+        confirm_effect: Option<EffectCheck>,
+    },
+    /// Presses a mouse button at one target, carries the cursor to another and releases it
+    /// there.
+    #[serde(rename = "drag")]
+    Drag {
+        /// Where the button is pressed: a fixed `point` or a color target.
+        from: DragEnd,
+        /// Where the button is released: a fixed `point` or a color target.
+        to: DragEnd,
+        /// Mouse button held during the drag.
+        #[serde(default)]
+        button: MouseButton,
+    },
+    /// Waits until a color target appears on screen, without clicking it.
+    #[serde(rename = "wait_for_color")]
+    WaitForColor {
+        /// Colors that make up the target.
+        #[serde(flatten)]
+        target: ColorTarget,
+        /// Preprocessing filters applied to the captured frame before matching.
+        #[serde(default)]
+        filters: Vec<Filter>,
+        /// How the captured frame is scanned for matching pixels.
+        #[serde(flatten)]
+        scan: ScanOptions,
+        /// Maximum time in ms to wait for the target.
+        #[serde(default = "default_wait_timeout")]
+        #[schemars(range(min = 1))]
+        timeout: u64,
+    },
+    /// Presses a button of the virtual game controller.
+    #[cfg(feature = "gamepad")]
+    #[serde(rename = "button")]
+    Button {
+        /// Controller button to press, named after the Xbox layout.
+        button: GamepadButton,
+        /// Time in ms the button is held, defaults to a quick tap.
+        #[serde(default)]
+        hold: Option<ScriptDelay>,
+    },
+    /// Pushes a stick or squeezes a trigger of the virtual game controller, then lets it go.
+    #[cfg(feature = "gamepad")]
+    #[serde(rename = "axis")]
+    Axis {
+        /// Stick axis or trigger to move.
+        axis: GamepadAxis,
+        /// Position to move it to: -1 (left or up) to 1 (right or down) for sticks, 0 to 1 for
+        /// triggers.
+        value: f64,
+        /// Time in ms the axis is held at the position before it springs back.
+        hold: ScriptDelay,
     },
+    /// Logs back in when the login screen is shown, otherwise does nothing.
+    #[serde(rename = "relogin")]
+    Relogin(Box<Relogin>),
+}
+
+impl BotEventType {
+    /// Returns the type name scripts use for the event.
+    pub fn name(&self) -> &'static str {
+        match self {
+            BotEventType::KeyPress { .. } => "keypress",
+            BotEventType::TypeText { .. } => "type_text",
+            BotEventType::Color { .. } => "color",
+            BotEventType::Image { .. } => "image",
+            #[cfg(feature = "template")]
+            BotEventType::Atlas { .. } => "atlas",
+            BotEventType::Drag { .. } => "drag",
+            BotEventType::WaitForColor { .. } => "wait_for_color",
+            BotEventType::Relogin(_) => "relogin",
+            #[cfg(feature = "gamepad")]
+            BotEventType::Button { .. } => "button",
+            #[cfg(feature = "gamepad")]
+            BotEventType::Axis { .. } => "axis",
+        }
+    }
 }
 
 impl BotEvent {
+    /// Checks the event's parameters for values that can never work at runtime.
+    pub fn validate(&self) -> Result<()> {
+        self.delay
+            .validate()
+            .context(format!("Invalid delay in event '{}'", self.id))?;
+        for assertion in &self.assert {
+            assertion
+                .validate()
+                .context(format!("Invalid assertion in event '{}'", self.id))?;
+        }
+        for (name, value) in [
+            ("mouse_speed", self.mouse_speed),
+            ("mouse_deviation", self.mouse_deviation),
+        ] {
+            if let Some(value) = value {
+                ensure!(
+                    value > 0.0,
+                    "Event '{}' needs a positive {}, got {}",
+                    self.id,
+                    name,
+                    value
+                );
+            }
+        }
+
+        let confirm_effect = match &self.event_type {
+            BotEventType::Color {
+                clicks,
+                confirm_effect,
+                ..
+            }
+            | BotEventType::Image {
+                clicks,
+                confirm_effect,
+                ..
+            } => {
+                ensure!(
+                    *clicks >= 1,
+                    "Event '{}' needs clicks of at least 1",
+                    self.id
+                );
+                confirm_effect.as_ref()
+            }
+            #[cfg(feature = "template")]
+            BotEventType::Atlas {
+                search,
+                clicks,
+                confirm_effect,
+                ..
+            } => {
+                ensure!(
+                    *clicks >= 1,
+                    "Event '{}' needs clicks of at least 1",
+                    self.id
+                );
+                search
+                    .validate()
+                    .context(format!("Invalid atlas in event '{}'", self.id))?;
+                confirm_effect.as_ref()
+            }
+            BotEventType::WaitForColor { timeout, .. } => {
+                ensure!(
+                    *timeout >= 1,
+                    "Event '{}' needs a timeout of at least 1 ms",
+                    self.id
+                );
+                None
+            }
+            BotEventType::Drag { from, to, .. } => {
+                from.validate()
+                    .context(format!("Invalid drag start in event '{}'", self.id))?;
+                to.validate()
+                    .context(format!("Invalid drag end in event '{}'", self.id))?;
+                None
+            }
+            BotEventType::TypeText {
+                text,
+                keystroke_delay,
+            } => {
+                ensure!(
+                    !matches!(text, ScriptString::Plain(text) if text.is_empty()),
+                    "Event '{}' has no text to type",
+                    self.id
+                );
+                if let Some(delay) = keystroke_delay {
+                    delay
+                        .validate()
+                        .context(format!("Invalid keystroke_delay in event '{}'", self.id))?;
+                }
+                ensure!(
+                    self.jump_targets().next().is_none(),
+                    "Event '{}' uses if_found or if_not_found, which only color, image, atlas, \
+                     drag and wait_for_color events support",
+                    self.id
+                );
+                None
+            }
+            BotEventType::KeyPress { .. } | BotEventType::Relogin(_) => {
+                ensure!(
+                    self.jump_targets().next().is_none(),
+                    "Event '{}' uses if_found or if_not_found, which only color, image, atlas, \
+                     drag and wait_for_color events support",
+                    self.id
+                );
+                None
+            }
+            #[cfg(feature = "gamepad")]
+            BotEventType::Button { hold, .. } => {
+                if let Some(hold) = hold {
+                    hold.validate()
+                        .context(format!("Invalid hold in event '{}'", self.id))?;
+                }
+                ensure!(
+                    self.jump_targets().next().is_none(),
+                    "Event '{}' uses if_found or if_not_found, which only color, image, atlas, \
+                     drag and wait_for_color events support",
+                    self.id
+                );
+                None
+            }
+            #[cfg(feature = "gamepad")]
+            BotEventType::Axis { axis, value, hold } => {
+                hold.validate()
+                    .context(format!("Invalid hold in event '{}'", self.id))?;
+                ensure!(
+                    axis.range().contains(value),
+                    "Event '{}' moves {} to {}, outside of {:?}",
+                    self.id,
+                    axis,
+                    value,
+                    axis.range()
+                );
+                ensure!(
+                    self.jump_targets().next().is_none(),
+                    "Event '{}' uses if_found or if_not_found, which only color, image, atlas, \
+                     drag and wait_for_color events support",
+                    self.id
+                );
+                None
+            }
+        };
+        if let Some(check) = confirm_effect {
+            check
+                .validate()
+                .context(format!("Invalid confirm_effect in event '{}'", self.id))?;
+        }
+
+        let filters = match &self.event_type {
+            BotEventType::Color {
+                target,
+                variants,
+                filters,
+                scan,
+                verify_hover,
+                max_candidates,
+                ..
+            } => {
+                for (theme, variant) in variants {
+                    variant.validate().context(format!(
+                        "Invalid '{}' variant of the color target in event '{}'",
+                        theme, self.id
+                    ))?;
+                }
+                ensure!(
+                    *max_candidates >= 1,
+                    "Event '{}' needs max_candidates of at least 1",
+                    self.id
+                );
+                if let Some(check) = verify_hover {
+                    check
+                        .validate()
+                        .context(format!("Invalid verify_hover in event '{}'", self.id))?;
+                }
+                target
+                    .validate()
+                    .context(format!("Invalid color target in event '{}'", self.id))?;
+                scan.validate()
+                    .context(format!("Invalid scan options in event '{}'", self.id))?;
+                ensure!(
+                    scan.match_mode == ColorMatcher::Rgb
+                        || !matches!(target, ColorTarget::Hsv { .. }),
+                    "Event '{}' sets a match_mode, which only applies to RGB and palette targets",
+                    self.id
+                );
+                ensure!(
+                    scan.result_mode != ResultMode::CountOnly,
+                    "Event '{}' uses count_only, which can't pick a point to click",
+                    self.id
+                );
+                filters.as_slice()
+            }
+            BotEventType::Image { filters, .. } => filters.as_slice(),
+            #[cfg(feature = "template")]
+            BotEventType::Atlas { search, .. } => search.filters.as_slice(),
+            BotEventType::WaitForColor {
+                target,
+                filters,
+                scan,
+                ..
+            } => {
+                target
+                    .validate()
+                    .context(format!("Invalid color target in event '{}'", self.id))?;
+                scan.validate()
+                    .context(format!("Invalid scan options in event '{}'", self.id))?;
+                ensure!(
+                    scan.match_mode == ColorMatcher::Rgb
+                        || !matches!(target, ColorTarget::Hsv { .. }),
+                    "Event '{}' sets a match_mode, which only applies to RGB and palette targets",
+                    self.id
+                );
+                filters.as_slice()
+            }
+            BotEventType::Relogin(relogin) => {
+                relogin
+                    .validate()
+                    .context(format!("Invalid relogin routine in event '{}'", self.id))?;
+                &[]
+            }
+            BotEventType::KeyPress { .. }
+            | BotEventType::TypeText { .. }
+            | BotEventType::Drag { .. } => &[],
+            #[cfg(feature = "gamepad")]
+            BotEventType::Button { .. } | BotEventType::Axis { .. } => &[],
+        };
+        for filter in filters {
+            filter
+                .validate()
+                .context(format!("Invalid filter in event '{}'", self.id))?;
+        }
+        Ok(())
+    }
+
+    /// Returns the backend capabilities needed to execute this event.
+    pub fn required_capabilities(&self) -> Capabilities {
+        match &self.event_type {
+            BotEventType::KeyPress {
+                keycode: ScriptString::Plain(keycode),
+            } => Capabilities {
+                key_chords: keycode.contains('+'),
+                ..Default::default()
+            },
+            BotEventType::Drag { .. } => Capabilities {
+                drag: true,
+                ..Default::default()
+            },
+            BotEventType::KeyPress { .. }
+            | BotEventType::TypeText { .. }
+            | BotEventType::Color { .. }
+            | BotEventType::Image { .. }
+            | BotEventType::WaitForColor { .. }
+            | BotEventType::Relogin(_) => Capabilities::default(),
+            #[cfg(feature = "template")]
+            BotEventType::Atlas { .. } => Capabilities::default(),
+            #[cfg(feature = "gamepad")]
+            BotEventType::Button { .. } | BotEventType::Axis { .. } => Capabilities {
+                gamepad: true,
+                ..Default::default()
+            },
+        }
+    }
+
+    /// Returns the names of the themes this event has target variants for.
+    pub fn theme_names(&self) -> impl Iterator<Item = &str> {
+        let variants = match &self.event_type {
+            BotEventType::Color { variants, .. } => Some(variants.keys()),
+            BotEventType::KeyPress { .. }
+            | BotEventType::TypeText { .. }
+            | BotEventType::Image { .. }
+            | BotEventType::Drag { .. }
+            | BotEventType::WaitForColor { .. }
+            | BotEventType::Relogin(_) => None,
+            #[cfg(feature = "template")]
+            BotEventType::Atlas { .. } => None,
+            #[cfg(feature = "gamepad")]
+            BotEventType::Button { .. } | BotEventType::Axis { .. } => None,
+        };
+        variants.into_iter().flatten().map(String::as_str)
+    }
+
+    /// Returns the event's delays, to resolve their profile references.
+    pub fn delays_mut(&mut self) -> impl Iterator<Item = &mut ScriptDelay> {
+        let own_delay = match &mut self.event_type {
+            BotEventType::TypeText {
+                keystroke_delay, ..
+            } => keystroke_delay.as_mut(),
+            #[cfg(feature = "gamepad")]
+            BotEventType::Button { hold, .. } => hold.as_mut(),
+            #[cfg(feature = "gamepad")]
+            BotEventType::Axis { hold, .. } => Some(hold),
+            _ => None,
+        };
+        std::iter::once(&mut self.delay).chain(own_delay)
+    }
+
+    /// Returns the ids of the events this event can branch to.
+    pub fn jump_targets(&self) -> impl Iterator<Item = &str> {
+        self.if_found
+            .iter()
+            .chain(&self.if_not_found)
+            .map(String::as_str)
+    }
+
+    /// Returns the event to continue at when the target wasn't found, or fails with `error`
+    /// when the event doesn't branch on a miss.
+    fn not_found(&self, error: anyhow::Error) -> Result<Option<&str>> {
+        match &self.if_not_found {
+            Some(next) => {
+                debug!(
+                    "Target of event '{}' isn't on screen, continuing at '{}'",
+                    self.id, next
+                );
+                decisions::record(DecisionKind::BranchTaken {
+                    to: next.clone(),
+                    found: false,
+                });
+                Ok(Some(next))
+            }
+            None => Err(error),
+        }
+    }
+
+    /// Handles a color target that wasn't found after every retry. Events with retries that
+    /// don't branch give up on the target with a warning, so the script moves on to the next
+    /// event.
+    fn color_not_found(&self, retry: &RetryPolicy, error: anyhow::Error) -> Result<Option<&str>> {
+        if self.if_not_found.is_none() && retry.moves_on(&error) {
+            warn!(
+                "Target of event '{}' wasn't found after {} retries, moving on: {:#}",
+                self.id, retry.retries, error
+            );
+            return Ok(None);
+        }
+        self.not_found(error)
+    }
+
+    /// Returns the names of the secrets this event refers to.
+    pub fn secret_names(&self) -> Vec<&str> {
+        match &self.event_type {
+            BotEventType::KeyPress { keycode } => keycode.secret_name().into_iter().collect(),
+            BotEventType::TypeText { text, .. } => text.secret_name().into_iter().collect(),
+            BotEventType::Relogin(relogin) => relogin
+                .credentials()
+                .filter_map(ScriptString::secret_name)
+                .collect(),
+            BotEventType::Color { .. }
+            | BotEventType::Image { .. }
+            | BotEventType::Drag { .. }
+            | BotEventType::WaitForColor { .. } => Vec::new(),
+            #[cfg(feature = "template")]
+            BotEventType::Atlas { .. } => Vec::new(),
+            #[cfg(feature = "gamepad")]
+            BotEventType::Button { .. } | BotEventType::Axis { .. } => Vec::new(),
+        }
+    }
+
+    /// Evaluates the event's condition, returning whether the event should run at this point.
+    pub fn should_run(&self, context: &RunContext) -> Result<bool> {
+        let Some(when) = &self.when else {
+            return Ok(true);
+        };
+        let run = when.evaluate(context).context(format!(
+            "Failed to evaluate the condition of event '{}'",
+            self.id
+        ))?;
+        if !run {
+            debug!("Skipping event '{}', '{}' doesn't hold", self.id, when);
+        }
+        Ok(run)
+    }
+
+    /// Checks the event's preconditions, returning whether the event should run.
+    fn check_assertions(&self) -> Result<bool> {
+        for assertion in &self.assert {
+            if assertion.holds().context(format!(
+                "Failed to check assertion '{}' of event '{}'",
+                assertion, self.id
+            ))? {
+                continue;
+            }
+
+            match self.on_assert_fail {
+                FailurePolicy::Abort => {
+                    bail!("Assertion '{}' failed for event '{}'", assertion, self.id)
+                }
+                FailurePolicy::Skip => {
+                    warn!(
+                        "Skipping event '{}', assertion '{}' failed",
+                        self.id, assertion
+                    );
+                    return Ok(false);
+                }
+                FailurePolicy::Warn => warn!(
+                    "Assertion '{}' failed for event '{}', running it anyway",
+                    assertion, self.id
+                ),
+            }
+        }
+        Ok(true)
+    }
+
     /// Executes the bot event based on its type.
-    pub fn exec(&self, config: &BotConfig) -> Result<()> {
-        // Sleeps for a randomized duration based on the configured delay model
-        let sleep = |delay: u64| -> Result<()> {
+    ///
+    /// Returns the id of the event to continue at when the event branches, `None` to continue
+    /// with the next event.
+    pub fn exec(
+        &self,
+        config: &BotConfig,
+        input: &mut dyn InputBackend,
+        secrets: &SecretStore,
+        rhythm: &mut Rhythm,
+    ) -> Result<Option<&str>> {
+        // Sleeps for a randomized duration based on the configured delay model and rhythm
+        let mut sleep = |delay: &ScriptDelay| -> Result<()> {
             const GAMMA_SHAPE: f64 = 1.5; // Shape that's not too clustered around the mean,
                                           // allowing for more variability
             let scale_ms = config.added_delay as f64 / GAMMA_SHAPE;
-            let max_delay_ms = Duration::from_millis(delay + config.max_added_delay);
-            let model = DelayModel::new(Duration::from_millis(delay))
+            let base_delay = delay.sample()?;
+            let max_delay_ms = base_delay + Duration::from_millis(config.max_added_delay);
+            let model = DelayModel::new(base_delay)
                 .with_short_gamma(GAMMA_SHAPE, scale_ms)
                 .with_max_delay(max_delay_ms);
             let mut rng = rand::rng();
-            let random_delay = model.next_delay(&mut rng)?;
+            let random_delay = rhythm
+                .next_delay(base_delay, model.next_delay(&mut rng)?)?
+                .mul_f64(config.tempo.unwrap_or(1.0));
 
             debug!("Sleeping for {:?} before next action", random_delay);
+            let _phase = profile::phase(Phase::Sleep);
             std::thread::sleep(random_delay);
 
             Ok(())
         };
 
+        if !self.check_assertions()? {
+            return Ok(None);
+        }
+
+        let mouse = MouseSettings::from_config(config)
+            .with_overrides(self.mouse_speed, self.mouse_deviation);
+
         for i in 0..self.count {
             if self.count > 1 {
                 debug!("Executing '{}' iteration {}/{}", self.id, i + 1, self.count);
@@ -88,36 +730,243 @@ impl BotEvent {
             match &self.event_type {
                 BotEventType::KeyPress { keycode } => {
                     debug!("Executing keypress '{}': '{}'", self.id, keycode);
-                    controls::toggle_key(keycode)?;
-                    sleep(self.delay)?;
+                    let result = controls::toggle_key(input, keycode.resolve(secrets)?);
+                    keycode.redact(result, "press")?;
+                    sleep(&self.delay)?;
                 }
-                BotEventType::Color { rgb } => {
-                    debug!(
-                        "Executing color event '{}': target RGB({},{},{})",
-                        self.id, rgb[0], rgb[1], rgb[2]
+                BotEventType::TypeText {
+                    text,
+                    keystroke_delay,
+                } => {
+                    debug!("Executing type_text '{}': '{}'", self.id, text);
+                    let result = controls::type_text(
+                        input,
+                        text.resolve(secrets)?,
+                        keystroke_delay.as_ref(),
                     );
-                    let target_color = PixelColor::new(rgb[0], rgb[1], rgb[2]);
-                    let target_pixel = vision::find_point_in_shape(&target_color)
-                        .context("Failed to find target pixel color")?;
-
-                    controls::move_mouse(target_pixel)?;
-                    controls::left_click()?;
-                    sleep(self.delay)?;
+                    text.redact(result, "type")?;
+                    sleep(&self.delay)?;
+                }
+                BotEventType::Color {
+                    target,
+                    variants,
+                    filters,
+                    scan,
+                    retry,
+                    verify_hover,
+                    max_candidates,
+                    button,
+                    clicks,
+                    confirm_effect,
+                } => {
+                    let target = theme::pick(target, variants);
+                    debug!("Executing color event '{}': target {}", self.id, target);
+                    match verify_hover {
+                        Some(check) => {
+                            let blobs = retry
+                                .run(&self.id, || Ok(vision::find_blobs(target, filters, scan)?));
+                            let blobs = match blobs {
+                                Ok(blobs) => blobs,
+                                Err(e) => {
+                                    targets::record_attempt(&self.id, false);
+                                    return self.color_not_found(
+                                        retry,
+                                        e.context("Failed to find target pixel color"),
+                                    );
+                                }
+                            };
+                            let mut rejected = 0;
+                            let result = verify::try_candidates(
+                                input,
+                                &blobs,
+                                *max_candidates,
+                                &mouse,
+                                || {
+                                    let verified = check.verify()?;
+                                    rejected += u64::from(!verified);
+                                    Ok(verified)
+                                },
+                            );
+                            targets::record_misclicks(&self.id, rejected);
+                            targets::record_attempt(&self.id, result.is_ok());
+                            if let Err(e) = result {
+                                return self.not_found(e);
+                            }
+                        }
+                        None => {
+                            let hit = retry.run(&self.id, || {
+                                Ok(vision::find_point_in_shape(target, filters, scan)?)
+                            });
+                            targets::record_attempt(&self.id, hit.is_ok());
+                            let hit = match hit {
+                                Ok(hit) => hit,
+                                Err(e) => {
+                                    return self.color_not_found(
+                                        retry,
+                                        e.context("Failed to find target pixel color"),
+                                    )
+                                }
+                            };
+                            controls::move_to_target(input, hit, &mouse)?;
+                        }
+                    }
+                    verify::click_confirmed(input, *button, *clicks, confirm_effect.as_ref())
+                        .context(format!("Click of event '{}' wasn't confirmed", self.id))?;
+                    sleep(&self.delay)?;
                 }
-                BotEventType::Image { image_path } => {
+                BotEventType::Image {
+                    image_path,
+                    filters,
+                    button,
+                    clicks,
+                    confirm_effect,
+                } => {
                     debug!(
                         "Executing image event '{}': searching for image '{}'",
                         self.id,
                         image_path.display()
                     );
-                    let target_pixel = vision::find_image_on_screen(image_path)
-                        .context("Failed to find target image on screen")?;
-                    controls::move_mouse(target_pixel)?;
-                    controls::left_click()?;
-                    sleep(self.delay)?;
+                    let hit = vision::find_image_on_screen(image_path, filters)
+                        .context("Failed to find target image on screen");
+                    targets::record_attempt(&self.id, hit.is_ok());
+                    let hit = match hit {
+                        Ok(hit) => hit,
+                        Err(e) => return self.not_found(e),
+                    };
+                    controls::move_to_target(input, hit, &mouse)?;
+                    verify::click_confirmed(input, *button, *clicks, confirm_effect.as_ref())
+                        .context(format!("Click of event '{}' wasn't confirmed", self.id))?;
+                    sleep(&self.delay)?;
+                }
+                #[cfg(feature = "template")]
+                BotEventType::Atlas {
+                    search,
+                    button,
+                    clicks,
+                    confirm_effect,
+                } => {
+                    debug!(
+                        "Executing atlas event '{}': searching for {} templates",
+                        self.id,
+                        search.templates.len()
+                    );
+                    let found = search.locate();
+                    targets::record_attempt(&self.id, matches!(found, Ok(Some(_))));
+                    let found = match found {
+                        Ok(Some(found)) => found,
+                        Ok(None) => {
+                            return self.not_found(anyhow!(
+                                "None of the atlas templates of event '{}' is on screen",
+                                self.id
+                            ))
+                        }
+                        Err(e) => return self.not_found(e.context("Failed to search the atlas")),
+                    };
+                    debug!(
+                        "Event '{}' found '{}' at {} (scale {:.2})",
+                        self.id,
+                        atlas::template_name(&found.template),
+                        found.hit.point,
+                        found.scale
+                    );
+                    controls::move_to_target(input, found.hit, &mouse)?;
+                    verify::click_confirmed(input, *button, *clicks, confirm_effect.as_ref())
+                        .context(format!("Click of event '{}' wasn't confirmed", self.id))?;
+                    sleep(&self.delay)?;
+                }
+                BotEventType::Drag { from, to, button } => {
+                    debug!("Executing drag event '{}'", self.id);
+                    let ends = from
+                        .locate()
+                        .context("Failed to find the drag start")
+                        .and_then(|from| {
+                            let to = to.locate().context("Failed to find the drag end")?;
+                            Ok((from, to))
+                        });
+                    targets::record_attempt(&self.id, ends.is_ok());
+                    let (from, to) = match ends {
+                        Ok(ends) => ends,
+                        Err(e) => return self.not_found(e),
+                    };
+                    drag::drag(input, from, to, *button, &mouse)
+                        .context(format!("Drag of event '{}' failed", self.id))?;
+                    sleep(&self.delay)?;
+                }
+                BotEventType::WaitForColor {
+                    target,
+                    filters,
+                    scan,
+                    timeout,
+                } => {
+                    debug!("Executing wait event '{}': target {}", self.id, target);
+                    let timeout = Duration::from_millis(*timeout);
+                    let appeared = wait_for_color(target, filters, scan, timeout);
+                    targets::record_attempt(&self.id, matches!(appeared, Ok(true)));
+                    if !appeared? {
+                        return self.not_found(anyhow!(ColorbotError::ColorNotFound).context(
+                            format!(
+                                "Target of event '{}' did not appear within {:?}",
+                                self.id, timeout
+                            ),
+                        ));
+                    }
+                    sleep(&self.delay)?;
+                }
+                BotEventType::Relogin(relogin) => {
+                    debug!("Executing relogin event '{}'", self.id);
+                    if relogin
+                        .run(input, secrets, &mouse)
+                        .context("Failed to log back in")?
+                    {
+                        debug!("Logged back in");
+                    }
+                    sleep(&self.delay)?;
+                }
+                #[cfg(feature = "gamepad")]
+                BotEventType::Button { button, hold } => {
+                    debug!("Executing button event '{}': {}", self.id, button);
+                    gamepad::press_button(input, *button, hold.as_ref())?;
+                    sleep(&self.delay)?;
+                }
+                #[cfg(feature = "gamepad")]
+                BotEventType::Axis { axis, value, hold } => {
+                    debug!("Executing axis event '{}': {} to {}", self.id, axis, value);
+                    gamepad::tilt_axis(input, *axis, *value, hold.sample()?)?;
+                    sleep(&self.delay)?;
                 }
             }
         }
-        Ok(())
+        if let Some(next) = &self.if_found {
+            decisions::record(DecisionKind::BranchTaken {
+                to: next.clone(),
+                found: true,
+            });
+        }
+        Ok(self.if_found.as_deref())
+    }
+}
+
+/// Polls the screen until a pixel matches the target, returning whether one did before the
+/// timeout.
+fn wait_for_color(
+    target: &ColorTarget,
+    filters: &[Filter],
+    scan: &ScanOptions,
+    timeout: Duration,
+) -> Result<bool> {
+    let presence = ScanOptions {
+        result_mode: ResultMode::FirstMatch,
+        ..scan.clone()
+    };
+    let start = Instant::now();
+    loop {
+        if vision::scan_screen(target, filters, &presence)?.count > 0 {
+            debug!("Target appeared after {:?}", start.elapsed());
+            return Ok(true);
+        }
+        if start.elapsed() >= timeout {
+            return Ok(false);
+        }
+        std::thread::sleep(WAIT_POLL_INTERVAL);
     }
 }
@@ -0,0 +1,121 @@
+//! Frame preprocessing filters applied before matching.
+//!
+//! Filters are composable stages that run in the order they're listed on an event. They are
+//! meant to stabilize matches on dithered or noisy rendering, e.g. by smoothing single-pixel
+//! noise with a small blur or collapsing near-identical shades with posterization.
+use anyhow::{ensure, Result};
+use image::RgbaImage;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// A single preprocessing stage.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Filter {
+    /// Averages every pixel with its neighbors within `radius` pixels.
+    BoxBlur {
+        #[schemars(range(min = 1))]
+        radius: u32,
+    },
+    /// Quantizes every color channel down to `levels` evenly spaced values.
+    Posterize {
+        #[schemars(range(min = 2))]
+        levels: u8,
+    },
+}
+
+impl Filter {
+    /// Checks that the filter's parameters are usable.
+    pub fn validate(&self) -> Result<()> {
+        match self {
+            Filter::BoxBlur { radius } => {
+                ensure!(*radius > 0, "Box blur radius must be positive")
+            }
+            Filter::Posterize { levels } => {
+                ensure!(*levels >= 2, "Posterize needs at least 2 levels")
+            }
+        }
+        Ok(())
+    }
+
+    /// Applies this filter to the image in place.
+    pub fn apply(&self, image: &mut RgbaImage) {
+        match self {
+            Filter::BoxBlur { radius } => box_blur(image, *radius),
+            Filter::Posterize { levels } => posterize(image, *levels),
+        }
+    }
+}
+
+/// Applies each filter in order.
+pub fn apply_all(image: &mut RgbaImage, filters: &[Filter]) {
+    for filter in filters {
+        filter.apply(image);
+    }
+}
+
+/// Blurs the image with a separable box kernel, clamping the window at the borders.
+fn box_blur(image: &mut RgbaImage, radius: u32) {
+    let (width, height) = image.dimensions();
+
+    // Horizontal pass followed by a vertical pass over the horizontally blurred result
+    let horizontal = RgbaImage::from_fn(width, height, |x, y| {
+        let lo = x.saturating_sub(radius);
+        let hi = (x + radius).min(width - 1);
+        average((lo..=hi).map(|sx| image.get_pixel(sx, y).0))
+    });
+    *image = RgbaImage::from_fn(width, height, |x, y| {
+        let lo = y.saturating_sub(radius);
+        let hi = (y + radius).min(height - 1);
+        average((lo..=hi).map(|sy| horizontal.get_pixel(x, sy).0))
+    });
+}
+
+/// Averages a window of RGBA pixels channel by channel.
+fn average(pixels: impl Iterator<Item = [u8; 4]>) -> image::Rgba<u8> {
+    let mut sums = [0u32; 4];
+    let mut count = 0;
+    for pixel in pixels {
+        for (sum, channel) in sums.iter_mut().zip(pixel) {
+            *sum += u32::from(channel);
+        }
+        count += 1;
+    }
+
+    image::Rgba(sums.map(|sum| ((sum + count / 2) / count) as u8))
+}
+
+/// Rounds every color channel to the nearest of `levels` evenly spaced values.
+fn posterize(image: &mut RgbaImage, levels: u8) {
+    let step = 255.0 / f64::from(levels - 1);
+    for pixel in image.pixels_mut() {
+        for channel in &mut pixel.0[..3] {
+            *channel = ((f64::from(*channel) / step).round() * step).round() as u8;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    #[test]
+    fn blur_averages_neighbors_and_posterize_rounds_channels() {
+        let mut image = RgbaImage::from_fn(3, 1, |x, _| match x {
+            1 => Rgba([255, 255, 255, 255]),
+            _ => Rgba([0, 0, 0, 255]),
+        });
+        Filter::BoxBlur { radius: 1 }.apply(&mut image);
+        // The window is clamped at the borders, so the edges average two pixels
+        assert_eq!(image.get_pixel(0, 0), &Rgba([128, 128, 128, 255]));
+        assert_eq!(image.get_pixel(1, 0), &Rgba([85, 85, 85, 255]));
+        assert_eq!(image.get_pixel(2, 0), &Rgba([128, 128, 128, 255]));
+
+        let mut image = RgbaImage::from_pixel(1, 1, Rgba([100, 200, 30, 77]));
+        apply_all(&mut image, &[Filter::Posterize { levels: 3 }]);
+        assert_eq!(image.get_pixel(0, 0), &Rgba([128, 255, 0, 77]));
+        Filter::Posterize { levels: 2 }.apply(&mut image);
+        assert_eq!(image.get_pixel(0, 0), &Rgba([255, 255, 0, 77]));
+    }
+}
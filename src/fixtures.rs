@@ -0,0 +1,112 @@
+//! Recording of the frames the vision pipeline consumes, as fixtures for offline testing.
+//!
+//! Each frame is saved with everything outside the region that was actually scanned blacked
+//! out, which keeps the PNGs small while they can still be replayed with `--frames` as full
+//! screenshots. Consecutive identical frames are saved only once.
+use crate::vision::{self, Region};
+
+use anyhow::{Context, Result};
+use image::codecs::png::{CompressionType, FilterType, PngEncoder};
+use image::{ImageEncoder, RgbaImage};
+use log::warn;
+use std::collections::hash_map::DefaultHasher;
+use std::fs::File;
+use std::hash::{Hash, Hasher};
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Directory frames are saved to and what was saved so far.
+struct Recorder {
+    dir: PathBuf,
+    saved: usize,
+    last_hash: Option<u64>,
+}
+
+impl Recorder {
+    fn save(&mut self, frame: &RgbaImage) -> Result<()> {
+        let mut hasher = DefaultHasher::new();
+        frame.dimensions().hash(&mut hasher);
+        frame.as_raw().hash(&mut hasher);
+        let hash = hasher.finish();
+        if self.last_hash == Some(hash) {
+            return Ok(());
+        }
+
+        let path = self.dir.join(format!("frame-{:06}.png", self.saved + 1));
+        let file = File::create(&path).context(format!("Failed to create {}", path.display()))?;
+        PngEncoder::new_with_quality(
+            BufWriter::new(file),
+            CompressionType::Best,
+            FilterType::Adaptive,
+        )
+        .write_image(
+            frame.as_raw(),
+            frame.width(),
+            frame.height(),
+            image::ExtendedColorType::Rgba8,
+        )
+        .context(format!("Failed to write {}", path.display()))?;
+
+        self.saved += 1;
+        self.last_hash = Some(hash);
+        Ok(())
+    }
+}
+
+static RECORDER: Mutex<Option<Recorder>> = Mutex::new(None);
+
+/// Starts saving consumed frames into `dir`, creating it if needed.
+pub fn start(dir: &Path) -> Result<()> {
+    std::fs::create_dir_all(dir).context(format!(
+        "Failed to create frames directory {}",
+        dir.display()
+    ))?;
+    if let Ok(mut recorder) = RECORDER.lock() {
+        *recorder = Some(Recorder {
+            dir: dir.to_path_buf(),
+            saved: 0,
+            last_hash: None,
+        });
+    }
+    Ok(())
+}
+
+/// Saves a captured frame of which only `region` is used, the whole frame without a region.
+///
+/// Does nothing unless recording was started. Failures are logged rather than ending the run.
+pub fn record(frame: &RgbaImage, region: Option<&Region>) {
+    let Ok(mut guard) = RECORDER.lock() else {
+        return;
+    };
+    let Some(recorder) = guard.as_mut() else {
+        return;
+    };
+
+    let result = match region {
+        Some(region) => {
+            let (cropped, origin) = vision::crop_to_region(frame.clone(), Some(region));
+            let mut masked = RgbaImage::new(frame.width(), frame.height());
+            image::imageops::replace(
+                &mut masked,
+                &cropped,
+                i64::from(origin.x),
+                i64::from(origin.y),
+            );
+            recorder.save(&masked)
+        }
+        None => recorder.save(frame),
+    };
+    if let Err(e) = result {
+        warn!("Failed to record frame: {:#}", e);
+    }
+}
+
+/// Number of frames saved since recording started.
+pub fn saved() -> usize {
+    RECORDER
+        .lock()
+        .ok()
+        .and_then(|recorder| recorder.as_ref().map(|recorder| recorder.saved))
+        .unwrap_or_default()
+}
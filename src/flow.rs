@@ -0,0 +1,292 @@
+//! Control flow between the events of a script and the check of their declared dependencies.
+//!
+//! The flow is also what `colorbot graph` draws.
+//!
+//! Each event leads to the events that can run right after it: the next one, the start of its
+//! repeat block when it ends one, and the targets of its branches. A pass over the script begins
+//! at the first event or at a checkpoint. An event that `requires` another must not be reachable
+//! from where a pass begins without going through that event, otherwise a branch or checkpoint
+//! lets it run without its prerequisite. The check doesn't evaluate `when` conditions, so an
+//! event the path goes through counts as having run.
+use crate::assertion::FailurePolicy;
+use crate::event::BotEvent;
+use crate::script::Script;
+
+use anyhow::{bail, Result};
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::fmt;
+
+/// Why one event can follow another.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EdgeKind {
+    /// The event ran, or was skipped, and the next one follows.
+    Next,
+    /// The event ends a repeat block that runs again.
+    Repeat,
+    /// The event found its target and branches with `if_found`.
+    Found,
+    /// The event missed its target and branches with `if_not_found`.
+    NotFound,
+}
+
+/// Event that can run right after another one.
+#[derive(Debug, Clone, Copy)]
+pub struct Edge {
+    /// Index of the following event.
+    pub to: usize,
+    pub kind: EdgeKind,
+}
+
+/// Events that can run after each event of a script, by index.
+pub struct Flow {
+    /// Edges leaving each event.
+    pub successors: Vec<Vec<Edge>>,
+    /// Indices where a pass over the script can begin, with a description for messages.
+    pub entries: Vec<(usize, String)>,
+}
+
+impl Flow {
+    /// Builds the flow of a loaded script.
+    pub fn new(script: &Script) -> Self {
+        let events = &script.events;
+        let successors = events
+            .iter()
+            .enumerate()
+            .map(|(index, event)| {
+                let mut edges = Vec::new();
+                // Events that continue normally or are skipped fall through to the event that
+                // next_index picks
+                let skippable = event.when.is_some()
+                    || (!event.assert.is_empty() && event.on_assert_fail == FailurePolicy::Skip);
+                let falls_through = event.if_found.is_none() || skippable;
+                if falls_through {
+                    if index + 1 < events.len() {
+                        edges.push(Edge {
+                            to: index + 1,
+                            kind: EdgeKind::Next,
+                        });
+                    }
+                    edges.extend(
+                        script
+                            .repeats
+                            .iter()
+                            .filter(|repeat| repeat.events.end == index + 1)
+                            .map(|repeat| Edge {
+                                to: repeat.events.start,
+                                kind: EdgeKind::Repeat,
+                            }),
+                    );
+                }
+                let branches = [
+                    (&event.if_found, EdgeKind::Found),
+                    (&event.if_not_found, EdgeKind::NotFound),
+                ];
+                for (target, kind) in branches {
+                    if let Some(to) = target.as_deref().and_then(|id| position(events, id)) {
+                        edges.push(Edge { to, kind });
+                    }
+                }
+                edges
+            })
+            .collect();
+
+        let mut entries = vec![(0, "the start".to_string())];
+        entries.extend(
+            script
+                .checkpoints
+                .iter()
+                .map(|(name, index)| (*index, format!("checkpoint '{}'", name))),
+        );
+        Self {
+            successors,
+            entries,
+        }
+    }
+
+    /// Returns a shortest path of event indices from where a pass begins to `target` that
+    /// doesn't go through any of `avoid`, along with the description of where it begins.
+    fn path_avoiding(&self, target: usize, avoid: &[usize]) -> Option<(&str, Vec<usize>)> {
+        let mut previous: Vec<Option<usize>> = vec![None; self.successors.len()];
+        let mut seen = vec![false; self.successors.len()];
+        let mut origin = vec![0; self.successors.len()];
+        let mut queue = VecDeque::new();
+        for (i, (index, _)) in self.entries.iter().enumerate() {
+            if *index < seen.len() && !seen[*index] && !avoid.contains(index) {
+                seen[*index] = true;
+                origin[*index] = i;
+                queue.push_back(*index);
+            }
+        }
+
+        while let Some(index) = queue.pop_front() {
+            if index == target {
+                let mut path = vec![index];
+                while let Some(before) = previous[path[path.len() - 1]] {
+                    path.push(before);
+                }
+                path.reverse();
+                return Some((&self.entries[origin[index]].1, path));
+            }
+            for next in self.successors[index].iter().map(|edge| edge.to) {
+                if !seen[next] && !avoid.contains(&next) {
+                    seen[next] = true;
+                    previous[next] = Some(index);
+                    origin[next] = origin[index];
+                    queue.push_back(next);
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Returns the index of the first event with the given id.
+fn position(events: &[BotEvent], id: &str) -> Option<usize> {
+    events.iter().position(|event| event.id == id)
+}
+
+/// Event that can run without one of the events it requires.
+pub struct Unmet {
+    /// Id of the event that declares the requirement.
+    pub event: String,
+    /// Id of the required event.
+    pub requirement: String,
+    /// Where the path that skips the requirement begins.
+    pub origin: String,
+    /// Ids of the events along that path, ending with the event.
+    pub path: Vec<String>,
+}
+
+impl fmt::Display for Unmet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let path: Vec<String> = self.path.iter().map(|id| format!("'{}'", id)).collect();
+        write!(
+            f,
+            "can run without '{}' before it, from {} through {}",
+            self.requirement,
+            self.origin,
+            path.join(" -> ")
+        )
+    }
+}
+
+/// Returns every requirement of the script's events that some path through the script skips.
+pub fn unmet_requirements(script: &Script) -> Vec<Unmet> {
+    let flow = Flow::new(script);
+    let events = &script.events;
+    let mut unmet = Vec::new();
+    for (index, event) in events.iter().enumerate() {
+        for requirement in &event.requires {
+            let avoid: Vec<usize> = events
+                .iter()
+                .enumerate()
+                .filter(|(_, other)| other.id == *requirement)
+                .map(|(i, _)| i)
+                .collect();
+            if let Some((origin, path)) = flow.path_avoiding(index, &avoid) {
+                unmet.push(Unmet {
+                    event: event.id.clone(),
+                    requirement: requirement.clone(),
+                    origin: origin.to_string(),
+                    path: path.iter().map(|&i| events[i].id.clone()).collect(),
+                });
+            }
+        }
+    }
+    unmet
+}
+
+/// Fails when an event of the script can run without one of the events it requires.
+pub fn check(script: &Script) -> Result<()> {
+    let unmet = unmet_requirements(script);
+    if unmet.is_empty() {
+        return Ok(());
+    }
+    let lines: Vec<String> = unmet
+        .iter()
+        .map(|unmet| format!("event '{}' {}", unmet.event, unmet))
+        .collect();
+    bail!(
+        "The script's branches break event requirements:\n{}",
+        lines.join("\n")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::Metadata;
+    use crate::script::{Repeat, Repetition};
+    use serde_json::json;
+    use std::collections::BTreeMap;
+
+    fn script(events: serde_json::Value) -> Script {
+        Script {
+            metadata: Metadata::default(),
+            events: serde_json::from_value(events).unwrap(),
+            rhythm: None,
+            forbidden: Vec::new(),
+            themes: None,
+            checkpoints: BTreeMap::new(),
+            transactions: Vec::new(),
+            repeats: Vec::new(),
+            tests: Vec::new(),
+        }
+    }
+
+    fn key(id: &str) -> serde_json::Value {
+        json!({ "id": id, "type": "keypress", "keycode": "a", "delay": 100 })
+    }
+
+    #[test]
+    fn straight_scripts_meet_requirements() {
+        let mut deposit = key("deposit");
+        deposit["requires"] = json!(["open_bank"]);
+        let script = script(json!([key("open_bank"), key("walk"), deposit]));
+        assert!(unmet_requirements(&script).is_empty());
+    }
+
+    #[test]
+    fn branches_and_checkpoints_can_skip_requirements() {
+        let mut find = json!({
+            "id": "find_bank", "type": "color", "rgb": [1, 2, 3], "delay": 100,
+            "if_found": "deposit"
+        });
+        let mut deposit = key("deposit");
+        deposit["requires"] = json!(["open_bank"]);
+        let mut script = script(json!([find.clone(), key("open_bank"), deposit.clone()]));
+        let unmet = unmet_requirements(&script);
+        assert_eq!(unmet.len(), 1);
+        assert_eq!(unmet[0].origin, "the start");
+        assert_eq!(unmet[0].path, ["find_bank", "deposit"]);
+
+        find["if_found"] = json!("open_bank");
+        script.events = serde_json::from_value(json!([find, key("open_bank"), deposit])).unwrap();
+        assert!(unmet_requirements(&script).is_empty());
+
+        script.checkpoints.insert("banking".to_string(), 2);
+        let unmet = unmet_requirements(&script);
+        assert_eq!(unmet.len(), 1);
+        assert_eq!(unmet[0].origin, "checkpoint 'banking'");
+        assert_eq!(unmet[0].path, ["deposit"]);
+    }
+
+    #[test]
+    fn repeat_blocks_loop_back() {
+        let mut drop = key("drop");
+        drop["requires"] = json!(["open_inventory"]);
+        let mut script = script(json!([key("walk"), drop, key("open_inventory")]));
+        // A block that loops over drop and open_inventory reaches drop without it on the
+        // first pass only
+        script.repeats.push(Repeat {
+            events: 1..3,
+            first_event: "drop".to_string(),
+            repetition: Repetition::Times(3),
+        });
+        let unmet = unmet_requirements(&script);
+        assert_eq!(unmet.len(), 1);
+        assert_eq!(unmet[0].path, ["walk", "drop"]);
+    }
+}
@@ -0,0 +1,138 @@
+//! Guard that keeps key events out of the wrong window.
+//!
+//! Keys sent while a terminal has the focus end up as input to the shell the bot was started
+//! from. Before every key event the focused window is checked: a terminal emulator, or any
+//! window other than the script's game window when the script names one, parks the key until the
+//! focus returns and fails the event when it doesn't within a timeout.
+use crate::window;
+
+use anyhow::{bail, Result};
+use log::{debug, warn};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Longest time a key is held back waiting for the focus to return.
+const PARK_TIMEOUT: Duration = Duration::from_secs(30);
+/// Interval between checks of the focus while a key is parked.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// WM_CLASS names of terminal emulators, compared case-insensitively with both the instance and
+/// class name.
+const TERMINAL_CLASSES: &[&str] = &[
+    "alacritty",
+    "foot",
+    "footclient",
+    "gnome-terminal",
+    "gnome-terminal-server",
+    "kgx",
+    "kitty",
+    "konsole",
+    "lxterminal",
+    "mate-terminal",
+    "ptyxis",
+    "qterminal",
+    "st",
+    "st-256color",
+    "terminator",
+    "terminology",
+    "tilix",
+    "urxvt",
+    "wezterm",
+    "wezterm-gui",
+    "xfce4-terminal",
+    "xterm",
+];
+
+/// Whether key events are checked, only during live runs.
+static ENABLED: AtomicBool = AtomicBool::new(false);
+/// Text the game window's title contains, when the script names one.
+static EXPECTED: Mutex<Option<String>> = Mutex::new(None);
+/// Whether a failure to read the focus was reported already.
+static UNREADABLE: AtomicBool = AtomicBool::new(false);
+
+/// Enables the guard for key events sent to the window whose title contains `expected`, or to
+/// any window that's not a terminal.
+pub fn enable(expected: Option<String>) {
+    if let Ok(mut current) = EXPECTED.lock() {
+        *current = expected;
+    }
+    UNREADABLE.store(false, Ordering::Relaxed);
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// Disables the guard, for runs that don't send real input.
+pub fn disable() {
+    ENABLED.store(false, Ordering::Relaxed);
+}
+
+/// Returns why key events must not be sent to the focused window, `None` when they may.
+fn refusal() -> Result<Option<String>> {
+    if let Some((instance, class)) = window::active_window_class()? {
+        let is_terminal = TERMINAL_CLASSES.iter().any(|terminal| {
+            terminal.eq_ignore_ascii_case(&instance) || terminal.eq_ignore_ascii_case(&class)
+        });
+        if is_terminal {
+            return Ok(Some(format!("the terminal {} has the focus", class)));
+        }
+    }
+    let expected = EXPECTED.lock().ok().and_then(|expected| expected.clone());
+    if let Some(expected) = expected {
+        let title = window::active_window_title()?;
+        if !title
+            .as_deref()
+            .is_some_and(|title| title.contains(&expected))
+        {
+            return Ok(Some(format!(
+                "'{}' has the focus instead of the '{}' window",
+                title.as_deref().unwrap_or("no window"),
+                expected
+            )));
+        }
+    }
+    Ok(None)
+}
+
+/// Waits until key events may be sent to the focused window.
+///
+/// Fails when the focus doesn't return to the game within the park timeout. When the focus
+/// can't be read, such as on Wayland, keys are sent unchecked.
+pub fn wait_for_target() -> Result<()> {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return Ok(());
+    }
+    let parked_at = Instant::now();
+    let mut logged = false;
+    loop {
+        let reason = match refusal() {
+            Ok(reason) => reason,
+            Err(e) => {
+                if !UNREADABLE.swap(true, Ordering::Relaxed) {
+                    warn!(
+                        "Failed to read the focused window, sending keys unchecked: {:#}",
+                        e
+                    );
+                }
+                return Ok(());
+            }
+        };
+        let Some(reason) = reason else {
+            if logged {
+                debug!("Focus returned after {:?}", parked_at.elapsed());
+            }
+            return Ok(());
+        };
+        if parked_at.elapsed() >= PARK_TIMEOUT {
+            bail!(
+                "Refusing to send keys, {} for over {} seconds",
+                reason,
+                PARK_TIMEOUT.as_secs()
+            );
+        }
+        if !logged {
+            warn!("Holding back key input, {}", reason);
+            logged = true;
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    }
+}
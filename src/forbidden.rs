@@ -0,0 +1,212 @@
+//! Forbidden screen regions that cursor paths route around.
+//!
+//! Scripts can mark areas such as a logout button as forbidden. A path whose samples would cross
+//! one is replanned through a waypoint beside the region, so the cursor never even passes over
+//! it, and moving to a point inside one is refused.
+use crate::vision::Region;
+use crate::windmouse::Point;
+
+use anyhow::{ensure, Context, Result};
+use std::sync::Mutex;
+
+/// Distance in pixels paths keep from forbidden regions, covering the tremor and homing wander
+/// applied after planning.
+const MARGIN_PX: f64 = 8.0;
+/// Extra distance in pixels of detour waypoints beyond the margin.
+const CLEARANCE_PX: f64 = 6.0;
+/// Number of times a leg is replanned before it's split at a detour waypoint.
+const ATTEMPTS: usize = 3;
+/// Maximum nesting of detour waypoints.
+const MAX_DEPTH: usize = 6;
+
+static FORBIDDEN: Mutex<Vec<Region>> = Mutex::new(Vec::new());
+
+/// Sets the regions paths must stay out of, replacing any set before.
+pub fn set(regions: Vec<Region>) {
+    if let Ok(mut forbidden) = FORBIDDEN.lock() {
+        *forbidden = regions;
+    }
+}
+
+/// A forbidden region grown by a margin, in continuous coordinates.
+#[derive(Debug, Clone, Copy)]
+struct Zone {
+    min: (f64, f64),
+    max: (f64, f64),
+}
+
+impl Zone {
+    fn new(region: &Region, margin: f64) -> Self {
+        Self {
+            min: (f64::from(region.x) - margin, f64::from(region.y) - margin),
+            max: (
+                f64::from(region.x) + f64::from(region.width) + margin,
+                f64::from(region.y) + f64::from(region.height) + margin,
+            ),
+        }
+    }
+
+    fn contains(&self, point: Point) -> bool {
+        let (x, y) = (f64::from(point.x), f64::from(point.y));
+        (self.min.0..=self.max.0).contains(&x) && (self.min.1..=self.max.1).contains(&y)
+    }
+
+    /// Returns whether the segment from `a` to `b` passes through the zone, using Liang-Barsky
+    /// clipping.
+    fn intersects(&self, a: Point, b: Point) -> bool {
+        let (x0, y0) = (f64::from(a.x), f64::from(a.y));
+        let (dx, dy) = (f64::from(b.x) - x0, f64::from(b.y) - y0);
+        let (mut enter, mut exit) = (0.0_f64, 1.0_f64);
+        for (p, q) in [
+            (-dx, x0 - self.min.0),
+            (dx, self.max.0 - x0),
+            (-dy, y0 - self.min.1),
+            (dy, self.max.1 - y0),
+        ] {
+            if p == 0.0 {
+                // Parallel to this edge, outside of it means no intersection at all
+                if q < 0.0 {
+                    return false;
+                }
+                continue;
+            }
+            let t = q / p;
+            if p < 0.0 {
+                enter = enter.max(t);
+            } else {
+                exit = exit.min(t);
+            }
+            if enter > exit {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Returns the corners of the zone pushed out by `clearance`.
+    fn corners(&self, clearance: f64) -> [Point; 4] {
+        let (x0, y0) = (
+            (self.min.0 - clearance).floor() as i32,
+            (self.min.1 - clearance).floor() as i32,
+        );
+        let (x1, y1) = (
+            (self.max.0 + clearance).ceil() as i32,
+            (self.max.1 + clearance).ceil() as i32,
+        );
+        [
+            Point::new(x0, y0),
+            Point::new(x1, y0),
+            Point::new(x0, y1),
+            Point::new(x1, y1),
+        ]
+    }
+}
+
+/// Returns the first zone a step of `path` passes through.
+fn first_crossing(path: &[Point], zones: &[Zone]) -> Option<Zone> {
+    path.windows(2).find_map(|step| {
+        zones
+            .iter()
+            .find(|zone| zone.intersects(step[0], step[1]))
+            .copied()
+    })
+}
+
+fn distance(a: Point, b: Point) -> f64 {
+    f64::hypot(f64::from(b.x - a.x), f64::from(b.y - a.y))
+}
+
+/// Returns whether the cursor may be moved to `point`, that is it's clear of every forbidden
+/// region by the margin paths keep.
+pub fn allows(point: Point) -> bool {
+    FORBIDDEN
+        .lock()
+        .map(|regions| {
+            regions
+                .iter()
+                .all(|region| !Zone::new(region, MARGIN_PX).contains(point))
+        })
+        .unwrap_or(true)
+}
+
+/// Plans a path from `start` to `dest` that avoids `zones`, splitting it at detour waypoints as
+/// needed.
+fn route_leg(
+    start: Point,
+    dest: Point,
+    zones: &[Zone],
+    plan: &mut dyn FnMut(Point, Point) -> Vec<Point>,
+    depth: usize,
+) -> Result<Vec<Point>> {
+    let mut attempts = 1;
+    let zone = loop {
+        let path = plan(start, dest);
+        match first_crossing(&path, zones) {
+            None => return Ok(path),
+            Some(zone) if attempts == ATTEMPTS => break zone,
+            Some(_) => attempts += 1,
+        }
+    };
+    ensure!(
+        depth < MAX_DEPTH,
+        "No path from {} to {} around the forbidden regions",
+        start,
+        dest
+    );
+
+    // Detour via the corner of the blocking zone that can be reached directly and keeps the path
+    // shortest, a leg that still crosses the zone picks the next corner in turn
+    let waypoint = zone
+        .corners(CLEARANCE_PX)
+        .into_iter()
+        .filter(|corner| *corner != start && !zone.intersects(start, *corner))
+        .filter(|corner| !zones.iter().any(|zone| zone.contains(*corner)))
+        .min_by(|a, b| {
+            let length = |corner: &Point| distance(start, *corner) + distance(*corner, dest);
+            length(a).total_cmp(&length(b))
+        })
+        .context(format!(
+            "No way around the forbidden region between {} and {}",
+            start, dest
+        ))?;
+
+    let mut path = route_leg(start, waypoint, zones, plan, depth + 1)?;
+    path.extend(
+        route_leg(waypoint, dest, zones, plan, depth + 1)?
+            .into_iter()
+            .skip(1),
+    );
+    Ok(path)
+}
+
+/// Plans a path from `start` to `dest` with `plan` that keeps out of the forbidden regions.
+///
+/// Regions the cursor is already in are ignored so it can leave them. Fails when `dest` is in or
+/// right next to a forbidden region.
+pub fn route(
+    start: Point,
+    dest: Point,
+    plan: &mut dyn FnMut(Point, Point) -> Vec<Point>,
+) -> Result<Vec<Point>> {
+    let zones: Vec<Zone> = FORBIDDEN
+        .lock()
+        .map(|regions| {
+            regions
+                .iter()
+                .map(|region| Zone::new(region, MARGIN_PX))
+                .filter(|zone| !zone.contains(start))
+                .collect()
+        })
+        .unwrap_or_default();
+    if zones.is_empty() {
+        return Ok(plan(start, dest));
+    }
+    ensure!(
+        !zones.iter().any(|zone| zone.contains(dest)),
+        "Refusing to move to {}, it's within {}px of a forbidden region",
+        dest,
+        MARGIN_PX
+    );
+
+    route_leg(start, dest, &zones, plan, 0)
+}
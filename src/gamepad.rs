@@ -0,0 +1,414 @@
+//! Virtual game controller driven through uinput, for games played with a controller.
+//!
+//! The controller presents itself as an Xbox 360 pad, the layout games and SDL recognize without
+//! configuration: two sticks, two analog triggers, a d-pad and the face, shoulder and menu
+//! buttons. It's added next to the selected mouse and keyboard backend instead of replacing it, so
+//! a script can mix controller input with clicks and key presses. Writing to `/dev/uinput` needs
+//! the same udev rule as the uinput backend, see `colorbot doctor`.
+use crate::backend::{Capabilities, InputBackend, MouseButton};
+use crate::delay::ScriptDelay;
+use crate::focus;
+use crate::timing;
+use crate::uinput::{DEVICE_SETTLE_TIME, UINPUT_PATH};
+use crate::windmouse::Point;
+
+use anyhow::{Context, Result};
+use evdev::uinput::VirtualDevice;
+use evdev::{
+    AbsInfo, AbsoluteAxisCode, AttributeSet, BusType, EventType, InputEvent, InputId, KeyCode,
+    UinputAbsSetup,
+};
+use schemars::JsonSchema;
+use serde::Deserialize;
+use std::collections::BTreeSet;
+use std::ops::RangeInclusive;
+use std::time::Duration;
+
+/// USB ids of the Xbox 360 controller the virtual one presents itself as.
+const XBOX_360_ID: (u16, u16) = (0x045e, 0x028e);
+
+/// Raw value of a stick pushed all the way.
+const STICK_MAX: i32 = 32767;
+
+/// Raw value of a fully squeezed trigger.
+const TRIGGER_MAX: i32 = 255;
+
+/// Time a tapped button is held.
+const TAP_HOLD_MS: RangeInclusive<u64> = 60..=140;
+
+/// Time an axis takes to travel to its position or back, like a thumb pushing the stick.
+const RAMP_MS: RangeInclusive<u64> = 40..=110;
+
+/// Intermediate positions reported while an axis travels.
+const RAMP_STEPS: u32 = 8;
+
+/// Button of the virtual controller, named after the Xbox layout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum GamepadButton {
+    /// Bottom face button, cross on PlayStation pads.
+    #[serde(alias = "south")]
+    A,
+    /// Right face button, circle on PlayStation pads.
+    #[serde(alias = "east")]
+    B,
+    /// Left face button, square on PlayStation pads.
+    #[serde(alias = "west")]
+    X,
+    /// Top face button, triangle on PlayStation pads.
+    #[serde(alias = "north")]
+    Y,
+    #[serde(alias = "lb")]
+    LeftBumper,
+    #[serde(alias = "rb")]
+    RightBumper,
+    #[serde(alias = "select")]
+    Back,
+    Start,
+    #[serde(alias = "home")]
+    Guide,
+    /// Click of the left stick.
+    #[serde(alias = "l3")]
+    LeftStick,
+    /// Click of the right stick.
+    #[serde(alias = "r3")]
+    RightStick,
+    DpadUp,
+    DpadDown,
+    DpadLeft,
+    DpadRight,
+}
+
+impl GamepadButton {
+    /// Returns the event that presses or releases the button.
+    ///
+    /// The d-pad is a hat like on a real Xbox 360 pad, so its buttons move the hat axes.
+    fn event(self, pressed: bool) -> InputEvent {
+        let hat = |axis: AbsoluteAxisCode, direction: i32| {
+            InputEvent::new(
+                EventType::ABSOLUTE.0,
+                axis.0,
+                if pressed { direction } else { 0 },
+            )
+        };
+        let key = match self {
+            Self::DpadUp => return hat(AbsoluteAxisCode::ABS_HAT0Y, -1),
+            Self::DpadDown => return hat(AbsoluteAxisCode::ABS_HAT0Y, 1),
+            Self::DpadLeft => return hat(AbsoluteAxisCode::ABS_HAT0X, -1),
+            Self::DpadRight => return hat(AbsoluteAxisCode::ABS_HAT0X, 1),
+            Self::A => KeyCode::BTN_SOUTH,
+            Self::B => KeyCode::BTN_EAST,
+            Self::X => KeyCode::BTN_WEST,
+            Self::Y => KeyCode::BTN_NORTH,
+            Self::LeftBumper => KeyCode::BTN_TL,
+            Self::RightBumper => KeyCode::BTN_TR,
+            Self::Back => KeyCode::BTN_SELECT,
+            Self::Start => KeyCode::BTN_START,
+            Self::Guide => KeyCode::BTN_MODE,
+            Self::LeftStick => KeyCode::BTN_THUMBL,
+            Self::RightStick => KeyCode::BTN_THUMBR,
+        };
+        InputEvent::new(EventType::KEY.0, key.code(), i32::from(pressed))
+    }
+
+    /// Returns the buttons that are keys rather than d-pad directions.
+    fn keys() -> AttributeSet<KeyCode> {
+        let mut keys = AttributeSet::new();
+        for key in [
+            KeyCode::BTN_SOUTH,
+            KeyCode::BTN_EAST,
+            KeyCode::BTN_WEST,
+            KeyCode::BTN_NORTH,
+            KeyCode::BTN_TL,
+            KeyCode::BTN_TR,
+            KeyCode::BTN_SELECT,
+            KeyCode::BTN_START,
+            KeyCode::BTN_MODE,
+            KeyCode::BTN_THUMBL,
+            KeyCode::BTN_THUMBR,
+        ] {
+            keys.insert(key);
+        }
+        keys
+    }
+}
+
+impl std::fmt::Display for GamepadButton {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::A => "a",
+            Self::B => "b",
+            Self::X => "x",
+            Self::Y => "y",
+            Self::LeftBumper => "left_bumper",
+            Self::RightBumper => "right_bumper",
+            Self::Back => "back",
+            Self::Start => "start",
+            Self::Guide => "guide",
+            Self::LeftStick => "left_stick",
+            Self::RightStick => "right_stick",
+            Self::DpadUp => "dpad_up",
+            Self::DpadDown => "dpad_down",
+            Self::DpadLeft => "dpad_left",
+            Self::DpadRight => "dpad_right",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Stick axis or trigger of the virtual controller.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum GamepadAxis {
+    LeftX,
+    LeftY,
+    RightX,
+    RightY,
+    #[serde(alias = "lt")]
+    LeftTrigger,
+    #[serde(alias = "rt")]
+    RightTrigger,
+}
+
+impl GamepadAxis {
+    const ALL: [GamepadAxis; 6] = [
+        Self::LeftX,
+        Self::LeftY,
+        Self::RightX,
+        Self::RightY,
+        Self::LeftTrigger,
+        Self::RightTrigger,
+    ];
+
+    /// Returns the evdev axis the Xbox 360 driver reports this axis on.
+    fn code(self) -> AbsoluteAxisCode {
+        match self {
+            Self::LeftX => AbsoluteAxisCode::ABS_X,
+            Self::LeftY => AbsoluteAxisCode::ABS_Y,
+            Self::RightX => AbsoluteAxisCode::ABS_RX,
+            Self::RightY => AbsoluteAxisCode::ABS_RY,
+            Self::LeftTrigger => AbsoluteAxisCode::ABS_Z,
+            Self::RightTrigger => AbsoluteAxisCode::ABS_RZ,
+        }
+    }
+
+    /// Returns the positions a script may move the axis to.
+    pub fn range(self) -> RangeInclusive<f64> {
+        match self {
+            Self::LeftTrigger | Self::RightTrigger => 0.0..=1.0,
+            Self::LeftX | Self::LeftY | Self::RightX | Self::RightY => -1.0..=1.0,
+        }
+    }
+
+    /// Returns the raw value of a position and the axis' raw range.
+    fn raw(self, value: f64) -> (i32, AbsInfo) {
+        let max = match self {
+            Self::LeftTrigger | Self::RightTrigger => TRIGGER_MAX,
+            Self::LeftX | Self::LeftY | Self::RightX | Self::RightY => STICK_MAX,
+        };
+        let min = if *self.range().start() < 0.0 { -max } else { 0 };
+        let raw = (value * f64::from(max)).round() as i32;
+        (raw.clamp(min, max), AbsInfo::new(0, min, max, 0, 0, 0))
+    }
+}
+
+impl std::fmt::Display for GamepadAxis {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::LeftX => "left_x",
+            Self::LeftY => "left_y",
+            Self::RightX => "right_x",
+            Self::RightY => "right_y",
+            Self::LeftTrigger => "left_trigger",
+            Self::RightTrigger => "right_trigger",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Backend that adds a virtual controller to another backend, which keeps delivering the mouse
+/// and keyboard input.
+pub struct GamepadBackend {
+    inner: Box<dyn InputBackend>,
+    device: VirtualDevice,
+    /// Buttons held down, released by `release_all`.
+    pressed: BTreeSet<GamepadButton>,
+    /// Axes moved off their rest position, reset by `release_all`.
+    moved: BTreeSet<GamepadAxis>,
+}
+
+impl GamepadBackend {
+    /// Creates the virtual controller next to `inner`.
+    pub fn probe(inner: Box<dyn InputBackend>) -> Result<Self> {
+        let mut builder = VirtualDevice::builder()
+            .context(format!(
+                "Failed to open {} for the virtual controller, run 'colorbot doctor' for setup help",
+                UINPUT_PATH
+            ))?
+            .name("colorbot virtual controller")
+            .input_id(InputId::new(
+                BusType::BUS_USB,
+                XBOX_360_ID.0,
+                XBOX_360_ID.1,
+                0x110,
+            ))
+            .with_keys(&GamepadButton::keys())?;
+        for axis in GamepadAxis::ALL {
+            let (_, info) = axis.raw(0.0);
+            builder = builder.with_absolute_axis(&UinputAbsSetup::new(axis.code(), info))?;
+        }
+        for hat in [AbsoluteAxisCode::ABS_HAT0X, AbsoluteAxisCode::ABS_HAT0Y] {
+            builder = builder
+                .with_absolute_axis(&UinputAbsSetup::new(hat, AbsInfo::new(0, -1, 1, 0, 0, 0)))?;
+        }
+        let device = builder
+            .build()
+            .context("Failed to create the uinput virtual controller")?;
+        std::thread::sleep(DEVICE_SETTLE_TIME);
+
+        Ok(Self {
+            inner,
+            device,
+            pressed: BTreeSet::new(),
+            moved: BTreeSet::new(),
+        })
+    }
+
+    fn emit(&mut self, event: InputEvent) -> Result<()> {
+        self.device
+            .emit(&[event])
+            .context("Failed to write to the uinput virtual controller")
+    }
+}
+
+impl InputBackend for GamepadBackend {
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            gamepad: true,
+            ..self.inner.capabilities()
+        }
+    }
+
+    fn move_cursor(&mut self, point: Point) -> Result<()> {
+        self.inner.move_cursor(point)
+    }
+
+    fn click(&mut self, button: MouseButton) -> Result<()> {
+        self.inner.click(button)
+    }
+
+    fn set_button(&mut self, button: MouseButton, pressed: bool) -> Result<()> {
+        self.inner.set_button(button, pressed)
+    }
+
+    fn key(&mut self, keycode: &str) -> Result<()> {
+        self.inner.key(keycode)
+    }
+
+    fn hold_key(&mut self, keycode: &str, duration: Duration) -> Result<()> {
+        self.inner.hold_key(keycode, duration)
+    }
+
+    fn text(&mut self, text: &str) -> Result<()> {
+        self.inner.text(text)
+    }
+
+    fn release_all(&mut self) -> Result<()> {
+        for button in std::mem::take(&mut self.pressed) {
+            self.emit(button.event(false))?;
+        }
+        for axis in std::mem::take(&mut self.moved) {
+            self.gamepad_axis(axis, 0.0)?;
+        }
+        self.inner.release_all()
+    }
+
+    fn gamepad_button(&mut self, button: GamepadButton, pressed: bool) -> Result<()> {
+        self.emit(button.event(pressed))?;
+        if pressed {
+            self.pressed.insert(button);
+        } else {
+            self.pressed.remove(&button);
+        }
+        Ok(())
+    }
+
+    fn gamepad_axis(&mut self, axis: GamepadAxis, value: f64) -> Result<()> {
+        let (raw, _) = axis.raw(value);
+        self.emit(InputEvent::new(EventType::ABSOLUTE.0, axis.code().0, raw))?;
+        if raw == 0 {
+            self.moved.remove(&axis);
+        } else {
+            self.moved.insert(axis);
+        }
+        Ok(())
+    }
+}
+
+/// Presses a button and releases it after `hold`, or after a quick tap when not given.
+pub fn press_button(
+    input: &mut dyn InputBackend,
+    button: GamepadButton,
+    hold: Option<&ScriptDelay>,
+) -> Result<()> {
+    let hold = match hold {
+        Some(hold) => hold.sample()?,
+        None => Duration::from_millis(rand::random_range(TAP_HOLD_MS)),
+    };
+
+    focus::wait_for_target()?;
+    input.gamepad_button(button, true)?;
+    std::thread::sleep(hold);
+    input.gamepad_button(button, false)
+}
+
+/// Moves an axis to `value`, holds it there for `hold` and lets it spring back to rest.
+///
+/// The axis travels over a few intermediate positions instead of jumping, and stops up to a few
+/// percent short of the position, as a thumb would.
+pub fn tilt_axis(
+    input: &mut dyn InputBackend,
+    axis: GamepadAxis,
+    value: f64,
+    hold: Duration,
+) -> Result<()> {
+    let value = value * rand::random_range(0.96..=1.0);
+
+    focus::wait_for_target()?;
+    ramp(input, axis, 0.0, value)?;
+    std::thread::sleep(hold);
+    ramp(input, axis, value, 0.0)
+}
+
+/// Moves an axis from one position to another with smoothstep easing.
+fn ramp(input: &mut dyn InputBackend, axis: GamepadAxis, from: f64, to: f64) -> Result<()> {
+    let step_time = Duration::from_millis(rand::random_range(RAMP_MS)) / RAMP_STEPS;
+    for step in 1..=RAMP_STEPS {
+        let t = f64::from(step) / f64::from(RAMP_STEPS);
+        let eased = t * t * (3.0 - 2.0 * t);
+        input.gamepad_axis(axis, from + (to - from) * eased)?;
+        if step < RAMP_STEPS {
+            timing::hybrid_sleep(step_time);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn positions_map_onto_raw_axis_ranges() {
+        assert_eq!(GamepadAxis::LeftX.raw(-1.0).0, -STICK_MAX);
+        assert_eq!(GamepadAxis::RightY.raw(0.5).0, 16384);
+        assert_eq!(GamepadAxis::LeftTrigger.raw(1.0).0, TRIGGER_MAX);
+        // Triggers have no negative half
+        assert_eq!(GamepadAxis::RightTrigger.raw(-0.5).0, 0);
+        assert_eq!(GamepadAxis::LeftY.raw(0.0).1.minimum(), -STICK_MAX);
+    }
+}
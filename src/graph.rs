@@ -0,0 +1,269 @@
+//! Picture of a script's control flow for the `graph` subcommand.
+//!
+//! The graph is the flow the requirement check follows: every event with the condition that
+//! triggers it, the fall through, repeat block loops and branches between events, and where a
+//! pass over the script can begin. Transactions and repeat blocks are drawn as groups around
+//! their events, and each transaction's rollback routine hangs off it as the recovery path a
+//! failure takes before the next pass starts over. With `--dot` the graph is printed in Graphviz
+//! DOT, e.g. `colorbot graph script.json --dot | dot -Tsvg -o flow.svg`.
+use crate::flow::{EdgeKind, Flow};
+use crate::output::{self, Render};
+use crate::script::{self, Repetition, Script};
+
+use anyhow::Result;
+use serde::Serialize;
+use std::io::Write;
+use std::path::Path;
+
+/// Event of the main script.
+#[derive(Serialize)]
+struct Node {
+    index: usize,
+    id: String,
+    #[serde(rename = "type")]
+    kind: &'static str,
+    /// Condition that must hold for the event to run.
+    when: Option<String>,
+}
+
+/// Event that can run right after another one.
+#[derive(Serialize)]
+struct Link {
+    from: usize,
+    to: usize,
+    kind: EdgeKind,
+}
+
+/// Event a pass over the script can begin at.
+#[derive(Serialize)]
+struct Entry {
+    name: String,
+    event: usize,
+}
+
+/// Events grouped by a transaction or repeat block.
+#[derive(Serialize)]
+struct Group {
+    label: String,
+    /// Indices of the grouped events.
+    events: Vec<usize>,
+    /// Ids of the events a transaction runs when one of its events fails.
+    rollback: Vec<String>,
+}
+
+/// Control flow of a script.
+#[derive(Serialize)]
+struct Graph {
+    entries: Vec<Entry>,
+    events: Vec<Node>,
+    edges: Vec<Link>,
+    groups: Vec<Group>,
+}
+
+impl Graph {
+    fn new(script: &Script) -> Self {
+        let flow = Flow::new(script);
+        let events = script
+            .events
+            .iter()
+            .enumerate()
+            .map(|(index, event)| Node {
+                index,
+                id: event.id.clone(),
+                kind: event.event_type.name(),
+                when: event.when.as_ref().map(ToString::to_string),
+            })
+            .collect();
+        let edges = flow
+            .successors
+            .iter()
+            .enumerate()
+            .flat_map(|(from, edges)| {
+                edges.iter().map(move |edge| Link {
+                    from,
+                    to: edge.to,
+                    kind: edge.kind,
+                })
+            })
+            .collect();
+        let entries = flow
+            .entries
+            .into_iter()
+            .filter(|(event, _)| *event < script.events.len())
+            .map(|(event, name)| Entry { name, event })
+            .collect();
+
+        let transactions = script.transactions.iter().map(|transaction| Group {
+            label: format!("transaction {}", transaction.name),
+            events: transaction.events.clone().collect(),
+            rollback: transaction
+                .on_fail
+                .iter()
+                .map(|event| event.id.clone())
+                .collect(),
+        });
+        let repeats = script.repeats.iter().map(|repeat| Group {
+            label: match &repeat.repetition {
+                Repetition::Times(times) => format!("repeat {} times", times),
+                Repetition::Until {
+                    max_times: Some(max),
+                    ..
+                } => format!("repeat until signature, at most {} times", max),
+                Repetition::Until { .. } => "repeat until signature".to_string(),
+            },
+            events: repeat.events.clone().collect(),
+            rollback: Vec::new(),
+        });
+        Self {
+            entries,
+            events,
+            edges,
+            groups: transactions.chain(repeats).collect(),
+        }
+    }
+
+    /// Writes the graph in Graphviz DOT.
+    fn write_dot(&self, out: &mut dyn Write) -> std::io::Result<()> {
+        writeln!(out, "digraph script {{")?;
+        writeln!(out, "  compound=true;")?;
+        writeln!(out, "  node [shape=box, style=rounded];")?;
+        for (i, entry) in self.entries.iter().enumerate() {
+            writeln!(
+                out,
+                "  entry{} [label={}, shape=oval, style=filled];",
+                i,
+                quote(&entry.name)
+            )?;
+            writeln!(out, "  entry{} -> e{};", i, entry.event)?;
+        }
+        for (i, group) in self.groups.iter().enumerate() {
+            writeln!(out, "  subgraph cluster{} {{", i)?;
+            writeln!(out, "    label={};", quote(&group.label))?;
+            for index in &group.events {
+                writeln!(out, "    e{};", index)?;
+            }
+            writeln!(out, "  }}")?;
+        }
+        for node in &self.events {
+            let mut label = format!("{}\n{}", node.id, node.kind);
+            if let Some(when) = &node.when {
+                label.push_str(&format!("\nwhen {}", when));
+            }
+            writeln!(out, "  e{} [label={}];", node.index, quote(&label))?;
+        }
+        for edge in &self.edges {
+            let style = match edge.kind {
+                EdgeKind::Next => "",
+                EdgeKind::Repeat => " [label=\"repeat\", style=dashed]",
+                EdgeKind::Found => " [label=\"found\", color=darkgreen]",
+                EdgeKind::NotFound => " [label=\"not found\", color=red]",
+            };
+            writeln!(out, "  e{} -> e{}{};", edge.from, edge.to, style)?;
+        }
+        if let Some(last) = self.events.last() {
+            writeln!(
+                out,
+                "  e{} -> entry0 [label=\"next pass\", style=dotted];",
+                last.index
+            )?;
+        }
+        for (i, group) in self.groups.iter().enumerate() {
+            // Recovery path: any failure in the transaction, its rollback, then a new pass
+            let Some(first) = group.events.first() else {
+                continue;
+            };
+            if group.rollback.is_empty() {
+                continue;
+            }
+            for (j, id) in group.rollback.iter().enumerate() {
+                writeln!(
+                    out,
+                    "  r{}_{} [label={}, style=\"rounded,dashed\"];",
+                    i,
+                    j,
+                    quote(id)
+                )?;
+                if j > 0 {
+                    writeln!(out, "  r{}_{} -> r{}_{} [style=dashed];", i, j - 1, i, j)?;
+                }
+            }
+            writeln!(
+                out,
+                "  e{} -> r{}_0 [label=\"on fail\", style=dashed, color=red, ltail=cluster{}];",
+                first, i, i
+            )?;
+            writeln!(
+                out,
+                "  r{}_{} -> entry0 [label=\"next pass\", style=dashed];",
+                i,
+                group.rollback.len() - 1
+            )?;
+        }
+        writeln!(out, "}}")
+    }
+}
+
+impl Render for Graph {
+    fn render(&self, out: &mut dyn Write) -> std::io::Result<()> {
+        for entry in &self.entries {
+            writeln!(out, "{} -> '{}'", entry.name, self.events[entry.event].id)?;
+        }
+        for node in &self.events {
+            let when = node
+                .when
+                .as_ref()
+                .map(|when| format!(" when {}", when))
+                .unwrap_or_default();
+            writeln!(out, "'{}' ({}){}", node.id, node.kind, when)?;
+            for edge in self.edges.iter().filter(|edge| edge.from == node.index) {
+                let kind = match edge.kind {
+                    EdgeKind::Next => "next",
+                    EdgeKind::Repeat => "repeat",
+                    EdgeKind::Found => "found",
+                    EdgeKind::NotFound => "not found",
+                };
+                writeln!(out, "  {:<9} -> '{}'", kind, self.events[edge.to].id)?;
+            }
+        }
+        for group in &self.groups {
+            let ids: Vec<String> = group
+                .events
+                .iter()
+                .map(|&index| format!("'{}'", self.events[index].id))
+                .collect();
+            writeln!(out, "{}: {}", group.label, ids.join(", "))?;
+            if !group.rollback.is_empty() {
+                let ids: Vec<String> = group
+                    .rollback
+                    .iter()
+                    .map(|id| format!("'{}'", id))
+                    .collect();
+                writeln!(out, "  on fail: {}, then a new pass", ids.join(", "))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Quotes a string for DOT.
+fn quote(text: &str) -> String {
+    format!(
+        "\"{}\"",
+        text.replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace('\n', "\\n")
+    )
+}
+
+/// Prints the control flow of a script, in Graphviz DOT with `dot`.
+pub fn run(path: &Path, dot: bool) -> Result<()> {
+    let script = script::load(path)?;
+    let graph = Graph::new(&script);
+    if dot {
+        let mut out = std::io::stdout().lock();
+        graph.write_dot(&mut out)?;
+        out.flush()?;
+        return Ok(());
+    }
+    output::print(&graph)
+}
@@ -0,0 +1,82 @@
+//! Deceleration and settling at the end of cursor paths.
+//!
+//! A hand doesn't stop dead on a target: it slows down sharply over the last part of a movement
+//! and makes a few tiny adjustments within the target before coming to rest. This stage stretches
+//! the delays over the end of a path and appends a short wander around its final point.
+use crate::windmouse::Point;
+
+use anyhow::{ensure, Result};
+
+/// Strength of the end-of-path homing behavior.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Homing {
+    /// Scales both the slowdown and the wander, 0 disables homing.
+    pub intensity: f64,
+}
+
+impl Homing {
+    /// Fraction of a path's points that decelerate.
+    const TAIL_FRACTION: std::ops::Range<f64> = 0.10..0.15;
+    /// Delay factor of the final point over the normal step at intensity 1.
+    const MAX_SLOWDOWN: f64 = 4.0;
+    /// Radius in pixels around the final point the cursor wanders in at intensity 1.
+    const WANDER_RADIUS_PX: f64 = 2.0;
+    /// Number of wander points at intensity 1.
+    const WANDER_POINTS: std::ops::RangeInclusive<u32> = 2..=5;
+
+    /// Checks that the intensity is usable.
+    pub fn validate(&self) -> Result<()> {
+        ensure!(
+            self.intensity >= 0.0,
+            "Homing intensity must not be negative, got {}",
+            self.intensity
+        );
+        Ok(())
+    }
+
+    /// Returns every point of `path` with the factor its step delay is stretched by, followed by
+    /// the wander around the final point.
+    ///
+    /// The returned path still ends on the final point of `path`.
+    pub fn apply(&self, path: &[Point]) -> Vec<(Point, f64)> {
+        let Some(&dest) = path.last() else {
+            return Vec::new();
+        };
+        if self.intensity == 0.0 {
+            return path.iter().map(|point| (*point, 1.0)).collect();
+        }
+
+        let n = path.len();
+        let tail = ((n as f64 * rand::random_range(Homing::TAIL_FRACTION)).ceil() as usize).max(1);
+        let slowdown = (Homing::MAX_SLOWDOWN - 1.0) * self.intensity;
+        let mut homed: Vec<(Point, f64)> = path
+            .iter()
+            .enumerate()
+            .map(|(i, point)| {
+                // Quadratic in the progress through the tail, so the slowdown is sharp at the end
+                let progress = (i + tail + 1).saturating_sub(n) as f64 / tail as f64;
+                (*point, 1.0 + slowdown * progress * progress)
+            })
+            .collect();
+
+        let radius = Homing::WANDER_RADIUS_PX * self.intensity;
+        let wander_points = f64::from(rand::random_range(Homing::WANDER_POINTS));
+        let wander = (wander_points * self.intensity.min(1.0)).round() as u32;
+        let mut last = dest;
+        for _ in 0..wander {
+            let point = Point::new(
+                dest.x + rand::random_range(-radius..=radius).round() as i32,
+                dest.y + rand::random_range(-radius..=radius).round() as i32,
+            );
+            if point != last {
+                homed.push((point, 1.0 + slowdown));
+                last = point;
+            }
+        }
+        if last != dest {
+            homed.push((dest, 1.0 + slowdown));
+        }
+
+        homed
+    }
+}
@@ -0,0 +1,320 @@
+//! Hotkeys that pause or stop a run from the keyboard.
+//!
+//! A background thread polls the keyboard for the pause and panic bindings. Pausing takes effect
+//! between events and holds the run until the pause key is pressed again, panicking stops the run
+//! before its next event. The bindings are read from a JSON file so they can be moved off keys
+//! the game uses, and they're checked against the keys the script sends, since the bot pressing a
+//! hotkey itself would pause or stop its own run.
+use crate::event::{BotEvent, BotEventType};
+use crate::indicator;
+use crate::record;
+use crate::secrets::ScriptString;
+
+use anyhow::{bail, ensure, Context, Result};
+use device_query::{DeviceQuery, DeviceState};
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+/// Interval between polls of the keyboard.
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+static PAUSED: AtomicBool = AtomicBool::new(false);
+static PANICKED: AtomicBool = AtomicBool::new(false);
+/// Whether the program driving the bot asked the run to end, which unlike a panic isn't an error.
+static STOPPING: AtomicBool = AtomicBool::new(false);
+/// Whether the hotkey thread is running, it's shared by every run in the process.
+static LISTENING: AtomicBool = AtomicBool::new(false);
+
+/// Key bindings of the hotkeys, each an xdotool key name or a chord such as `ctrl+alt+p`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Hotkeys {
+    /// Pauses the run between events, or resumes it.
+    pub pause: String,
+    /// Stops the run before its next event.
+    pub panic: String,
+}
+
+impl Default for Hotkeys {
+    fn default() -> Self {
+        Self {
+            pause: "ctrl+alt+p".to_string(),
+            panic: "ctrl+alt+q".to_string(),
+        }
+    }
+}
+
+/// Returns the canonical name of a key as produced by [`record::key_name`], so that aliases such
+/// as `control` and `Escape` compare equal to what the keyboard reports.
+fn normalize(key: &str) -> String {
+    match key.to_ascii_lowercase().as_str() {
+        "control" | "control_l" | "control_r" => "ctrl".to_string(),
+        "shift_l" | "shift_r" => "shift".to_string(),
+        "alt_l" | "alt_r" => "alt".to_string(),
+        "super_l" | "super_r" | "meta" => "super".to_string(),
+        "esc" => "escape".to_string(),
+        "enter" => "return".to_string(),
+        "page_up" => "prior".to_string(),
+        "page_down" => "next".to_string(),
+        key => key.to_string(),
+    }
+}
+
+/// Splits a binding or keycode into its normalized keys.
+fn chord(keys: &str) -> BTreeSet<String> {
+    keys.split('+').map(normalize).collect()
+}
+
+impl Hotkeys {
+    /// Returns the default path of the bindings file.
+    ///
+    /// Follows the XDG base directory spec, falling back to the current directory when no home
+    /// directory is known.
+    pub fn default_path() -> PathBuf {
+        if let Some(config) = std::env::var_os("XDG_CONFIG_HOME") {
+            return PathBuf::from(config).join("colorbot").join("hotkeys.json");
+        }
+        match std::env::var_os("HOME") {
+            Some(home) => PathBuf::from(home)
+                .join(".config")
+                .join("colorbot")
+                .join("hotkeys.json"),
+            None => PathBuf::from("hotkeys.json"),
+        }
+    }
+
+    /// Loads the bindings from `path`, or from the default file when it exists.
+    ///
+    /// Without either, the default bindings are used. Returns the file they were read from.
+    pub fn load(path: Option<&Path>) -> Result<(Self, Option<PathBuf>)> {
+        let path = match path {
+            Some(path) => path.to_path_buf(),
+            None => {
+                let path = Self::default_path();
+                if !path.exists() {
+                    return Ok((Self::default(), None));
+                }
+                path
+            }
+        };
+        let content = std::fs::read_to_string(&path)
+            .context(format!("Failed to read hotkeys file {}", path.display()))?;
+        let hotkeys: Self = serde_json::from_str(&content)
+            .context(format!("Failed to parse hotkeys file {}", path.display()))?;
+        hotkeys
+            .validate()
+            .context(format!("Invalid hotkeys file {}", path.display()))?;
+        Ok((hotkeys, Some(path)))
+    }
+
+    /// Returns every binding with its name.
+    pub fn bindings(&self) -> [(&'static str, &str); 2] {
+        [("pause", &self.pause), ("panic", &self.panic)]
+    }
+
+    /// Checks that every binding names keys and that no two bindings overlap.
+    pub fn validate(&self) -> Result<()> {
+        let bindings = self.bindings();
+        for (i, (name, keys)) in bindings.iter().enumerate() {
+            ensure!(
+                keys.split('+').all(|key| !key.trim().is_empty()),
+                "The {} hotkey '{}' has an empty key",
+                name,
+                keys
+            );
+            for (other, other_keys) in &bindings[..i] {
+                let (a, b) = (chord(keys), chord(other_keys));
+                ensure!(
+                    !a.is_subset(&b) && !b.is_subset(&a),
+                    "The {} hotkey '{}' conflicts with the {} hotkey '{}'",
+                    name,
+                    keys,
+                    other,
+                    other_keys
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Fails when one of the events sends keys that would trigger a hotkey.
+    pub fn check_conflicts<'a>(
+        &self,
+        events: impl IntoIterator<Item = &'a BotEvent>,
+    ) -> Result<()> {
+        for event in events {
+            let BotEventType::KeyPress {
+                keycode: ScriptString::Plain(keycode),
+            } = &event.event_type
+            else {
+                continue;
+            };
+            let sent = chord(keycode);
+            for (name, keys) in self.bindings() {
+                if chord(keys).is_subset(&sent) {
+                    bail!(
+                        "Event '{}' presses '{}', which triggers the {} hotkey '{}', bind it to \
+                         other keys in the hotkeys file (see `colorbot keys`)",
+                        event.id,
+                        keycode,
+                        name,
+                        keys
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Starts the thread that watches the keyboard for the hotkeys, unless it's already running.
+    pub fn listen(self) -> Result<()> {
+        if LISTENING.swap(true, Ordering::Relaxed) {
+            return Ok(());
+        }
+        std::thread::Builder::new()
+            .name("hotkeys".to_string())
+            .spawn(move || self.watch())
+            .context("Failed to start the hotkey thread")?;
+        Ok(())
+    }
+
+    fn watch(&self) {
+        let Some(device_state) = DeviceState::checked_new() else {
+            warn!("The keyboard is read through the X server, the hotkeys don't work without one");
+            return;
+        };
+        let (pause, panic) = (chord(&self.pause), chord(&self.panic));
+        let mut was_pressed = BTreeSet::new();
+        loop {
+            let pressed: BTreeSet<String> = device_state
+                .get_keys()
+                .iter()
+                .filter_map(record::key_name)
+                .map(|(name, _)| normalize(&name))
+                .collect();
+            // Only react when the chord completes, not for as long as it's held
+            let triggered =
+                |keys: &BTreeSet<String>| keys.is_subset(&pressed) && !keys.is_subset(&was_pressed);
+            if triggered(&panic) {
+                warn!("Panic hotkey pressed, stopping before the next event");
+                PANICKED.store(true, Ordering::Relaxed);
+            } else if triggered(&pause) {
+                let paused = !PAUSED.fetch_xor(true, Ordering::Relaxed);
+                warn!(
+                    "{}",
+                    if paused {
+                        "Pause hotkey pressed, pausing before the next event"
+                    } else {
+                        "Pause hotkey pressed, resuming"
+                    }
+                );
+            }
+            was_pressed = pressed;
+            std::thread::sleep(POLL_INTERVAL);
+        }
+    }
+}
+
+/// Clears a pause or panic left over from a previous run in the same process.
+pub fn reset() {
+    PAUSED.store(false, Ordering::Relaxed);
+    PANICKED.store(false, Ordering::Relaxed);
+}
+
+/// Pauses the run before its next event or resumes it, like pressing the pause hotkey.
+pub fn set_paused(paused: bool) {
+    PAUSED.store(paused, Ordering::Relaxed);
+}
+
+/// Asks the run to end before its next event, or withdraws the request. The run then ends like
+/// its runtime ran out.
+///
+/// Requests aren't cleared by [`reset`], so a stop asked for while a run is still starting
+/// isn't lost.
+pub fn set_stopping(stopping: bool) {
+    STOPPING.store(stopping, Ordering::Relaxed);
+}
+
+/// Whether the run was asked to end.
+pub fn stopping() -> bool {
+    STOPPING.load(Ordering::Relaxed)
+}
+
+/// Blocks while the run is paused and fails once the panic hotkey was pressed.
+///
+/// A run asked to end doesn't stay paused, callers check [`stopping`] afterwards.
+pub fn checkpoint() -> Result<()> {
+    let mut logged = false;
+    loop {
+        ensure!(
+            !PANICKED.load(Ordering::Relaxed),
+            "Stopped by the panic hotkey"
+        );
+        if !PAUSED.load(Ordering::Relaxed) || stopping() {
+            if logged {
+                indicator::set_paused(false);
+            }
+            return Ok(());
+        }
+        if !logged {
+            debug!("Paused by the hotkey");
+            indicator::set_paused(true);
+            logged = true;
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// Imports bindings from or exports them to a file when given, and prints the active bindings
+/// when `list` is set or there's nothing to import or export.
+pub fn run(
+    path: Option<&Path>,
+    list: bool,
+    export: Option<&Path>,
+    import: Option<&Path>,
+) -> Result<()> {
+    if let Some(source) = import {
+        let (hotkeys, _) = Hotkeys::load(Some(source))?;
+        let target = path.map_or_else(Hotkeys::default_path, Path::to_path_buf);
+        write(&hotkeys, &target)?;
+        eprintln!(
+            "Imported hotkeys from {} into {}",
+            source.display(),
+            target.display()
+        );
+    }
+
+    let (hotkeys, source) = Hotkeys::load(path)?;
+    if list || (export.is_none() && import.is_none()) {
+        match &source {
+            Some(source) => println!("Hotkeys from {}", source.display()),
+            None => println!(
+                "Default hotkeys, override them in {}",
+                Hotkeys::default_path().display()
+            ),
+        }
+        for (name, keys) in hotkeys.bindings() {
+            println!("  {:<8} {}", name, keys);
+        }
+    }
+
+    if let Some(target) = export {
+        write(&hotkeys, target)?;
+        eprintln!("Exported hotkeys to {}", target.display());
+    }
+    Ok(())
+}
+
+/// Writes the bindings as pretty-printed JSON, creating the parent directory if needed.
+fn write(hotkeys: &Hotkeys, path: &Path) -> Result<()> {
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        std::fs::create_dir_all(dir)
+            .context(format!("Failed to create directory {}", dir.display()))?;
+    }
+    std::fs::write(path, serde_json::to_string_pretty(hotkeys)? + "\n")
+        .context(format!("Failed to write hotkeys file {}", path.display()))
+}
@@ -0,0 +1,179 @@
+//! Low-intensity idle scripts run during breaks.
+//!
+//! Instead of going completely still during a break, the bot can loosely imitate an idle player:
+//! drifting the mouse, hovering over interface elements and rotating the camera. Idle scripts are
+//! JSON arrays of [`IdleAction`]s executed by their own small interpreter, separate from the main
+//! event loop, which repeats the actions until the break ends.
+use crate::backend::InputBackend;
+use crate::controls::{self, MouseSettings};
+use crate::focus;
+use crate::vision::Region;
+use crate::windmouse::Point;
+
+use anyhow::{ensure, Context, Result};
+use log::debug;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// A single idle action.
+#[derive(Debug, Deserialize, JsonSchema)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum IdleAction {
+    /// Drifts the mouse to a random point within `max_distance` pixels of the cursor.
+    Wander {
+        #[serde(default = "default_wander_distance")]
+        #[schemars(range(min = 1))]
+        max_distance: u32,
+    },
+    /// Moves the mouse into the region and lingers there for a random time in ms.
+    Hover { region: Region, linger: [u64; 2] },
+    /// Holds the left or right arrow key for a random time in ms to turn the camera.
+    RotateCamera { hold: [u64; 2] },
+    /// Does nothing for a random time in ms.
+    Pause { duration: [u64; 2] },
+}
+
+fn default_wander_distance() -> u32 {
+    150
+}
+
+/// Checks that an inclusive `[min, max]` range isn't reversed.
+fn validate_range(range: &[u64; 2]) -> Result<()> {
+    ensure!(
+        range[0] <= range[1],
+        "Duration range [{}, {}] is reversed",
+        range[0],
+        range[1]
+    );
+    Ok(())
+}
+
+/// Samples a random duration from an inclusive `[min, max]` range in ms.
+fn random_duration(range: [u64; 2]) -> Duration {
+    Duration::from_millis(rand::random_range(range[0]..=range[1]))
+}
+
+/// Sleeps for `duration`, cut short at the deadline.
+fn sleep_until_deadline(duration: Duration, deadline: Instant) {
+    let remaining = deadline.saturating_duration_since(Instant::now());
+    std::thread::sleep(duration.min(remaining));
+}
+
+impl IdleAction {
+    /// Checks that the action's ranges are usable.
+    fn validate(&self) -> Result<()> {
+        match self {
+            IdleAction::Wander { max_distance } => {
+                ensure!(*max_distance > 0, "wander max_distance must be positive")
+            }
+            IdleAction::Hover { region, linger } => {
+                ensure!(
+                    region.width > 0 && region.height > 0,
+                    "hover region must not be empty"
+                );
+                validate_range(linger)?;
+            }
+            IdleAction::RotateCamera { hold } => validate_range(hold)?,
+            IdleAction::Pause { duration } => validate_range(duration)?,
+        }
+        Ok(())
+    }
+
+    /// Performs the action, never running past the deadline by more than one mouse move.
+    fn run(
+        &self,
+        input: &mut dyn InputBackend,
+        mouse: &MouseSettings,
+        deadline: Instant,
+    ) -> Result<()> {
+        match self {
+            IdleAction::Wander { max_distance } => {
+                let reach = i32::try_from(*max_distance)?;
+                let cursor = controls::get_mouse_pos();
+                let target = Point::new(
+                    (cursor.x + rand::random_range(-reach..=reach)).max(0),
+                    (cursor.y + rand::random_range(-reach..=reach)).max(0),
+                );
+                controls::move_mouse(input, target, mouse)?;
+            }
+            IdleAction::Hover { region, linger } => {
+                let target = Point::new(
+                    region.x + i32::try_from(rand::random_range(0..region.width))?,
+                    region.y + i32::try_from(rand::random_range(0..region.height))?,
+                );
+                controls::move_mouse(input, target, mouse)?;
+                sleep_until_deadline(random_duration(*linger), deadline);
+            }
+            IdleAction::RotateCamera { hold } => {
+                let key = if rand::random() { "Left" } else { "Right" };
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                focus::wait_for_target()?;
+                input.hold_key(key, random_duration(*hold).min(remaining))?;
+            }
+            IdleAction::Pause { duration } => {
+                sleep_until_deadline(random_duration(*duration), deadline);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A named sequence of idle actions.
+#[derive(Debug)]
+pub struct IdleScript {
+    name: String,
+    actions: Vec<IdleAction>,
+}
+
+impl IdleScript {
+    /// Reads and validates an idle script from a JSON file.
+    pub fn load(path: &Path) -> Result<Self> {
+        let file =
+            File::open(path).context(format!("Failed to open idle script {}", path.display()))?;
+        let actions: Vec<IdleAction> = serde_json::from_reader(BufReader::new(file))
+            .context(format!("Failed to parse idle script {}", path.display()))?;
+        ensure!(
+            !actions.is_empty(),
+            "Idle script {} is empty",
+            path.display()
+        );
+        for action in &actions {
+            action
+                .validate()
+                .context(format!("Invalid action in idle script {}", path.display()))?;
+        }
+
+        Ok(Self {
+            name: path.display().to_string(),
+            actions,
+        })
+    }
+
+    /// Returns the script's name for logging.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Repeats the script's actions until the deadline passes.
+    pub fn run_until(
+        &self,
+        input: &mut dyn InputBackend,
+        mouse: &MouseSettings,
+        deadline: Instant,
+    ) -> Result<()> {
+        while Instant::now() < deadline {
+            for action in &self.actions {
+                if Instant::now() >= deadline {
+                    break;
+                }
+                debug!("Idle action {:?}", action);
+                action.run(input, mouse, deadline)?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,162 @@
+//! On-screen indicator of whether the bot is controlling input.
+//!
+//! Viewers of a stream or recording can't tell the bot's input from the user's. During a live
+//! run a small square sits in a corner of the screen, green while the bot runs events and amber
+//! while it's paused by the hotkey or on a break without an idle script. The square lets input
+//! through to what's under it, and color scans skip it, so it never becomes a target itself.
+use crate::vision::Region;
+
+use anyhow::{ensure, Context, Result};
+use clap::ValueEnum;
+use log::debug;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::thread::JoinHandle;
+use std::time::Duration;
+use x11rb::connection::{Connection, RequestConnection};
+use x11rb::protocol::shape::{self, ConnectionExt as _};
+use x11rb::protocol::xproto::{
+    ChangeWindowAttributesAux, ClipOrdering, ConfigureWindowAux, ConnectionExt, CreateWindowAux,
+    StackMode, WindowClass,
+};
+
+/// Side of the indicator square in pixels.
+const SIZE_PX: u16 = 14;
+/// Interval between checks of the run's state.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// Color of the square while the bot controls input.
+const ACTIVE_COLOR: u32 = 0x0022_cc44;
+/// Color of the square while the bot is paused.
+const PAUSED_COLOR: u32 = 0x00ff_b000;
+
+/// Corner of the screen the indicator is shown in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Corner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+static PAUSED: AtomicBool = AtomicBool::new(false);
+static STOPPING: AtomicBool = AtomicBool::new(false);
+/// Screen area covered by the indicator while it's shown.
+static REGION: Mutex<Option<Region>> = Mutex::new(None);
+static THREAD: Mutex<Option<JoinHandle<()>>> = Mutex::new(None);
+
+/// Shows the indicator in `corner` until [`stop`] is called.
+pub fn start(corner: Corner) -> Result<()> {
+    stop();
+    let (conn, screen_num) = x11rb::connect(None).context("Failed to connect to the X server")?;
+    // Without the shape extension the square would swallow clicks meant for the game
+    ensure!(
+        conn.extension_information(shape::X11_EXTENSION_NAME)?
+            .is_some(),
+        "The X server doesn't support the shape extension"
+    );
+    let screen = &conn.setup().roots[screen_num];
+    let (width, height) = (screen.width_in_pixels, screen.height_in_pixels);
+    let x = match corner {
+        Corner::TopLeft | Corner::BottomLeft => 0,
+        Corner::TopRight | Corner::BottomRight => width.saturating_sub(SIZE_PX),
+    };
+    let y = match corner {
+        Corner::TopLeft | Corner::TopRight => 0,
+        Corner::BottomLeft | Corner::BottomRight => height.saturating_sub(SIZE_PX),
+    };
+
+    let window = conn.generate_id()?;
+    conn.create_window(
+        screen.root_depth,
+        window,
+        screen.root,
+        i16::try_from(x)?,
+        i16::try_from(y)?,
+        SIZE_PX,
+        SIZE_PX,
+        0,
+        WindowClass::INPUT_OUTPUT,
+        screen.root_visual,
+        &CreateWindowAux::new()
+            .override_redirect(1)
+            .background_pixel(ACTIVE_COLOR),
+    )?;
+    // An empty input region passes every click through to the window below
+    conn.shape_rectangles(
+        shape::SO::SET,
+        shape::SK::INPUT,
+        ClipOrdering::UNSORTED,
+        window,
+        0,
+        0,
+        &[],
+    )?;
+    conn.map_window(window)?;
+    conn.flush()?;
+
+    PAUSED.store(false, Ordering::Relaxed);
+    STOPPING.store(false, Ordering::Relaxed);
+    if let Ok(mut region) = REGION.lock() {
+        *region = Some(Region {
+            x: i32::from(x),
+            y: i32::from(y),
+            width: u32::from(SIZE_PX),
+            height: u32::from(SIZE_PX),
+        });
+    }
+    let thread = std::thread::Builder::new()
+        .name("indicator".to_string())
+        .spawn(move || {
+            // The window starts out in the active color
+            let mut painted = false;
+            while !STOPPING.load(Ordering::Relaxed) {
+                let paused = PAUSED.load(Ordering::Relaxed);
+                if paused != painted {
+                    painted = paused;
+                    let color = if paused { PAUSED_COLOR } else { ACTIVE_COLOR };
+                    let _ = conn.change_window_attributes(
+                        window,
+                        &ChangeWindowAttributesAux::new().background_pixel(color),
+                    );
+                    let _ = conn.clear_area(false, window, 0, 0, 0, 0);
+                }
+                // Windows raised by the game would hide the square otherwise
+                let _ = conn.configure_window(
+                    window,
+                    &ConfigureWindowAux::new().stack_mode(StackMode::ABOVE),
+                );
+                let _ = conn.flush();
+                std::thread::sleep(POLL_INTERVAL);
+            }
+            let _ = conn.destroy_window(window);
+            let _ = conn.flush();
+        })
+        .context("Failed to start the indicator thread")?;
+    if let Ok(mut slot) = THREAD.lock() {
+        *slot = Some(thread);
+    }
+    debug!("Showing the activity indicator at ({}, {})", x, y);
+    Ok(())
+}
+
+/// Shows whether the bot is paused.
+pub fn set_paused(paused: bool) {
+    PAUSED.store(paused, Ordering::Relaxed);
+}
+
+/// Removes the indicator from the screen.
+pub fn stop() {
+    let thread = THREAD.lock().ok().and_then(|mut thread| thread.take());
+    if let Some(thread) = thread {
+        STOPPING.store(true, Ordering::Relaxed);
+        let _ = thread.join();
+    }
+    if let Ok(mut region) = REGION.lock() {
+        *region = None;
+    }
+}
+
+/// Returns the screen area the indicator covers, `None` while it isn't shown.
+pub fn region() -> Option<Region> {
+    REGION.lock().ok().and_then(|region| *region)
+}
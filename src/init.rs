@@ -0,0 +1,413 @@
+//! Starter scripts written by the `init` subcommand.
+//!
+//! Every template is a complete script that loads as is. The comments explaining its fields come
+//! from the descriptions in the JSON schema of the event types, the same ones `colorbot actions`
+//! lists, so they follow the fields as they change. The script-level settings are explained by a
+//! header, since the script object itself has no schema.
+use crate::actions;
+use crate::event::BotEvent;
+
+use anyhow::{ensure, Context, Result};
+use clap::ValueEnum;
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+/// Column that comments are wrapped at.
+const COMMENT_WIDTH: usize = 90;
+
+const COLOR_CLICK: &str = r#"{
+  "metadata": { "game": "Example Game", "version": "1.0", "resolution": [1920, 1080] },
+  "delays": {
+    "short": [200, 400],
+    "think": { "type": "lognormal", "median": 1200, "sigma": 0.4 }
+  },
+  "rhythm": {
+    "burst_length": [3, 8],
+    "pause": { "type": "lognormal", "median": 2500, "sigma": 0.5 }
+  },
+  "events": [
+    {
+      "id": "click_ore",
+      "type": "color",
+      "colors": [[112, 96, 80], [128, 110, 92]],
+      "search_region": { "x": 400, "y": 200, "width": 800, "height": 600 },
+      "delay": "think"
+    },
+    {
+      "id": "drop_ore",
+      "type": "keypress",
+      "keycode": "shift",
+      "delay": "short"
+    }
+  ]
+}
+"#;
+
+const POSITION_LOOP: &str = r#"{
+  "metadata": { "game": "Example Game", "version": "1.0", "resolution": [1920, 1080] },
+  "delays": {
+    "short": [200, 400],
+    "think": { "type": "lognormal", "median": 1200, "sigma": 0.4 }
+  },
+  "events": [
+    {
+      "repeat": 5,
+      "events": [
+        {
+          "id": "move_item",
+          "type": "drag",
+          "from": { "point": { "x": 600, "y": 420 } },
+          "to": { "point": { "x": 240, "y": 330 } },
+          "button": "left",
+          "delay": "short"
+        },
+        {
+          "id": "confirm",
+          "type": "keypress",
+          "keycode": "space",
+          "delay": "short"
+        }
+      ]
+    },
+    {
+      "id": "rest",
+      "type": "keypress",
+      "keycode": "Escape",
+      "when": "iteration % 10 == 0",
+      "delay": "think"
+    }
+  ]
+}
+"#;
+
+const STATE_MACHINE: &str = r#"{
+  "metadata": { "game": "Example Game", "version": "1.0", "resolution": [1920, 1080] },
+  "delays": {
+    "short": [200, 400],
+    "think": { "type": "lognormal", "median": 1200, "sigma": 0.4 }
+  },
+  "events": [
+    {
+      "id": "find_bank",
+      "type": "color",
+      "rgb": [87, 62, 23],
+      "if_found": "wait_for_bank",
+      "if_not_found": "walk_to_bank",
+      "delay": "think"
+    },
+    {
+      "id": "walk_to_bank",
+      "type": "color",
+      "rgb": [214, 180, 40],
+      "search_region": { "x": 1700, "y": 20, "width": 200, "height": 200 },
+      "if_found": "find_bank",
+      "delay": "think"
+    },
+    { "checkpoint": "bank_open" },
+    {
+      "transaction": "deposit",
+      "events": [
+        {
+          "id": "wait_for_bank",
+          "type": "wait_for_color",
+          "rgb": [255, 152, 31],
+          "timeout": 8000,
+          "delay": "short"
+        },
+        {
+          "id": "deposit_all",
+          "type": "color",
+          "rgb": [255, 152, 31],
+          "requires": ["wait_for_bank"],
+          "delay": "short"
+        }
+      ],
+      "on_fail": [
+        { "id": "close_bank", "type": "keypress", "keycode": "Escape", "delay": 600 }
+      ]
+    }
+  ]
+}
+"#;
+
+/// Kind of starter script.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Template {
+    /// Clicks a color target found on screen, then drops the result with a key press.
+    ColorClick,
+    /// Loops over fixed screen positions with drags and key presses.
+    PositionLoop,
+    /// Branches between states depending on what's on screen, with checkpoints and a rollback.
+    StateMachine,
+}
+
+impl Template {
+    /// Returns the name the template is selected by.
+    fn name(&self) -> &'static str {
+        match self {
+            Self::ColorClick => "color-click",
+            Self::PositionLoop => "position-loop",
+            Self::StateMachine => "state-machine",
+        }
+    }
+
+    /// Returns the script of the template, with its fields in the order they're explained.
+    fn script(&self) -> &'static str {
+        match self {
+            Self::ColorClick => COLOR_CLICK,
+            Self::PositionLoop => POSITION_LOOP,
+            Self::StateMachine => STATE_MACHINE,
+        }
+    }
+
+    /// Returns the explanation of the template's script-level settings.
+    fn header(&self, path: &Path) -> Vec<String> {
+        let mut lines = vec![
+            format!(
+                "Starter script from `colorbot init --template {}`, colorbot {}.",
+                self.name(),
+                env!("CARGO_PKG_VERSION")
+            ),
+            "Lines starting with // are comments. Adjust the colors, regions and keys to your \
+             game, sample colors with `colorbot watch-color` or `colorbot sample-target`, then \
+             check the script with `colorbot validate`."
+                .to_string(),
+            "metadata: the game, version and screen resolution the script was written for, \
+             checked before a run starts."
+                .to_string(),
+            "delays: named delay profiles that events refer to, as a [min, max] range in ms or \
+             a distribution."
+                .to_string(),
+        ];
+        match self {
+            Self::ColorClick => lines.push(
+                "rhythm: bursts of 3 to 8 events separated by longer pauses, like a player who \
+                 looks away now and then."
+                    .to_string(),
+            ),
+            Self::PositionLoop => lines.push(
+                "The repeat block runs its events 5 times in a row before the script goes on."
+                    .to_string(),
+            ),
+            Self::StateMachine => lines.push(
+                "if_found and if_not_found jump to another event depending on what's on screen: \
+                 the bank is clicked when it's in view, otherwise the minimap icon walks closer \
+                 and the search starts over. The checkpoint names a state a run can start at \
+                 with --start-at-checkpoint, and the transaction's on_fail routine closes the \
+                 bank when one of its events fails."
+                    .to_string(),
+            ),
+        }
+        lines.push(format!(
+            "Long sessions should take breaks: colorbot run {} --break-every 45m --break-for 3m..8m",
+            path.display()
+        ));
+        lines
+    }
+}
+
+/// Descriptions of the event types and fields, by name, from the event schema.
+///
+/// Fields are described by the first schema that has them, unless the event type they're set on
+/// describes them itself, since the same field can mean different things for different types.
+struct Descriptions {
+    types: BTreeMap<String, String>,
+    fields: BTreeMap<String, String>,
+    type_fields: BTreeMap<(String, String), String>,
+}
+
+impl Descriptions {
+    fn new() -> Result<Self> {
+        let schema = serde_json::to_value(schemars::schema_for!(BotEvent))?;
+        let mut descriptions = Self {
+            types: BTreeMap::new(),
+            fields: BTreeMap::new(),
+            type_fields: BTreeMap::new(),
+        };
+        descriptions.collect(&schema);
+        Ok(descriptions)
+    }
+
+    /// Returns the description of a field set on an event of type `kind`.
+    fn field(&self, kind: &str, name: &str) -> Option<&String> {
+        self.type_fields
+            .get(&(kind.to_string(), name.to_string()))
+            .or_else(|| self.fields.get(name))
+    }
+
+    /// Collects the descriptions of every object schema nested in `schema`.
+    fn collect(&mut self, schema: &Value) {
+        match schema {
+            Value::Object(object) => {
+                let kind = schema
+                    .pointer("/properties/type/const")
+                    .and_then(Value::as_str);
+                if let Some(kind) = kind {
+                    self.types
+                        .entry(kind.to_string())
+                        .or_insert_with(|| description(schema));
+                }
+                if let Some(properties) = object.get("properties").and_then(Value::as_object) {
+                    for (name, field) in properties {
+                        if actions::summary(field).is_empty() {
+                            continue;
+                        }
+                        self.fields
+                            .entry(name.clone())
+                            .or_insert_with(|| description(field));
+                        if let Some(kind) = kind {
+                            self.type_fields
+                                .entry((kind.to_string(), name.clone()))
+                                .or_insert_with(|| description(field));
+                        }
+                    }
+                }
+                object.values().for_each(|value| self.collect(value));
+            }
+            Value::Array(values) => values.iter().for_each(|value| self.collect(value)),
+            _ => {}
+        }
+    }
+}
+
+/// Returns the first paragraph of a schema's description on a single line.
+fn description(schema: &Value) -> String {
+    let text = schema
+        .get("description")
+        .and_then(Value::as_str)
+        .unwrap_or_default();
+    let paragraph = text.split("\n\n").next().unwrap_or_default();
+    paragraph.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Returns the key of a line of JSON, if it starts with one.
+fn line_key(line: &str) -> Option<&str> {
+    let rest = line.trim_start().strip_prefix('"')?;
+    let (key, after) = rest.split_once('"')?;
+    after.starts_with(':').then_some(key)
+}
+
+/// Returns the string value of `key` on a line of JSON.
+fn line_value<'a>(line: &'a str, key: &str) -> Option<&'a str> {
+    let (_, rest) = line.split_once(&format!("\"{}\":", key))?;
+    let rest = rest.trim_start().strip_prefix('"')?;
+    rest.split_once('"').map(|(value, _)| value)
+}
+
+/// Appends `text` as comment lines wrapped at the comment width.
+fn push_comment(out: &mut String, indent: &str, text: &str) {
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        if !line.is_empty() && indent.len() + 3 + line.len() + 1 + word.len() > COMMENT_WIDTH {
+            out.push_str(&format!("{}// {}\n", indent, line));
+            line.clear();
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(word);
+    }
+    if !line.is_empty() {
+        out.push_str(&format!("{}// {}\n", indent, line));
+    }
+}
+
+/// Writes the script with comments above the first use of every described field and type.
+fn annotate(script: &str, header: &[String], descriptions: &Descriptions) -> String {
+    let mut text = String::new();
+    for line in header {
+        push_comment(&mut text, "", line);
+    }
+    let mut described = BTreeSet::new();
+    // Type of the event being written, the templates give it right after the id
+    let mut kind = "";
+    for line in script.lines() {
+        let indent = &line[..line.len() - line.trim_start().len()];
+        // Events written on a single line only get their type explained
+        let comment = match line_value(line, "type") {
+            Some(name) => {
+                kind = name;
+                descriptions.types.get(name).map(|text| (name, text))
+            }
+            None => {
+                line_key(line).and_then(|key| descriptions.field(kind, key).map(|text| (key, text)))
+            }
+        };
+        if let Some((name, description)) = comment {
+            if described.insert((name.to_string(), description.clone())) {
+                push_comment(&mut text, indent, &format!("{}: {}", name, description));
+            }
+        }
+        text.push_str(line);
+        text.push('\n');
+    }
+    text
+}
+
+/// Returns the commented script of a template.
+fn render(template: Template, path: &Path) -> Result<String> {
+    Ok(annotate(
+        template.script(),
+        &template.header(path),
+        &Descriptions::new()?,
+    ))
+}
+
+/// Writes a starter script from a template, refusing to replace an existing file unless `force`.
+pub fn run(path: &Path, template: Template, force: bool) -> Result<()> {
+    ensure!(
+        force || !path.exists(),
+        "{} already exists, pass --force to replace it",
+        path.display()
+    );
+    std::fs::write(path, render(template, path)?)
+        .context(format!("Failed to write {}", path.display()))?;
+    println!(
+        "Wrote a {} starter script to {}, check it with `colorbot validate {}`",
+        template.name(),
+        path.display(),
+        path.display()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::script;
+
+    #[test]
+    fn templates_load_with_their_comments() {
+        let dir = std::env::temp_dir().join(format!("colorbot-init-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for template in [
+            Template::ColorClick,
+            Template::PositionLoop,
+            Template::StateMachine,
+        ] {
+            let path = dir.join(format!("{}.json", template.name()));
+            let text = render(template, &path).unwrap();
+            assert!(text.contains("// delay: "), "{}", text);
+            std::fs::write(&path, text).unwrap();
+            let loaded = script::load(&path);
+            assert!(
+                loaded.is_ok(),
+                "{}: {:#}",
+                template.name(),
+                loaded.unwrap_err()
+            );
+        }
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn comments_are_blanked_outside_strings() {
+        let text = "{\"url\": \"http://a\", // note\n\"b\": 1}";
+        let stripped = script::strip_comments(text);
+        assert_eq!(stripped.len(), text.len());
+        let value: Value = serde_json::from_str(&stripped).unwrap();
+        assert_eq!(value["url"], "http://a");
+        assert_eq!(value["b"], 1);
+    }
+}
@@ -0,0 +1,80 @@
+//! Guard against several colorbot processes driving the same display at once.
+//!
+//! Two bots moving the same cursor fight over it and produce erratic input. Each run claims an
+//! abstract Unix socket named after its display, which the kernel releases when the process
+//! exits, so a crash never leaves a stale lock behind. A second run on the same display finds
+//! the name taken, asks the holder for its process id and refuses to start.
+use anyhow::{bail, Context, Result};
+use log::{debug, warn};
+use std::io::{Read, Write};
+use std::os::linux::net::SocketAddrExt;
+use std::os::unix::fs::MetadataExt;
+use std::os::unix::net::{SocketAddr, UnixListener, UnixStream};
+
+/// Returns the name of the lock for the current display.
+///
+/// X11 displays are shared by every user of the machine. Wayland display names are only unique
+/// per user, so the user id is part of the name.
+pub fn lock_name() -> String {
+    if let Some(display) = std::env::var_os("DISPLAY") {
+        return format!("colorbot-x11-{}", display.to_string_lossy());
+    }
+    let uid = std::fs::metadata("/proc/self")
+        .map(|metadata| metadata.uid())
+        .unwrap_or_default();
+    match std::env::var_os("WAYLAND_DISPLAY") {
+        Some(display) => format!("colorbot-wayland-{}-{}", uid, display.to_string_lossy()),
+        None => format!("colorbot-nodisplay-{}", uid),
+    }
+}
+
+/// Asks the process holding the lock for its process id.
+fn holder_pid(address: &SocketAddr) -> Option<u32> {
+    let mut stream = UnixStream::connect_addr(address).ok()?;
+    let mut pid = String::new();
+    stream.read_to_string(&mut pid).ok()?;
+    pid.trim().parse().ok()
+}
+
+/// Claims the display for this process until it exits.
+///
+/// Fails when another colorbot process holds the display, unless `force` is set.
+pub fn lock_display(force: bool) -> Result<()> {
+    let name = lock_name();
+    let address = SocketAddr::from_abstract_name(name.as_bytes())
+        .context("Failed to build the instance lock address")?;
+
+    let listener = match UnixListener::bind_addr(&address) {
+        Ok(listener) => listener,
+        Err(e) if e.kind() == std::io::ErrorKind::AddrInUse => {
+            let holder = holder_pid(&address)
+                .map(|pid| format!(" (pid {})", pid))
+                .unwrap_or_default();
+            if force {
+                warn!(
+                    "Another colorbot{} is driving this display, running anyway because of --force",
+                    holder
+                );
+                return Ok(());
+            }
+            bail!(
+                "Another colorbot{} is already driving this display, stop it first or pass \
+                 --force to run anyway",
+                holder
+            );
+        }
+        Err(e) => return Err(e).context("Failed to claim the instance lock"),
+    };
+    debug!("Claimed instance lock '{}'", name);
+
+    // Answer other instances with our pid, the listener lives as long as the process
+    std::thread::Builder::new()
+        .name("instance-lock".to_string())
+        .spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                let _ = write!(stream, "{}", std::process::id());
+            }
+        })
+        .context("Failed to start the instance lock thread")?;
+    Ok(())
+}
@@ -0,0 +1,135 @@
+//! Profiles of the user's in-game key bindings, referenced by action name in scripts.
+//!
+//! Players bind game actions to different keys, so a script that presses F3 to open the
+//! inventory only works for some of them. Keypress events can name an action instead, as in
+//! `"keycode": {"action": "open_inventory"}`, and a keybinds profile maps every action to the
+//! keys the user bound it to. Sharing a script then only takes swapping the profile:
+//!
+//! ```json
+//! { "open_inventory": "F3", "hop_world": "ctrl+shift+Right" }
+//! ```
+use crate::event::{BotEvent, BotEventType};
+use crate::hotkeys::Hotkeys;
+use crate::secrets::ScriptString;
+
+use anyhow::{bail, ensure, Context, Result};
+use log::debug;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// Keys bound to each action, in xdotool format.
+#[derive(Debug, Default)]
+pub struct Keybinds {
+    actions: BTreeMap<String, String>,
+}
+
+impl Keybinds {
+    /// Returns the default path of the profile, next to the hotkeys file.
+    pub fn default_path() -> PathBuf {
+        Hotkeys::default_path().with_file_name("keybinds.json")
+    }
+
+    /// Loads the profile from `path`, or from the default file when it exists.
+    ///
+    /// Without either, no actions are defined.
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let path = match path {
+            Some(path) => path.to_path_buf(),
+            None => {
+                let path = Self::default_path();
+                if !path.exists() {
+                    return Ok(Self::default());
+                }
+                path
+            }
+        };
+        let content = std::fs::read_to_string(&path).context(format!(
+            "Failed to read keybinds profile {}",
+            path.display()
+        ))?;
+        let actions: BTreeMap<String, String> = serde_json::from_str(&content).context(format!(
+            "Failed to parse keybinds profile {}",
+            path.display()
+        ))?;
+        for (action, keys) in &actions {
+            ensure!(
+                !action.is_empty(),
+                "Keybinds profile {} has an action without a name",
+                path.display()
+            );
+            ensure!(
+                keys.split('+').all(|key| !key.trim().is_empty()),
+                "Action '{}' in keybinds profile {} is bound to '{}', which has an empty key",
+                action,
+                path.display(),
+                keys
+            );
+        }
+        debug!(
+            "Loaded {} key bindings from {}",
+            actions.len(),
+            path.display()
+        );
+        Ok(Self { actions })
+    }
+
+    /// Replaces the actions that keypress events refer to with the keys bound to them.
+    ///
+    /// Fails when an event refers to an action the profile doesn't define, or uses an action
+    /// anywhere but as the key of a keypress event.
+    pub fn resolve<'a>(&self, events: impl IntoIterator<Item = &'a mut BotEvent>) -> Result<()> {
+        for event in events {
+            match &mut event.event_type {
+                BotEventType::KeyPress { keycode } => {
+                    let ScriptString::Action { action } = keycode else {
+                        continue;
+                    };
+                    let Some(keys) = self.actions.get(action.as_str()) else {
+                        bail!(
+                            "Event '{}' presses action '{}', which the keybinds profile doesn't \
+                             bind, add it to {} or pass a profile with --keybinds",
+                            event.id,
+                            action,
+                            Self::default_path().display()
+                        );
+                    };
+                    *keycode = ScriptString::Plain(keys.clone());
+                }
+                BotEventType::Relogin(relogin) => {
+                    if let Some(ScriptString::Action { action }) = relogin
+                        .credentials()
+                        .find(|credential| matches!(credential, ScriptString::Action { .. }))
+                    {
+                        bail!(
+                            "Event '{}' types action '{}', actions only work as the key of a \
+                             keypress event",
+                            event.id,
+                            action
+                        );
+                    }
+                }
+                BotEventType::TypeText {
+                    text: ScriptString::Action { action },
+                    ..
+                } => {
+                    bail!(
+                        "Event '{}' types action '{}', actions only work as the key of a \
+                         keypress event",
+                        event.id,
+                        action
+                    );
+                }
+                BotEventType::TypeText { .. }
+                | BotEventType::Color { .. }
+                | BotEventType::Image { .. }
+                | BotEventType::Drag { .. }
+                | BotEventType::WaitForColor { .. } => {}
+                #[cfg(feature = "template")]
+                BotEventType::Atlas { .. } => {}
+                #[cfg(feature = "gamepad")]
+                BotEventType::Button { .. } | BotEventType::Axis { .. } => {}
+            }
+        }
+        Ok(())
+    }
+}
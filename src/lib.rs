@@ -0,0 +1,97 @@
+//! Colorbot: A scriptable Old School Runescape automation bot.
+//!
+//! This bot reads JSON event scripts and executes them in a loop for a specified duration,
+//! supporting mouse movements, keypresses, color-based pixel detection, and custom actions.
+//!
+//! The `colorbot` binary is a thin command line over this library. Other programs drive a bot
+//! through [`Bot`], which runs a script on a background thread they pause, resume and stop.
+pub(crate) mod actions;
+pub(crate) mod assertion;
+#[cfg(feature = "template")]
+pub(crate) mod atlas;
+pub(crate) mod autoregion;
+pub(crate) mod backend;
+pub mod bot;
+pub(crate) mod breaks;
+#[doc(hidden)]
+pub mod cli;
+pub(crate) mod colormatch;
+pub(crate) mod compare;
+pub(crate) mod condition;
+pub mod config;
+pub(crate) mod control;
+pub(crate) mod controls;
+pub(crate) mod countdown;
+pub(crate) mod crash;
+pub(crate) mod decisions;
+pub(crate) mod delay;
+pub(crate) mod detach;
+pub(crate) mod diagnostics;
+pub(crate) mod display;
+pub(crate) mod doctor;
+pub(crate) mod drag;
+pub(crate) mod dryrun;
+pub mod error;
+pub(crate) mod event;
+pub(crate) mod filter;
+pub(crate) mod fixtures;
+pub(crate) mod flow;
+pub(crate) mod focus;
+pub(crate) mod forbidden;
+#[cfg(feature = "gamepad")]
+pub(crate) mod gamepad;
+pub(crate) mod graph;
+pub(crate) mod homing;
+pub(crate) mod hotkeys;
+pub(crate) mod idle;
+pub(crate) mod indicator;
+pub(crate) mod init;
+pub(crate) mod instance;
+pub(crate) mod keybinds;
+pub(crate) mod lint;
+pub(crate) mod matchcache;
+pub(crate) mod metadata;
+pub(crate) mod movement;
+pub(crate) mod output;
+pub(crate) mod overlay;
+pub(crate) mod overshoot;
+pub(crate) mod pacing;
+pub(crate) mod picker;
+pub(crate) mod portal;
+pub(crate) mod process;
+pub(crate) mod profile;
+pub(crate) mod record;
+pub(crate) mod reload;
+pub(crate) mod relogin;
+pub(crate) mod replay;
+pub mod report;
+pub(crate) mod retry;
+pub(crate) mod rhythm;
+pub(crate) mod rundir;
+pub(crate) mod runner;
+pub(crate) mod runtime;
+pub(crate) mod script;
+pub(crate) mod scripttest;
+pub(crate) mod secrets;
+pub(crate) mod signature;
+pub(crate) mod stall;
+pub(crate) mod stats;
+#[cfg(feature = "stats-db")]
+pub(crate) mod statsdb;
+pub(crate) mod targetcheck;
+pub(crate) mod targets;
+pub(crate) mod theme;
+pub(crate) mod timing;
+pub(crate) mod tint;
+pub(crate) mod tremor;
+pub(crate) mod tune;
+pub(crate) mod turbo;
+pub(crate) mod uinput;
+pub(crate) mod verify;
+pub(crate) mod vision;
+pub(crate) mod watchdog;
+pub(crate) mod windmouse;
+pub(crate) mod window;
+pub(crate) mod ydotool;
+
+pub use bot::{Bot, BotBuilder, BotHandle};
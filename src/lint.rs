@@ -0,0 +1,351 @@
+//! Static checks of a bot script for the `validate` subcommand.
+//!
+//! Loading a script stops at its first problem, so a malformed script used to surface one
+//! error at a time, often only once a run started. Validation goes through the script entry by
+//! entry and reports everything it finds at once: JSON and schema errors such as unknown event
+//! types, invalid delays, branches to events that don't exist, duplicate event ids, regions that
+//! lie outside the display and branches that let an event run without the events it requires.
+use crate::delay::ScriptDelay;
+use crate::display;
+use crate::error::ColorbotError;
+use crate::event::BotEvent;
+use crate::flow;
+use crate::output::{self, Render};
+use crate::script;
+use crate::window;
+
+use anyhow::{anyhow, Context, Result};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::Write;
+use std::path::Path;
+
+/// How serious a problem is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum Severity {
+    /// The script doesn't load.
+    Error,
+    /// The script loads, but likely doesn't do what was intended.
+    Warning,
+}
+
+/// Problem found in a script.
+#[derive(Serialize)]
+struct Diagnostic {
+    severity: Severity,
+    /// Where in the script the problem is, empty for the script as a whole.
+    location: String,
+    message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        if self.location.is_empty() {
+            write!(f, "{}: {}", severity, self.message)
+        } else {
+            write!(f, "{}: {}: {}", severity, self.location, self.message)
+        }
+    }
+}
+
+/// Diagnostics collected while going through a script.
+#[derive(Default)]
+struct Diagnostics(Vec<Diagnostic>);
+
+impl Diagnostics {
+    fn push(&mut self, severity: Severity, location: &str, message: impl fmt::Display) {
+        self.0.push(Diagnostic {
+            severity,
+            location: location.to_string(),
+            message: message.to_string(),
+        });
+    }
+
+    fn error(&mut self, location: &str, message: impl fmt::Display) {
+        self.push(Severity::Error, location, message);
+    }
+
+    fn warning(&mut self, location: &str, message: impl fmt::Display) {
+        self.push(Severity::Warning, location, message);
+    }
+
+    fn count(&self, severity: Severity) -> usize {
+        self.0
+            .iter()
+            .filter(|diagnostic| diagnostic.severity == severity)
+            .count()
+    }
+}
+
+/// Names an entry of the event list by its position and, when it has one, its id.
+fn entry_location(index: usize, value: &Value) -> String {
+    match value.get("id").and_then(Value::as_str) {
+        Some(id) => format!("entry {} ('{}')", index + 1, id),
+        None => format!("entry {}", index + 1),
+    }
+}
+
+/// Checks the events of the script, returning the events that parsed.
+fn check_entries(
+    entries: &[Value],
+    delays: &BTreeMap<String, ScriptDelay>,
+    diagnostics: &mut Diagnostics,
+) -> Vec<BotEvent> {
+    let mut events = Vec::new();
+    for (i, value) in entries.iter().enumerate() {
+        let location = entry_location(i, value);
+        let parsed = match script::entry_events(value.clone()) {
+            Ok(parsed) => parsed,
+            Err(e) => {
+                diagnostics.error(&location, format!("{:#}", e));
+                continue;
+            }
+        };
+        for mut event in parsed {
+            // Events of transactions and repeat blocks are named within their entry
+            let location = match value.get("id") {
+                Some(_) => location.clone(),
+                None => format!("{}, event '{}'", location, event.id),
+            };
+            let resolved: Result<(), _> = event
+                .delays_mut()
+                .try_for_each(|delay| delay.resolve(delays));
+            if let Err(e) = resolved {
+                diagnostics.error(&location, format!("{:#}", e));
+            } else if let Err(e) = event.validate() {
+                diagnostics.error(&location, format!("{:#}", e));
+            }
+            events.push(event);
+        }
+    }
+    events
+}
+
+/// Checks that event ids are unique and that branches lead to existing events.
+fn check_ids(events: &[BotEvent], diagnostics: &mut Diagnostics) {
+    for (i, event) in events.iter().enumerate() {
+        if events[..i].iter().any(|other| other.id == event.id) {
+            diagnostics.warning(
+                &format!("event '{}'", event.id),
+                "the id is used by an earlier event too, branches and conditions refer to the \
+                 first one",
+            );
+        }
+        for target in event.jump_targets() {
+            if !events.iter().any(|other| other.id == target) {
+                diagnostics.error(
+                    &format!("event '{}'", event.id),
+                    format!("branches to '{}', which no event of the script is", target),
+                );
+            }
+        }
+        for requirement in &event.requires {
+            if !events.iter().any(|other| other.id == *requirement) {
+                diagnostics.error(
+                    &format!("event '{}'", event.id),
+                    format!(
+                        "requires '{}', which no event of the script is",
+                        requirement
+                    ),
+                );
+            }
+        }
+    }
+}
+
+/// Returns the region an object describes, if it has the fields of one.
+fn as_region(value: &Value) -> Option<(i64, i64, i64, i64)> {
+    let field = |name| value.get(name).and_then(Value::as_i64);
+    Some((field("x")?, field("y")?, field("width")?, field("height")?))
+}
+
+/// Checks every region in the script against the display size.
+fn check_bounds(value: &Value, location: &str, size: (i64, i64), diagnostics: &mut Diagnostics) {
+    match value {
+        Value::Object(object) => {
+            if let Some((x, y, width, height)) = as_region(value) {
+                let (screen_width, screen_height) = size;
+                if x >= screen_width || y >= screen_height || x + width <= 0 || y + height <= 0 {
+                    diagnostics.error(
+                        location,
+                        format!(
+                            "region {},{} {}x{} lies outside the {}x{} display",
+                            x, y, width, height, screen_width, screen_height
+                        ),
+                    );
+                } else if x < 0 || y < 0 || x + width > screen_width || y + height > screen_height {
+                    diagnostics.warning(
+                        location,
+                        format!(
+                            "region {},{} {}x{} extends past the {}x{} display",
+                            x, y, width, height, screen_width, screen_height
+                        ),
+                    );
+                }
+                return;
+            }
+            for (key, value) in object {
+                let location = match location {
+                    "" => key.clone(),
+                    _ => format!("{} > {}", location, key),
+                };
+                check_bounds(value, &location, size, diagnostics);
+            }
+        }
+        Value::Array(values) => {
+            for value in values {
+                check_bounds(value, location, size, diagnostics);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Returns the size of the display to check regions against.
+fn display_size(index: Option<usize>) -> Result<(i64, i64)> {
+    match index {
+        Some(index) => {
+            let monitor = display::list()?
+                .into_iter()
+                .nth(index)
+                .context(format!("There's no display {}", index))?;
+            Ok((i64::from(monitor.width), i64::from(monitor.height)))
+        }
+        None => {
+            let (width, height) = window::screen_size()?;
+            Ok((i64::from(width), i64::from(height)))
+        }
+    }
+}
+
+/// Goes through the script and collects every problem found.
+fn check(path: &Path, display: Option<usize>) -> Result<Diagnostics> {
+    let mut diagnostics = Diagnostics::default();
+    let content =
+        std::fs::read_to_string(path).context(format!("Failed to read {}", path.display()))?;
+    let value: Value = match serde_json::from_str(&script::strip_comments(&content)) {
+        Ok(value) => value,
+        Err(e) => {
+            diagnostics.error(&format!("line {}, column {}", e.line(), e.column()), e);
+            return Ok(diagnostics);
+        }
+    };
+
+    let (entries, delays) = match &value {
+        Value::Array(entries) => (entries.as_slice(), BTreeMap::new()),
+        Value::Object(object) => {
+            let Some(Value::Array(entries)) = object.get("events") else {
+                diagnostics.error("", "the script object needs an 'events' array");
+                return Ok(diagnostics);
+            };
+            let delays = match object.get("delays") {
+                Some(delays) => match serde_json::from_value(delays.clone()) {
+                    Ok(delays) => delays,
+                    Err(e) => {
+                        diagnostics.error("delays", e);
+                        BTreeMap::new()
+                    }
+                },
+                None => BTreeMap::new(),
+            };
+            (entries.as_slice(), delays)
+        }
+        _ => {
+            diagnostics.error(
+                "",
+                "a script is an array of events or an object with 'events'",
+            );
+            return Ok(diagnostics);
+        }
+    };
+
+    let events = check_entries(entries, &delays, &mut diagnostics);
+    check_ids(&events, &mut diagnostics);
+
+    match display_size(display) {
+        Ok(size) => {
+            if let Value::Object(object) = &value {
+                for (key, value) in object.iter().filter(|(key, _)| *key != "events") {
+                    check_bounds(value, key, size, &mut diagnostics);
+                }
+            }
+            for (i, entry) in entries.iter().enumerate() {
+                check_bounds(entry, &entry_location(i, entry), size, &mut diagnostics);
+            }
+        }
+        Err(e) => diagnostics.warning("", format!("skipping region bounds checks, {:#}", e)),
+    }
+
+    // Loading catches what the entry checks can't see, such as checkpoints and themes, but it
+    // stops at the first problem, which is already reported when there are errors
+    if diagnostics.count(Severity::Error) == 0 {
+        match script::load(path) {
+            Ok(script) => {
+                for unmet in flow::unmet_requirements(&script) {
+                    diagnostics.error(&format!("event '{}'", unmet.event), unmet);
+                }
+            }
+            Err(e) => diagnostics.error("", format!("{:#}", e)),
+        }
+    }
+    Ok(diagnostics)
+}
+
+/// Outcome of validating a script.
+#[derive(Serialize)]
+struct Report<'a> {
+    script: &'a Path,
+    errors: usize,
+    warnings: usize,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Render for Report<'_> {
+    fn render(&self, out: &mut dyn Write) -> std::io::Result<()> {
+        for diagnostic in &self.diagnostics {
+            writeln!(out, "{}", diagnostic)?;
+        }
+        match (self.errors, self.warnings) {
+            (0, 0) => writeln!(out, "{} is valid", self.script.display()),
+            (0, warnings) => writeln!(
+                out,
+                "{} is valid with {} warnings",
+                self.script.display(),
+                warnings
+            ),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Validates a script and prints every problem found, failing when the script doesn't load.
+pub fn run(path: &Path, display: Option<usize>) -> Result<()> {
+    let diagnostics = check(path, display)?;
+    let report = Report {
+        script: path,
+        errors: diagnostics.count(Severity::Error),
+        warnings: diagnostics.count(Severity::Warning),
+        diagnostics: diagnostics.0,
+    };
+    output::print(&report)?;
+    if report.errors > 0 {
+        return Err(ColorbotError::ScriptParse(
+            path.to_path_buf(),
+            anyhow!(
+                "{} has {} errors and {} warnings",
+                path.display(),
+                report.errors,
+                report.warnings
+            ),
+        )
+        .into());
+    }
+    Ok(())
+}
@@ -0,0 +1,130 @@
+//! Cache of template match locations, invalidated when the matched area changes.
+//!
+//! Template matching searches the whole screen and is by far the most expensive lookup. Between
+//! two lookups of the same template the screen usually only changed elsewhere, so the location
+//! of the last match is kept along with perceptual hashes of the area it covered. While the
+//! area still hashes the same, the template is taken to be there without searching again. Hits
+//! and misses per template end up in the run report, where a low hit rate points at a template
+//! whose surroundings keep changing.
+use crate::filter::Filter;
+use crate::vision;
+use crate::windmouse::Point;
+
+use image::RgbaImage;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Hits and misses of the cache for one template.
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
+pub struct CacheCounts {
+    /// Lookups answered from the cache.
+    pub hits: u64,
+    /// Lookups that had to search the screen.
+    pub misses: u64,
+}
+
+/// Area covered by the last match of a template.
+struct Entry {
+    origin: Point,
+    width: u32,
+    height: u32,
+    /// Average and difference hash of the area when it matched.
+    hashes: (u64, u64),
+}
+
+/// Template path and the filters it was matched with, since filters change the match.
+type Key = (PathBuf, String);
+
+static ENTRIES: Mutex<Option<HashMap<Key, Entry>>> = Mutex::new(None);
+static COUNTS: Mutex<BTreeMap<String, CacheCounts>> = Mutex::new(BTreeMap::new());
+
+fn key(path: &Path, filters: &[Filter]) -> Key {
+    (path.to_path_buf(), format!("{:?}", filters))
+}
+
+/// Returns the average and difference hash of an area of the frame.
+fn area_hashes(frame: &RgbaImage, origin: Point, width: u32, height: u32) -> Option<(u64, u64)> {
+    let x = u32::try_from(origin.x).ok()?;
+    let y = u32::try_from(origin.y).ok()?;
+    if x + width > frame.width() || y + height > frame.height() {
+        return None;
+    }
+    let area = image::imageops::crop_imm(frame, x, y, width, height).to_image();
+    Some((vision::average_hash(&area), vision::difference_hash(&area)))
+}
+
+fn count(path: &Path, hit: bool) {
+    if let Ok(mut counts) = COUNTS.lock() {
+        let counts = counts.entry(path.display().to_string()).or_default();
+        if hit {
+            counts.hits += 1;
+        } else {
+            counts.misses += 1;
+        }
+    }
+}
+
+/// Returns the origin and size of the cached match of a template when the area it covered
+/// looks unchanged in `frame`, the filtered frame the template would be searched in.
+pub fn lookup(path: &Path, filters: &[Filter], frame: &RgbaImage) -> Option<(Point, u32, u32)> {
+    let mut entries = ENTRIES.lock().ok()?;
+    let entries = entries.get_or_insert_with(HashMap::new);
+    let key = key(path, filters);
+    let unchanged = entries.get(&key).is_some_and(|entry| {
+        area_hashes(frame, entry.origin, entry.width, entry.height) == Some(entry.hashes)
+    });
+    count(path, unchanged);
+    if !unchanged {
+        entries.remove(&key);
+        return None;
+    }
+    entries
+        .get(&key)
+        .map(|entry| (entry.origin, entry.width, entry.height))
+}
+
+/// Remembers where a template matched in `frame`.
+pub fn store(
+    path: &Path,
+    filters: &[Filter],
+    frame: &RgbaImage,
+    origin: Point,
+    width: u32,
+    height: u32,
+) {
+    let Some(hashes) = area_hashes(frame, origin, width, height) else {
+        return;
+    };
+    if let Ok(mut entries) = ENTRIES.lock() {
+        entries.get_or_insert_with(HashMap::new).insert(
+            key(path, filters),
+            Entry {
+                origin,
+                width,
+                height,
+                hashes,
+            },
+        );
+    }
+}
+
+/// Returns the cache counts of every template that was looked up, keyed by template path.
+pub fn counts() -> BTreeMap<String, CacheCounts> {
+    COUNTS
+        .lock()
+        .map(|counts| counts.clone())
+        .unwrap_or_default()
+}
+
+/// Forgets every cached match and clears the counts, so a following run in the same process
+/// starts from scratch.
+pub fn reset() {
+    if let Ok(mut entries) = ENTRIES.lock() {
+        *entries = None;
+    }
+    if let Ok(mut counts) = COUNTS.lock() {
+        counts.clear();
+    }
+}
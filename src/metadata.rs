@@ -0,0 +1,105 @@
+//! Script metadata describing the setup a script was written for.
+//!
+//! Coordinates, regions and colors in a script only make sense for the screen, client and
+//! backend they were authored with. The metadata block records that setup, and the run fails
+//! fast with a clear message when the live environment doesn't match, instead of misclicking.
+use crate::backend::{Capabilities, Capability, InputBackend};
+use crate::display;
+use crate::window::{self, WindowInfo};
+
+use anyhow::{ensure, Context, Result};
+use log::debug;
+use schemars::JsonSchema;
+use serde::Deserialize;
+
+/// Setup a script expects, given as `metadata` in the object form of a script.
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct Metadata {
+    /// Game the script automates, informational only.
+    #[serde(default)]
+    pub game: Option<String>,
+    /// Game or client version the script was written against, informational only.
+    #[serde(default)]
+    pub version: Option<String>,
+    /// Screen resolution as `[width, height]` the script's coordinates assume.
+    #[serde(default)]
+    pub resolution: Option<[u16; 2]>,
+    /// Text the title of the game window contains, the window must be open when the run starts.
+    #[serde(default)]
+    pub window: Option<String>,
+    /// WM_CLASS instance or class name of the game window, such as `net-runelite-client-RuneLite`,
+    /// to tell it apart from other windows with a similar title.
+    #[serde(default)]
+    pub window_class: Option<String>,
+    /// Whether coordinates and regions are relative to the game window's top-left corner rather
+    /// than the display, so the script keeps working when the window moves.
+    #[serde(default)]
+    pub relative_to_window: bool,
+    /// Whether to raise and focus the game window before every event that doesn't have the focus.
+    #[serde(default)]
+    pub raise_window: bool,
+    /// Backend capabilities the script can't work without.
+    #[serde(default)]
+    pub requires: Vec<Capability>,
+}
+
+impl Metadata {
+    /// Checks that the screen and open windows match the script's expectations, returning the
+    /// game window when the script names one.
+    pub fn check_environment(&self) -> Result<Option<WindowInfo>> {
+        if let (Some(game), version) = (&self.game, &self.version) {
+            debug!(
+                "Script is for {} {}",
+                game,
+                version.as_deref().unwrap_or("(any version)")
+            );
+        }
+
+        if let Some([width, height]) = self.resolution {
+            let (actual_width, actual_height) = match display::selected() {
+                Some(monitor) => (monitor.width, monitor.height),
+                None => display::screen_size()?,
+            };
+            ensure!(
+                (actual_width, actual_height) == (u32::from(width), u32::from(height)),
+                "The script expects a {}x{} screen, but the screen is {}x{}",
+                width,
+                height,
+                actual_width,
+                actual_height
+            );
+        }
+
+        self.game_window()
+    }
+
+    /// Finds the game window described by `window` and `window_class`, `None` without either.
+    pub fn game_window(&self) -> Result<Option<WindowInfo>> {
+        ensure!(
+            self.window.is_some()
+                || self.window_class.is_some()
+                || !(self.relative_to_window || self.raise_window),
+            "relative_to_window and raise_window need the window or window_class of the game"
+        );
+        if self.window.is_none() && self.window_class.is_none() {
+            return Ok(None);
+        }
+        window::find_window_matching(self.window.as_deref(), self.window_class.as_deref())
+            .context("The script expects its game window to be open")
+            .map(Some)
+    }
+
+    /// Checks that the selected input backend provides every capability the script requires.
+    pub fn check_backend(&self, input: &dyn InputBackend) -> Result<()> {
+        let required: Capabilities = self.requires.iter().copied().collect();
+        let missing = input.capabilities().missing(&required);
+        ensure!(
+            missing.is_empty(),
+            "The script requires {} which the {} backend does not support, pick another one with --backend",
+            missing.join(", "),
+            input.name()
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,169 @@
+//! Generators of the cursor paths that mouse movements follow, selected with `--mouse-algo`.
+//!
+//! WindMouse simulates gravity toward the destination and random gusts of wind, so its paths
+//! speed up, wobble and slow down differently every time. The cubic Bézier generator draws a
+//! single smooth curve bent to a random side and eases in and out along it, which looks calmer
+//! but more alike from one movement to the next. Tremor, homing and forbidden regions apply to
+//! the paths of either.
+use crate::windmouse::{Point, WindMouseParams};
+
+use clap::ValueEnum;
+
+/// Algorithm that generates cursor paths.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum MouseAlgorithm {
+    /// Gravity and wind physics.
+    #[default]
+    #[value(name = "windmouse")]
+    WindMouse,
+    /// Single eased cubic Bézier curve.
+    Bezier,
+}
+
+/// Generator of cursor paths.
+pub trait MovementStrategy {
+    /// Returns the cursor path from `start` to `dest`, one point per cursor update.
+    ///
+    /// The path starts at `start`, ends at `dest`, and every point differs from the one before it.
+    fn path(&self, start: Point, dest: Point) -> Vec<Point>;
+}
+
+/// Cubic Bézier curve bent sideways by a fraction of the distance.
+#[derive(Debug, Clone, Copy)]
+pub struct Bezier {
+    /// Sideways offset of the control points as a fraction of the distance, negative bends the
+    /// other way.
+    bend: f64,
+    /// Distance covered between two cursor updates at full speed.
+    step_px: f64,
+}
+
+impl Bezier {
+    /// Largest bend at a path deviation of 1.
+    const MAX_BEND: f64 = 0.25;
+    /// Distance between cursor updates at full speed.
+    const STEP_PX: f64 = 12.0;
+    /// Distance between cursor updates at full speed of a precise movement.
+    const PRECISE_STEP_PX: f64 = 3.0;
+    /// Fewest cursor updates of a path, so short paths still ease in and out.
+    const MIN_STEPS: usize = 8;
+
+    /// Creates a curve bent to a random side, scaled by the path deviation.
+    pub fn new(deviation: f64) -> Self {
+        Self {
+            bend: rand::random_range(-Self::MAX_BEND..=Self::MAX_BEND) * deviation,
+            step_px: Self::STEP_PX,
+        }
+    }
+
+    /// Creates a slow, nearly straight curve for a short corrective movement onto a small target.
+    pub fn precise(deviation: f64) -> Self {
+        Self {
+            bend: rand::random_range(-Self::MAX_BEND..=Self::MAX_BEND) * deviation / 4.0,
+            step_px: Self::PRECISE_STEP_PX,
+        }
+    }
+}
+
+impl MovementStrategy for Bezier {
+    /// Samples the curve so that the cursor speeds up and slows down at its ends.
+    fn path(&self, start: Point, dest: Point) -> Vec<Point> {
+        let (x0, y0) = (f64::from(start.x), f64::from(start.y));
+        let (dx, dy) = (f64::from(dest.x) - x0, f64::from(dest.y) - y0);
+        let distance = dx.hypot(dy);
+        // Control points a third and two thirds along the way, pushed to the same side
+        let (nx, ny) = (-dy * self.bend, dx * self.bend);
+        let controls = [
+            (x0 + dx / 3.0 + nx, y0 + dy / 3.0 + ny),
+            (x0 + dx * 2.0 / 3.0 + nx, y0 + dy * 2.0 / 3.0 + ny),
+        ];
+
+        let steps = ((distance / self.step_px).ceil() as usize).max(Self::MIN_STEPS);
+        let mut path = vec![start];
+        for i in 1..=steps {
+            let s = i as f64 / steps as f64;
+            // Smoothstep easing spaces the samples closely at both ends
+            let t = s * s * (3.0 - 2.0 * s);
+            let u = 1.0 - t;
+            let x = u * u * u * x0
+                + 3.0 * u * u * t * controls[0].0
+                + 3.0 * u * t * t * controls[1].0
+                + t * t * t * f64::from(dest.x);
+            let y = u * u * u * y0
+                + 3.0 * u * u * t * controls[0].1
+                + 3.0 * u * t * t * controls[1].1
+                + t * t * t * f64::from(dest.y);
+            let point = if i == steps {
+                dest
+            } else {
+                Point::new(x.round() as i32, y.round() as i32)
+            };
+            if path.last() != Some(&point) {
+                path.push(point);
+            }
+        }
+        path
+    }
+}
+
+/// Returns the path generator of a regular movement.
+pub fn strategy(algorithm: MouseAlgorithm, deviation: f64) -> Box<dyn MovementStrategy> {
+    match algorithm {
+        MouseAlgorithm::WindMouse => {
+            let mut params = WindMouseParams::new();
+            params.wind *= deviation;
+            Box::new(params)
+        }
+        MouseAlgorithm::Bezier => Box::new(Bezier::new(deviation)),
+    }
+}
+
+/// Returns the path generator of a short, slow corrective movement.
+pub fn precise_strategy(algorithm: MouseAlgorithm, deviation: f64) -> Box<dyn MovementStrategy> {
+    match algorithm {
+        MouseAlgorithm::WindMouse => Box::new(WindMouseParams::precise()),
+        MouseAlgorithm::Bezier => Box::new(Bezier::precise(deviation)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_connects(path: &[Point], start: Point, dest: Point) {
+        assert_eq!(path.first(), Some(&start));
+        assert_eq!(path.last(), Some(&dest));
+        assert!(path.windows(2).all(|pair| pair[0] != pair[1]));
+    }
+
+    #[test]
+    fn every_strategy_connects_the_ends() {
+        let start = Point::new(10, 20);
+        let dest = Point::new(410, 120);
+        for algorithm in [MouseAlgorithm::WindMouse, MouseAlgorithm::Bezier] {
+            assert_connects(&strategy(algorithm, 1.0).path(start, dest), start, dest);
+            assert_connects(
+                &precise_strategy(algorithm, 1.0).path(start, dest),
+                start,
+                dest,
+            );
+        }
+        assert_eq!(Bezier::new(1.0).path(start, start), vec![start]);
+    }
+
+    #[test]
+    fn bezier_bends_to_one_side_and_eases() {
+        let curve = Bezier {
+            bend: Bezier::MAX_BEND,
+            step_px: Bezier::STEP_PX,
+        };
+        let path = curve.path(Point::new(0, 0), Point::new(400, 0));
+        assert!(path.iter().all(|point| point.y >= 0));
+        assert!(path.iter().any(|point| point.y > 50));
+
+        // Steps are shorter at the ends than in the middle
+        let gap = |a: &Point, b: &Point| f64::from(b.x - a.x).hypot(f64::from(b.y - a.y));
+        let middle = path.len() / 2;
+        assert!(gap(&path[0], &path[1]) < gap(&path[middle], &path[middle + 1]));
+    }
+}
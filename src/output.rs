@@ -0,0 +1,52 @@
+//! Rendering of subcommand results as text for people or as JSON for other programs.
+//!
+//! Tools build their result as a value that serializes to JSON and renders itself as text. With
+//! `--output json` the value is printed as a single JSON document on stdout, otherwise as text.
+//! Progress and prompts go to stderr either way, so stdout stays parseable.
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use serde::Serialize;
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// How subcommands print their results.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// Human readable text.
+    #[default]
+    Text,
+    /// One JSON document on stdout.
+    Json,
+}
+
+/// Whether results are printed as JSON.
+static JSON: AtomicBool = AtomicBool::new(false);
+
+/// Sets how results are printed for the rest of the process.
+pub fn set_format(format: OutputFormat) {
+    JSON.store(format == OutputFormat::Json, Ordering::Relaxed);
+}
+
+/// Returns whether results are printed as JSON.
+pub fn is_json() -> bool {
+    JSON.load(Ordering::Relaxed)
+}
+
+/// Result of a subcommand that can be printed as text or JSON.
+pub trait Render: Serialize {
+    /// Writes the result as human readable text.
+    fn render(&self, out: &mut dyn Write) -> std::io::Result<()>;
+}
+
+/// Prints a result on stdout in the selected format.
+pub fn print(result: &impl Render) -> Result<()> {
+    let mut out = std::io::stdout().lock();
+    if is_json() {
+        serde_json::to_writer_pretty(&mut out, result).context("Failed to print the result")?;
+        writeln!(out)?;
+    } else {
+        result.render(&mut out)?;
+    }
+    out.flush()?;
+    Ok(())
+}
@@ -0,0 +1,320 @@
+//! Minimal X11 overlay windows for the interactive authoring tools.
+//!
+//! The overlay covers the whole screen with a frozen screenshot and lets the user draw on top of
+//! it with the mouse. It talks to the X server directly through x11rb so no toolkit is needed.
+use crate::vision::Region;
+use crate::windmouse::Point;
+
+use anyhow::{bail, Context, Result};
+use image::RgbaImage;
+use x11rb::connection::{Connection, RequestConnection};
+use x11rb::protocol::xproto::{
+    ConnectionExt, CreateGCAux, CreateWindowAux, EventMask, GrabMode, GrabStatus, ImageFormat,
+    Rectangle, Segment, SubwindowMode, WindowClass, GX,
+};
+use x11rb::protocol::Event;
+use x11rb::rust_connection::RustConnection;
+use x11rb::wrapper::ConnectionExt as _;
+use x11rb::CURRENT_TIME;
+
+/// Glyph index of the crosshair cursor in the standard X cursor font.
+const XC_CROSSHAIR: u16 = 34;
+
+/// Fullscreen override-redirect window showing a frozen screenshot.
+pub struct Overlay {
+    conn: RustConnection,
+    window: u32,
+    gc: u32,
+    xor_gc: u32,
+    width: u16,
+    height: u16,
+    depth: u8,
+    /// Screenshot pixels in the server's native BGRX layout.
+    pixels: Vec<u8>,
+}
+
+impl Overlay {
+    /// Opens an overlay displaying `screenshot` across the whole screen.
+    pub fn new(screenshot: &RgbaImage) -> Result<Self> {
+        let (conn, screen_num) =
+            x11rb::connect(None).context("Failed to connect to the X server")?;
+        let screen = &conn.setup().roots[screen_num];
+        let root = screen.root;
+        let depth = screen.root_depth;
+        let width = u16::try_from(screenshot.width())?;
+        let height = u16::try_from(screenshot.height())?;
+
+        // Convert RGBA back into the 32bpp BGRX layout used by 24/32-bit TrueColor visuals
+        let mut pixels = Vec::with_capacity(screenshot.as_raw().len());
+        for rgba in screenshot.as_raw().chunks_exact(4) {
+            pixels.extend_from_slice(&[rgba[2], rgba[1], rgba[0], 0]);
+        }
+
+        let font = conn.generate_id()?;
+        conn.open_font(font, b"cursor")?;
+        let cursor = conn.generate_id()?;
+        conn.create_glyph_cursor(
+            cursor,
+            font,
+            font,
+            XC_CROSSHAIR,
+            XC_CROSSHAIR + 1,
+            0,
+            0,
+            0,
+            0xffff,
+            0xffff,
+            0xffff,
+        )?;
+
+        let window = conn.generate_id()?;
+        conn.create_window(
+            depth,
+            window,
+            root,
+            0,
+            0,
+            width,
+            height,
+            0,
+            WindowClass::INPUT_OUTPUT,
+            screen.root_visual,
+            &CreateWindowAux::new()
+                .override_redirect(1)
+                .background_pixel(screen.black_pixel)
+                .cursor(cursor)
+                .event_mask(
+                    EventMask::EXPOSURE
+                        | EventMask::BUTTON_PRESS
+                        | EventMask::BUTTON_RELEASE
+                        | EventMask::POINTER_MOTION
+                        | EventMask::KEY_PRESS,
+                ),
+        )?;
+
+        let gc = conn.generate_id()?;
+        conn.create_gc(gc, window, &CreateGCAux::new())?;
+
+        // XOR drawing lets a rectangle be erased by drawing it a second time
+        let xor_gc = conn.generate_id()?;
+        conn.create_gc(
+            xor_gc,
+            window,
+            &CreateGCAux::new()
+                .function(GX::XOR)
+                .foreground(0x00ff_ffff)
+                .line_width(2)
+                .subwindow_mode(SubwindowMode::INCLUDE_INFERIORS),
+        )?;
+
+        conn.map_window(window)?;
+        conn.close_font(font)?;
+        conn.sync()?;
+
+        let overlay = Self {
+            conn,
+            window,
+            gc,
+            xor_gc,
+            width,
+            height,
+            depth,
+            pixels,
+        };
+        overlay.grab_input()?;
+
+        Ok(overlay)
+    }
+
+    /// Grabs the pointer and keyboard so that all input goes to the overlay.
+    fn grab_input(&self) -> Result<()> {
+        const GRAB_ATTEMPTS: u32 = 50;
+
+        // The window may not be viewable immediately after mapping, so retry briefly
+        for _ in 0..GRAB_ATTEMPTS {
+            let pointer = self
+                .conn
+                .grab_pointer(
+                    false,
+                    self.window,
+                    EventMask::BUTTON_PRESS | EventMask::BUTTON_RELEASE | EventMask::POINTER_MOTION,
+                    GrabMode::ASYNC,
+                    GrabMode::ASYNC,
+                    self.window,
+                    x11rb::NONE,
+                    CURRENT_TIME,
+                )?
+                .reply()?;
+            if pointer.status == GrabStatus::SUCCESS {
+                self.conn
+                    .grab_keyboard(
+                        false,
+                        self.window,
+                        CURRENT_TIME,
+                        GrabMode::ASYNC,
+                        GrabMode::ASYNC,
+                    )?
+                    .reply()?;
+                return Ok(());
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+
+        bail!("Failed to grab the pointer for the overlay window");
+    }
+
+    /// Paints the frozen screenshot, split into strips that fit the maximum request size.
+    fn draw_screenshot(&self) -> Result<()> {
+        let row_bytes = usize::from(self.width) * 4;
+        let max_rows = ((self.conn.maximum_request_bytes() - 64) / row_bytes).max(1);
+
+        for (i, strip) in self.pixels.chunks(row_bytes * max_rows).enumerate() {
+            let rows = u16::try_from(strip.len() / row_bytes)?;
+            let y = i16::try_from(i * max_rows)?;
+            self.conn.put_image(
+                ImageFormat::Z_PIXMAP,
+                self.window,
+                self.gc,
+                self.width,
+                rows,
+                0,
+                y,
+                0,
+                self.depth,
+                strip,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Toggles a rectangle outline using the XOR context.
+    fn toggle_rectangle(&self, region: &Region) -> Result<()> {
+        self.conn.poly_rectangle(
+            self.window,
+            self.xor_gc,
+            &[Rectangle {
+                x: i16::try_from(region.x)?,
+                y: i16::try_from(region.y)?,
+                width: u16::try_from(region.width)?,
+                height: u16::try_from(region.height)?,
+            }],
+        )?;
+        self.conn.flush()?;
+        Ok(())
+    }
+
+    /// Toggles a crosshair spanning the whole overlay through `point`, using the XOR context.
+    fn toggle_crosshair(&self, point: Point) -> Result<()> {
+        let (x, y) = (i16::try_from(point.x)?, i16::try_from(point.y)?);
+        let (right, bottom) = (i16::try_from(self.width)?, i16::try_from(self.height)?);
+        self.conn.poly_segment(
+            self.window,
+            self.xor_gc,
+            &[
+                Segment {
+                    x1: 0,
+                    y1: y,
+                    x2: right,
+                    y2: y,
+                },
+                Segment {
+                    x1: x,
+                    y1: 0,
+                    x2: x,
+                    y2: bottom,
+                },
+            ],
+        )?;
+        self.conn.flush()?;
+        Ok(())
+    }
+
+    /// Draws a crosshair following the cursor until the user left clicks and returns the
+    /// clicked point in screen coordinates.
+    ///
+    /// `on_move` is called with every position the cursor moves to. Any key press cancels.
+    pub fn pick_point(&mut self, mut on_move: impl FnMut(Point) -> Result<()>) -> Result<Point> {
+        let mut current: Option<Point> = None;
+
+        loop {
+            match self.conn.wait_for_event()? {
+                Event::Expose(e) if e.count == 0 => {
+                    self.draw_screenshot()?;
+                    if let Some(point) = current {
+                        self.toggle_crosshair(point)?;
+                    }
+                    self.conn.flush()?;
+                }
+                Event::MotionNotify(e) => {
+                    if let Some(previous) = current.take() {
+                        self.toggle_crosshair(previous)?;
+                    }
+                    let point = Point::new(i32::from(e.event_x), i32::from(e.event_y));
+                    self.toggle_crosshair(point)?;
+                    current = Some(point);
+                    on_move(Point::new(i32::from(e.root_x), i32::from(e.root_y)))?;
+                }
+                Event::ButtonPress(e) if e.detail == 1 => {
+                    return Ok(Point::new(i32::from(e.root_x), i32::from(e.root_y)));
+                }
+                Event::KeyPress(_) => bail!("Point selection cancelled"),
+                _ => {}
+            }
+        }
+    }
+
+    /// Lets the user drag out a rectangle with the left mouse button.
+    ///
+    /// Any key press cancels the selection.
+    pub fn select_region(&mut self) -> Result<Region> {
+        let mut anchor: Option<Point> = None;
+        let mut current: Option<Region> = None;
+
+        loop {
+            match self.conn.wait_for_event()? {
+                Event::Expose(e) if e.count == 0 => {
+                    self.draw_screenshot()?;
+                    if let Some(region) = &current {
+                        self.toggle_rectangle(region)?;
+                    }
+                    self.conn.flush()?;
+                }
+                Event::ButtonPress(e) if e.detail == 1 => {
+                    anchor = Some(Point::new(i32::from(e.event_x), i32::from(e.event_y)));
+                }
+                Event::MotionNotify(e) => {
+                    if let Some(start) = anchor {
+                        if let Some(previous) = current.take() {
+                            self.toggle_rectangle(&previous)?;
+                        }
+                        let end = Point::new(i32::from(e.event_x), i32::from(e.event_y));
+                        let region = Region::from_corners(start, end);
+                        self.toggle_rectangle(&region)?;
+                        current = Some(region);
+                    }
+                }
+                Event::ButtonRelease(e) if e.detail == 1 => {
+                    if let Some(start) = anchor {
+                        let end = Point::new(i32::from(e.event_x), i32::from(e.event_y));
+                        return Ok(Region::from_corners(start, end));
+                    }
+                }
+                Event::KeyPress(_) => bail!("Region selection cancelled"),
+                _ => {}
+            }
+        }
+    }
+}
+
+impl Drop for Overlay {
+    fn drop(&mut self) {
+        // Best effort cleanup, the server releases everything when the connection closes anyway
+        let _ = self.conn.ungrab_pointer(CURRENT_TIME);
+        let _ = self.conn.ungrab_keyboard(CURRENT_TIME);
+        let _ = self.conn.free_gc(self.gc);
+        let _ = self.conn.free_gc(self.xor_gc);
+        let _ = self.conn.destroy_window(self.window);
+        let _ = self.conn.flush();
+    }
+}
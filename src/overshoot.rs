@@ -0,0 +1,79 @@
+//! Overshooting the destination of longer mouse movements.
+//!
+//! Fast aimed movements often carry the hand a little past the target, which is then corrected
+//! with a short, slow movement back onto it. With a configurable probability, a movement is aimed
+//! at a point just beyond its destination instead, and the caller corrects from there.
+use crate::forbidden;
+use crate::windmouse::Point;
+
+use anyhow::{ensure, Result};
+
+/// How often and how far mouse movements overshoot their destination.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Overshoot {
+    /// Probability that a movement overshoots, 0 disables overshooting.
+    pub probability: f64,
+}
+
+impl Overshoot {
+    /// Movements shorter than this in pixels land directly.
+    const MIN_DISTANCE_PX: f64 = 80.0;
+    /// Distance past the destination as a fraction of the movement's length.
+    const DISTANCE_FRACTION: std::ops::RangeInclusive<f64> = 0.03..=0.08;
+    /// Bounds in pixels of the distance past the destination.
+    const DISTANCE_PX: std::ops::RangeInclusive<f64> = 3.0..=24.0;
+    /// Largest sideways scatter as a fraction of the distance past the destination.
+    const MAX_SCATTER: f64 = 0.4;
+
+    /// Checks that the probability is usable.
+    pub fn validate(&self) -> Result<()> {
+        ensure!(
+            (0.0..=1.0).contains(&self.probability),
+            "Overshoot probability must be between 0 and 1, got {}",
+            self.probability
+        );
+        Ok(())
+    }
+
+    /// Returns the point past `dest` a movement from `start` should overshoot to, or `None` when
+    /// it should land directly.
+    ///
+    /// Points in or next to a forbidden region are never returned.
+    pub fn point(&self, start: Point, dest: Point) -> Option<Point> {
+        let (dx, dy) = (f64::from(dest.x - start.x), f64::from(dest.y - start.y));
+        let distance = dx.hypot(dy);
+        if distance < Self::MIN_DISTANCE_PX || !rand::random_bool(self.probability) {
+            return None;
+        }
+
+        let past = (distance * rand::random_range(Self::DISTANCE_FRACTION))
+            .clamp(*Self::DISTANCE_PX.start(), *Self::DISTANCE_PX.end());
+        let scatter = rand::random_range(-Self::MAX_SCATTER..=Self::MAX_SCATTER) * past;
+        let (ux, uy) = (dx / distance, dy / distance);
+        let point = Point::new(
+            dest.x + (ux * past - uy * scatter).round() as i32,
+            dest.y + (uy * past + ux * scatter).round() as i32,
+        );
+        forbidden::allows(point).then_some(point)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overshoots_long_movements_a_few_pixels_past_the_destination() {
+        let always = Overshoot { probability: 1.0 };
+        let start = Point::new(0, 0);
+        let dest = Point::new(400, 0);
+        assert_eq!(always.point(start, Point::new(50, 0)), None);
+        for _ in 0..50 {
+            let point = always.point(start, dest).unwrap();
+            assert!((403..=426).contains(&point.x), "overshot to {}", point);
+            assert!(point.y.abs() <= 10, "overshot to {}", point);
+        }
+        let never = Overshoot { probability: 0.0 };
+        assert_eq!(never.point(start, dest), None);
+    }
+}
@@ -0,0 +1,155 @@
+//! Pacing that keeps the bot from pinning a CPU core.
+//!
+//! Three limits work together: a minimum sleep after every executed event, a cap on how often the
+//! screen is captured, and an optional soft CPU usage target. The CPU target is enforced by
+//! sampling the process's own CPU time and stretching the post-event sleep whenever usage since the
+//! previous sample was above the target.
+use crate::config::BotConfig;
+use crate::profile::{self, Phase};
+
+use anyhow::{Context, Result};
+use log::{debug, warn};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Clock ticks per second used by `/proc/<pid>/stat`, fixed at 100 on every mainstream kernel.
+const USER_HZ: u64 = 100;
+
+/// Longest extra sleep added in a single cycle to get back under the CPU target.
+const MAX_THROTTLE: Duration = Duration::from_secs(5);
+
+/// Minimum time in µs between two screen captures, zero when uncapped.
+static MIN_CAPTURE_INTERVAL_US: AtomicU64 = AtomicU64::new(0);
+
+/// When the last screen capture started.
+static LAST_CAPTURE: Mutex<Option<Instant>> = Mutex::new(None);
+
+/// Caps screen captures at `fps` frames per second, zero disables the cap.
+pub fn set_max_capture_fps(fps: u32) {
+    let interval = if fps == 0 {
+        0
+    } else {
+        1_000_000 / u64::from(fps)
+    };
+    MIN_CAPTURE_INTERVAL_US.store(interval, Ordering::Relaxed);
+}
+
+/// Blocks until the capture cap allows taking another screenshot.
+pub fn wait_for_capture_slot() {
+    let _phase = profile::phase(Phase::Sleep);
+    let interval = Duration::from_micros(MIN_CAPTURE_INTERVAL_US.load(Ordering::Relaxed));
+    let Ok(mut last) = LAST_CAPTURE.lock() else {
+        return;
+    };
+    if let Some(wait) = last.and_then(|last| interval.checked_sub(last.elapsed())) {
+        std::thread::sleep(wait);
+    }
+    *last = Some(Instant::now());
+}
+
+/// Reads the CPU time consumed by this process so far.
+fn process_cpu_time() -> Result<Duration> {
+    let stat =
+        std::fs::read_to_string("/proc/self/stat").context("Failed to read /proc/self/stat")?;
+    // The command name may contain spaces, the remaining fields start after its closing paren
+    let fields: Vec<&str> = stat
+        .rsplit_once(')')
+        .context("Malformed /proc/self/stat")?
+        .1
+        .split_whitespace()
+        .collect();
+    let ticks = |i: usize| -> Result<u64> {
+        fields
+            .get(i)
+            .context("Malformed /proc/self/stat")?
+            .parse::<u64>()
+            .context("Malformed /proc/self/stat")
+    };
+    // utime and stime are the 14th and 15th fields, the first two were split off above
+    let total = ticks(11)? + ticks(12)?;
+    Ok(Duration::from_millis(total * 1_000 / USER_HZ))
+}
+
+/// CPU time consumed at a point in wall clock time.
+#[derive(Debug, Clone, Copy)]
+struct CpuSample {
+    cpu: Duration,
+    at: Instant,
+}
+
+impl CpuSample {
+    fn now() -> Option<Self> {
+        match process_cpu_time() {
+            Ok(cpu) => Some(Self {
+                cpu,
+                at: Instant::now(),
+            }),
+            Err(e) => {
+                warn!("Failed to sample CPU usage: {:#}", e);
+                None
+            }
+        }
+    }
+}
+
+/// Sleeps between executed events to honor the minimum cycle sleep and the CPU target.
+pub struct Pacer {
+    min_cycle_sleep: Duration,
+    max_cpu_percent: Option<f64>,
+    first_sample: Option<CpuSample>,
+    last_sample: Option<CpuSample>,
+    throttled: Duration,
+}
+
+impl Pacer {
+    /// Creates a pacer from the pacing options and applies the capture cap.
+    pub fn from_config(config: &BotConfig) -> Self {
+        set_max_capture_fps(config.max_capture_fps);
+        let sample = CpuSample::now();
+        Self {
+            min_cycle_sleep: Duration::from_millis(config.min_cycle_sleep),
+            max_cpu_percent: config.max_cpu_percent,
+            first_sample: sample,
+            last_sample: sample,
+            throttled: Duration::ZERO,
+        }
+    }
+
+    /// Sleeps at the end of a cycle, longer when CPU usage since the last cycle was too high.
+    pub fn end_cycle(&mut self) {
+        let mut sleep = self.min_cycle_sleep;
+
+        if let (Some(target), Some(last)) = (self.max_cpu_percent, self.last_sample) {
+            self.last_sample = CpuSample::now();
+            if let Some(now) = self.last_sample {
+                let cpu = now.cpu.saturating_sub(last.cpu);
+                let wall = now.at.duration_since(last.at);
+                // Sleeping until the cycle's CPU time is `target` percent of its wall time
+                let needed = cpu.mul_f64(100.0 / target).saturating_sub(wall);
+                let throttle = needed.min(MAX_THROTTLE);
+                if throttle > sleep {
+                    debug!("CPU usage above {}%, throttling for {:?}", target, throttle);
+                    self.throttled += throttle - sleep;
+                    sleep = throttle;
+                }
+            }
+        }
+
+        let _phase = profile::phase(Phase::Sleep);
+        std::thread::sleep(sleep);
+    }
+
+    /// Average CPU usage in percent of one core since the pacer was created.
+    pub fn cpu_percent(&self) -> Option<f64> {
+        let first = self.first_sample?;
+        let cpu = process_cpu_time().ok()?.saturating_sub(first.cpu);
+        let wall = first.at.elapsed();
+        (!wall.is_zero()).then(|| cpu.as_secs_f64() / wall.as_secs_f64() * 100.0)
+    }
+
+    /// Total extra time slept to stay under the CPU target.
+    pub fn throttled(&self) -> Duration {
+        self.throttled
+    }
+}
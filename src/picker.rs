@@ -0,0 +1,210 @@
+//! Interactive helpers for authoring bot scripts.
+//!
+//! These tools let the user point at things on screen and print the corresponding script JSON,
+//! so that coordinates and regions don't have to be measured by hand.
+use crate::overlay::Overlay;
+use crate::vision::{
+    self, ColorTarget, HashAlgorithm, PixelColor, Region, ResultMode, ScanOptions,
+};
+use crate::windmouse::Point;
+use crate::window;
+
+use anyhow::{ensure, Context, Result};
+use device_query::{DeviceQuery, DeviceState};
+use std::collections::HashMap;
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+/// Lets the user drag a rectangle on a frozen screenshot and prints it as region JSON.
+pub fn select_region() -> Result<()> {
+    let screenshot = vision::capture_screen_rgba().context("Failed to capture the screen")?;
+    let region = {
+        let mut overlay = Overlay::new(&screenshot).context("Failed to open overlay window")?;
+        overlay.select_region()?
+    };
+
+    println!("{}", serde_json::to_string_pretty(&region)?);
+
+    Ok(())
+}
+
+/// Draws a crosshair following the cursor over a frozen screenshot with live coordinates, and
+/// prints the point JSON once the user left clicks.
+///
+/// When `window_title` is given, coordinates are reported relative to that window's origin.
+pub fn pick_point(window_title: Option<&str>) -> Result<()> {
+    let target_window = window_title.map(window::find_window).transpose()?;
+    if let Some(info) = &target_window {
+        eprintln!(
+            "Reporting coordinates relative to '{}' ({}x{} at {})",
+            info.title, info.width, info.height, info.origin
+        );
+    }
+    eprintln!("Left click to pick a point, press any key to cancel");
+
+    let relative = |absolute: Point| match &target_window {
+        Some(info) => info.to_relative(absolute),
+        None => absolute,
+    };
+    let screenshot = vision::capture_screen_rgba().context("Failed to capture the screen")?;
+    let picked = {
+        let mut overlay = Overlay::new(&screenshot).context("Failed to open overlay window")?;
+        overlay.pick_point(|absolute| {
+            eprint!("\r{:<24}", relative(absolute).to_string());
+            std::io::stderr().flush()?;
+            Ok(())
+        })
+    };
+    eprintln!();
+    let point = relative(picked?);
+    println!("{}", serde_json::to_string_pretty(&point)?);
+
+    Ok(())
+}
+
+/// Streams the color under the cursor at 10 Hz until interrupted.
+///
+/// With `on_change` set, a line is printed only when the color under the cursor changes.
+pub fn watch_color(on_change: bool) -> Result<()> {
+    const WATCH_INTERVAL: Duration = Duration::from_millis(100);
+
+    eprintln!("Watching the color under the cursor, press Ctrl+C to stop");
+
+    let device_state = DeviceState::new();
+    let mut last_color = None;
+    loop {
+        let mouse = device_state.get_mouse();
+        let point = Point::new(mouse.coords.0, mouse.coords.1);
+        let color = vision::color_at(point)?;
+
+        if !on_change || last_color != Some(color) {
+            println!(
+                "{:<14} {:<20} {}",
+                point.to_string(),
+                color.to_string(),
+                color.to_hsv()
+            );
+            last_color = Some(color);
+        }
+
+        std::thread::sleep(WATCH_INTERVAL);
+    }
+}
+
+/// Observes a screen region for a while and prints a target covering its colors.
+///
+/// The target is a palette of the observed colors, or the tightest HSV range containing them
+/// when `hsv` is set.
+///
+/// Colors are ranked by how often they were seen. Near-duplicates within the matching tolerance
+/// are merged and colors covering less than `MIN_SHARE` of the samples are dropped as noise.
+pub fn sample_target(region: Region, duration: Duration, hsv: bool) -> Result<()> {
+    const SAMPLE_INTERVAL: Duration = Duration::from_millis(100);
+    const MIN_SHARE: f64 = 0.01;
+    const MAX_COLORS: usize = 16;
+
+    ensure!(
+        region.width > 0 && region.height > 0,
+        "Sample region must not be empty"
+    );
+
+    eprintln!("Sampling region for {:?}", duration);
+
+    let mut counts: HashMap<[u8; 3], usize> = HashMap::new();
+    let mut total = 0;
+    let mut frames = 0;
+    let start = Instant::now();
+    while start.elapsed() < duration {
+        let screenshot = vision::capture_screen_rgba().context("Failed to capture the screen")?;
+        for y in region.y..region.y + i32::try_from(region.height)? {
+            for x in region.x..region.x + i32::try_from(region.width)? {
+                let (Ok(px), Ok(py)) = (u32::try_from(x), u32::try_from(y)) else {
+                    continue;
+                };
+                if let Some(pixel) = screenshot.get_pixel_checked(px, py) {
+                    *counts.entry([pixel[0], pixel[1], pixel[2]]).or_default() += 1;
+                    total += 1;
+                }
+            }
+        }
+        frames += 1;
+        std::thread::sleep(SAMPLE_INTERVAL);
+    }
+    ensure!(total > 0, "Sample region lies outside the captured screen");
+
+    let mut ranked: Vec<([u8; 3], usize)> = counts.into_iter().collect();
+    ranked.sort_by_key(|&(_, count)| std::cmp::Reverse(count));
+
+    let mut colors: Vec<[u8; 3]> = Vec::new();
+    for (rgb, count) in ranked {
+        if colors.len() >= MAX_COLORS || (count as f64) < total as f64 * MIN_SHARE {
+            break;
+        }
+        let candidate = PixelColor::new(rgb[0], rgb[1], rgb[2]);
+        let is_duplicate = colors.iter().any(|c| {
+            candidate.is_match(&PixelColor::new(c[0], c[1], c[2]), ColorTarget::TOLERANCE)
+        });
+        if !is_duplicate {
+            colors.push(rgb);
+        }
+    }
+
+    eprintln!(
+        "Observed {} frames, keeping {} distinct colors",
+        frames,
+        colors.len()
+    );
+    let target = if hsv {
+        let pixels: Vec<PixelColor> = colors
+            .iter()
+            .map(|c| PixelColor::new(c[0], c[1], c[2]))
+            .collect();
+        ColorTarget::hsv_covering(&pixels).context("No colors were sampled")?
+    } else {
+        ColorTarget::Palette { colors }
+    };
+
+    // Report how much of the whole screen the target covers, since a target that matches large
+    // parts of the background will produce misclicks
+    let count_only = ScanOptions {
+        result_mode: ResultMode::CountOnly,
+        ..Default::default()
+    };
+    let on_screen = vision::scan_screen(&target, &[], &count_only)?.count;
+    let in_region = vision::scan_screen(
+        &target,
+        &[],
+        &ScanOptions {
+            search_region: Some(region),
+            ..count_only
+        },
+    )?
+    .count;
+    eprintln!(
+        "Target matches {} pixels on the current screen, {} of them inside the region",
+        on_screen, in_region
+    );
+
+    println!("{}", serde_json::to_string_pretty(&target)?);
+
+    Ok(())
+}
+
+/// Hashes a region of the screen and prints a `phash_matches` signature that recognizes it.
+pub fn phash(region: Region, algorithm: HashAlgorithm) -> Result<()> {
+    ensure!(
+        region.width > 0 && region.height > 0,
+        "Hashed region must not be empty"
+    );
+    let hash = vision::region_hash(&region, algorithm)?;
+    let signature = serde_json::json!({
+        "phash_matches": {
+            "region": region,
+            "hash": vision::format_hash(hash),
+            "max_distance": 6,
+            "algorithm": algorithm,
+        }
+    });
+    println!("{}", serde_json::to_string_pretty(&signature)?);
+    Ok(())
+}
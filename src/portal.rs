@@ -0,0 +1,387 @@
+//! Screen capture through the xdg-desktop-portal ScreenCast interface.
+//!
+//! On Wayland sessions, capturing the X11 root window only shows windows running under XWayland.
+//! The portal asks the compositor to share a monitor as a PipeWire stream instead, which works on
+//! any desktop that ships xdg-desktop-portal. The first run asks which monitor to share, later
+//! runs reuse that choice through the restore token the portal hands out. Frames are read from
+//! the stream by GStreamer's `pipewiresrc` in a `gst-launch-1.0` child writing raw RGBA frames to
+//! a pipe, and a reader thread keeps the newest one for the next capture.
+use crate::process::Supervised;
+use crate::rundir::RunDir;
+
+use anyhow::{anyhow, bail, ensure, Context, Result};
+use image::RgbaImage;
+use log::{debug, warn};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::Read;
+use std::os::fd::OwnedFd;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use zbus::blocking::{Connection, Proxy};
+use zbus::zvariant::{self, DeserializeDict, DynamicType, ObjectPath, OwnedValue, Type, Value};
+
+const PORTAL_DESTINATION: &str = "org.freedesktop.portal.Desktop";
+const PORTAL_PATH: &str = "/org/freedesktop/portal/desktop";
+const SCREENCAST_INTERFACE: &str = "org.freedesktop.portal.ScreenCast";
+const SESSION_INTERFACE: &str = "org.freedesktop.portal.Session";
+const REQUEST_INTERFACE: &str = "org.freedesktop.portal.Request";
+
+/// Source type bit of a monitor in `SelectSources`.
+const SOURCE_MONITOR: u32 = 1;
+/// Cursor mode bit leaving the cursor out of the frames, so it's never mistaken for a target.
+const CURSOR_HIDDEN: u32 = 1;
+/// Persist mode keeping the shared monitor until the user revokes it, needs version 4.
+const PERSIST_UNTIL_REVOKED: u32 = 2;
+/// Longest wait for the stream's first frame.
+const FIRST_FRAME_TIMEOUT: Duration = Duration::from_secs(5);
+/// Delay between checks for the first frame.
+const FRAME_POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+/// Session bus connection shared by every portal call.
+static CONNECTION: Mutex<Option<Connection>> = Mutex::new(None);
+/// Open screencast session, set up on the first capture.
+static SESSION: Mutex<Option<Session>> = Mutex::new(None);
+
+/// Results of `CreateSession`.
+#[derive(DeserializeDict, Type)]
+#[zvariant(signature = "dict")]
+struct CreateSessionResults {
+    session_handle: String,
+}
+
+/// Results of `Start`.
+#[derive(DeserializeDict, Type)]
+#[zvariant(signature = "dict")]
+struct StartResults {
+    streams: Vec<Stream>,
+    restore_token: Option<String>,
+}
+
+/// PipeWire node id and properties of a shared stream.
+#[derive(Deserialize, Type)]
+struct Stream(u32, StreamProperties);
+
+#[derive(DeserializeDict, Type)]
+#[zvariant(signature = "dict")]
+struct StreamProperties {
+    /// Size of the monitor in the compositor's logical coordinates.
+    size: Option<(i32, i32)>,
+}
+
+/// Shared monitor being read by a GStreamer child.
+struct Session {
+    handle: zvariant::OwnedObjectPath,
+    width: u32,
+    height: u32,
+    latest: Arc<Mutex<Option<RgbaImage>>>,
+    child: Supervised,
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        // Best effort, the portal also closes the session when the bus connection goes away
+        if let Ok(connection) = connection() {
+            let _ = Proxy::new(
+                &connection,
+                PORTAL_DESTINATION,
+                &self.handle,
+                SESSION_INTERFACE,
+            )
+            .and_then(|session| session.call::<_, _, ()>("Close", &()));
+        }
+    }
+}
+
+/// Returns the session bus connection, connecting on first use.
+fn connection() -> Result<Connection> {
+    let mut guard = CONNECTION
+        .lock()
+        .map_err(|_| anyhow!("Portal connection lock is poisoned"))?;
+    if let Some(connection) = guard.as_ref() {
+        return Ok(connection.clone());
+    }
+    let connection = Connection::session().context("Failed to connect to the D-Bus session bus")?;
+    *guard = Some(connection.clone());
+    Ok(connection)
+}
+
+/// Returns a proxy of the ScreenCast interface.
+fn screencast(connection: &Connection) -> Result<Proxy<'static>> {
+    Ok(Proxy::new(
+        connection,
+        PORTAL_DESTINATION,
+        PORTAL_PATH,
+        SCREENCAST_INTERFACE,
+    )?)
+}
+
+/// Returns the version of the portal's ScreenCast interface, failing when it isn't available.
+pub fn screencast_version() -> Result<u32> {
+    screencast(&connection()?)?
+        .get_property("version")
+        .context("The ScreenCast portal is not available")
+}
+
+/// Checks that GStreamer and its PipeWire plugin, which read the shared monitor, are installed.
+pub fn check_gstreamer() -> Result<()> {
+    let found = Command::new("gst-inspect-1.0")
+        .arg("pipewiresrc")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .context("gst-inspect-1.0 isn't installed, install GStreamer")?;
+    ensure!(
+        found.success(),
+        "GStreamer's pipewiresrc element is missing, install the PipeWire GStreamer plugin"
+    );
+    Ok(())
+}
+
+/// Calls a portal method that answers through a Request object, returning the response's
+/// results.
+///
+/// `options` must carry `token` as its `handle_token`, which names the Request object.
+fn request<B, R>(connection: &Connection, method: &str, body: &B, token: &str) -> Result<R>
+where
+    B: Serialize + DynamicType,
+    R: DeserializeOwned + Type,
+{
+    let sender = connection
+        .unique_name()
+        .context("The session bus connection has no name")?
+        .trim_start_matches(':')
+        .replace('.', "_");
+    // Subscribe to the response before making the call so it can't be missed
+    let request = Proxy::new(
+        connection,
+        PORTAL_DESTINATION,
+        format!("{}/request/{}/{}", PORTAL_PATH, sender, token),
+        REQUEST_INTERFACE,
+    )?;
+    let mut responses = request.receive_signal("Response")?;
+
+    let _: zvariant::OwnedObjectPath = screencast(connection)?.call(method, body).context(
+        format!("Failed to call {} on the ScreenCast portal", method),
+    )?;
+
+    let message = responses
+        .next()
+        .context("The portal closed the request without responding")?;
+    let (response, results): (u32, R) = message.body().deserialize().context(format!(
+        "Failed to read the portal's response to {}",
+        method
+    ))?;
+    match response {
+        0 => Ok(results),
+        1 => bail!("Screen sharing was cancelled in the portal's dialog"),
+        _ => bail!(
+            "The portal refused {} (response {}), allow screen sharing for this app in the \
+             desktop's privacy settings",
+            method,
+            response
+        ),
+    }
+}
+
+/// Returns a new token naming a Request or Session object.
+fn new_token() -> String {
+    format!("colorbot{}", rand::random::<u32>())
+}
+
+/// Returns where the token restoring the shared monitor without a dialog is kept.
+fn restore_token_path() -> PathBuf {
+    RunDir::default_root().with_file_name("screencast-token")
+}
+
+/// Remembers the token restoring the shared monitor, only warning when it can't be saved.
+fn save_restore_token(token: &str) {
+    let path = restore_token_path();
+    let saved = path
+        .parent()
+        .map_or(Ok(()), std::fs::create_dir_all)
+        .and_then(|_| std::fs::write(&path, token));
+    if let Err(e) = saved {
+        warn!(
+            "Failed to save the screencast restore token to {}: {}",
+            path.display(),
+            e
+        );
+    }
+}
+
+/// Asks the portal to share a monitor and starts reading it.
+fn open_session() -> Result<Session> {
+    let connection = connection()?;
+    let proxy = screencast(&connection)?;
+    let version: u32 = proxy
+        .get_property("version")
+        .context("The ScreenCast portal is not available")?;
+
+    let token = new_token();
+    let options = HashMap::from([
+        ("handle_token", Value::from(token.as_str())),
+        ("session_handle_token", Value::from(new_token())),
+    ]);
+    let created: CreateSessionResults = request(&connection, "CreateSession", &options, &token)?;
+    let handle: zvariant::OwnedObjectPath = ObjectPath::try_from(created.session_handle.as_str())
+        .context("The portal returned an invalid session handle")?
+        .into();
+
+    let token = new_token();
+    let mut options = HashMap::from([
+        ("handle_token", Value::from(token.as_str())),
+        ("types", Value::from(SOURCE_MONITOR)),
+        ("multiple", Value::from(false)),
+    ]);
+    let cursor_modes: u32 = proxy.get_property("AvailableCursorModes").unwrap_or(0);
+    if cursor_modes & CURSOR_HIDDEN != 0 {
+        options.insert("cursor_mode", Value::from(CURSOR_HIDDEN));
+    }
+    let restore_token = std::fs::read_to_string(restore_token_path()).ok();
+    if version >= 4 {
+        options.insert("persist_mode", Value::from(PERSIST_UNTIL_REVOKED));
+        if let Some(restore_token) = &restore_token {
+            options.insert("restore_token", Value::from(restore_token.trim()));
+        }
+    }
+    let _: HashMap<String, OwnedValue> =
+        request(&connection, "SelectSources", &(&handle, options), &token)?;
+
+    let token = new_token();
+    let options = HashMap::from([("handle_token", Value::from(token.as_str()))]);
+    let started: StartResults = request(&connection, "Start", &(&handle, "", options), &token)?;
+    if let Some(restore_token) = &started.restore_token {
+        save_restore_token(restore_token);
+    }
+    let Stream(node, properties) = started
+        .streams
+        .into_iter()
+        .next()
+        .context("The portal started screen sharing without a stream")?;
+    let (width, height) = properties
+        .size
+        .context("The portal didn't report the size of the shared monitor")?;
+    let (width, height) = (u32::try_from(width)?, u32::try_from(height)?);
+
+    let remote: zvariant::OwnedFd = proxy
+        .call(
+            "OpenPipeWireRemote",
+            &(&handle, HashMap::<&str, Value>::new()),
+        )
+        .context("Failed to open the PipeWire connection of the screencast")?;
+    // The connection is handed to the GStreamer child as its stdin, so no other child inherits it
+    let remote = Stdio::from(OwnedFd::from(remote));
+
+    // Frames are scaled to the monitor's logical size, which the compositor places input in
+    let mut child = Supervised::spawn(
+        Command::new("gst-launch-1.0").args([
+            "-q".to_string(),
+            "pipewiresrc".to_string(),
+            "fd=0".to_string(),
+            format!("path={}", node),
+            "!".to_string(),
+            "videoconvert".to_string(),
+            "!".to_string(),
+            "videoscale".to_string(),
+            "!".to_string(),
+            format!("video/x-raw,format=RGBA,width={},height={}", width, height),
+            "!".to_string(),
+            "fdsink".to_string(),
+            "sync=false".to_string(),
+        ]),
+        "gst-launch-1.0",
+        remote,
+    )
+    .context("Install GStreamer and its PipeWire plugin")?;
+    let stdout = child.take_stdout().context("No pipe to gst-launch-1.0")?;
+
+    let latest = Arc::new(Mutex::new(None));
+    let frames = Arc::clone(&latest);
+    std::thread::Builder::new()
+        .name("screencast".to_string())
+        .spawn(move || read_frames(stdout, width, height, &frames))
+        .context("Failed to start the screencast reader thread")?;
+    debug!(
+        "Sharing a {}x{} monitor through PipeWire node {}",
+        width, height, node
+    );
+
+    Ok(Session {
+        handle,
+        width,
+        height,
+        latest,
+        child,
+    })
+}
+
+/// Reads raw frames until the stream ends, keeping the newest one.
+fn read_frames(mut stream: impl Read, width: u32, height: u32, latest: &Mutex<Option<RgbaImage>>) {
+    let size = width as usize * height as usize * 4;
+    loop {
+        let mut pixels = vec![0; size];
+        if let Err(e) = stream.read_exact(&mut pixels) {
+            debug!("The screencast stream ended: {}", e);
+            return;
+        }
+        if let (Some(frame), Ok(mut latest)) =
+            (RgbaImage::from_raw(width, height, pixels), latest.lock())
+        {
+            *latest = Some(frame);
+        }
+    }
+}
+
+/// Starts sharing a monitor unless it's shared already, asking the user which one on the first
+/// run.
+pub fn open() -> Result<()> {
+    let mut session = SESSION
+        .lock()
+        .map_err(|_| anyhow!("Screencast session lock is poisoned"))?;
+    if session.is_none() {
+        *session = Some(open_session()?);
+    }
+    Ok(())
+}
+
+/// Returns the newest frame of the shared monitor, sharing one first when none is.
+pub fn capture() -> Result<RgbaImage> {
+    open()?;
+    let mut guard = SESSION
+        .lock()
+        .map_err(|_| anyhow!("Screencast session lock is poisoned"))?;
+    let session = guard.as_mut().context("No monitor is shared")?;
+    let deadline = Instant::now() + FIRST_FRAME_TIMEOUT;
+    loop {
+        // A session that ended is dropped and shared again on the next capture
+        if session.child.exited()? {
+            *guard = None;
+            bail!("The screencast stream ended, see the log for gst-launch-1.0's output");
+        }
+        if let Some(frame) = session.latest.lock().ok().and_then(|frame| frame.clone()) {
+            return Ok(frame);
+        }
+        if Instant::now() >= deadline {
+            *guard = None;
+            bail!(
+                "The screencast stream delivered no frame within {:?}, see the log for \
+                 gst-launch-1.0's output",
+                FIRST_FRAME_TIMEOUT
+            );
+        }
+        std::thread::sleep(FRAME_POLL_INTERVAL);
+    }
+}
+
+/// Returns the size of the shared monitor, `None` while none is shared.
+pub fn stream_size() -> Option<(u32, u32)> {
+    SESSION.lock().ok().and_then(|session| {
+        session
+            .as_ref()
+            .map(|session| (session.width, session.height))
+    })
+}
@@ -0,0 +1,225 @@
+//! Supervision of spawned child processes.
+//!
+//! Every external program is run through [`run`], which enforces a timeout, kills the child if the
+//! caller bails out early, reaps it so no zombies are left behind, and forwards its stderr to the
+//! log. Programs that keep running alongside the bot are started as a [`Supervised`] child, which
+//! is killed and reaped when dropped. Running children are registered so the watchdog can kill
+//! them when the run gets stuck.
+use crate::error::ColorbotError;
+
+use anyhow::{bail, Context, Result};
+use log::debug;
+use nix::sys::signal::{self, Signal};
+use nix::unistd::Pid;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::process::{Child, ChildStdout, Command, Output, Stdio};
+use std::sync::Mutex;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// Longest delay between two checks whether a child exited.
+const MAX_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Process ids of running supervised children.
+static CHILDREN: Mutex<Vec<u32>> = Mutex::new(Vec::new());
+
+/// Running child that is killed and reaped when dropped before it exited.
+struct ChildGuard {
+    child: Child,
+    exited: bool,
+}
+
+impl ChildGuard {
+    fn new(child: Child) -> Self {
+        if let Ok(mut children) = CHILDREN.lock() {
+            children.push(child.id());
+        }
+        Self {
+            child,
+            exited: false,
+        }
+    }
+}
+
+impl Drop for ChildGuard {
+    fn drop(&mut self) {
+        if !self.exited {
+            let _ = self.child.kill();
+            let _ = self.child.wait();
+        }
+        if let Ok(mut children) = CHILDREN.lock() {
+            children.retain(|pid| *pid != self.child.id());
+        }
+    }
+}
+
+/// Reads a child's output stream to the end on a separate thread, so the child never blocks on
+/// a full pipe.
+fn drain(stream: Option<impl Read + Send + 'static>) -> Option<JoinHandle<Vec<u8>>> {
+    stream.map(|mut stream| {
+        thread::spawn(move || {
+            let mut buf = Vec::new();
+            let _ = stream.read_to_end(&mut buf);
+            buf
+        })
+    })
+}
+
+/// Runs `command` to completion, killing it when it takes longer than `timeout`.
+///
+/// `label` names the command in errors and logs instead of its arguments, which may be secret.
+/// Stdin is closed and stdout and stderr are captured, with stderr forwarded to the debug log.
+pub fn run(command: &mut Command, label: &str, timeout: Duration) -> Result<Output> {
+    run_with_stdin(command, label, timeout, None)
+}
+
+/// Like [`run`], but writes `stdin` to the command's standard input when given.
+///
+/// Secrets are passed this way, since any local user can read a process's arguments.
+pub fn run_with_stdin(
+    command: &mut Command,
+    label: &str,
+    timeout: Duration,
+    stdin: Option<&[u8]>,
+) -> Result<Output> {
+    let child = command
+        .stdin(if stdin.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context(format!("Failed to start {}", label))?;
+    let mut guard = ChildGuard::new(child);
+    // Written on a separate thread so a child that doesn't read its input can still time out
+    let writer = guard
+        .child
+        .stdin
+        .take()
+        .zip(stdin)
+        .map(|(mut pipe, input)| {
+            let input = input.to_vec();
+            thread::spawn(move || pipe.write_all(&input))
+        });
+    let stdout = drain(guard.child.stdout.take());
+    let stderr = drain(guard.child.stderr.take());
+
+    let deadline = Instant::now() + timeout;
+    let mut poll_interval = Duration::from_millis(1);
+    let status = loop {
+        if let Some(status) = guard
+            .child
+            .try_wait()
+            .context(format!("Failed to wait for {}", label))?
+        {
+            break status;
+        }
+        if Instant::now() >= deadline {
+            // Dropping the guard kills and reaps the child
+            drop(guard);
+            bail!("{} timed out after {:?} and was killed", label, timeout);
+        }
+        thread::sleep(poll_interval);
+        poll_interval = (poll_interval * 2).min(MAX_POLL_INTERVAL);
+    };
+    guard.exited = true;
+    if let Some(writer) = writer {
+        match writer.join() {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => debug!("{}: failed to write its input: {}", label, e),
+            Err(_) => debug!("{}: the thread writing its input panicked", label),
+        }
+    }
+
+    let join = |handle: Option<JoinHandle<Vec<u8>>>| {
+        handle
+            .and_then(|handle| handle.join().ok())
+            .unwrap_or_default()
+    };
+    let output = Output {
+        status,
+        stdout: join(stdout),
+        stderr: join(stderr),
+    };
+    for line in String::from_utf8_lossy(&output.stderr).lines() {
+        debug!("{}: {}", label, line);
+    }
+
+    Ok(output)
+}
+
+/// Program running alongside the bot, killed and reaped when dropped.
+pub struct Supervised {
+    guard: ChildGuard,
+}
+
+impl Supervised {
+    /// Starts `command` with `stdin` as its standard input, its stdout piped to the caller and
+    /// its stderr forwarded to the debug log under `label`.
+    pub fn spawn(command: &mut Command, label: &str, stdin: Stdio) -> Result<Self> {
+        let child = command
+            .stdin(stdin)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .context(format!("Failed to start {}", label))?;
+        let mut guard = ChildGuard::new(child);
+        if let Some(stderr) = guard.child.stderr.take() {
+            let label = label.to_string();
+            thread::spawn(move || {
+                for line in BufReader::new(stderr).lines().map_while(Result::ok) {
+                    debug!("{}: {}", label, line);
+                }
+            });
+        }
+        Ok(Self { guard })
+    }
+
+    /// Takes the pipe the program writes its output to.
+    pub fn take_stdout(&mut self) -> Option<ChildStdout> {
+        self.guard.child.stdout.take()
+    }
+
+    /// Whether the program exited, reaping it if so.
+    pub fn exited(&mut self) -> Result<bool> {
+        let exited = self
+            .guard
+            .child
+            .try_wait()
+            .context("Failed to check on a supervised child")?
+            .is_some();
+        if exited && !self.guard.exited {
+            self.guard.exited = true;
+            // A reaped child's pid may be reused, so the watchdog mustn't kill it anymore
+            if let Ok(mut children) = CHILDREN.lock() {
+                children.retain(|pid| *pid != self.guard.child.id());
+            }
+        }
+        Ok(exited)
+    }
+}
+
+/// Turns a failure of [`run`] to start `program` because it isn't installed into
+/// [`ColorbotError::ToolMissing`], other failures are returned as they are.
+pub fn missing_tool(error: anyhow::Error, program: &'static str) -> anyhow::Error {
+    match error.downcast_ref::<io::Error>() {
+        Some(e) if e.kind() == io::ErrorKind::NotFound => {
+            ColorbotError::ToolMissing(program).into()
+        }
+        _ => error,
+    }
+}
+
+/// Kills every running supervised child, returning how many were killed.
+pub fn kill_all() -> usize {
+    let Ok(children) = CHILDREN.lock() else {
+        return 0;
+    };
+    // Signalled by pid, the children themselves are owned by the threads that started them
+    children
+        .iter()
+        .filter(|pid| signal::kill(Pid::from_raw(**pid as i32), Signal::SIGKILL).is_ok())
+        .count()
+}
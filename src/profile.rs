@@ -0,0 +1,195 @@
+//! Lightweight self-profiling of where each event spends its time.
+//!
+//! Code marks the phase it's in with a [`PhaseGuard`]. Phases nest and time is charged
+//! exclusively, so a capture inside a scan counts towards the capture phase only. Anything not
+//! covered by a more specific phase while an event runs counts as execution.
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Phase of an event's execution.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    /// Grabbing a frame from the screen.
+    Capture,
+    /// Searching a frame for colors or templates.
+    Scan,
+    /// Grouping matched pixels into clusters.
+    Cluster,
+    /// Choosing the point to move to.
+    Plan,
+    /// Driving input and waiting for the game to react.
+    Execute,
+    /// Deliberate delays between actions.
+    Sleep,
+}
+
+impl Phase {
+    const COUNT: usize = 6;
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// Total time per phase in milliseconds.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct PhaseTimes {
+    pub capture_ms: f64,
+    pub scan_ms: f64,
+    pub cluster_ms: f64,
+    pub plan_ms: f64,
+    pub execute_ms: f64,
+    pub sleep_ms: f64,
+}
+
+impl PhaseTimes {
+    /// Time spent looking for the target, everything before input is sent.
+    pub fn search_ms(&self) -> f64 {
+        self.capture_ms + self.scan_ms + self.cluster_ms + self.plan_ms
+    }
+
+    fn from_durations(durations: &[Duration; Phase::COUNT]) -> Self {
+        let ms = |phase: Phase| durations[phase.index()].as_secs_f64() * 1_000.0;
+        Self {
+            capture_ms: ms(Phase::Capture),
+            scan_ms: ms(Phase::Scan),
+            cluster_ms: ms(Phase::Cluster),
+            plan_ms: ms(Phase::Plan),
+            execute_ms: ms(Phase::Execute),
+            sleep_ms: ms(Phase::Sleep),
+        }
+    }
+}
+
+/// Phase timings of one script event aggregated over all of its runs.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct EventProfile {
+    /// Number of times the event ran.
+    pub runs: u64,
+    /// Time spent in each phase across all runs.
+    pub total: PhaseTimes,
+    /// Average time spent in each phase per run.
+    pub mean: PhaseTimes,
+}
+
+/// Tracks the active phase of the running event and the per-event totals.
+struct Profiler {
+    /// Active phase and when it was last resumed.
+    current: Option<(Phase, Instant)>,
+    /// Phases interrupted by a nested one, innermost last.
+    suspended: Vec<Phase>,
+    /// Durations of the running event.
+    event: [Duration; Phase::COUNT],
+    /// Durations and run counts of every finished event by id.
+    totals: BTreeMap<String, (u64, [Duration; Phase::COUNT])>,
+}
+
+static PROFILER: Mutex<Profiler> = Mutex::new(Profiler {
+    current: None,
+    suspended: Vec::new(),
+    event: [Duration::ZERO; Phase::COUNT],
+    totals: BTreeMap::new(),
+});
+
+impl Profiler {
+    /// Charges the time since the active phase was last resumed to it.
+    fn charge_current(&mut self) {
+        if let Some((phase, since)) = self.current {
+            self.event[phase.index()] += since.elapsed();
+        }
+    }
+
+    fn enter(&mut self, phase: Phase) {
+        self.charge_current();
+        if let Some((outer, _)) = self.current {
+            self.suspended.push(outer);
+        }
+        self.current = Some((phase, Instant::now()));
+    }
+
+    fn exit(&mut self) {
+        self.charge_current();
+        self.current = self.suspended.pop().map(|outer| (outer, Instant::now()));
+    }
+}
+
+/// Marks the enclosing scope as a phase until dropped.
+pub struct PhaseGuard(());
+
+impl Drop for PhaseGuard {
+    fn drop(&mut self) {
+        if let Ok(mut profiler) = PROFILER.lock() {
+            profiler.exit();
+        }
+    }
+}
+
+/// Enters `phase` until the returned guard is dropped.
+#[must_use = "the phase ends when the guard is dropped"]
+pub fn phase(phase: Phase) -> PhaseGuard {
+    if let Ok(mut profiler) = PROFILER.lock() {
+        profiler.enter(phase);
+    }
+    PhaseGuard(())
+}
+
+/// Starts timing a run of a script event, which begins in the execute phase.
+#[must_use = "the event run ends when the guard is dropped"]
+pub fn begin_event(id: &str) -> EventGuard {
+    if let Ok(mut profiler) = PROFILER.lock() {
+        profiler.current = None;
+        profiler.suspended.clear();
+        profiler.event = [Duration::ZERO; Phase::COUNT];
+        profiler.enter(Phase::Execute);
+    }
+    EventGuard { id: id.to_string() }
+}
+
+/// Folds the phase timings of a script event run into the totals when dropped.
+pub struct EventGuard {
+    id: String,
+}
+
+impl Drop for EventGuard {
+    fn drop(&mut self) {
+        let Ok(mut profiler) = PROFILER.lock() else {
+            return;
+        };
+        profiler.exit();
+        let event = profiler.event;
+        let (runs, totals) = profiler.totals.entry(self.id.clone()).or_default();
+        *runs += 1;
+        for (total, duration) in totals.iter_mut().zip(event) {
+            *total += duration;
+        }
+    }
+}
+
+/// Returns the aggregated phase timings of every script event that ran.
+pub fn event_profiles() -> BTreeMap<String, EventProfile> {
+    let Ok(profiler) = PROFILER.lock() else {
+        return BTreeMap::new();
+    };
+    profiler
+        .totals
+        .iter()
+        .map(|(id, (runs, totals))| {
+            let mean = totals.map(|total| total / u32::try_from(*runs).unwrap_or(u32::MAX));
+            let profile = EventProfile {
+                runs: *runs,
+                total: PhaseTimes::from_durations(totals),
+                mean: PhaseTimes::from_durations(&mean),
+            };
+            (id.clone(), profile)
+        })
+        .collect()
+}
+
+/// Discards the phase timings of all finished event runs.
+pub fn reset() {
+    if let Ok(mut profiler) = PROFILER.lock() {
+        profiler.totals.clear();
+    }
+}
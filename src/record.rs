@@ -0,0 +1,214 @@
+//! Recording of real clicks and key presses into a bot script.
+//!
+//! The mouse buttons and keyboard are polled while the user plays normally. Every left click
+//! becomes a color event for the pixel color under the cursor, restricted to a search region
+//! around the clicked point, and every key press becomes a keypress event. The time until the
+//! next recorded action becomes each event's delay.
+use crate::vision;
+use crate::windmouse::Point;
+
+use anyhow::{ensure, Context, Result};
+use device_query::{DeviceQuery, DeviceState, Keycode};
+use log::warn;
+use serde_json::{json, Value};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// Interval between polls of the mouse and keyboard.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+/// Distance in pixels from a recorded click to the edges of its event's search region.
+const REGION_RADIUS_PX: i32 = 40;
+/// Delay in ms of the last recorded event, which has no following action to measure against.
+const LAST_DELAY_MS: u128 = 1000;
+
+/// Index of the left mouse button in device_query's button state.
+const LEFT_BUTTON: usize = 1;
+
+/// Translates a device_query key into its xdotool name and whether it's a modifier.
+pub fn key_name(key: &Keycode) -> Option<(String, bool)> {
+    let name = key.to_string();
+    if let Some(digit) = name.strip_prefix("Key").filter(|digit| digit.len() == 1) {
+        return Some((digit.to_string(), false));
+    }
+    if name.len() == 1 {
+        return Some((name.to_ascii_lowercase(), false));
+    }
+    if name.starts_with('F') && name[1..].parse::<u8>().is_ok() {
+        return Some((name, false));
+    }
+
+    let (xdotool, modifier) = match name.as_str() {
+        "LControl" | "RControl" => ("ctrl", true),
+        "LShift" | "RShift" => ("shift", true),
+        "LAlt" | "RAlt" => ("alt", true),
+        "LMeta" | "RMeta" | "Command" | "RCommand" => ("super", true),
+        "Escape" => ("Escape", false),
+        "Enter" => ("Return", false),
+        "Space" => ("space", false),
+        "Tab" => ("Tab", false),
+        "Backspace" => ("BackSpace", false),
+        "Delete" => ("Delete", false),
+        "Insert" => ("Insert", false),
+        "Home" => ("Home", false),
+        "End" => ("End", false),
+        "PageUp" => ("Prior", false),
+        "PageDown" => ("Next", false),
+        "Up" => ("Up", false),
+        "Down" => ("Down", false),
+        "Left" => ("Left", false),
+        "Right" => ("Right", false),
+        _ => return None,
+    };
+    Some((xdotool.to_string(), modifier))
+}
+
+/// Action captured from the user's input.
+enum Action {
+    Click { point: Point, rgb: [u8; 3] },
+    Key(String),
+}
+
+/// Modifier that's held down, remembered until it's either used in a chord or released alone.
+struct HeldModifier {
+    name: String,
+    pressed_at: Instant,
+    chorded: bool,
+}
+
+/// Records clicks and key presses until `stop_key` is pressed or `duration` has passed, and
+/// writes them as a bot script to `out`.
+pub fn run(out: &Path, stop_key: &str, duration: Option<Duration>) -> Result<()> {
+    eprintln!(
+        "Recording clicks and key presses, press {} to stop{}",
+        stop_key,
+        duration
+            .map(|duration| format!(" (or wait {:?})", duration))
+            .unwrap_or_default()
+    );
+
+    let device_state = DeviceState::new();
+    let start = Instant::now();
+    let mut actions: Vec<(Instant, Action)> = Vec::new();
+    let mut modifiers: Vec<HeldModifier> = Vec::new();
+    let mut unsupported: Vec<String> = Vec::new();
+    // Ignore input that's still held from launching the command
+    let mut was_clicked = true;
+    let mut was_pressed: Vec<Keycode> = device_state.get_keys();
+
+    while duration.is_none_or(|duration| start.elapsed() < duration) {
+        let now = Instant::now();
+        let mouse = device_state.get_mouse();
+        let clicked = mouse
+            .button_pressed
+            .get(LEFT_BUTTON)
+            .copied()
+            .unwrap_or(false);
+        if clicked && !was_clicked {
+            let point = Point::new(mouse.coords.0, mouse.coords.1);
+            let color = vision::color_at(point)
+                .context(format!("Failed to sample the color at {}", point))?;
+            eprintln!("Click at {} on {}", point, color);
+            actions.push((
+                now,
+                Action::Click {
+                    point,
+                    rgb: color.rgb(),
+                },
+            ));
+        }
+        was_clicked = clicked;
+
+        let pressed = device_state.get_keys();
+        for key in pressed.iter().filter(|key| !was_pressed.contains(key)) {
+            let Some((name, modifier)) = key_name(key) else {
+                if !unsupported.contains(&key.to_string()) {
+                    warn!("Ignoring key {}, it has no xdotool name", key);
+                    unsupported.push(key.to_string());
+                }
+                continue;
+            };
+            if name.eq_ignore_ascii_case(stop_key) {
+                return write_script(out, &actions);
+            }
+            if modifier {
+                modifiers.push(HeldModifier {
+                    name,
+                    pressed_at: now,
+                    chorded: false,
+                });
+                continue;
+            }
+
+            let mut chord: Vec<&str> = modifiers
+                .iter_mut()
+                .map(|held| {
+                    held.chorded = true;
+                    held.name.as_str()
+                })
+                .collect();
+            chord.push(&name);
+            let keycode = chord.join("+");
+            eprintln!("Key {}", keycode);
+            actions.push((now, Action::Key(keycode)));
+        }
+
+        // A modifier released without being part of a chord was a key press of its own
+        modifiers.retain(|held| {
+            let down = pressed
+                .iter()
+                .any(|key| key_name(key).is_some_and(|(name, _)| name == held.name));
+            if !down && !held.chorded {
+                actions.push((held.pressed_at, Action::Key(held.name.clone())));
+            }
+            down
+        });
+        was_pressed = pressed;
+
+        std::thread::sleep(POLL_INTERVAL);
+    }
+
+    write_script(out, &actions)
+}
+
+/// Writes the recorded actions as a plain event array script.
+fn write_script(out: &Path, actions: &[(Instant, Action)]) -> Result<()> {
+    ensure!(!actions.is_empty(), "Nothing was recorded");
+
+    let mut actions: Vec<&(Instant, Action)> = actions.iter().collect();
+    actions.sort_by_key(|(at, _)| *at);
+    let events: Vec<Value> = actions
+        .iter()
+        .enumerate()
+        .map(|(i, (at, action))| {
+            let delay = actions
+                .get(i + 1)
+                .map(|(next, _)| next.duration_since(*at).as_millis())
+                .unwrap_or(LAST_DELAY_MS);
+            match action {
+                Action::Click { point, rgb } => json!({
+                    "id": format!("click_{}", i + 1),
+                    "type": "color",
+                    "rgb": rgb,
+                    "search_region": {
+                        "x": point.x - REGION_RADIUS_PX,
+                        "y": point.y - REGION_RADIUS_PX,
+                        "width": 2 * REGION_RADIUS_PX + 1,
+                        "height": 2 * REGION_RADIUS_PX + 1,
+                    },
+                    "delay": delay,
+                }),
+                Action::Key(keycode) => json!({
+                    "id": format!("key_{}", i + 1),
+                    "type": "keypress",
+                    "keycode": keycode,
+                    "delay": delay,
+                }),
+            }
+        })
+        .collect();
+
+    std::fs::write(out, serde_json::to_string_pretty(&events)? + "\n")
+        .context(format!("Failed to write script {}", out.display()))?;
+    eprintln!("Wrote {} events to {}", events.len(), out.display());
+    Ok(())
+}
@@ -0,0 +1,82 @@
+//! Watches the running script's file for changes to reload it between iterations.
+//!
+//! The script's directory is watched rather than the file itself, since editors often save by
+//! writing a new file and renaming it over the old one, which would leave a watch on the file
+//! pointing at the replaced inode. The watch is non-blocking: the run loop asks whether the file
+//! changed once per iteration and never waits on it.
+use anyhow::{Context, Result};
+use nix::errno::Errno;
+use nix::sys::inotify::{AddWatchFlags, InitFlags, Inotify};
+use std::ffi::OsString;
+use std::path::Path;
+
+/// Watch on the directory of a script file.
+pub struct ScriptWatcher {
+    inotify: Inotify,
+    file_name: OsString,
+}
+
+impl ScriptWatcher {
+    /// Starts watching the directory of `path` for writes to and replacements of the file.
+    pub fn new(path: &Path) -> Result<Self> {
+        let file_name = path
+            .file_name()
+            .context(format!("{} is not a file", path.display()))?
+            .to_os_string();
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        let inotify = Inotify::init(InitFlags::IN_NONBLOCK | InitFlags::IN_CLOEXEC)
+            .context("Failed to set up inotify")?;
+        inotify
+            .add_watch(
+                dir,
+                AddWatchFlags::IN_CLOSE_WRITE | AddWatchFlags::IN_MOVED_TO,
+            )
+            .context(format!("Failed to watch {}", dir.display()))?;
+        Ok(Self { inotify, file_name })
+    }
+
+    /// Returns whether the file was written or replaced since the last call.
+    pub fn changed(&mut self) -> Result<bool> {
+        let mut changed = false;
+        loop {
+            let events = match self.inotify.read_events() {
+                Ok(events) => events,
+                Err(Errno::EAGAIN) => return Ok(changed),
+                Err(e) => return Err(e).context("Failed to read inotify events"),
+            };
+            changed |= events
+                .iter()
+                .any(|event| event.name.as_ref() == Some(&self.file_name));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn notices_writes_and_replacements_of_the_file_only() {
+        let dir = std::env::temp_dir().join(format!("colorbot-reload-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("script.json");
+        std::fs::write(&path, "[]").unwrap();
+
+        let mut watcher = ScriptWatcher::new(&path).unwrap();
+        assert!(!watcher.changed().unwrap());
+        std::fs::write(dir.join("other.json"), "[]").unwrap();
+        assert!(!watcher.changed().unwrap());
+        std::fs::write(&path, "[ ]").unwrap();
+        assert!(watcher.changed().unwrap());
+        assert!(!watcher.changed().unwrap());
+        // Saved the way many editors do, through a temporary file renamed over the script
+        std::fs::write(dir.join("script.json.tmp"), "[]").unwrap();
+        std::fs::rename(dir.join("script.json.tmp"), &path).unwrap();
+        assert!(watcher.changed().unwrap());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,144 @@
+//! Recovery routine that logs the account back in after a disconnect.
+//!
+//! The routine recognizes the login screen by its signature, clicks through the screens leading
+//! to the credential fields, types the username and password with humanized typing and finally
+//! enters the PIN and dismisses the lobby when configured. Credentials are usually secret
+//! references so they never appear in the script, logs or reports.
+use crate::backend::InputBackend;
+use crate::controls::{self, MouseSettings};
+use crate::secrets::{ScriptString, SecretStore};
+use crate::signature::Signature;
+use crate::vision::TargetHit;
+
+use anyhow::{Context, Result};
+use log::debug;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use std::time::Duration;
+
+/// PIN prompt shown after logging in.
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct PinEntry {
+    /// Signature of the PIN prompt.
+    pub screen: Signature,
+    /// PIN to type once the prompt appears.
+    pub value: ScriptString,
+}
+
+/// Login and lobby screens and the credentials needed to get past them.
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct Relogin {
+    /// Signature of the login screen, the routine does nothing when it isn't shown.
+    pub login_screen: Signature,
+
+    /// Elements clicked in order to reach the credential fields, e.g. an "Existing user" button.
+    #[serde(default)]
+    pub click_through: Vec<Signature>,
+
+    /// Username typed into the first credential field.
+    pub username: ScriptString,
+
+    /// Password typed into the second credential field.
+    pub password: ScriptString,
+
+    /// PIN prompt to answer after logging in.
+    #[serde(default)]
+    pub pin: Option<PinEntry>,
+
+    /// Element clicked to leave the lobby and enter the game.
+    #[serde(default)]
+    pub lobby_screen: Option<Signature>,
+
+    /// Maximum time in ms to wait for each screen to appear.
+    #[serde(default = "default_screen_timeout")]
+    pub screen_timeout: u64,
+}
+
+fn default_screen_timeout() -> u64 {
+    15_000
+}
+
+impl Relogin {
+    /// Checks every signature used by the routine.
+    pub fn validate(&self) -> Result<()> {
+        self.login_screen
+            .validate()
+            .context("Invalid login_screen")?;
+        for signature in &self.click_through {
+            signature.validate().context("Invalid click_through")?;
+        }
+        if let Some(pin) = &self.pin {
+            pin.screen.validate().context("Invalid pin screen")?;
+        }
+        if let Some(lobby) = &self.lobby_screen {
+            lobby.validate().context("Invalid lobby_screen")?;
+        }
+        Ok(())
+    }
+
+    /// Returns the credentials the routine types.
+    pub fn credentials(&self) -> impl Iterator<Item = &ScriptString> {
+        [&self.username, &self.password]
+            .into_iter()
+            .chain(self.pin.as_ref().map(|pin| &pin.value))
+    }
+
+    /// Logs back in if the login screen is shown, returning whether it was.
+    pub fn run(
+        &self,
+        input: &mut dyn InputBackend,
+        secrets: &SecretStore,
+        mouse: &MouseSettings,
+    ) -> Result<bool> {
+        if !self.login_screen.is_present()? {
+            debug!("Login screen not shown, no relogin needed");
+            return Ok(false);
+        }
+
+        let timeout = Duration::from_millis(self.screen_timeout);
+        let click = |input: &mut dyn InputBackend, hit: TargetHit| -> Result<()> {
+            controls::move_to_target(input, hit, mouse)?;
+            controls::left_click(input)
+        };
+
+        for (i, signature) in self.click_through.iter().enumerate() {
+            debug!("Clicking through login screen {}", i + 1);
+            let hit = signature
+                .wait_for(timeout)
+                .context(format!("Login screen {} not found", i + 1))?;
+            click(input, hit)?;
+        }
+
+        debug!("Entering credentials");
+        type_value(input, &self.username, secrets)?;
+        controls::toggle_key(input, "Tab")?;
+        type_value(input, &self.password, secrets)?;
+        controls::toggle_key(input, "Return")?;
+
+        if let Some(pin) = &self.pin {
+            pin.screen
+                .wait_for(timeout)
+                .context("PIN prompt not found")?;
+            debug!("Entering PIN");
+            type_value(input, &pin.value, secrets)?;
+        }
+
+        if let Some(lobby) = &self.lobby_screen {
+            let hit = lobby.wait_for(timeout).context("Lobby screen not found")?;
+            debug!("Leaving the lobby");
+            click(input, hit)?;
+        }
+
+        Ok(true)
+    }
+}
+
+/// Types a possibly secret value without letting errors leak it.
+fn type_value(
+    input: &mut dyn InputBackend,
+    value: &ScriptString,
+    secrets: &SecretStore,
+) -> Result<()> {
+    let result = controls::type_text(input, value.resolve(secrets)?, None);
+    value.redact(result, "type")
+}
@@ -0,0 +1,135 @@
+//! Replay of screenshots in place of the live screen.
+//!
+//! While a replay is active every screen capture returns a screenshot instead. Replaying a
+//! directory of screenshots in order lets a script's detection and branching be exercised
+//! deterministically without the game running, and a single still frame lets targets be checked
+//! against a screenshot.
+use anyhow::{ensure, Context, Result};
+use clap::ValueEnum;
+use image::RgbaImage;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// When a replay moves on to the next screenshot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum FrameAdvance {
+    /// After every executed script event.
+    Event,
+    /// After every screen capture.
+    Capture,
+}
+
+/// Screenshots being replayed and the position in them.
+struct Replay {
+    frames: Vec<PathBuf>,
+    position: usize,
+    /// How the replay advances, a still frame never does.
+    advance: Option<FrameAdvance>,
+    /// Decoded frame at the current position.
+    current: Option<RgbaImage>,
+}
+
+impl Replay {
+    /// Returns the frame at the current position, decoding it on first use.
+    fn frame(&mut self) -> Result<RgbaImage> {
+        if let Some(frame) = &self.current {
+            return Ok(frame.clone());
+        }
+        let path = self.frames.get(self.position).context(format!(
+            "All {} replayed frames have been used",
+            self.frames.len()
+        ))?;
+        let frame = image::open(path)
+            .context(format!("Failed to open frame {}", path.display()))?
+            .to_rgba8();
+        self.current = Some(frame.clone());
+        Ok(frame)
+    }
+
+    fn advance(&mut self) {
+        self.position += 1;
+        self.current = None;
+    }
+}
+
+static REPLAY: Mutex<Option<Replay>> = Mutex::new(None);
+
+/// Replays the PNG screenshots in `dir` in file name order, advancing as given.
+///
+/// Returns the number of frames found.
+pub fn start_dir(dir: &Path, advance: FrameAdvance) -> Result<usize> {
+    let mut frames: Vec<PathBuf> = std::fs::read_dir(dir)
+        .context(format!("Failed to read frames directory {}", dir.display()))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|ext| ext.eq_ignore_ascii_case("png"))
+        })
+        .collect();
+    frames.sort();
+    ensure!(
+        !frames.is_empty(),
+        "No PNG frames found in {}",
+        dir.display()
+    );
+
+    let count = frames.len();
+    set(Replay {
+        frames,
+        position: 0,
+        advance: Some(advance),
+        current: None,
+    });
+    Ok(count)
+}
+
+/// Returns `frame` for every capture from now on.
+pub fn start_still(frame: RgbaImage) {
+    set(Replay {
+        frames: Vec::new(),
+        position: 0,
+        advance: None,
+        current: Some(frame),
+    });
+}
+
+fn set(replay: Replay) {
+    if let Ok(mut current) = REPLAY.lock() {
+        *current = Some(replay);
+    }
+}
+
+/// Returns the frame a capture should see, or `None` when no replay is active.
+pub fn capture() -> Option<Result<RgbaImage>> {
+    let mut guard = REPLAY.lock().ok()?;
+    let replay = guard.as_mut()?;
+    let frame = replay.frame();
+    if replay.advance == Some(FrameAdvance::Capture) {
+        replay.advance();
+    }
+    Some(frame)
+}
+
+/// Moves on to the next frame after an executed event, when advancing per event.
+pub fn end_event() {
+    if let Ok(mut guard) = REPLAY.lock() {
+        if let Some(replay) = guard.as_mut() {
+            if replay.advance == Some(FrameAdvance::Event) {
+                replay.advance();
+            }
+        }
+    }
+}
+
+/// Returns whether a replay of a frames directory has used up every frame.
+pub fn finished() -> bool {
+    REPLAY
+        .lock()
+        .ok()
+        .and_then(|guard| {
+            guard
+                .as_ref()
+                .map(|replay| replay.advance.is_some() && replay.position >= replay.frames.len())
+        })
+        .unwrap_or(false)
+}
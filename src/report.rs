@@ -0,0 +1,97 @@
+//! End-of-run reports written to the run directory.
+//!
+//! The types of the report's fields are exported from here.
+use crate::error::ColorbotError;
+pub use crate::matchcache::CacheCounts;
+pub use crate::profile::{EventProfile, PhaseTimes};
+use crate::rundir::{ArtifactKind, RunDir};
+pub use crate::targets::TargetCounts;
+pub use crate::timing::JitterSummary;
+pub use crate::turbo::BudgetOverrun;
+pub use crate::vision::Region;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+
+/// Summary of a completed bot run.
+///
+/// Fields missing from reports written by older versions are read as their defaults.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RunReport {
+    /// Script that was executed.
+    pub script: PathBuf,
+    /// Input backend that delivered clicks and keys.
+    pub backend: String,
+    /// Input latency in ms used for mouse pacing.
+    pub input_latency_ms: Option<u64>,
+    /// Session tempo factor applied to delays and mouse movements.
+    pub tempo: f64,
+    /// Number of full passes over the script.
+    pub iterations: u64,
+    /// Wall clock duration of the run in seconds.
+    pub elapsed_secs: f64,
+    /// Deviation of the gaps between cursor path steps from the poll interval.
+    pub path_step_jitter: JitterSummary,
+    /// Average CPU usage in percent of one core.
+    pub cpu_percent: Option<f64>,
+    /// Extra time in seconds spent sleeping to stay under the CPU target.
+    pub throttled_secs: f64,
+    /// Number of times the watchdog found the run stuck and it recovered.
+    pub stuck_incidents: u64,
+    /// Number of transactions that failed part way and were rolled back.
+    pub rollbacks: u64,
+    /// Mouse clicks sent over the run.
+    pub clicks: u64,
+    /// Time each script event spent capturing, scanning, clustering, planning, executing and
+    /// sleeping, keyed by event id.
+    pub phases: BTreeMap<String, EventProfile>,
+    /// How often color and image events found their target, keyed by event id.
+    pub targets: BTreeMap<String, TargetCounts>,
+    /// How often template lookups were answered from the match cache, keyed by template path.
+    #[serde(default)]
+    pub template_cache: BTreeMap<String, CacheCounts>,
+    /// Search regions inferred for color events from their earlier matches, keyed by event id.
+    pub inferred_regions: BTreeMap<String, Region>,
+    /// Events whose detection was slower than the turbo budget, slowest first.
+    pub turbo_overruns: Vec<BudgetOverrun>,
+    /// Error that ended the run early, if any.
+    pub error: Option<String>,
+}
+
+impl RunReport {
+    /// Path of the report within a run directory.
+    pub(crate) fn path(run_dir: &RunDir) -> Result<PathBuf> {
+        run_dir.artifact_path(ArtifactKind::Report, "report.json")
+    }
+
+    /// Target counts summed over all events.
+    pub fn total_targets(&self) -> TargetCounts {
+        let mut total = TargetCounts::default();
+        for counts in self.targets.values() {
+            total.add(counts);
+        }
+        total
+    }
+
+    /// Reads a report written by a previous run.
+    pub fn load(path: &Path) -> Result<Self, ColorbotError> {
+        let file = File::open(path).context(format!("Failed to open {}", path.display()))?;
+        let report = serde_json::from_reader(BufReader::new(file))
+            .context(format!("Failed to parse run report {}", path.display()))?;
+        Ok(report)
+    }
+
+    /// Writes the report as pretty-printed JSON into the run directory.
+    pub(crate) fn write(&self, run_dir: &RunDir) -> Result<PathBuf> {
+        let path = Self::path(run_dir)?;
+        let file = File::create(&path).context("Failed to create run report")?;
+        serde_json::to_writer_pretty(BufWriter::new(file), self)
+            .context("Failed to write run report")?;
+        Ok(path)
+    }
+}
@@ -0,0 +1,109 @@
+//! Searching again for color targets that weren't on screen.
+//!
+//! A target hidden for a frame, by a game animation or a player walking past, isn't gone. A
+//! color event with `retries` captures the screen and searches again after a short delay, which
+//! doubles with every retry, before giving up on the target.
+use crate::error::{self, ColorbotError};
+
+use anyhow::Result;
+use log::debug;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use std::time::Duration;
+
+/// Longest delay between two retries, however many came before.
+const MAX_DELAY: Duration = Duration::from_secs(10);
+
+fn default_retry_delay_ms() -> u64 {
+    250
+}
+
+/// How often and after how long a color event searches again when its target isn't found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, JsonSchema)]
+pub struct RetryPolicy {
+    /// Number of times to capture the screen and search again after the target wasn't found.
+    /// With retries, an event whose target is never found logs a warning and the script moves
+    /// on instead of failing.
+    #[serde(default)]
+    pub retries: u32,
+    /// Delay in ms before the first retry, doubling before every further one.
+    #[serde(default = "default_retry_delay_ms")]
+    pub retry_delay_ms: u64,
+}
+
+impl RetryPolicy {
+    /// Returns the delay before retry number `retry`, counting from 1.
+    pub fn delay(&self, retry: u32) -> Duration {
+        let factor = 1u32
+            .checked_shl(retry.saturating_sub(1))
+            .unwrap_or(u32::MAX);
+        Duration::from_millis(self.retry_delay_ms)
+            .saturating_mul(factor)
+            .min(MAX_DELAY)
+    }
+
+    /// Runs `search` and again after each delay while it finds no matching pixels, until it
+    /// finds them or the retries run out. Other failures end the search right away.
+    pub fn run<T>(&self, id: &str, mut search: impl FnMut() -> Result<T>) -> Result<T> {
+        let mut retry = 0;
+        loop {
+            match search() {
+                Err(e) if retry < self.retries && is_not_found(&e) => {
+                    retry += 1;
+                    let delay = self.delay(retry);
+                    debug!(
+                        "Target of event '{}' not found, retry {}/{} in {:?}",
+                        id, retry, self.retries, delay
+                    );
+                    std::thread::sleep(delay);
+                }
+                outcome => return outcome,
+            }
+        }
+    }
+
+    /// Whether the event gives up on a target that wasn't found without failing.
+    pub fn moves_on(&self, error: &anyhow::Error) -> bool {
+        self.retries > 0 && is_not_found(error)
+    }
+}
+
+/// Whether a search failed because no pixel matched the target.
+fn is_not_found(error: &anyhow::Error) -> bool {
+    matches!(error::kind(error), Some(ColorbotError::ColorNotFound))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::{anyhow, bail};
+
+    #[test]
+    fn retries_missed_targets_with_doubling_delays() {
+        let policy = RetryPolicy {
+            retries: 3,
+            retry_delay_ms: 1,
+        };
+        assert_eq!(policy.delay(1), Duration::from_millis(1));
+        assert_eq!(policy.delay(3), Duration::from_millis(4));
+        assert_eq!(policy.delay(40), MAX_DELAY);
+
+        let mut searches = 0;
+        let found = policy.run("fish", || {
+            searches += 1;
+            if searches < 3 {
+                bail!(ColorbotError::ColorNotFound);
+            }
+            Ok(searches)
+        });
+        assert_eq!(found.unwrap(), 3);
+
+        let mut searches = 0;
+        let failed: Result<()> = policy.run("fish", || {
+            searches += 1;
+            bail!(ColorbotError::CaptureFailed(anyhow!("No display")))
+        });
+        assert!(failed.is_err());
+        assert_eq!(searches, 1);
+    }
+}
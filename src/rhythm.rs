@@ -0,0 +1,87 @@
+//! Burst-and-pause rhythm of actions.
+//!
+//! People don't space their actions independently. They work through a few actions in quick
+//! succession, then pause for a while before the next burst. With a rhythm configured, the
+//! humanized part of each event delay is shortened within a burst, and a longer pause is added
+//! once the burst is over.
+use crate::timing::DelayDistribution;
+
+use anyhow::{ensure, Context, Result};
+use log::debug;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use std::time::Duration;
+
+/// Script-level rhythm parameters.
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct RhythmConfig {
+    /// Inclusive range of the number of actions in a burst.
+    pub burst_length: [u32; 2],
+    /// Pause in milliseconds taken after each burst.
+    pub pause: DelayDistribution,
+    /// Factor applied to the humanized part of delays within a burst.
+    #[serde(default = "default_burst_speedup")]
+    pub burst_speedup: f64,
+}
+
+fn default_burst_speedup() -> f64 {
+    0.5
+}
+
+impl RhythmConfig {
+    /// Checks the rhythm's parameters.
+    pub fn validate(&self) -> Result<()> {
+        let [min, max] = self.burst_length;
+        ensure!(
+            min >= 1 && min <= max,
+            "Rhythm burst_length must be a range of at least one action, got [{}, {}]",
+            min,
+            max
+        );
+        ensure!(
+            self.burst_speedup > 0.0 && self.burst_speedup <= 1.0,
+            "Rhythm burst_speedup must be within (0, 1], got {}",
+            self.burst_speedup
+        );
+        self.pause.validate().context("Invalid rhythm pause")
+    }
+}
+
+/// Tracks the position within the current burst.
+pub struct Rhythm {
+    config: Option<RhythmConfig>,
+    remaining: u32,
+}
+
+impl Rhythm {
+    /// Creates a rhythm, without a config every delay is left unchanged.
+    pub fn new(config: Option<RhythmConfig>) -> Self {
+        Self {
+            config,
+            remaining: 0,
+        }
+    }
+
+    /// Shapes the delay after an action given its script `base` and its `humanized` total.
+    pub fn next_delay(&mut self, base: Duration, humanized: Duration) -> Result<Duration> {
+        let Some(config) = &self.config else {
+            return Ok(humanized);
+        };
+
+        if self.remaining == 0 {
+            let [min, max] = config.burst_length;
+            self.remaining = rand::random_range(min..=max);
+        }
+        self.remaining -= 1;
+
+        let extra = humanized.saturating_sub(base).mul_f64(config.burst_speedup);
+        if self.remaining > 0 {
+            return Ok(base + extra);
+        }
+
+        let pause = config.pause.sample(&mut rand::rng())?;
+        debug!("Burst over, pausing for an extra {:?}", pause);
+        Ok(base + extra + pause)
+    }
+}
@@ -0,0 +1,140 @@
+//! Per-run working directories for logs, screenshots, reports, and other artifacts.
+//!
+//! Every bot run gets its own directory under a configurable root. Old run directories are
+//! removed automatically so that only the most recent runs are kept.
+use anyhow::{Context, Result};
+use log::debug;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Prefix shared by all run directory names, used to recognize them during cleanup.
+const RUN_DIR_PREFIX: &str = "run-";
+
+/// Kinds of artifacts a run can produce, each stored in its own subdirectory.
+#[derive(Debug, Clone, Copy)]
+pub enum ArtifactKind {
+    Report,
+    Crash,
+    Diagnostics,
+}
+
+impl ArtifactKind {
+    /// Returns the subdirectory name for this artifact kind.
+    fn dir_name(self) -> &'static str {
+        match self {
+            ArtifactKind::Report => "reports",
+            ArtifactKind::Crash => "crash",
+            ArtifactKind::Diagnostics => "diagnostics",
+        }
+    }
+}
+
+/// Working directory owned by a single bot run.
+#[derive(Debug, Clone)]
+pub struct RunDir {
+    path: PathBuf,
+}
+
+impl RunDir {
+    /// Returns the default root for run directories.
+    ///
+    /// Follows the XDG base directory spec, falling back to the system temp directory when no
+    /// home directory is known.
+    pub fn default_root() -> PathBuf {
+        if let Some(state) = std::env::var_os("XDG_STATE_HOME") {
+            return PathBuf::from(state).join("colorbot").join("runs");
+        }
+        if let Some(home) = std::env::var_os("HOME") {
+            return PathBuf::from(home)
+                .join(".local")
+                .join("state")
+                .join("colorbot")
+                .join("runs");
+        }
+        std::env::temp_dir().join("colorbot").join("runs")
+    }
+
+    /// Creates a fresh run directory under `root` and prunes old runs beyond `keep_runs`.
+    ///
+    /// The script being run is copied into the directory so the run can be reproduced later
+    /// even if the original file is edited.
+    pub fn create(root: &Path, keep_runs: usize, script: &Path) -> Result<Self> {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        let path = root.join(format!(
+            "{}{}-{}",
+            RUN_DIR_PREFIX,
+            timestamp,
+            std::process::id()
+        ));
+        fs::create_dir_all(&path)
+            .context(format!("Failed to create run directory {}", path.display()))?;
+
+        let run_dir = Self { path };
+        if let Some(name) = script.file_name() {
+            fs::copy(script, run_dir.path.join(name)).context("Failed to copy script")?;
+        }
+
+        prune_old_runs(root, keep_runs)?;
+
+        Ok(run_dir)
+    }
+
+    /// Refers to a run directory that was already created, by another process.
+    pub fn existing(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    /// Returns the run directory path.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the path of the run's log file.
+    pub fn log_path(&self) -> PathBuf {
+        self.path.join("colorbot.log")
+    }
+
+    /// Returns the path of the file that takes the terminal output of a detached run.
+    pub fn output_path(&self) -> PathBuf {
+        self.path.join("output.log")
+    }
+
+    /// Returns a path for a new artifact, creating its subdirectory if needed.
+    pub fn artifact_path(&self, kind: ArtifactKind, file_name: &str) -> Result<PathBuf> {
+        let dir = self.path.join(kind.dir_name());
+        fs::create_dir_all(&dir).context(format!(
+            "Failed to create artifact directory {}",
+            dir.display()
+        ))?;
+        Ok(dir.join(file_name))
+    }
+}
+
+/// Removes the oldest run directories so that at most `keep_runs` remain.
+fn prune_old_runs(root: &Path, keep_runs: usize) -> Result<()> {
+    let mut runs: Vec<(SystemTime, PathBuf)> = Vec::new();
+    for entry in fs::read_dir(root)? {
+        let entry = entry?;
+        let is_run_dir = entry.file_type()?.is_dir()
+            && entry
+                .file_name()
+                .to_string_lossy()
+                .starts_with(RUN_DIR_PREFIX);
+        if is_run_dir {
+            runs.push((entry.metadata()?.modified()?, entry.path()));
+        }
+    }
+
+    // Newest first, everything past the retention limit goes
+    runs.sort_by_key(|(modified, _)| std::cmp::Reverse(*modified));
+    for (_, path) in runs.into_iter().skip(keep_runs) {
+        debug!("Removing old run directory {}", path.display());
+        fs::remove_dir_all(&path).context(format!(
+            "Failed to remove old run directory {}",
+            path.display()
+        ))?;
+    }
+
+    Ok(())
+}
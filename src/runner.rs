@@ -0,0 +1,623 @@
+//! The event loop of a run, shared by `colorbot run`, `colorbot tune` and [`crate::bot::Bot`].
+use crate::backend::{DryRunBackend, InputBackend};
+use crate::breaks::BreakScheduler;
+use crate::condition::RunContext;
+use crate::config::BotConfig;
+use crate::controls::MouseSettings;
+use crate::decisions::DecisionKind;
+use crate::event::BotEvent;
+#[cfg(feature = "gamepad")]
+use crate::gamepad;
+use crate::hotkeys::Hotkeys;
+use crate::idle::IdleScript;
+use crate::keybinds::Keybinds;
+use crate::pacing::Pacer;
+use crate::reload::ScriptWatcher;
+use crate::report::RunReport;
+use crate::rhythm::Rhythm;
+use crate::rundir::RunDir;
+use crate::runtime::{self, Priority};
+use crate::script::{Script, Transaction};
+use crate::secrets::SecretStore;
+use crate::stall::StallDetector;
+use crate::theme::ThemeSelector;
+use crate::vision::CaptureSource;
+use crate::watchdog::Watchdog;
+use crate::windmouse::Point;
+use crate::{
+    autoregion, backend, control, controls, countdown, crash, decisions, diagnostics, display,
+    dryrun, fixtures, flow, focus, forbidden, hotkeys, indicator, matchcache, profile, replay,
+    script, secrets, stats, targets, theme, timing, tint, turbo, vision, window,
+};
+
+use anyhow::{bail, ensure, Context, Result};
+use log::{debug, info, warn};
+use std::{
+    collections::HashSet,
+    time::{Duration, Instant},
+};
+
+/// Time the window manager gets to raise the game window before input is sent to it.
+const RAISE_SETTLE: Duration = Duration::from_millis(150);
+
+/// Warns about events that use actions the selected input backend can't perform.
+fn warn_unsupported_actions(events: &[BotEvent], input: &dyn InputBackend) {
+    let capabilities = input.capabilities();
+
+    for event in events {
+        let missing = capabilities.missing(&event.required_capabilities());
+        if !missing.is_empty() {
+            warn!(
+                "Event '{}' uses {} which the {} backend does not support",
+                event.id,
+                missing.join(", "),
+                input.name()
+            );
+        }
+    }
+}
+
+/// Unlocks the configured secrets file and checks that it has every secret the events use.
+fn unlock_secrets<'a>(
+    config: &BotConfig,
+    events: impl IntoIterator<Item = &'a BotEvent>,
+) -> Result<SecretStore> {
+    let secrets = match &config.secrets {
+        Some(path) => {
+            let password = secrets::unlock_password(config.secrets_keyring)?;
+            SecretStore::load(path, &password)?
+        }
+        None => SecretStore::default(),
+    };
+    check_secrets(&secrets, events)?;
+    Ok(secrets)
+}
+
+/// Checks that the secret store has every secret the events use.
+fn check_secrets<'a>(
+    secrets: &SecretStore,
+    events: impl IntoIterator<Item = &'a BotEvent>,
+) -> Result<()> {
+    for event in events {
+        for name in event.secret_names() {
+            ensure!(
+                secrets.contains(name),
+                "Event '{}' uses secret '{}' which is not in the secrets file, pass one with --secrets",
+                event.id,
+                name
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Loads the script again after its file changed, checking it like at startup.
+///
+/// The secrets file isn't unlocked again, so the new script can only use secrets the running
+/// one could.
+fn reload_script(
+    config: &BotConfig,
+    input: &dyn InputBackend,
+    secrets: &SecretStore,
+) -> Result<Script> {
+    let mut script = script::load(&config.script)?;
+    flow::check(&script)?;
+    let rollbacks = script
+        .transactions
+        .iter_mut()
+        .flat_map(|transaction| &mut transaction.on_fail);
+    Keybinds::load(config.keybinds.as_deref())?
+        .resolve(script.events.iter_mut().chain(rollbacks))?;
+    let events = || {
+        script.events.iter().chain(
+            script
+                .transactions
+                .iter()
+                .flat_map(|transaction| &transaction.on_fail),
+        )
+    };
+    check_secrets(secrets, events())?;
+    if config.frames.is_none() {
+        let (hotkeys, _) = Hotkeys::load(config.hotkeys.as_deref())?;
+        hotkeys.check_conflicts(events())?;
+    }
+    script.metadata.check_backend(input)?;
+    warn_unsupported_actions(&script.events, input);
+    Ok(script)
+}
+
+/// Runs the recovery script after the watchdog found the run stuck, aborting when there is none.
+fn recover_stuck_run(
+    config: &BotConfig,
+    input: &mut dyn InputBackend,
+    secrets: &SecretStore,
+    recovery: &[BotEvent],
+    stuck_result: Result<Option<&str>>,
+) -> Result<()> {
+    if let Err(e) = stuck_result {
+        warn!("Stuck event ended with an error: {:#}", e);
+    }
+    ensure!(
+        !recovery.is_empty(),
+        "Aborting stuck run, pass --recovery-script to recover instead"
+    );
+
+    warn!("Running recovery script for stuck run");
+    let mut rhythm = Rhythm::new(None);
+    for event in recovery {
+        event
+            .exec(config, input, secrets, &mut rhythm)
+            .context(format!("Recovery event '{}' failed", event.id))?;
+    }
+    Ok(())
+}
+
+/// Runs the rollback routine of a transaction after one of its events failed with `error`.
+fn roll_back(
+    config: &BotConfig,
+    input: &mut dyn InputBackend,
+    secrets: &SecretStore,
+    transaction: &Transaction,
+    error: anyhow::Error,
+) -> Result<()> {
+    warn!(
+        "Transaction '{}' failed, rolling back: {:#}",
+        transaction.name, error
+    );
+    let mut rhythm = Rhythm::new(None);
+    for event in &transaction.on_fail {
+        event
+            .exec(config, input, secrets, &mut rhythm)
+            .context(format!(
+                "Rollback event '{}' of transaction '{}' failed",
+                event.id, transaction.name
+            ))?;
+    }
+    Ok(())
+}
+
+/// Executes the bot event loop repeatedly until the specified runtime expires.
+pub fn run_event_loop(mut config: BotConfig, run_dir: &RunDir) -> Result<()> {
+    if config.turbo {
+        ensure!(
+            config.turbo_budget > 0.0,
+            "--turbo-budget must be positive, got {}",
+            config.turbo_budget
+        );
+        turbo::apply(&mut config);
+    }
+    if let Some(percent) = config.max_cpu_percent {
+        ensure!(
+            percent > 0.0,
+            "--max-cpu-percent must be positive, got {}",
+            percent
+        );
+    }
+
+    let tempo = match config.tempo {
+        Some(tempo) => tempo,
+        None => {
+            ensure!(
+                config.tempo_min <= config.tempo_max,
+                "--tempo-min must not exceed --tempo-max"
+            );
+            rand::random_range(config.tempo_min..=config.tempo_max)
+        }
+    };
+    ensure!(tempo > 0.0, "The tempo must be positive, got {}", tempo);
+    config.tempo = Some(tempo);
+    debug!("Session tempo is {:.3}", tempo);
+    ensure!(
+        config.path_deviation > 0.0,
+        "--path-deviation must be positive, got {}",
+        config.path_deviation
+    );
+    MouseSettings::from_config(&config).validate()?;
+    vision::set_color_tolerance(config.color_tolerance);
+    vision::set_color_offset([0, 0, 0]);
+    tint::set_max_offset(config.max_color_offset);
+    ensure!(
+        config.display.is_none() || config.capture != CaptureSource::Portal,
+        "--display doesn't apply to --capture portal, the portal asks which monitor to share"
+    );
+    vision::set_capture_source(config.capture);
+    runtime::apply(&Priority::from_config(&config)).context("Failed to set the bot's priority")?;
+
+    // Counters are process-wide, a tuning session runs several times in one process
+    profile::reset();
+    targets::reset();
+    matchcache::reset();
+    autoregion::stop();
+    stats::reset();
+    timing::reset_path_jitter();
+    if config.infer_regions {
+        autoregion::start(&config.script)?;
+    }
+
+    let mut script = script::load(&config.script)?;
+    flow::check(&script)?;
+    // Replayed frames stand in for the live setup
+    let game_window = if config.frames.is_none() {
+        display::select(config.display)?;
+        script
+            .metadata
+            .check_environment()
+            .context("The script doesn't match this setup")?
+    } else {
+        None
+    };
+    display::follow_window(
+        game_window
+            .clone()
+            .filter(|_| script.metadata.relative_to_window),
+    )?;
+    // Dry runs leave the desktop alone
+    let raise_window = game_window.filter(|_| script.metadata.raise_window && !config.dry_run);
+    let mut start = script.start_index(config.start_at_checkpoint.as_deref())?;
+    forbidden::set(std::mem::take(&mut script.forbidden));
+    theme::set_active(None);
+    let mut themes = script.themes.take().map(ThemeSelector::new);
+    let mut events = std::mem::take(&mut script.events);
+    let mut rhythm = Rhythm::new(script.rhythm.take());
+    debug!("Loaded {} events from script", events.len());
+    let mut recovery = match &config.recovery_script {
+        Some(path) => {
+            script::load(path)
+                .context("Failed to load recovery script")?
+                .events
+        }
+        None => Vec::new(),
+    };
+    ensure!(
+        recovery
+            .iter()
+            .all(|event| event.jump_targets().next().is_none()),
+        "Recovery scripts run straight through and can't use if_found or if_not_found"
+    );
+    let rollbacks = script
+        .transactions
+        .iter_mut()
+        .flat_map(|transaction| &mut transaction.on_fail);
+    Keybinds::load(config.keybinds.as_deref())?
+        .resolve(events.iter_mut().chain(&mut recovery).chain(rollbacks))?;
+    let rollbacks = script
+        .transactions
+        .iter()
+        .flat_map(|transaction| &transaction.on_fail);
+    let secrets = unlock_secrets(&config, events.iter().chain(&recovery).chain(rollbacks))?;
+
+    hotkeys::reset();
+    // Replayed runs don't need a keyboard
+    if config.frames.is_none() {
+        let (hotkeys, _) = Hotkeys::load(config.hotkeys.as_deref())?;
+        let rollbacks = script
+            .transactions
+            .iter()
+            .flat_map(|transaction| &transaction.on_fail);
+        hotkeys.check_conflicts(events.iter().chain(&recovery).chain(rollbacks))?;
+        hotkeys.listen()?;
+    }
+
+    let watchdog_timeout =
+        (config.watchdog_minutes > 0).then(|| Duration::from_secs(config.watchdog_minutes * 60));
+    if let (Some(timeout), Some(_)) = (watchdog_timeout, config.break_every) {
+        ensure!(
+            timeout > config.break_for.max(),
+            "--watchdog-minutes must be longer than the breaks"
+        );
+    }
+
+    ensure!(
+        config.dry_run_paths.is_none() || config.dry_run || config.frames.is_some(),
+        "--dry-run-paths needs --dry-run or --frames"
+    );
+    if config.dry_run {
+        // Measuring would move the real cursor
+        config.input_latency.get_or_insert(0);
+    }
+    if let Some(dir) = &config.frames {
+        let count = replay::start_dir(dir, config.frame_advance)?;
+        debug!("Replaying {} frames from {}", count, dir.display());
+        // Measuring would move the real cursor
+        config.input_latency.get_or_insert(0);
+    }
+    if let Some(dir) = &config.record_frames {
+        fixtures::start(dir)?;
+    }
+    diagnostics::start(run_dir);
+    if config.frames.is_none() {
+        // Set up capturing once instead of on the first event
+        vision::open_capturer()?;
+    }
+    if config.input_latency.is_none() && config.backend.tracks_cursor() {
+        // The measurement watches the cursor arrive, which these backends can't see
+        debug!(
+            "Not measuring the input latency of the {:?} backend",
+            config.backend
+        );
+        config.input_latency = Some(0);
+    }
+    if config.input_latency.is_none() {
+        match controls::measure_input_latency() {
+            Ok(latency) => {
+                debug!("Measured input latency of {:?}", latency);
+                config.input_latency = Some(u64::try_from(latency.as_millis())?);
+            }
+            Err(e) => warn!("Failed to measure input latency, assuming none: {:#}", e),
+        }
+    }
+
+    let mut input: Box<dyn InputBackend> = if config.frames.is_some() {
+        Box::new(DryRunBackend::new(display::to_screen(Point::new(0, 0))))
+    } else if config.dry_run {
+        // Paths start from where the real cursor rests
+        Box::new(DryRunBackend::new(controls::get_screen_mouse_pos()))
+    } else {
+        backend::select_backend(config.backend, config.buttons)?
+    };
+    #[cfg(feature = "gamepad")]
+    if config.frames.is_none()
+        && !config.dry_run
+        && (script
+            .metadata
+            .requires
+            .contains(&backend::Capability::Gamepad)
+            || events
+                .iter()
+                .any(|event| event.required_capabilities().gamepad))
+    {
+        input = Box::new(gamepad::GamepadBackend::probe(input)?);
+    }
+    script.metadata.check_backend(input.as_ref())?;
+    warn_unsupported_actions(&events, input.as_ref());
+
+    let mut breaks = match config.break_every {
+        Some(every) => {
+            let idle_scripts = config
+                .idle_script
+                .iter()
+                .map(|path| IdleScript::load(path))
+                .collect::<Result<Vec<_>>>()?;
+            Some(BreakScheduler::new(every, config.break_for, idle_scripts))
+        }
+        None => None,
+    };
+    let mut mouse = MouseSettings::from_config(&config);
+    let mut pacer = Pacer::from_config(&config);
+    if config.frames.is_none() && !config.dry_run {
+        countdown::run(config.countdown, script.metadata.window.as_deref())?;
+        focus::enable(script.metadata.window.clone());
+        if !config.no_indicator {
+            if let Err(e) = indicator::start(config.indicator_corner) {
+                warn!("Not showing the activity indicator: {:#}", e);
+            }
+        }
+        // A modifier still held from launching the command would corrupt the first keys sent
+        if let Err(e) = input.release_all() {
+            warn!("Failed to release modifiers before the run: {:#}", e);
+        }
+    } else {
+        focus::disable();
+    }
+    let mut watchdog = Watchdog::start(watchdog_timeout)?;
+    let mut stall = (config.stall_cycles > 0)
+        .then(|| StallDetector::new(config.stall_cycles, config.stall_region));
+
+    let runtime = Duration::from_secs(config.runtime);
+    let start_time = Instant::now();
+    let end_time = start_time + runtime;
+    debug!("Starting event loop for {} seconds", config.runtime);
+    if let Some(name) = &config.start_at_checkpoint {
+        debug!("Starting at checkpoint '{}'", name);
+    }
+
+    control::set_event_ids(events.iter().map(|event| event.id.clone()).collect());
+    let mut watcher = if config.no_reload {
+        None
+    } else {
+        ScriptWatcher::new(&config.script)
+            .inspect_err(|e| warn!("Not reloading the script when it changes: {:#}", e))
+            .ok()
+    };
+    let mut disabled = HashSet::new();
+    let mut iteration = 0;
+    let mut rollbacks = 0;
+    let outcome = (|| -> Result<()> {
+        while Instant::now() < end_time && !replay::finished() && !hotkeys::stopping() {
+            debug!("Starting iteration {}", iteration);
+            if let Some(watcher) = &mut watcher {
+                if iteration > 0 && watcher.changed()? {
+                    match reload_script(&config, input.as_ref(), &secrets) {
+                        Ok(mut reloaded) => {
+                            forbidden::set(std::mem::take(&mut reloaded.forbidden));
+                            themes = reloaded.themes.take().map(ThemeSelector::new);
+                            events = std::mem::take(&mut reloaded.events);
+                            rhythm = Rhythm::new(reloaded.rhythm.take());
+                            script = reloaded;
+                            control::set_event_ids(
+                                events.iter().map(|event| event.id.clone()).collect(),
+                            );
+                            info!(
+                                "Reloaded {} with {} events",
+                                config.script.display(),
+                                events.len()
+                            );
+                        }
+                        Err(e) => warn!(
+                            "Keeping the running script, {} doesn't load: {:#}",
+                            config.script.display(),
+                            e
+                        ),
+                    }
+                }
+            }
+
+            let mut next = start;
+            let mut passes = vec![0; script.repeats.len()];
+            while let Some(event) = events.get(next) {
+                let index = next;
+                if replay::finished() {
+                    break;
+                }
+                // A pause isn't a stuck run, however long it lasts
+                watchdog.suspend();
+                let resumed = hotkeys::checkpoint();
+                watchdog.resume();
+                resumed?;
+                if hotkeys::stopping() {
+                    break;
+                }
+                display::refresh_window()?;
+                if let Some(window) = &raise_window {
+                    if window::activate(window).context("Failed to raise the game window")? {
+                        debug!("Raised the game window '{}'", window.title);
+                        std::thread::sleep(RAISE_SETTLE);
+                    }
+                }
+                for adjustment in control::take_adjustments() {
+                    control::apply(adjustment, &mut config, &mut disabled);
+                    mouse = MouseSettings::from_config(&config);
+                }
+                if let Some(breaks) = &mut breaks {
+                    crash::set_current_event(None);
+                    watchdog.event_started("break");
+                    crash::release_inputs_on_panic(input.as_mut(), |input| {
+                        breaks.take_break_if_due(input, &mouse)
+                    })?;
+                    watchdog.event_finished();
+                }
+                if let Some(themes) = &mut themes {
+                    themes.select_if_due()?;
+                }
+                let context = RunContext {
+                    iteration: iteration + 1,
+                    elapsed: start_time.elapsed(),
+                    remaining: end_time.saturating_duration_since(Instant::now()),
+                };
+                if disabled.contains(&event.id) || !event.should_run(&context)? {
+                    next = script.next_index(index, &mut passes)?;
+                    continue;
+                }
+                crash::set_current_event(Some(&event.id));
+                decisions::begin_event(&event.id, iteration + 1);
+                watchdog.event_started(&event.id);
+                let _profile = profile::begin_event(&event.id);
+                let result = crash::release_inputs_on_panic(input.as_mut(), |input| {
+                    event.exec(&config, input, &secrets, &mut rhythm)
+                });
+                if watchdog.take_incident() {
+                    if watchdog.aborted() {
+                        if let Err(e) = input.release_all() {
+                            warn!("Failed to release held inputs: {:#}", e);
+                        }
+                        bail!(
+                            "Aborting the run, event '{}' stayed stuck after the incident",
+                            event.id
+                        );
+                    }
+                    recover_stuck_run(&config, input.as_mut(), &secrets, &recovery, result)?;
+                    watchdog.event_finished();
+                    next = script.next_index(index, &mut passes)?;
+                    continue;
+                }
+                match result {
+                    Ok(Some(target)) => {
+                        next = events
+                            .iter()
+                            .position(|other| other.id == target)
+                            .context(format!("Event '{}' branches to unknown event", event.id))?;
+                    }
+                    Ok(None) => next = script.next_index(index, &mut passes)?,
+                    Err(e) => {
+                        decisions::record(DecisionKind::EventFailed {
+                            error: format!("{:#}", e),
+                        });
+                        let Some(transaction) = script.transaction_at(index) else {
+                            return Err(e);
+                        };
+                        watchdog.event_started(&format!("{} rollback", transaction.name));
+                        roll_back(&config, input.as_mut(), &secrets, transaction, e)?;
+                        watchdog.event_finished();
+                        rollbacks += 1;
+                        // Later events may depend on the transaction, start over from the top
+                        break;
+                    }
+                }
+                watchdog.event_finished();
+                if let Some(stall) = &mut stall {
+                    if stall.observe()? {
+                        warn!(
+                            "The screen didn't change for {} events in a row, the game may be \
+                             frozen or ignoring input",
+                            stall.cycles()
+                        );
+                        recover_stuck_run(&config, input.as_mut(), &secrets, &recovery, Ok(None))?;
+                    }
+                }
+                replay::end_event();
+                pacer.end_cycle();
+            }
+            start = 0;
+            iteration += 1;
+        }
+        Ok(())
+    })();
+
+    indicator::stop();
+    if hotkeys::stopping() {
+        debug!("Stopped on request");
+        hotkeys::set_stopping(false);
+    }
+    match &outcome {
+        Ok(()) => debug!("Event loop completed after {} iterations", iteration),
+        Err(_) => debug!("Event loop failed during iteration {}", iteration),
+    }
+
+    if config.record_frames.is_some() {
+        debug!("Recorded {} frames", fixtures::saved());
+    }
+    if let Some(path) = &config.dry_run_paths {
+        match dryrun::save_paths(path) {
+            Ok(()) => debug!("Drew the mouse paths to {}", path.display()),
+            Err(e) => warn!("Failed to draw the mouse paths: {:#}", e),
+        }
+    }
+
+    if config.infer_regions {
+        if let Err(e) = autoregion::save() {
+            warn!("Failed to save the inferred search regions: {:#}", e);
+        }
+    }
+    let phases = profile::event_profiles();
+    let turbo_overruns = if config.turbo {
+        let overruns = turbo::check_budget(&phases, config.turbo_budget);
+        turbo::log_overruns(&overruns, config.turbo_budget);
+        overruns
+    } else {
+        Vec::new()
+    };
+    let report = RunReport {
+        script: config.script.clone(),
+        backend: input.name().to_string(),
+        input_latency_ms: config.input_latency,
+        tempo,
+        iterations: iteration,
+        elapsed_secs: start_time.elapsed().as_secs_f64(),
+        path_step_jitter: timing::path_jitter_summary(),
+        cpu_percent: pacer.cpu_percent(),
+        throttled_secs: pacer.throttled().as_secs_f64(),
+        stuck_incidents: watchdog.incidents(),
+        rollbacks,
+        clicks: stats::clicks(),
+        phases,
+        targets: targets::event_counts(),
+        template_cache: matchcache::counts(),
+        inferred_regions: autoregion::regions(),
+        turbo_overruns,
+        error: outcome.as_ref().err().map(|e| format!("{:#}", e)),
+    };
+    let report_path = report.write(run_dir)?;
+    debug!("Wrote run report to {}", report_path.display());
+
+    outcome
+}
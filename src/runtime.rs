@@ -0,0 +1,342 @@
+//! Scheduling priority of the bot process, so it doesn't take CPU time or disk bandwidth from
+//! the game.
+//!
+//! On weaker machines capturing and scanning the screen competes with the game for the same
+//! cores, and the game drops frames. A run can lower its CPU priority with `--nice`, its IO
+//! priority with `--ionice` and keep capturing and scanning on some cores with `--pin-cores`,
+//! leaving the others to the game. The settings are applied with the `setpriority`,
+//! `ioprio_set` and `sched_setaffinity` system calls. The nice level, IO class and cores are read
+//! back afterwards, since the kernel may quietly refuse them.
+use crate::config::BotConfig;
+
+use anyhow::{bail, ensure, Context, Result};
+use clap::ValueEnum;
+use log::debug;
+use nix::errno::Errno;
+use nix::libc;
+use nix::sched::{self, CpuSet};
+use nix::unistd::{self, Pid};
+use std::path::Path;
+use std::str::FromStr;
+
+/// `IOPRIO_WHO_PROCESS`, the IO priority calls take a thread id with it.
+const IOPRIO_WHO_PROCESS: libc::c_int = 1;
+/// Bits of an IO priority below its class.
+const IOPRIO_CLASS_SHIFT: u32 = 13;
+/// Priority within the realtime and best-effort classes, the kernel's default.
+const IOPRIO_DEFAULT_LEVEL: libc::c_int = 4;
+
+/// IO scheduling class, as taken by `ionice -c`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum IoClass {
+    /// Served before every other process, needs root.
+    Realtime,
+    /// The default class, served by priority.
+    BestEffort,
+    /// Only served when no other process needs the disk.
+    Idle,
+}
+
+impl IoClass {
+    /// Returns the IO priority the kernel takes for the class.
+    fn ioprio(self) -> libc::c_int {
+        let (class, level) = match self {
+            Self::Realtime => (1, IOPRIO_DEFAULT_LEVEL),
+            Self::BestEffort => (2, IOPRIO_DEFAULT_LEVEL),
+            Self::Idle => (3, 0),
+        };
+        (class << IOPRIO_CLASS_SHIFT) | level
+    }
+
+    /// Returns the class of an IO priority the kernel reports.
+    fn from_ioprio(ioprio: libc::c_int) -> Option<Self> {
+        match ioprio >> IOPRIO_CLASS_SHIFT {
+            1 => Some(Self::Realtime),
+            // Threads that never had a class set report none, served as best effort
+            0 | 2 => Some(Self::BestEffort),
+            3 => Some(Self::Idle),
+            _ => None,
+        }
+    }
+}
+
+/// Cores to pin to, as `--pin-cores` takes them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cores(pub Vec<usize>);
+
+impl FromStr for Cores {
+    type Err = anyhow::Error;
+
+    /// Parses a list of cores such as `2,3` or `4-7`.
+    fn from_str(text: &str) -> Result<Self> {
+        parse_cores(text).map(Self)
+    }
+}
+
+/// Priority settings of a run.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Priority {
+    /// Nice level from -20 to 19, higher yields the CPU to other processes sooner.
+    pub nice: Option<i32>,
+    pub io_class: Option<IoClass>,
+    /// Cores the capturing and scanning thread runs on, empty for any core.
+    pub cores: Vec<usize>,
+}
+
+impl Priority {
+    /// Collects the priority settings of `config`.
+    pub fn from_config(config: &BotConfig) -> Self {
+        Self {
+            nice: config.nice,
+            io_class: config.ionice,
+            cores: config
+                .pin_cores
+                .as_ref()
+                .map(|cores| cores.0.clone())
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Whether every setting is left at the default.
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Parses a list of cores such as `2,3` or `4-7`, sorted and without duplicates.
+fn parse_cores(text: &str) -> Result<Vec<usize>> {
+    let mut cores = Vec::new();
+    for part in text.split(',').map(str::trim) {
+        let (first, last) = match part.split_once('-') {
+            Some((first, last)) => (first.trim(), last.trim()),
+            None => (part, part),
+        };
+        let parse = |core: &str| {
+            core.parse::<usize>()
+                .context(format!("'{}' is not a core number", core))
+        };
+        let (first, last) = (parse(first)?, parse(last)?);
+        ensure!(first <= last, "The core range '{}' is backwards", part);
+        cores.extend(first..=last);
+    }
+    cores.sort_unstable();
+    cores.dedup();
+    Ok(cores)
+}
+
+/// Formats cores as a comma separated list.
+fn core_list(cores: &[usize]) -> String {
+    cores
+        .iter()
+        .map(usize::to_string)
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Returns the ids of the threads of this process.
+fn thread_ids() -> Result<Vec<Pid>> {
+    let tasks = std::fs::read_dir("/proc/self/task").context("Failed to list the threads")?;
+    Ok(tasks
+        .flatten()
+        .filter_map(|task| task.file_name().to_str()?.parse().ok())
+        .map(Pid::from_raw)
+        .collect())
+}
+
+/// Sets the nice level of a thread.
+fn set_nice(tid: Pid, nice: i32) -> Result<()> {
+    // SAFETY: setpriority only reads its arguments
+    let result = unsafe { libc::setpriority(libc::PRIO_PROCESS, tid.as_raw() as libc::id_t, nice) };
+    Errno::result(result).context(format!("Failed to set the nice level of thread {}", tid))?;
+    Ok(())
+}
+
+/// Sets the IO class of a thread.
+fn set_io_class(tid: Pid, class: IoClass) -> Result<()> {
+    // SAFETY: ioprio_set only reads its arguments
+    let result = unsafe {
+        libc::syscall(
+            libc::SYS_ioprio_set,
+            IOPRIO_WHO_PROCESS,
+            tid.as_raw(),
+            class.ioprio(),
+        )
+    };
+    Errno::result(result).context(format!("Failed to set the IO class of thread {}", tid))?;
+    Ok(())
+}
+
+/// Reads the IO class of a thread.
+fn io_class_of(tid: Pid) -> Result<IoClass> {
+    // SAFETY: ioprio_get only reads its arguments
+    let result = unsafe { libc::syscall(libc::SYS_ioprio_get, IOPRIO_WHO_PROCESS, tid.as_raw()) };
+    let ioprio = Errno::result(result).context("Failed to read the IO class")?;
+    IoClass::from_ioprio(ioprio as libc::c_int)
+        .context(format!("Unknown IO priority {:#x}", ioprio))
+}
+
+/// Reads the nice level of a thread from its stat file.
+fn nice_of(tid: Pid) -> Result<i32> {
+    let path = Path::new("/proc/self/task")
+        .join(tid.to_string())
+        .join("stat");
+    let stat =
+        std::fs::read_to_string(&path).context(format!("Failed to read {}", path.display()))?;
+    // The command name may contain spaces, the fields after it don't
+    let fields: Vec<&str> = stat
+        .rsplit_once(')')
+        .map(|(_, fields)| fields.split_whitespace().collect())
+        .unwrap_or_default();
+    // Nice is the 19th field, the 17th after the command name
+    fields
+        .get(16)
+        .and_then(|nice| nice.parse().ok())
+        .context(format!("Failed to parse {}", path.display()))
+}
+
+/// Reads the cores the calling thread may run on, as the kernel lists them.
+fn allowed_cores() -> Result<Vec<usize>> {
+    let status = std::fs::read_to_string("/proc/thread-self/status")
+        .context("Failed to read the thread status")?;
+    let list = status
+        .lines()
+        .find_map(|line| line.strip_prefix("Cpus_allowed_list:"))
+        .context("The thread status doesn't list the allowed cores")?;
+    parse_cores(list)
+}
+
+/// Returns the cores the system has online, which may have gaps such as `0-3,8-11`.
+fn online_cores() -> Result<Vec<usize>> {
+    let online = std::fs::read_to_string("/sys/devices/system/cpu/online")
+        .context("Failed to read the online cores")?;
+    parse_cores(online.trim())
+}
+
+/// Applies the priority settings to this process and checks that they took effect.
+///
+/// Niceness and IO priority are set for every thread, threads started later inherit them. The
+/// calling thread, which captures and scans the screen, is pinned to the cores, and so are the
+/// threads it starts later on.
+pub fn apply(priority: &Priority) -> Result<()> {
+    if priority.is_default() {
+        return Ok(());
+    }
+    let threads = thread_ids()?;
+    if let Some(nice) = priority.nice {
+        for tid in &threads {
+            set_nice(*tid, nice)?;
+        }
+        let actual = nice_of(unistd::gettid())?;
+        ensure!(
+            actual == nice,
+            "The nice level is {} instead of {}, levels below 0 need root or CAP_SYS_NICE",
+            actual,
+            nice
+        );
+        debug!("Running at nice level {}", nice);
+    }
+    if let Some(class) = priority.io_class {
+        for tid in &threads {
+            set_io_class(*tid, class)?;
+        }
+        let actual = io_class_of(unistd::gettid())?;
+        ensure!(
+            actual == class,
+            "The IO class is {:?} instead of {:?}, the realtime class needs root",
+            actual,
+            class
+        );
+        debug!("Running in the {:?} IO class", class);
+    }
+    if !priority.cores.is_empty() {
+        let online = online_cores()?;
+        if let Some(core) = priority.cores.iter().find(|core| !online.contains(core)) {
+            bail!(
+                "Core {} isn't online, the online cores are {}",
+                core,
+                core_list(&online)
+            );
+        }
+        let list = core_list(&priority.cores);
+        let mut set = CpuSet::new();
+        for core in &priority.cores {
+            set.set(*core)
+                .context(format!("Core {} is beyond the supported cores", core))?;
+        }
+        // Pid 0 is the calling thread
+        sched::sched_setaffinity(Pid::from_raw(0), &set)
+            .context(format!("Failed to pin capturing to cores {}", list))?;
+        let actual = allowed_cores()?;
+        ensure!(
+            actual == priority.cores,
+            "Capturing runs on cores {} instead of {}",
+            core_list(&actual),
+            list
+        );
+        debug!("Capturing and scanning on cores {}", list);
+    }
+    Ok(())
+}
+
+/// Describes what the priority settings can do on this system, for `colorbot doctor`.
+///
+/// Fails when the current priority can't be read, since the settings then can't be checked.
+pub fn check() -> Result<String> {
+    let nice = nice_of(unistd::gettid())?;
+    io_class_of(unistd::gettid())?;
+    let cores = online_cores()?.len();
+    // The soft limit is 20 minus the lowest nice level the process may set
+    let limits = std::fs::read_to_string("/proc/self/limits").unwrap_or_default();
+    let max_nice = limits
+        .lines()
+        .find(|line| line.starts_with("Max nice priority"))
+        .and_then(|line| line.split_whitespace().nth(3))
+        .and_then(|limit| limit.parse::<i32>().ok())
+        .unwrap_or(0);
+    let lowest = if nice_is_unrestricted() {
+        -20
+    } else {
+        (20 - max_nice).min(nice)
+    };
+    Ok(format!(
+        "{} cores online, running at nice {}, --nice can go down to {}",
+        cores, nice, lowest
+    ))
+}
+
+/// Whether the process runs as root, which may set any nice level.
+fn nice_is_unrestricted() -> bool {
+    std::fs::read_to_string("/proc/self/status")
+        .ok()
+        .and_then(|status| {
+            let uids = status.lines().find_map(|line| line.strip_prefix("Uid:"))?;
+            // Real, effective, saved and filesystem ids, the effective one counts
+            uids.split_whitespace().nth(1).map(|uid| uid == "0")
+        })
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_core_lists_and_ranges() {
+        assert_eq!(parse_cores("3").unwrap(), vec![3]);
+        assert_eq!(parse_cores("2,3").unwrap(), vec![2, 3]);
+        assert_eq!(parse_cores("4-6, 1").unwrap(), vec![1, 4, 5, 6]);
+        assert_eq!(parse_cores("0-1,1").unwrap(), vec![0, 1]);
+        assert!(parse_cores("3-1").is_err());
+        assert!(parse_cores("two").is_err());
+    }
+
+    #[test]
+    fn io_classes_round_trip_through_ioprio() {
+        for class in [IoClass::Realtime, IoClass::BestEffort, IoClass::Idle] {
+            assert_eq!(IoClass::from_ioprio(class.ioprio()), Some(class));
+        }
+        assert_eq!(IoClass::Idle.ioprio(), 3 << 13);
+        assert_eq!(IoClass::from_ioprio(0), Some(IoClass::BestEffort));
+        assert_eq!(IoClass::from_ioprio(7 << 13), None);
+    }
+}
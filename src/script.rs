@@ -0,0 +1,572 @@
+//! Bot script files.
+//!
+//! A script is either a plain array of events or an object holding the events together with
+//! script-level settings, such as named delay profiles that events refer to by name and the
+//! burst-and-pause rhythm:
+//!
+//! ```json
+//! {
+//!   "delays": { "short": [200, 400], "think": [800, 1500] },
+//!   "rhythm": { "burst_length": [3, 8], "pause": { "type": "lognormal", "median": 2500, "sigma": 0.5 } },
+//!   "events": [{ "id": "drop", "type": "keypress", "keycode": "shift", "delay": "short" }]
+//! }
+//! ```
+//!
+//! The object form can also hold a `metadata` block describing the screen, window and backend
+//! the script was written for, which is checked before the run starts, and `forbidden` regions
+//! the cursor must never enter. Its `themes` name visual themes such as day and night, each
+//! recognized by a probe signature, that color events can have target variants for:
+//!
+//! ```json
+//! "themes": { "probes": [{ "name": "night", "rgb": [20, 24, 60], "search_region": {...} }] }
+//! ```
+//!
+//! Either form may mark known-safe states between events with named checkpoints such as
+//! `{ "checkpoint": "bank_open" }`, where a run can begin instead of at the first event, and
+//! group events into transactions whose `on_fail` routine rolls back a partial failure:
+//!
+//! ```json
+//! { "transaction": "bank", "events": [...], "on_fail": [{ "id": "close", "type": "keypress", "keycode": "Escape", "delay": 600 }] }
+//! ```
+//!
+//! Repeat blocks run their events a number of times, or until a signature is on screen:
+//!
+//! ```json
+//! { "repeat": { "until": { "rgb": [255, 0, 0] }, "max_times": 28 }, "events": [...] }
+//! ```
+//!
+//! The object form may also hold `tests` for `colorbot test`, see the `scripttest` module.
+use crate::delay::ScriptDelay;
+use crate::error::ColorbotError;
+use crate::event::BotEvent;
+use crate::metadata::Metadata;
+use crate::rhythm::RhythmConfig;
+use crate::scripttest::ScriptTest;
+use crate::signature::Signature;
+use crate::theme::ThemeConfig;
+use crate::vision::Region;
+
+use anyhow::{bail, ensure, Context, Result};
+use log::{debug, warn};
+use serde::Deserialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::ops::Range;
+use std::path::Path;
+
+/// Script in its object form.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ScriptFile {
+    /// Setup the script expects.
+    #[serde(default)]
+    metadata: Metadata,
+    /// Named delay profiles.
+    #[serde(default)]
+    delays: BTreeMap<String, ScriptDelay>,
+    /// Burst-and-pause rhythm applied to event delays.
+    #[serde(default)]
+    rhythm: Option<RhythmConfig>,
+    /// Screen regions the cursor must never enter.
+    #[serde(default)]
+    forbidden: Vec<Region>,
+    /// Visual themes that color events have target variants for.
+    #[serde(default)]
+    themes: Option<ThemeConfig>,
+    /// Events executed in order, interleaved with checkpoint markers.
+    events: Vec<serde_json::Value>,
+    /// Test cases of the events' detection, run by `colorbot test`.
+    #[serde(default)]
+    tests: Vec<ScriptTest>,
+}
+
+/// Transaction as written in a script's event list.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TransactionEntry {
+    /// Name of the transaction for logging.
+    transaction: String,
+    /// Events that succeed or fail as a whole.
+    events: Vec<BotEvent>,
+    /// Events run after any of the events fails, to get back to a known state.
+    #[serde(default)]
+    on_fail: Vec<BotEvent>,
+}
+
+/// Repeat block as written in a script's event list.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RepeatEntry {
+    /// How often the events run.
+    repeat: Repetition,
+    /// Events run in order on every pass.
+    events: Vec<BotEvent>,
+}
+
+/// Entry of a script's event list.
+enum Entry {
+    /// Named checkpoint before the next event.
+    Checkpoint(String),
+    Transaction(Box<TransactionEntry>),
+    Repeat(Box<RepeatEntry>),
+    Event(Box<BotEvent>),
+}
+
+impl Entry {
+    /// Parses an entry, which is a checkpoint when it's an object with only a checkpoint name.
+    fn parse(value: serde_json::Value) -> Result<Self> {
+        if let Some(object) = value.as_object() {
+            if let Some(name) = object.get("checkpoint") {
+                ensure!(
+                    object.len() == 1,
+                    "Checkpoint markers must not have other fields"
+                );
+                let Some(name) = name.as_str() else {
+                    bail!("Checkpoint name must be a string");
+                };
+                ensure!(!name.is_empty(), "Checkpoint name must not be empty");
+                return Ok(Entry::Checkpoint(name.to_string()));
+            }
+            if object.contains_key("transaction") {
+                return Ok(Entry::Transaction(Box::new(serde_json::from_value(value)?)));
+            }
+            if object.contains_key("repeat") {
+                return Ok(Entry::Repeat(Box::new(serde_json::from_value(value)?)));
+            }
+        }
+        Ok(Entry::Event(Box::new(serde_json::from_value(value)?)))
+    }
+}
+
+/// Parses a single entry of a script's event list and returns its events followed by the
+/// rollback events of a transaction, for checks that go through a script entry by entry.
+pub fn entry_events(value: serde_json::Value) -> Result<Vec<BotEvent>> {
+    Ok(match Entry::parse(value)? {
+        Entry::Checkpoint(_) => Vec::new(),
+        Entry::Transaction(transaction) => {
+            let TransactionEntry {
+                events, on_fail, ..
+            } = *transaction;
+            events.into_iter().chain(on_fail).collect()
+        }
+        Entry::Repeat(repeat) => repeat.events,
+        Entry::Event(event) => vec![*event],
+    })
+}
+
+/// Consecutive script events that succeed or fail as a whole.
+#[derive(Debug)]
+pub struct Transaction {
+    /// Name of the transaction for logging.
+    pub name: String,
+    /// Indices of the transaction's events in the script.
+    pub events: Range<usize>,
+    /// Events run after any of the events fails, to get back to a known state.
+    pub on_fail: Vec<BotEvent>,
+}
+
+/// How often the events of a repeat block run.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum Repetition {
+    /// A fixed number of passes.
+    Times(u32),
+    /// Passes until a signature is on screen, checked after every pass, and at most `max_times`
+    /// passes when given.
+    Until {
+        until: Box<Signature>,
+        #[serde(default)]
+        max_times: Option<u32>,
+    },
+}
+
+/// Consecutive script events that run several times in a row.
+#[derive(Debug)]
+pub struct Repeat {
+    /// Indices of the block's events in the script.
+    pub events: Range<usize>,
+    /// Id of the block's first event, which names the block in logs.
+    pub first_event: String,
+    /// How often the events run.
+    pub repetition: Repetition,
+}
+
+impl Repeat {
+    /// Returns whether the block runs again after `passes` passes.
+    fn again(&self, passes: u32) -> Result<bool> {
+        match &self.repetition {
+            Repetition::Times(times) => Ok(passes < *times),
+            Repetition::Until { until, max_times } => {
+                let present = until.is_present().context(format!(
+                    "Failed to check the end of the repeat block starting at '{}'",
+                    self.first_event
+                ))?;
+                if present {
+                    debug!(
+                        "Repeat block starting at '{}' ended after {} passes",
+                        self.first_event, passes
+                    );
+                    return Ok(false);
+                }
+                if max_times.is_some_and(|max| passes >= max) {
+                    warn!(
+                        "Repeat block starting at '{}' stopped after {} passes, its end condition \
+                         never appeared",
+                        self.first_event, passes
+                    );
+                    return Ok(false);
+                }
+                Ok(true)
+            }
+        }
+    }
+}
+
+/// A loaded and validated bot script.
+#[derive(Debug)]
+pub struct Script {
+    /// Setup the script expects.
+    pub metadata: Metadata,
+    /// Events executed in order.
+    pub events: Vec<BotEvent>,
+    /// Burst-and-pause rhythm applied to event delays.
+    pub rhythm: Option<RhythmConfig>,
+    /// Screen regions the cursor must never enter.
+    pub forbidden: Vec<Region>,
+    /// Visual themes that color events have target variants for.
+    pub themes: Option<ThemeConfig>,
+    /// Index of the event following each named checkpoint.
+    pub checkpoints: BTreeMap<String, usize>,
+    /// Transactions grouping some of the events.
+    pub transactions: Vec<Transaction>,
+    /// Repeat blocks grouping some of the events.
+    pub repeats: Vec<Repeat>,
+    /// Test cases of the events' detection.
+    pub tests: Vec<ScriptTest>,
+}
+
+impl Script {
+    /// Returns the transaction the event at `index` belongs to, if any.
+    pub fn transaction_at(&self, index: usize) -> Option<&Transaction> {
+        self.transactions
+            .iter()
+            .find(|transaction| transaction.events.contains(&index))
+    }
+
+    /// Returns the index of the event to run after the one at `index`.
+    ///
+    /// After the last event of a repeat block that runs again, that's the block's first event.
+    /// `passes` counts the passes of every repeat block and is reset when a block ends.
+    pub fn next_index(&self, index: usize, passes: &mut [u32]) -> Result<usize> {
+        let Some((i, repeat)) = self
+            .repeats
+            .iter()
+            .enumerate()
+            .find(|(_, repeat)| repeat.events.end == index + 1)
+        else {
+            return Ok(index + 1);
+        };
+        passes[i] += 1;
+        if repeat.again(passes[i])? {
+            return Ok(repeat.events.start);
+        }
+        passes[i] = 0;
+        Ok(index + 1)
+    }
+
+    /// Index of the event to start at, the first one unless a checkpoint is given.
+    pub fn start_index(&self, checkpoint: Option<&str>) -> Result<usize> {
+        let Some(name) = checkpoint else {
+            return Ok(0);
+        };
+        match self.checkpoints.get(name) {
+            Some(index) => Ok(*index),
+            None if self.checkpoints.is_empty() => {
+                bail!("Unknown checkpoint '{}', the script has none", name)
+            }
+            None => bail!(
+                "Unknown checkpoint '{}', the script has {}",
+                name,
+                self.checkpoints
+                    .keys()
+                    .map(|name| format!("'{}'", name))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        }
+    }
+}
+
+/// Reads a bot script, resolves delay profiles and validates every event.
+pub fn load(path: &Path) -> Result<Script, ColorbotError> {
+    Ok(read_script(path)?)
+}
+
+fn read_script(path: &Path) -> Result<Script> {
+    let parse_error =
+        |e: serde_json::Error| ColorbotError::ScriptParse(path.to_path_buf(), e.into());
+    let content = std::fs::read_to_string(path).context("Failed to open bot script")?;
+    let value: serde_json::Value =
+        serde_json::from_str(&strip_comments(&content)).map_err(parse_error)?;
+
+    // Parse the form directly so errors point at the actual problem rather than at the form
+    let mut script = if value.is_array() {
+        ScriptFile {
+            metadata: Metadata::default(),
+            delays: BTreeMap::new(),
+            rhythm: None,
+            forbidden: Vec::new(),
+            themes: None,
+            events: serde_json::from_value(value).map_err(parse_error)?,
+            tests: Vec::new(),
+        }
+    } else {
+        serde_json::from_value(value).map_err(parse_error)?
+    };
+
+    if let Some(rhythm) = &script.rhythm {
+        rhythm.validate().context("Invalid script rhythm")?;
+    }
+    let rhythm = script.rhythm.take();
+    for region in &script.forbidden {
+        ensure!(
+            region.width > 0 && region.height > 0,
+            "Forbidden region {:?} must not be empty",
+            region
+        );
+    }
+
+    let mut events = Vec::new();
+    let mut checkpoints = BTreeMap::new();
+    let mut transactions: Vec<Transaction> = Vec::new();
+    let mut repeats: Vec<Repeat> = Vec::new();
+    for (i, value) in std::mem::take(&mut script.events).into_iter().enumerate() {
+        match Entry::parse(value).context(format!("Failed to parse script entry {}", i + 1))? {
+            Entry::Checkpoint(name) => {
+                ensure!(
+                    checkpoints.insert(name.clone(), events.len()).is_none(),
+                    "Checkpoint '{}' is defined more than once",
+                    name
+                );
+            }
+            Entry::Transaction(transaction) => {
+                let TransactionEntry {
+                    transaction: name,
+                    events: steps,
+                    on_fail,
+                } = *transaction;
+                ensure!(
+                    !steps.is_empty(),
+                    "Transaction '{}' must have at least one event",
+                    name
+                );
+                ensure!(
+                    transactions.iter().all(|other| other.name != name),
+                    "Transaction '{}' is defined more than once",
+                    name
+                );
+                let first = events.len();
+                events.extend(steps);
+                transactions.push(Transaction {
+                    name,
+                    events: first..events.len(),
+                    on_fail,
+                });
+            }
+            Entry::Repeat(repeat) => {
+                let RepeatEntry {
+                    repeat: repetition,
+                    events: steps,
+                } = *repeat;
+                ensure!(
+                    !steps.is_empty(),
+                    "Repeat block {} must have at least one event",
+                    i + 1
+                );
+                match &repetition {
+                    Repetition::Times(times) => {
+                        ensure!(*times >= 1, "Repeat block {} must run at least once", i + 1)
+                    }
+                    Repetition::Until { until, max_times } => {
+                        until
+                            .validate()
+                            .context(format!("Invalid end condition of repeat block {}", i + 1))?;
+                        ensure!(
+                            max_times.is_none_or(|max| max >= 1),
+                            "Repeat block {} must run at least once",
+                            i + 1
+                        );
+                    }
+                }
+                let first = events.len();
+                let first_event = steps[0].id.clone();
+                events.extend(steps);
+                repeats.push(Repeat {
+                    events: first..events.len(),
+                    first_event,
+                    repetition,
+                });
+            }
+            Entry::Event(event) => events.push(*event),
+        }
+    }
+    if let Some((name, _)) = checkpoints
+        .iter()
+        .find(|(_, index)| **index == events.len())
+    {
+        bail!("Checkpoint '{}' must be followed by an event", name);
+    }
+    let rollbacks = transactions
+        .iter()
+        .flat_map(|transaction| &transaction.on_fail);
+    for event in events.iter().chain(rollbacks) {
+        for id in event.when.iter().flat_map(|when| when.event_ids()) {
+            ensure!(
+                events.iter().any(|other| other.id == id),
+                "Condition of event '{}' refers to unknown event '{}'",
+                event.id,
+                id
+            );
+        }
+    }
+
+    let rollbacks = transactions
+        .iter()
+        .flat_map(|transaction| &transaction.on_fail);
+    for event in events.iter().chain(rollbacks) {
+        for id in &event.requires {
+            ensure!(*id != event.id, "Event '{}' can't require itself", event.id);
+            ensure!(
+                events.iter().any(|other| other.id == *id),
+                "Event '{}' requires unknown event '{}'",
+                event.id,
+                id
+            );
+        }
+    }
+
+    for event in &events {
+        for id in event.jump_targets() {
+            ensure!(
+                events.iter().any(|other| other.id == id),
+                "Event '{}' branches to unknown event '{}'",
+                event.id,
+                id
+            );
+        }
+    }
+    for transaction in &transactions {
+        if let Some(event) = transaction
+            .on_fail
+            .iter()
+            .find(|event| event.jump_targets().next().is_some())
+        {
+            bail!(
+                "Rollback event '{}' of transaction '{}' branches, rollbacks run straight through",
+                event.id,
+                transaction.name
+            );
+        }
+    }
+
+    if let Some(themes) = &script.themes {
+        themes.validate().context("Invalid script themes")?;
+    }
+    let rollbacks = transactions
+        .iter()
+        .flat_map(|transaction| &transaction.on_fail);
+    for event in events.iter().chain(rollbacks) {
+        for name in event.theme_names() {
+            ensure!(
+                script
+                    .themes
+                    .as_ref()
+                    .is_some_and(|themes| themes.contains(name)),
+                "Event '{}' has a variant for unknown theme '{}'",
+                event.id,
+                name
+            );
+        }
+    }
+
+    let rollbacks = transactions
+        .iter_mut()
+        .flat_map(|transaction| &mut transaction.on_fail);
+    resolve_delays(&script.delays, events.iter_mut().chain(rollbacks))?;
+    for test in &script.tests {
+        let rollbacks = transactions
+            .iter()
+            .flat_map(|transaction| &transaction.on_fail);
+        test.validate(events.iter().chain(rollbacks))?;
+    }
+
+    Ok(Script {
+        metadata: script.metadata,
+        events,
+        rhythm,
+        forbidden: script.forbidden,
+        themes: script.themes,
+        checkpoints,
+        transactions,
+        repeats,
+        tests: script.tests,
+    })
+}
+
+/// Blanks out `//` comments that run to the end of a line outside of strings.
+///
+/// Comments are replaced with spaces rather than removed, so parse errors still point at the
+/// line and column of the original text.
+pub fn strip_comments(text: &str) -> String {
+    let mut stripped = String::with_capacity(text.len());
+    let mut in_string = false;
+    let mut escaped = false;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if in_string {
+            in_string = escaped || c != '"';
+            escaped = !escaped && c == '\\';
+            stripped.push(c);
+        } else if c == '/' && chars.peek() == Some(&'/') {
+            stripped.push(' ');
+            while chars.next_if(|c| *c != '\n').is_some() {
+                stripped.push(' ');
+            }
+        } else {
+            in_string = c == '"';
+            stripped.push(c);
+        }
+    }
+    stripped
+}
+
+/// Resolves the events' delay profile references and warns about unused profiles.
+fn resolve_delays<'a>(
+    delays: &BTreeMap<String, ScriptDelay>,
+    events: impl IntoIterator<Item = &'a mut BotEvent>,
+) -> Result<()> {
+    for (name, delay) in delays {
+        delay
+            .validate()
+            .context(format!("Invalid delay profile '{}'", name))?;
+    }
+
+    let mut used = BTreeSet::new();
+    for event in events {
+        let id = event.id.clone();
+        for delay in event.delays_mut() {
+            if let ScriptDelay::Profile(name) = delay {
+                used.insert(name.clone());
+            }
+            delay
+                .resolve(delays)
+                .context(format!("Invalid delay in event '{}'", id))?;
+        }
+        event.validate()?;
+    }
+
+    for name in delays.keys().filter(|name| !used.contains(*name)) {
+        warn!("Delay profile '{}' is not used by any event", name);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,335 @@
+//! Test cases embedded in scripts, run by the `test` subcommand.
+//!
+//! A test looks for one event's target in a fixture screenshot, the same way the event would on
+//! the live screen, and checks how many pixels matched and where the target was found:
+//!
+//! ```json
+//! "tests": [{
+//!   "given_frame": "fixtures/bank_open.png",
+//!   "event": "withdraw",
+//!   "expect": { "matches_at_least": 1, "target_within": { "x": 40, "y": 60, "width": 30, "height": 30 } }
+//! }]
+//! ```
+//!
+//! Screenshots recorded with `--record-frames` or taken when the detection last worked make the
+//! tests regression tests for the script's colors, templates and filters after a game update.
+use crate::drag::DragEnd;
+use crate::event::{BotEvent, BotEventType};
+use crate::filter::Filter;
+use crate::output::{self, Render};
+use crate::replay;
+use crate::script::{self, Script};
+use crate::vision::{self, ColorTarget, Region, ResultMode, ScanOptions};
+use crate::windmouse::Point;
+
+use anyhow::{bail, ensure, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Test case of a script.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScriptTest {
+    /// Name shown in the results, defaults to the event id and screenshot.
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Full-screen screenshot the event's target is searched in.
+    pub given_frame: PathBuf,
+    /// Id of the event whose target is searched for.
+    pub event: String,
+    pub expect: Expectation,
+}
+
+/// What a test expects of the search.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Expectation {
+    /// Fewest matching pixels, or found templates.
+    #[serde(default)]
+    pub matches_at_least: Option<usize>,
+    /// Most matching pixels, 0 to expect that the target isn't found.
+    #[serde(default)]
+    pub matches_at_most: Option<usize>,
+    /// Region the point the event would click must lie in.
+    #[serde(default)]
+    pub target_within: Option<Region>,
+}
+
+impl ScriptTest {
+    /// Checks that the test expects something of an event the script has.
+    pub fn validate<'a>(&self, mut events: impl Iterator<Item = &'a BotEvent>) -> Result<()> {
+        let expect = &self.expect;
+        ensure!(
+            expect.matches_at_least.is_some()
+                || expect.matches_at_most.is_some()
+                || expect.target_within.is_some(),
+            "Test '{}' expects nothing, give matches_at_least, matches_at_most or target_within",
+            self.name()
+        );
+        if let (Some(least), Some(most)) = (expect.matches_at_least, expect.matches_at_most) {
+            ensure!(
+                least <= most,
+                "Test '{}' expects at least {} and at most {} matches",
+                self.name(),
+                least,
+                most
+            );
+        }
+        let Some(event) = events.find(|event| event.id == self.event) else {
+            bail!(
+                "Test '{}' refers to unknown event '{}'",
+                self.name(),
+                self.event
+            );
+        };
+        ensure!(
+            has_target(&event.event_type),
+            "Test '{}' refers to event '{}', which has no target to test",
+            self.name(),
+            self.event
+        );
+        Ok(())
+    }
+
+    /// Returns the name of the test.
+    fn name(&self) -> String {
+        self.name
+            .clone()
+            .unwrap_or_else(|| format!("{} on {}", self.event, self.given_frame.display()))
+    }
+
+    /// Searches the screenshot for the event's target and returns the failed expectations.
+    fn run(&self, event: &BotEvent) -> Result<Vec<String>> {
+        let frame = image::open(&self.given_frame)
+            .context(format!(
+                "Failed to open screenshot {}",
+                self.given_frame.display()
+            ))?
+            .to_rgba8();
+        replay::start_still(frame);
+        let observed = observe(event)?;
+
+        let mut failures = Vec::new();
+        if let Some(least) = self.expect.matches_at_least {
+            if observed.matches < least {
+                failures.push(format!(
+                    "{} matches, expected at least {}",
+                    observed.matches, least
+                ));
+            }
+        }
+        if let Some(most) = self.expect.matches_at_most {
+            if observed.matches > most {
+                failures.push(format!(
+                    "{} matches, expected at most {}",
+                    observed.matches, most
+                ));
+            }
+        }
+        if let Some(region) = &self.expect.target_within {
+            match observed.target {
+                Some(point) if region.contains(point) => {}
+                Some(point) => failures.push(format!(
+                    "target at {}, expected within {}",
+                    point,
+                    describe(region)
+                )),
+                None => failures.push(format!("no target, expected within {}", describe(region))),
+            }
+        }
+        Ok(failures)
+    }
+}
+
+/// Returns whether events of a type search the screen for a target.
+fn has_target(event_type: &BotEventType) -> bool {
+    match event_type {
+        BotEventType::Color { .. }
+        | BotEventType::Image { .. }
+        | BotEventType::Drag { .. }
+        | BotEventType::WaitForColor { .. } => true,
+        #[cfg(feature = "template")]
+        BotEventType::Atlas { .. } => true,
+        BotEventType::KeyPress { .. }
+        | BotEventType::TypeText { .. }
+        | BotEventType::Relogin(_) => false,
+        #[cfg(feature = "gamepad")]
+        BotEventType::Button { .. } | BotEventType::Axis { .. } => false,
+    }
+}
+
+/// Describes a region in the `x,y,width,height` shorthand.
+fn describe(region: &Region) -> String {
+    format!(
+        "{},{},{},{}",
+        region.x, region.y, region.width, region.height
+    )
+}
+
+/// What the search for an event's target found.
+struct Observation {
+    /// Matching pixels, or 1 for a found template.
+    matches: usize,
+    /// Point the event would move the cursor to.
+    target: Option<Point>,
+}
+
+/// Searches the screen for a color target like a color event does.
+fn observe_color(
+    target: &ColorTarget,
+    filters: &[Filter],
+    scan: &ScanOptions,
+) -> Result<Observation> {
+    let count_only = ScanOptions {
+        result_mode: ResultMode::CountOnly,
+        ..scan.clone()
+    };
+    let matches = vision::scan_screen(target, filters, &count_only)?.count;
+    let target = (matches > 0)
+        .then(|| vision::find_point_in_shape(target, filters, scan))
+        .transpose()?
+        .map(|hit| hit.point);
+    Ok(Observation { matches, target })
+}
+
+/// Searches the screen for an event's target.
+fn observe(event: &BotEvent) -> Result<Observation> {
+    let found = |point: Option<Point>| Observation {
+        matches: usize::from(point.is_some()),
+        target: point,
+    };
+    match &event.event_type {
+        BotEventType::Color {
+            target,
+            filters,
+            scan,
+            ..
+        }
+        | BotEventType::WaitForColor {
+            target,
+            filters,
+            scan,
+            ..
+        } => observe_color(target, filters, scan),
+        BotEventType::Image {
+            image_path,
+            filters,
+            ..
+        } => Ok(found(
+            vision::locate_image_on_screen(image_path, filters)?.map(|hit| hit.point),
+        )),
+        #[cfg(feature = "template")]
+        BotEventType::Atlas { search, .. } => {
+            Ok(found(search.locate()?.map(|found| found.hit.point)))
+        }
+        BotEventType::Drag { from, .. } => match from {
+            DragEnd::Color {
+                target,
+                filters,
+                scan,
+            } => observe_color(target, filters, scan),
+            DragEnd::Point { point } => Ok(found(Some(*point))),
+        },
+        // Loading rejects tests of events without a target
+        BotEventType::KeyPress { .. }
+        | BotEventType::TypeText { .. }
+        | BotEventType::Relogin(_) => {
+            bail!("Event '{}' has no target to test", event.id)
+        }
+        #[cfg(feature = "gamepad")]
+        BotEventType::Button { .. } | BotEventType::Axis { .. } => {
+            bail!("Event '{}' has no target to test", event.id)
+        }
+    }
+}
+
+/// Outcome of a single test.
+#[derive(Serialize)]
+struct TestResult {
+    name: String,
+    event: String,
+    passed: bool,
+    /// Failed expectations, or the error that stopped the test.
+    failures: Vec<String>,
+}
+
+/// Outcome of every test of a script.
+#[derive(Serialize)]
+struct Report {
+    passed: usize,
+    tests: Vec<TestResult>,
+}
+
+impl Render for Report {
+    fn render(&self, out: &mut dyn Write) -> std::io::Result<()> {
+        for test in &self.tests {
+            let status = if test.passed { "ok" } else { "FAIL" };
+            writeln!(out, "{:<4} {}", status, test.name)?;
+            for failure in &test.failures {
+                writeln!(out, "       {}", failure)?;
+            }
+        }
+        writeln!(
+            out,
+            "\n{} of {} tests passed",
+            self.passed,
+            self.tests.len()
+        )
+    }
+}
+
+/// Returns the event a test refers to, including rollback events.
+fn find_event<'a>(script: &'a Script, id: &str) -> Option<&'a BotEvent> {
+    let rollbacks = script
+        .transactions
+        .iter()
+        .flat_map(|transaction| &transaction.on_fail);
+    script
+        .events
+        .iter()
+        .chain(rollbacks)
+        .find(|event| event.id == id)
+}
+
+/// Runs the tests of a script and prints their results, failing when any test fails.
+pub fn run(path: &Path) -> Result<()> {
+    let script = script::load(path)?;
+    ensure!(
+        !script.tests.is_empty(),
+        "{} has no tests, add them to the script's 'tests' list",
+        path.display()
+    );
+
+    let tests: Vec<TestResult> = script
+        .tests
+        .iter()
+        .map(|test| {
+            // Loading checked that every test refers to an event
+            let failures = match find_event(&script, &test.event).map(|event| test.run(event)) {
+                Some(Ok(failures)) => failures,
+                Some(Err(e)) => vec![format!("error: {:#}", e)],
+                None => vec![format!("unknown event '{}'", test.event)],
+            };
+            TestResult {
+                name: test.name(),
+                event: test.event.clone(),
+                passed: failures.is_empty(),
+                failures,
+            }
+        })
+        .collect();
+    let report = Report {
+        passed: tests.iter().filter(|test| test.passed).count(),
+        tests,
+    };
+    output::print(&report)?;
+    let failed = report.tests.len() - report.passed;
+    ensure!(
+        failed == 0,
+        "{} of {} tests failed",
+        failed,
+        report.tests.len()
+    );
+    Ok(())
+}
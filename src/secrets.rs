@@ -0,0 +1,345 @@
+//! Encrypted storage for sensitive script values such as bank PINs and passwords.
+//!
+//! Secrets live in a separate file encrypted with ChaCha20-Poly1305 under a key derived from a
+//! password with Argon2id. The password is read from the desktop keyring through `secret-tool`
+//! or prompted for at startup. Scripts refer to secrets by name and their values never appear in
+//! logs or reports.
+use crate::process;
+
+use anyhow::{anyhow, bail, ensure, Context, Result};
+use argon2::Argon2;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::Path;
+use std::process::Command;
+use std::time::Duration;
+
+/// Attribute pair identifying the secrets password in the desktop keyring.
+const KEYRING_ATTRIBUTES: [&str; 2] = ["application", "colorbot"];
+
+/// Writes `contents` to `path` readable by the owner only, replacing the old file in one step so
+/// a crash can't leave it truncated.
+fn write_private(path: &Path, contents: &[u8]) -> Result<()> {
+    let mut tmp_name = path.file_name().context("No file name")?.to_os_string();
+    tmp_name.push(".tmp");
+    let tmp = path.with_file_name(tmp_name);
+    // A leftover from a crashed write would keep its old permissions
+    let _ = fs::remove_file(&tmp);
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(&tmp)
+        .context(format!("Failed to create {}", tmp.display()))?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    fs::rename(&tmp, path).context(format!("Failed to replace {}", path.display()))
+}
+
+/// On-disk representation of an encrypted secrets file.
+#[derive(Debug, Serialize, Deserialize)]
+struct EncryptedFile {
+    salt: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+/// String value in a script that is either written inline or looked up in the secret store.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(untagged)]
+pub enum ScriptString {
+    /// Plain value written directly in the script.
+    Plain(String),
+    /// Reference to a named value in the secret store.
+    Secret { secret: String },
+    /// Reference to a key binding of the keybinds profile, replaced by its keys before the run.
+    Action { action: String },
+}
+
+impl ScriptString {
+    /// Returns the actual value, looking secrets up in the store.
+    pub fn resolve<'a>(&'a self, secrets: &'a SecretStore) -> Result<&'a str> {
+        match self {
+            ScriptString::Plain(value) => Ok(value),
+            ScriptString::Secret { secret } => secrets.get(secret),
+            ScriptString::Action { action } => {
+                bail!(
+                    "Action '{}' only works as the key of a keypress event",
+                    action
+                )
+            }
+        }
+    }
+
+    /// Passes `result` through for plain values, but replaces the error for secrets since
+    /// backend errors may quote the value.
+    pub fn redact<T>(&self, result: Result<T>, action: &str) -> Result<T> {
+        match self {
+            ScriptString::Plain(_) | ScriptString::Action { .. } => result,
+            ScriptString::Secret { .. } => {
+                result.map_err(|_| anyhow!("Failed to {} {}", action, self))
+            }
+        }
+    }
+
+    /// Returns the name of the referenced secret, if any.
+    pub fn secret_name(&self) -> Option<&str> {
+        match self {
+            ScriptString::Plain(_) | ScriptString::Action { .. } => None,
+            ScriptString::Secret { secret } => Some(secret),
+        }
+    }
+}
+
+impl std::fmt::Display for ScriptString {
+    /// Shows plain values as is and redacts secrets down to their name.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ScriptString::Plain(value) => write!(f, "{}", value),
+            ScriptString::Secret { secret } => write!(f, "<secret:{}>", secret),
+            ScriptString::Action { action } => write!(f, "<action:{}>", action),
+        }
+    }
+}
+
+/// Decrypted secret values keyed by name.
+#[derive(Default)]
+pub struct SecretStore {
+    values: BTreeMap<String, String>,
+}
+
+impl std::fmt::Debug for SecretStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SecretStore")
+            .field("names", &self.values.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+/// Derives the file encryption key from the password and salt.
+fn derive_key(password: &str, salt: &[u8]) -> Result<Key> {
+    let mut key = Key::default();
+    Argon2::default()
+        .hash_password_into(password.as_bytes(), salt, &mut key)
+        .map_err(|e| anyhow!("Failed to derive secrets key: {}", e))?;
+    Ok(key)
+}
+
+impl SecretStore {
+    /// Decrypts the secrets file at `path`.
+    pub fn load(path: &Path, password: &str) -> Result<Self> {
+        let contents = fs::read_to_string(path)
+            .context(format!("Failed to read secrets file {}", path.display()))?;
+        let file: EncryptedFile =
+            serde_json::from_str(&contents).context("Failed to parse secrets file")?;
+
+        let salt = STANDARD.decode(file.salt)?;
+        let nonce = STANDARD.decode(file.nonce)?;
+        let ciphertext = STANDARD.decode(file.ciphertext)?;
+        ensure!(nonce.len() == 12, "Secrets file has a malformed nonce");
+
+        let cipher = ChaCha20Poly1305::new(&derive_key(password, &salt)?);
+        let plaintext = cipher
+            .decrypt(Nonce::from_slice(&nonce), ciphertext.as_slice())
+            .map_err(|_| anyhow!("Failed to decrypt secrets file, wrong password?"))?;
+        let values = serde_json::from_slice(&plaintext).context("Corrupt secrets payload")?;
+
+        Ok(Self { values })
+    }
+
+    /// Encrypts the secrets into the file at `path` with a fresh salt and nonce.
+    pub fn save(&self, path: &Path, password: &str) -> Result<()> {
+        let salt: [u8; 16] = rand::random();
+        let nonce: [u8; 12] = rand::random();
+        let plaintext = serde_json::to_vec(&self.values)?;
+
+        let cipher = ChaCha20Poly1305::new(&derive_key(password, &salt)?);
+        let ciphertext = cipher
+            .encrypt(Nonce::from_slice(&nonce), plaintext.as_slice())
+            .map_err(|_| anyhow!("Failed to encrypt secrets"))?;
+
+        let file = EncryptedFile {
+            salt: STANDARD.encode(salt),
+            nonce: STANDARD.encode(nonce),
+            ciphertext: STANDARD.encode(ciphertext),
+        };
+        write_private(path, serde_json::to_string_pretty(&file)?.as_bytes())
+            .context(format!("Failed to write secrets file {}", path.display()))
+    }
+
+    /// Looks up a secret by name.
+    pub fn get(&self, name: &str) -> Result<&str> {
+        self.values
+            .get(name)
+            .map(String::as_str)
+            .context(format!("Secret '{}' is not in the secret store", name))
+    }
+
+    /// Returns whether a secret with this name exists.
+    pub fn contains(&self, name: &str) -> bool {
+        self.values.contains_key(name)
+    }
+
+    /// Inserts or replaces a secret.
+    pub fn set(&mut self, name: &str, value: String) {
+        self.values.insert(name.to_string(), value);
+    }
+
+    /// Removes a secret, returning whether it existed.
+    pub fn remove(&mut self, name: &str) -> bool {
+        self.values.remove(name).is_some()
+    }
+
+    /// Returns the names of all stored secrets.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.values.keys().map(String::as_str)
+    }
+}
+
+/// Reads the secrets password from the desktop keyring via libsecret's `secret-tool`.
+fn keyring_password() -> Result<String> {
+    // The keyring may show an unlock prompt, so give the user time to answer it
+    let output = process::run(
+        Command::new("secret-tool")
+            .arg("lookup")
+            .args(KEYRING_ATTRIBUTES),
+        "secret-tool",
+        Duration::from_secs(60),
+    )
+    .context("Failed to run secret-tool, is libsecret installed?")?;
+    if !output.status.success() || output.stdout.is_empty() {
+        bail!(
+            "No secrets password in the keyring, store one with 'secret-tool store --label colorbot {}'",
+            KEYRING_ATTRIBUTES.join(" ")
+        );
+    }
+
+    Ok(String::from_utf8(output.stdout)?
+        .trim_end_matches('\n')
+        .to_string())
+}
+
+/// Obtains the secrets password from the keyring or by prompting on the terminal.
+pub fn unlock_password(use_keyring: bool) -> Result<String> {
+    if use_keyring {
+        keyring_password()
+    } else {
+        rpassword::prompt_password("Secrets password: ").context("Failed to read password")
+    }
+}
+
+/// Subcommand actions for managing a secrets file.
+#[derive(clap::Subcommand, Debug)]
+pub enum SecretsAction {
+    /// Add or replace a secret, prompting for its value.
+    Set {
+        /// Name the script uses to refer to the secret.
+        name: String,
+    },
+    /// Remove a secret.
+    Remove {
+        /// Name of the secret to remove.
+        name: String,
+    },
+    /// List the names of stored secrets without revealing their values.
+    List,
+}
+
+/// Manages the secrets file at `path`, creating it on first use.
+pub fn manage(path: &Path, use_keyring: bool, action: SecretsAction) -> Result<()> {
+    let (mut store, password) = if path.exists() {
+        let password = unlock_password(use_keyring)?;
+        (SecretStore::load(path, &password)?, password)
+    } else {
+        let password = if use_keyring {
+            keyring_password()?
+        } else {
+            let password = rpassword::prompt_password("New secrets password: ")?;
+            let confirm = rpassword::prompt_password("Confirm password: ")?;
+            ensure!(password == confirm, "Passwords do not match");
+            password
+        };
+        (SecretStore::default(), password)
+    };
+
+    match action {
+        SecretsAction::Set { name } => {
+            let value = rpassword::prompt_password(format!("Value for '{}': ", name))?;
+            store.set(&name, value);
+            store.save(path, &password)?;
+        }
+        SecretsAction::Remove { name } => {
+            ensure!(store.remove(&name), "Secret '{}' does not exist", name);
+            store.save(path, &password)?;
+        }
+        SecretsAction::List => {
+            for name in store.names() {
+                println!("{}", name);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    #[test]
+    fn store_round_trips_and_rejects_wrong_passwords_and_tampering() {
+        let dir = std::env::temp_dir().join(format!("colorbot-secrets-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("secrets.json");
+        // A leftover from a crashed write doesn't pass its permissions on
+        fs::write(dir.join("secrets.json.tmp"), "").unwrap();
+        fs::set_permissions(
+            dir.join("secrets.json.tmp"),
+            fs::Permissions::from_mode(0o644),
+        )
+        .unwrap();
+
+        let mut store = SecretStore::default();
+        store.set("password", "hunter2".to_string());
+        store.save(&path, "correct horse").unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        assert!(!fs::read_to_string(&path).unwrap().contains("hunter2"));
+
+        let loaded = SecretStore::load(&path, "correct horse").unwrap();
+        assert_eq!(loaded.get("password").unwrap(), "hunter2");
+        assert!(SecretStore::load(&path, "wrong horse").is_err());
+
+        let mut file: EncryptedFile =
+            serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        let mut ciphertext = STANDARD.decode(&file.ciphertext).unwrap();
+        ciphertext[0] ^= 1;
+        file.ciphertext = STANDARD.encode(ciphertext);
+        fs::write(&path, serde_json::to_string(&file).unwrap()).unwrap();
+        assert!(SecretStore::load(&path, "correct horse").is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn redacts_secret_values_from_errors() {
+        let failure = || -> Result<()> { Err(anyhow!("xdotool failed typing 'hunter2'")) };
+        let secret = ScriptString::Secret {
+            secret: "password".to_string(),
+        };
+        let error = secret.redact(failure(), "type").unwrap_err();
+        assert_eq!(format!("{:#}", error), "Failed to type <secret:password>");
+
+        let plain = ScriptString::Plain("hello".to_string());
+        let error = plain.redact(failure(), "type").unwrap_err();
+        assert!(error.to_string().contains("hunter2"));
+        assert!(plain.redact(Ok(()), "type").is_ok());
+    }
+}
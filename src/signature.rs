@@ -0,0 +1,192 @@
+//! Screen signatures used to recognize which game screen is currently shown.
+//!
+//! A signature is either a template image or a color target within a search region. Signatures
+//! can be checked once or waited for, and the located point can be clicked to advance screens.
+//! A color signature that's missing because the game's colors shifted teaches the run the shift,
+//! see [`crate::tint`].
+use crate::filter::Filter;
+use crate::tint;
+use crate::vision::{self, ColorTarget, HashAlgorithm, Region, ResultMode, ScanOptions, TargetHit};
+use crate::windmouse::Point;
+
+use anyhow::{bail, ensure, Result};
+use log::{debug, warn};
+use schemars::JsonSchema;
+use serde::Deserialize;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+/// Time between checks while waiting for a signature to appear.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Visual fingerprint identifying a screen or a clickable element on it.
+#[derive(Debug, Deserialize, JsonSchema)]
+#[serde(untagged)]
+pub enum Signature {
+    /// Template image searched for across the screen.
+    Image {
+        image_path: PathBuf,
+        #[serde(default)]
+        filters: Vec<Filter>,
+    },
+    /// Color target, usually restricted to a search region.
+    Color {
+        #[serde(flatten)]
+        target: ColorTarget,
+        #[serde(flatten)]
+        scan: ScanOptions,
+    },
+    /// Perceptual hash of a screen region, recognizing a whole UI state at once.
+    Phash { phash_matches: PhashCheck },
+}
+
+fn default_max_distance() -> u32 {
+    6
+}
+
+/// Perceptual hash a screen region must be close to.
+#[derive(Debug, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct PhashCheck {
+    /// Region that's hashed.
+    pub region: Region,
+    /// Expected hash as 16 hex digits, see the `phash` tool.
+    pub hash: String,
+    /// Largest number of differing bits for the region to match.
+    #[serde(default = "default_max_distance")]
+    pub max_distance: u32,
+    /// Hash algorithm the expected hash was computed with.
+    #[serde(default)]
+    pub algorithm: HashAlgorithm,
+}
+
+impl PhashCheck {
+    /// Returns whether the region's current hash is within the distance of the expected one.
+    pub fn matches(&self) -> Result<bool> {
+        let expected = vision::parse_hash(&self.hash)?;
+        let actual = vision::region_hash(&self.region, self.algorithm)?;
+        let distance = vision::hash_distance(expected, actual);
+        debug!(
+            "Region hash {} is {} bits from {}",
+            vision::format_hash(actual),
+            distance,
+            self.hash
+        );
+        Ok(distance <= self.max_distance)
+    }
+}
+
+impl Signature {
+    /// Checks the signature's parameters for values that can never work at runtime.
+    pub fn validate(&self) -> Result<()> {
+        match self {
+            Signature::Image { filters, .. } => {
+                for filter in filters {
+                    filter.validate()?;
+                }
+            }
+            Signature::Color { target, scan } => {
+                target.validate()?;
+                scan.validate()?;
+                ensure!(
+                    scan.result_mode != ResultMode::CountOnly,
+                    "Signatures can't use count_only, it can't locate a point"
+                );
+            }
+            Signature::Phash { phash_matches } => {
+                vision::parse_hash(&phash_matches.hash)?;
+                ensure!(
+                    phash_matches.region.width > 0 && phash_matches.region.height > 0,
+                    "The hashed region must not be empty"
+                );
+                ensure!(
+                    phash_matches.max_distance < 64,
+                    "max_distance must be below 64, a distance of 64 matches any region"
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns whether the signature is currently on screen.
+    pub fn is_present(&self) -> Result<bool> {
+        if self.is_present_without_learning()? {
+            return Ok(true);
+        }
+        let Signature::Color { target, scan } = self else {
+            return Ok(false);
+        };
+
+        let Some(offset) = tint::learn(target, scan)? else {
+            return Ok(false);
+        };
+        warn!(
+            "Signature {} only matches with a color offset of {:?}, applying it to every color \
+             target from now on",
+            target, offset
+        );
+        vision::set_color_offset(offset);
+        self.is_present_without_learning()
+    }
+
+    /// Returns whether the signature is currently on screen with the current color offset.
+    pub fn is_present_without_learning(&self) -> Result<bool> {
+        match self {
+            Signature::Image {
+                image_path,
+                filters,
+            } => Ok(vision::locate_image_on_screen(image_path, filters)?.is_some()),
+            Signature::Color { target, scan } => {
+                let presence = ScanOptions {
+                    result_mode: ResultMode::FirstMatch,
+                    ..scan.clone()
+                };
+                Ok(vision::scan_screen(target, &[], &presence)?.count > 0)
+            }
+            Signature::Phash { phash_matches } => phash_matches.matches(),
+        }
+    }
+
+    /// Returns a hit on the signature if it's currently on screen.
+    pub fn locate(&self) -> Result<Option<TargetHit>> {
+        match self {
+            Signature::Image {
+                image_path,
+                filters,
+            } => Ok(vision::locate_image_on_screen(image_path, filters)?),
+            Signature::Color { target, scan } => {
+                if !self.is_present()? {
+                    return Ok(None);
+                }
+                Ok(Some(vision::find_point_in_shape(target, &[], scan)?))
+            }
+            Signature::Phash { phash_matches } => {
+                if !phash_matches.matches()? {
+                    return Ok(None);
+                }
+                let region = &phash_matches.region;
+                Ok(Some(TargetHit {
+                    point: Point::new(
+                        region.x + i32::try_from(region.width / 2)?,
+                        region.y + i32::try_from(region.height / 2)?,
+                    ),
+                    area: u64::from(region.width) * u64::from(region.height),
+                }))
+            }
+        }
+    }
+
+    /// Polls until the signature appears and returns a hit on it.
+    pub fn wait_for(&self, timeout: Duration) -> Result<TargetHit> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(hit) = self.locate()? {
+                return Ok(hit);
+            }
+            if Instant::now() >= deadline {
+                bail!("Signature did not appear within {:?}", timeout);
+            }
+            std::thread::sleep(POLL_INTERVAL);
+        }
+    }
+}
@@ -0,0 +1,67 @@
+//! Detection of a game that no longer reacts to the bot's input.
+//!
+//! A frozen client or a window that ignores input still lets events complete: color targets stay
+//! where they were and keys are sent into the void, so the watchdog sees progress. After every
+//! event the watched region is hashed, and when its hash stays essentially the same for the
+//! configured number of events in a row the run is declared stalled.
+use crate::vision::{self, Region};
+
+use anyhow::{Context, Result};
+use log::debug;
+
+/// Largest number of differing hash bits for two frames to count as unchanged.
+const MAX_DISTANCE: u32 = 2;
+
+/// Tracks how many events in a row left the watched region unchanged.
+pub struct StallDetector {
+    region: Option<Region>,
+    cycles: u32,
+    last_hash: Option<u64>,
+    unchanged: u32,
+}
+
+impl StallDetector {
+    /// Creates a detector that declares a stall after `cycles` events that didn't change
+    /// `region`, or the whole screen without one.
+    pub fn new(cycles: u32, region: Option<Region>) -> Self {
+        Self {
+            region,
+            cycles,
+            last_hash: None,
+            unchanged: 0,
+        }
+    }
+
+    /// Hashes the watched region after an event and returns whether the run is stalled.
+    ///
+    /// The count starts over after a stall is reported.
+    pub fn observe(&mut self) -> Result<bool> {
+        let frame = vision::capture_screen_rgba().context("Failed to capture the stall region")?;
+        let (frame, _) = vision::crop_to_region(frame, self.region.as_ref());
+        let hash = vision::difference_hash(&frame);
+
+        let unchanged = self
+            .last_hash
+            .is_some_and(|last| vision::hash_distance(last, hash) <= MAX_DISTANCE);
+        self.last_hash = Some(hash);
+        if !unchanged {
+            self.unchanged = 0;
+            return Ok(false);
+        }
+        self.unchanged += 1;
+        debug!(
+            "Watched region unchanged after {} events in a row",
+            self.unchanged
+        );
+        if self.unchanged < self.cycles {
+            return Ok(false);
+        }
+        self.unchanged = 0;
+        Ok(true)
+    }
+
+    /// Returns the number of unchanged events in a row that declare a stall.
+    pub fn cycles(&self) -> u32 {
+        self.cycles
+    }
+}
@@ -0,0 +1,200 @@
+//! Per-event summary of a run, printed when it ends.
+//!
+//! The summary is assembled from the run report: how often each event ran, how often it found
+//! its target and how long the search took on average, next to the total number of clicks. Events
+//! that never found their target are called out, since a run keeps going past them without
+//! failing.
+use crate::output::{self, Render};
+use crate::report::RunReport;
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+static CLICKS: AtomicU64 = AtomicU64::new(0);
+
+/// Counts a mouse click sent by the bot.
+pub fn record_click() {
+    CLICKS.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Number of mouse clicks sent since the last reset.
+pub fn clicks() -> u64 {
+    CLICKS.load(Ordering::Relaxed)
+}
+
+/// Clears the click count, so a following run in the same process starts from zero.
+pub fn reset() {
+    CLICKS.store(0, Ordering::Relaxed);
+}
+
+/// Outcome of one script event over the whole run.
+#[derive(Debug, Default, Serialize)]
+pub struct EventStats {
+    /// Number of times the event ran.
+    pub runs: u64,
+    /// Number of times the event found its target, 0 for events without one.
+    pub found: u64,
+    /// Number of times the event didn't find its target.
+    pub missed: u64,
+    /// Average time in ms spent capturing, scanning, clustering and planning per run.
+    pub search_ms: f64,
+}
+
+impl EventStats {
+    /// Whether the event looked for a target every time and never found it.
+    pub fn never_found(&self) -> bool {
+        self.found == 0 && self.missed > 0
+    }
+}
+
+/// Summary of a run by event.
+#[derive(Debug, Default, Serialize)]
+pub struct SessionStats {
+    /// Number of full passes over the script.
+    pub iterations: u64,
+    /// Wall clock duration of the run in seconds.
+    pub elapsed_secs: f64,
+    /// Mouse clicks sent over the run.
+    pub clicks: u64,
+    /// Outcome of every event that ran, keyed by event id.
+    pub events: BTreeMap<String, EventStats>,
+}
+
+impl SessionStats {
+    /// Collects the per-event outcomes of a finished run.
+    pub fn from_report(report: &RunReport) -> Self {
+        let mut events: BTreeMap<String, EventStats> = report
+            .phases
+            .iter()
+            .map(|(id, profile)| {
+                let stats = EventStats {
+                    runs: profile.runs,
+                    search_ms: profile.mean.search_ms(),
+                    ..Default::default()
+                };
+                (id.clone(), stats)
+            })
+            .collect();
+        for (id, counts) in &report.targets {
+            let stats = events.entry(id.clone()).or_default();
+            stats.found = counts.found;
+            stats.missed = counts.missed;
+        }
+        Self {
+            iterations: report.iterations,
+            elapsed_secs: report.elapsed_secs,
+            clicks: report.clicks,
+            events,
+        }
+    }
+
+    /// Writes the summary as pretty-printed JSON.
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let file = File::create(path).context(format!("Failed to create {}", path.display()))?;
+        serde_json::to_writer_pretty(BufWriter::new(file), self)
+            .context(format!("Failed to write {}", path.display()))
+    }
+}
+
+impl Render for SessionStats {
+    fn render(&self, out: &mut dyn Write) -> std::io::Result<()> {
+        writeln!(
+            out,
+            "{} iterations in {:.0} s, {} clicks",
+            self.iterations, self.elapsed_secs, self.clicks
+        )?;
+        writeln!(
+            out,
+            "{:<24} {:>8} {:>8} {:>8} {:>8} {:>10}",
+            "event", "runs", "found", "missed", "hit %", "search ms"
+        )?;
+        for (id, stats) in &self.events {
+            let attempts = stats.found + stats.missed;
+            let hit_rate = if attempts > 0 {
+                format!("{:.0}", stats.found as f64 * 100.0 / attempts as f64)
+            } else {
+                "-".to_string()
+            };
+            writeln!(
+                out,
+                "{:<24} {:>8} {:>8} {:>8} {:>8} {:>10.1}",
+                id, stats.runs, stats.found, stats.missed, hit_rate, stats.search_ms
+            )?;
+        }
+
+        let never_found: Vec<&str> = self
+            .events
+            .iter()
+            .filter(|(_, stats)| stats.never_found())
+            .map(|(id, _)| id.as_str())
+            .collect();
+        if !never_found.is_empty() {
+            writeln!(out, "Never found their target: {}", never_found.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Prints the summary of the run whose report is at `report_path`, and writes it as JSON to
+/// `out` when given.
+pub fn summarize(report_path: &Path, out: Option<&Path>) -> Result<()> {
+    let summary = SessionStats::from_report(&RunReport::load(report_path)?);
+    output::print(&summary)?;
+    if let Some(path) = out {
+        summary.write(path)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::profile::EventProfile;
+    use crate::targets::TargetCounts;
+
+    #[test]
+    fn merges_runs_with_target_counts_and_flags_events_that_never_hit() {
+        let mut report = RunReport {
+            clicks: 7,
+            ..Default::default()
+        };
+        for id in ["bank", "fish", "wait"] {
+            let profile = EventProfile {
+                runs: 10,
+                ..Default::default()
+            };
+            report.phases.insert(id.to_string(), profile);
+        }
+        let hits = TargetCounts {
+            found: 7,
+            missed: 3,
+            misclicks: 0,
+        };
+        let misses = TargetCounts {
+            missed: 10,
+            ..Default::default()
+        };
+        report.targets.insert("fish".to_string(), hits);
+        report.targets.insert("bank".to_string(), misses);
+
+        let stats = SessionStats::from_report(&report);
+        assert_eq!(stats.clicks, 7);
+        assert_eq!(stats.events["fish"].found, 7);
+        assert!(stats.events["bank"].never_found());
+        assert!(!stats.events["wait"].never_found());
+
+        let mut text = Vec::new();
+        stats.render(&mut text).unwrap();
+        let text = String::from_utf8(text).unwrap();
+        assert!(
+            text.ends_with("Never found their target: bank\n"),
+            "{}",
+            text
+        );
+    }
+}
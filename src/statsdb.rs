@@ -0,0 +1,320 @@
+//! Persistent statistics of past runs in an SQLite database, behind the `stats-db` feature.
+//!
+//! Every finished run is added to the database with its duration, target hit counts and the
+//! kind of error that ended it, keyed by the script and a hash of the script's content. The
+//! `stats` subcommand summarizes all scripts, or one script by week and by version, so long-term
+//! reliability trends and the effect of script edits show up over weeks of runs.
+use crate::output::{self, Render};
+use crate::report::RunReport;
+use crate::rundir::RunDir;
+
+use anyhow::{Context, Result};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::Serialize;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS runs (
+        id INTEGER PRIMARY KEY,
+        script TEXT NOT NULL,
+        script_hash TEXT NOT NULL,
+        started_at INTEGER NOT NULL,
+        elapsed_secs REAL NOT NULL,
+        iterations INTEGER NOT NULL,
+        stuck_incidents INTEGER NOT NULL,
+        rollbacks INTEGER NOT NULL,
+        failure TEXT
+    );
+    CREATE TABLE IF NOT EXISTS targets (
+        run_id INTEGER NOT NULL REFERENCES runs(id),
+        event_id TEXT NOT NULL,
+        found INTEGER NOT NULL,
+        missed INTEGER NOT NULL,
+        misclicks INTEGER NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS runs_by_script ON runs(script, started_at);
+";
+
+/// Returns the default database path next to the default run directory root.
+pub fn default_path() -> PathBuf {
+    RunDir::default_root().with_file_name("stats.db")
+}
+
+/// Opens the database, creating it and its tables on first use.
+fn open(path: &Path) -> Result<Connection> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .context(format!("Failed to create directory {}", dir.display()))?;
+    }
+    let connection = Connection::open(path).context(format!(
+        "Failed to open statistics database {}",
+        path.display()
+    ))?;
+    connection
+        .execute_batch(SCHEMA)
+        .context("Failed to create the statistics tables")?;
+    Ok(connection)
+}
+
+/// Hashes a script's content with FNV-1a, so the same version of a script always gets the same
+/// hash.
+fn content_hash(content: &[u8]) -> String {
+    let hash = content
+        .iter()
+        .fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+            (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+        });
+    format!("{:016x}", hash)
+}
+
+/// Kind of error that ended a run: the outermost context of the error chain, which names what
+/// failed without the details that differ from run to run.
+fn failure_kind(error: &str) -> String {
+    error.split(": ").next().unwrap_or(error).to_string()
+}
+
+/// Adds the report of the run in `run_dir` to the database at `path`.
+pub fn record(path: &Path, run_dir: &RunDir) -> Result<()> {
+    let report = RunReport::load(&RunReport::path(run_dir)?)?;
+    let script = std::fs::canonicalize(&report.script).unwrap_or_else(|_| report.script.clone());
+    // The run directory holds the copy of the script that actually ran
+    let copy = report
+        .script
+        .file_name()
+        .map(|name| run_dir.path().join(name))
+        .context("The report has no script file name")?;
+    let content = std::fs::read(&copy).context(format!("Failed to read {}", copy.display()))?;
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs_f64();
+    let started_at = (now - report.elapsed_secs) as i64;
+
+    let mut connection = open(path)?;
+    let transaction = connection.transaction()?;
+    transaction.execute(
+        "INSERT INTO runs (script, script_hash, started_at, elapsed_secs, iterations, \
+         stuck_incidents, rollbacks, failure) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+        params![
+            script.to_string_lossy(),
+            content_hash(&content),
+            started_at,
+            report.elapsed_secs,
+            report.iterations,
+            report.stuck_incidents,
+            report.rollbacks,
+            report.error.as_deref().map(failure_kind),
+        ],
+    )?;
+    let run_id = transaction.last_insert_rowid();
+    for (event_id, counts) in &report.targets {
+        transaction.execute(
+            "INSERT INTO targets (run_id, event_id, found, missed, misclicks) \
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                run_id,
+                event_id,
+                counts.found,
+                counts.missed,
+                counts.misclicks
+            ],
+        )?;
+    }
+    transaction.commit()?;
+    Ok(())
+}
+
+/// Formats a hit rate from summed found and missed counts.
+fn hit_rate(found: i64, missed: i64) -> String {
+    if found + missed == 0 {
+        return "-".to_string();
+    }
+    format!("{:.1}%", found as f64 / (found + missed) as f64 * 100.0)
+}
+
+/// Aggregates of the runs in one group, as selected by `GROUP_COLUMNS`.
+const GROUP_COLUMNS: &str = "
+    COUNT(*),
+    SUM(elapsed_secs) / 3600.0,
+    COALESCE(SUM((SELECT SUM(found) FROM targets WHERE run_id = runs.id)), 0),
+    COALESCE(SUM((SELECT SUM(missed) FROM targets WHERE run_id = runs.id)), 0),
+    COUNT(failure)";
+
+/// Aggregated runs of a script, week or script version.
+#[derive(Serialize)]
+struct GroupStats {
+    name: String,
+    runs: i64,
+    hours: f64,
+    found: i64,
+    missed: i64,
+    failed: i64,
+}
+
+/// Runs a grouped query, one group per row labeled by its first column.
+fn query_groups(
+    connection: &Connection,
+    sql: &str,
+    script: Option<&str>,
+) -> Result<Vec<GroupStats>> {
+    let mut statement = connection.prepare(sql)?;
+    let mut rows = match script {
+        Some(script) => statement.query(params![script])?,
+        None => statement.query([])?,
+    };
+    let mut groups = Vec::new();
+    while let Some(row) = rows.next()? {
+        groups.push(GroupStats {
+            name: row.get(0)?,
+            runs: row.get(1)?,
+            hours: row.get(2)?,
+            found: row.get(3)?,
+            missed: row.get(4)?,
+            failed: row.get(5)?,
+        });
+    }
+    Ok(groups)
+}
+
+/// Writes groups as a table headed by `label`.
+fn render_groups(out: &mut dyn Write, label: &str, groups: &[GroupStats]) -> std::io::Result<()> {
+    writeln!(
+        out,
+        "{:<48} {:>6} {:>8} {:>9} {:>8}",
+        label, "runs", "hours", "hit rate", "failed"
+    )?;
+    for group in groups {
+        writeln!(
+            out,
+            "{:<48} {:>6} {:>8.1} {:>9} {:>8}",
+            group.name,
+            group.runs,
+            group.hours,
+            hit_rate(group.found, group.missed),
+            group.failed
+        )?;
+    }
+    Ok(())
+}
+
+/// Summary of every script in the database.
+#[derive(Serialize)]
+struct Overview {
+    scripts: Vec<GroupStats>,
+}
+
+impl Render for Overview {
+    fn render(&self, out: &mut dyn Write) -> std::io::Result<()> {
+        render_groups(out, "script", &self.scripts)
+    }
+}
+
+/// Number of runs that ended with one kind of failure.
+#[derive(Serialize)]
+struct FailureCount {
+    failure: String,
+    runs: i64,
+}
+
+/// Trends of a single script.
+#[derive(Serialize)]
+struct Trends {
+    script: String,
+    /// Whether any run of the script is recorded, the groups are empty otherwise.
+    recorded: bool,
+    weeks: Vec<GroupStats>,
+    versions: Vec<GroupStats>,
+    failures: Vec<FailureCount>,
+}
+
+impl Render for Trends {
+    fn render(&self, out: &mut dyn Write) -> std::io::Result<()> {
+        if !self.recorded {
+            return writeln!(out, "No runs of {} are recorded", self.script);
+        }
+        writeln!(out, "{}\n", self.script)?;
+        render_groups(out, "week", &self.weeks)?;
+        writeln!(out)?;
+        render_groups(out, "version (first run)", &self.versions)?;
+        writeln!(out, "\n{:<48} {:>6}", "failure", "runs")?;
+        for failure in &self.failures {
+            writeln!(out, "{:<48} {:>6}", failure.failure, failure.runs)?;
+        }
+        Ok(())
+    }
+}
+
+/// Prints statistics of every script in the database, or the trends of a single script.
+pub fn run(path: Option<&Path>, script: Option<&Path>) -> Result<()> {
+    let path = path.map_or_else(default_path, Path::to_path_buf);
+    let connection = open(&path)?;
+
+    let Some(script) = script else {
+        let scripts = query_groups(
+            &connection,
+            &format!(
+                "SELECT script, {} FROM runs GROUP BY script ORDER BY MAX(started_at) DESC",
+                GROUP_COLUMNS
+            ),
+            None,
+        )?;
+        return output::print(&Overview { scripts });
+    };
+
+    let script = std::fs::canonicalize(script).unwrap_or_else(|_| script.to_path_buf());
+    let script = script.to_string_lossy().into_owned();
+    let known: Option<i64> = connection
+        .query_row(
+            "SELECT 1 FROM runs WHERE script = ?1 LIMIT 1",
+            params![script],
+            |row| row.get(0),
+        )
+        .optional()?;
+    if known.is_none() {
+        return output::print(&Trends {
+            script,
+            recorded: false,
+            weeks: Vec::new(),
+            versions: Vec::new(),
+            failures: Vec::new(),
+        });
+    }
+
+    let weeks = query_groups(
+        &connection,
+        &format!(
+            "SELECT strftime('%Y-W%W', started_at, 'unixepoch'), {} FROM runs WHERE script = ?1 \
+             GROUP BY 1 ORDER BY 1",
+            GROUP_COLUMNS
+        ),
+        Some(&script),
+    )?;
+    let versions = query_groups(
+        &connection,
+        &format!(
+            "SELECT script_hash || ' (' || date(MIN(started_at), 'unixepoch') || ')', {} \
+             FROM runs WHERE script = ?1 GROUP BY script_hash ORDER BY MIN(started_at)",
+            GROUP_COLUMNS
+        ),
+        Some(&script),
+    )?;
+
+    let mut statement = connection.prepare(
+        "SELECT failure, COUNT(*) FROM runs WHERE script = ?1 AND failure IS NOT NULL \
+         GROUP BY failure ORDER BY 2 DESC",
+    )?;
+    let mut rows = statement.query(params![script])?;
+    let mut failures = Vec::new();
+    while let Some(row) = rows.next()? {
+        failures.push(FailureCount {
+            failure: row.get(0)?,
+            runs: row.get(1)?,
+        });
+    }
+    output::print(&Trends {
+        script,
+        recorded: true,
+        weeks,
+        versions,
+        failures,
+    })
+}
@@ -0,0 +1,312 @@
+//! One-off check of the targets a script looks for, for the `verify-targets` subcommand.
+//!
+//! Every color target, template image and signature in a script is searched for once, on the
+//! live screen or in a screenshot, and reported as matching or not. After a game update this
+//! quickly shows which targets need new colors or templates without running the script.
+use crate::assertion::Assertion;
+#[cfg(feature = "template")]
+use crate::atlas::{self, AtlasSearch};
+use crate::drag::DragEnd;
+use crate::event::{BotEvent, BotEventType};
+use crate::filter::Filter;
+use crate::output::{self, Render};
+use crate::replay;
+use crate::script;
+use crate::signature::Signature;
+use crate::vision::{self, ColorTarget, ResultMode, ScanOptions};
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::io::Write;
+use std::path::Path;
+
+/// What a check looks for on screen.
+enum Lookup<'a> {
+    Color {
+        target: &'a ColorTarget,
+        filters: &'a [Filter],
+        scan: &'a ScanOptions,
+    },
+    Image {
+        path: &'a Path,
+        filters: &'a [Filter],
+    },
+    Signature(&'a Signature),
+    #[cfg(feature = "template")]
+    Atlas(&'a AtlasSearch),
+}
+
+impl Lookup<'_> {
+    /// Searches the screen once, returning a description of the match if there is one.
+    fn run(&self) -> Result<Option<String>> {
+        match self {
+            Lookup::Color {
+                target,
+                filters,
+                scan,
+            } => {
+                let count_only = ScanOptions {
+                    result_mode: ResultMode::CountOnly,
+                    ..(*scan).clone()
+                };
+                let count = vision::scan_screen(target, filters, &count_only)?.count;
+                Ok((count > 0).then(|| format!("{} matching pixels", count)))
+            }
+            Lookup::Image { path, filters } => Ok(vision::locate_image_on_screen(path, filters)?
+                .map(|hit| format!("found at {}", hit.point))),
+            Lookup::Signature(signature) => Ok(signature
+                .locate()?
+                .map(|hit| format!("found at {}", hit.point))),
+            #[cfg(feature = "template")]
+            Lookup::Atlas(search) => Ok(search.locate()?.map(|found| {
+                format!(
+                    "{} found at {}",
+                    atlas::template_name(&found.template),
+                    found.hit.point
+                )
+            })),
+        }
+    }
+}
+
+/// A single target of a script event.
+struct Check<'a> {
+    event: &'a str,
+    what: String,
+    lookup: Lookup<'a>,
+    /// Caveat shown next to the result, for targets that are only expected in some situations.
+    note: Option<&'static str>,
+}
+
+/// Outcome of a single check.
+#[derive(Serialize)]
+struct CheckResult<'a> {
+    event: &'a str,
+    what: &'a str,
+    /// `match`, `no match` or `error`.
+    status: &'static str,
+    /// Where the target was found, or the error.
+    detail: String,
+    /// Caveat for targets that are only expected in some situations, when they didn't match.
+    note: Option<&'static str>,
+}
+
+/// Outcome of every check of a script.
+#[derive(Serialize)]
+struct Report<'a> {
+    matched: usize,
+    targets: Vec<CheckResult<'a>>,
+}
+
+impl Render for Report<'_> {
+    fn render(&self, out: &mut dyn Write) -> std::io::Result<()> {
+        for result in &self.targets {
+            let note = result
+                .note
+                .map(|note| format!(" ({})", note))
+                .unwrap_or_default();
+            writeln!(
+                out,
+                "{:<20} {:<32} {:<9} {}{}",
+                result.event, result.what, result.status, result.detail, note
+            )?;
+        }
+        writeln!(
+            out,
+            "\n{} of {} targets match",
+            self.matched,
+            self.targets.len()
+        )
+    }
+}
+
+/// Returns the checks for every target of an event.
+fn event_checks(event: &BotEvent) -> Vec<Check<'_>> {
+    let check = |what: &str, lookup, note| Check {
+        event: &event.id,
+        what: what.to_string(),
+        lookup,
+        note,
+    };
+
+    let mut checks = Vec::new();
+    for assertion in &event.assert {
+        match assertion {
+            Assertion::Present { signature } | Assertion::Absent { signature } => {
+                checks.push(check(
+                    &format!("assert {}", assertion),
+                    Lookup::Signature(signature),
+                    None,
+                ))
+            }
+            Assertion::WindowFocused { .. } => {}
+        }
+    }
+
+    match &event.event_type {
+        BotEventType::Color {
+            target,
+            variants,
+            filters,
+            scan,
+            verify_hover,
+            ..
+        } => {
+            checks.push(check(
+                "color target",
+                Lookup::Color {
+                    target,
+                    filters,
+                    scan,
+                },
+                None,
+            ));
+            for (theme, target) in variants {
+                checks.push(check(
+                    &format!("color target ({})", theme),
+                    Lookup::Color {
+                        target,
+                        filters,
+                        scan,
+                    },
+                    Some("only shown in that theme"),
+                ));
+            }
+            if let Some(hover) = verify_hover {
+                checks.push(check(
+                    "hover tooltip",
+                    Lookup::Signature(&hover.tooltip),
+                    Some("only shown while hovering the target"),
+                ));
+            }
+        }
+        BotEventType::WaitForColor {
+            target,
+            filters,
+            scan,
+            ..
+        } => checks.push(check(
+            "awaited color target",
+            Lookup::Color {
+                target,
+                filters,
+                scan,
+            },
+            Some("may only appear later in the run"),
+        )),
+        BotEventType::Drag { from, to, .. } => {
+            for (what, end) in [("drag start", from), ("drag end", to)] {
+                if let DragEnd::Color {
+                    target,
+                    filters,
+                    scan,
+                } = end
+                {
+                    checks.push(check(
+                        what,
+                        Lookup::Color {
+                            target,
+                            filters,
+                            scan,
+                        },
+                        None,
+                    ));
+                }
+            }
+        }
+        BotEventType::Image {
+            image_path,
+            filters,
+            ..
+        } => checks.push(check(
+            "template image",
+            Lookup::Image {
+                path: image_path,
+                filters,
+            },
+            None,
+        )),
+        BotEventType::Relogin(relogin) => {
+            let note = Some("only shown while logged out");
+            checks.push(check(
+                "login screen",
+                Lookup::Signature(&relogin.login_screen),
+                note,
+            ));
+            for (i, signature) in relogin.click_through.iter().enumerate() {
+                checks.push(check(
+                    &format!("click_through {}", i + 1),
+                    Lookup::Signature(signature),
+                    note,
+                ));
+            }
+            if let Some(pin) = &relogin.pin {
+                checks.push(check("pin screen", Lookup::Signature(&pin.screen), note));
+            }
+            if let Some(lobby) = &relogin.lobby_screen {
+                checks.push(check("lobby screen", Lookup::Signature(lobby), note));
+            }
+        }
+        #[cfg(feature = "template")]
+        BotEventType::Atlas { search, .. } => {
+            checks.push(check("atlas templates", Lookup::Atlas(search), None))
+        }
+        BotEventType::KeyPress { .. } | BotEventType::TypeText { .. } => {}
+        #[cfg(feature = "gamepad")]
+        BotEventType::Button { .. } | BotEventType::Axis { .. } => {}
+    }
+    checks
+}
+
+/// Checks every target in the script against the live screen or, when given, a screenshot.
+pub fn run(script_path: &Path, screenshot: Option<&Path>) -> Result<()> {
+    let script = script::load(script_path)?;
+    if let Some(path) = screenshot {
+        let frame = image::open(path)
+            .context(format!("Failed to open screenshot {}", path.display()))?
+            .to_rgba8();
+        replay::start_still(frame);
+    }
+
+    let rollbacks = script
+        .transactions
+        .iter()
+        .flat_map(|transaction| &transaction.on_fail);
+    let probes = script
+        .themes
+        .iter()
+        .flat_map(|themes| &themes.probes)
+        .map(|probe| Check {
+            event: "themes",
+            what: format!("probe {}", probe.name),
+            lookup: Lookup::Signature(&probe.signature),
+            note: Some("only shown in that theme"),
+        });
+    let checks: Vec<Check> = probes
+        .chain(script.events.iter().chain(rollbacks).flat_map(event_checks))
+        .collect();
+    let results: Vec<CheckResult> = checks
+        .iter()
+        .map(|check| {
+            let (status, detail) = match check.lookup.run() {
+                Ok(Some(detail)) => ("match", detail),
+                Ok(None) => ("no match", String::new()),
+                Err(e) => ("error", format!("{:#}", e)),
+            };
+            CheckResult {
+                event: check.event,
+                what: &check.what,
+                status,
+                detail,
+                note: check.note.filter(|_| status != "match"),
+            }
+        })
+        .collect();
+    output::print(&Report {
+        matched: results
+            .iter()
+            .filter(|result| result.status == "match")
+            .count(),
+        targets: results,
+    })
+}
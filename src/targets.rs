@@ -0,0 +1,85 @@
+//! Counts of how often each script event found its target.
+//!
+//! Color and image events record whether their target was found and how many hovered candidates
+//! failed verification. The counts end up in the run report, where they give a hit rate per event.
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+/// Target outcomes of one script event aggregated over all of its runs.
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
+pub struct TargetCounts {
+    /// Number of times the target was found.
+    pub found: u64,
+    /// Number of times the target could not be found.
+    pub missed: u64,
+    /// Candidates that were hovered but failed verification, which would have been misclicks
+    /// without it.
+    pub misclicks: u64,
+}
+
+impl TargetCounts {
+    /// Fraction of attempts that found the target, `None` before the first attempt.
+    pub fn hit_rate(&self) -> Option<f64> {
+        let attempts = self.found + self.missed;
+        (attempts > 0).then(|| self.found as f64 / attempts as f64)
+    }
+
+    /// Adds the counts of `other` to these.
+    pub fn add(&mut self, other: &TargetCounts) {
+        self.found += other.found;
+        self.missed += other.missed;
+        self.misclicks += other.misclicks;
+    }
+}
+
+static COUNTS: Mutex<BTreeMap<String, TargetCounts>> = Mutex::new(BTreeMap::new());
+
+/// Applies `update` to the counts of the event `id`.
+fn update(id: &str, update: impl FnOnce(&mut TargetCounts)) {
+    if let Ok(mut counts) = COUNTS.lock() {
+        update(counts.entry(id.to_string()).or_default());
+    }
+}
+
+/// Records whether the event `id` found its target.
+pub fn record_attempt(id: &str, found: bool) {
+    update(id, |counts| {
+        if found {
+            counts.found += 1;
+        } else {
+            counts.missed += 1;
+        }
+    });
+}
+
+/// Records `count` hovered candidates of the event `id` that failed verification.
+pub fn record_misclicks(id: &str, count: u64) {
+    if count > 0 {
+        update(id, |counts| counts.misclicks += count);
+    }
+}
+
+/// Number of times the event `id` found its target.
+pub fn found(id: &str) -> u64 {
+    COUNTS
+        .lock()
+        .ok()
+        .and_then(|counts| counts.get(id).map(|counts| counts.found))
+        .unwrap_or_default()
+}
+
+/// Returns the target counts of every event that looked for a target.
+pub fn event_counts() -> BTreeMap<String, TargetCounts> {
+    COUNTS
+        .lock()
+        .map(|counts| counts.clone())
+        .unwrap_or_default()
+}
+
+/// Clears all counts, so a following run in the same process starts from zero.
+pub fn reset() {
+    if let Ok(mut counts) = COUNTS.lock() {
+        counts.clear();
+    }
+}
@@ -0,0 +1,146 @@
+//! Visual themes such as day and night that select between variants of color targets.
+//!
+//! A script can list themes, each recognized by a probe signature, and color events can define
+//! a variant of their target for any of them. The first theme whose probe is on screen becomes the
+//! active one when the run starts and is re-evaluated periodically, so a single script keeps
+//! working when the game switches to night or a dark UI.
+use crate::signature::Signature;
+use crate::vision::{self, ColorTarget};
+
+use anyhow::{anyhow, ensure, Context, Result};
+use log::debug;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+fn default_recheck_secs() -> u64 {
+    60
+}
+
+/// Theme recognized by a signature that's only on screen while the theme is shown.
+#[derive(Debug, Deserialize)]
+pub struct ThemeProbe {
+    /// Name that color events refer to in their variants.
+    pub name: String,
+    /// Signature that identifies the theme.
+    #[serde(flatten)]
+    pub signature: Signature,
+}
+
+/// Themes of a script, in the order their probes are checked.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ThemeConfig {
+    /// Probes checked in order, the first one on screen selects its theme.
+    pub probes: Vec<ThemeProbe>,
+    /// Seconds between re-evaluations of the probes during a run, 0 only checks at the start.
+    #[serde(default = "default_recheck_secs")]
+    pub recheck_secs: u64,
+}
+
+impl ThemeConfig {
+    /// Checks that the themes are uniquely named and their probes usable.
+    pub fn validate(&self) -> Result<()> {
+        ensure!(!self.probes.is_empty(), "Themes need at least one probe");
+        for (i, probe) in self.probes.iter().enumerate() {
+            ensure!(!probe.name.is_empty(), "Theme names must not be empty");
+            ensure!(
+                self.probes[..i]
+                    .iter()
+                    .all(|other| other.name != probe.name),
+                "Theme '{}' is defined more than once",
+                probe.name
+            );
+            probe
+                .signature
+                .validate()
+                .context(format!("Invalid probe of theme '{}'", probe.name))?;
+        }
+        Ok(())
+    }
+
+    /// Returns whether a theme with the given name exists.
+    pub fn contains(&self, name: &str) -> bool {
+        self.probes.iter().any(|probe| probe.name == name)
+    }
+}
+
+/// Name of the theme currently on screen, `None` until a probe matched.
+static ACTIVE: Mutex<Option<String>> = Mutex::new(None);
+
+/// Sets the active theme.
+pub fn set_active(name: Option<String>) {
+    if let Ok(mut active) = ACTIVE.lock() {
+        *active = name;
+    }
+}
+
+/// Returns the target variant for the active theme, or `target` when it has none.
+pub fn pick<'a>(
+    target: &'a ColorTarget,
+    variants: &'a BTreeMap<String, ColorTarget>,
+) -> &'a ColorTarget {
+    ACTIVE
+        .lock()
+        .ok()
+        .and_then(|active| active.as_ref().and_then(|name| variants.get(name)))
+        .unwrap_or(target)
+}
+
+/// Re-evaluates the theme probes of a run at the configured interval.
+pub struct ThemeSelector {
+    config: ThemeConfig,
+    last_check: Option<Instant>,
+}
+
+impl ThemeSelector {
+    pub fn new(config: ThemeConfig) -> Self {
+        Self {
+            config,
+            last_check: None,
+        }
+    }
+
+    /// Checks the probes when they haven't been checked yet or the interval has passed, and
+    /// switches to the first theme whose probe is on screen.
+    ///
+    /// When no probe matches, the active theme stays as it is.
+    pub fn select_if_due(&mut self) -> Result<()> {
+        let interval = Duration::from_secs(self.config.recheck_secs);
+        let due = match self.last_check {
+            None => true,
+            Some(last) => !interval.is_zero() && last.elapsed() >= interval,
+        };
+        if !due {
+            return Ok(());
+        }
+        self.last_check = Some(Instant::now());
+
+        for probe in &self.config.probes {
+            // Learning a color offset could make a probe match another theme's colors
+            let present = probe
+                .signature
+                .is_present_without_learning()
+                .context(format!(
+                    "Failed to check the probe of theme '{}'",
+                    probe.name
+                ))?;
+            if !present {
+                continue;
+            }
+            let mut active = ACTIVE
+                .lock()
+                .map_err(|_| anyhow!("Theme lock is poisoned"))?;
+            if active.as_deref() != Some(probe.name.as_str()) {
+                debug!("Switching to theme '{}'", probe.name);
+                *active = Some(probe.name.clone());
+                // An offset learned for the old theme's colors doesn't carry over
+                vision::set_color_offset([0, 0, 0]);
+            }
+            return Ok(());
+        }
+        debug!("No theme probe is on screen, keeping the current theme");
+        Ok(())
+    }
+}
@@ -0,0 +1,274 @@
+//! Precise sleeps, timing jitter measurement, and delay distributions.
+//!
+//! `std::thread::sleep` regularly overshoots by a millisecond or more, which is a large fraction
+//! of the 8-16 ms between cursor path steps. Backends that can deliver input with sub-millisecond
+//! precision use a hybrid sleep instead: a coarse sleep for most of the duration followed by a
+//! short spin until the deadline.
+//!
+//! Human delays between actions are long-tailed rather than uniform, so scripts can draw delays
+//! from Gaussian and log-normal distributions as well.
+use anyhow::{ensure, Result};
+use rand::{Rng, RngExt};
+use rand_distr::{Distribution, LogNormal, Normal};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Remaining time that is spun rather than slept.
+const SPIN_THRESHOLD: Duration = Duration::from_micros(1_500);
+
+/// Width of each jitter histogram bucket in microseconds.
+const BUCKET_WIDTH_US: u64 = 100;
+
+/// Number of histogram buckets, the last one collects everything above 10 ms.
+const BUCKETS: usize = 101;
+
+/// Jitter of every cursor path step taken during the run.
+static PATH_JITTER: Mutex<JitterRecorder> = Mutex::new(JitterRecorder::new());
+
+/// Sleeps for `duration`, spinning through the last stretch to hit the deadline closely.
+pub fn hybrid_sleep(duration: Duration) {
+    let deadline = Instant::now() + duration;
+    if let Some(coarse) = duration.checked_sub(SPIN_THRESHOLD) {
+        std::thread::sleep(coarse);
+    }
+    while Instant::now() < deadline {
+        std::hint::spin_loop();
+    }
+}
+
+/// Sleeps between two cursor path steps and records how far the actual gap was off.
+pub fn path_step_sleep(duration: Duration, precise: bool) {
+    let start = Instant::now();
+    if precise {
+        hybrid_sleep(duration);
+    } else {
+        std::thread::sleep(duration);
+    }
+    let jitter = start.elapsed().abs_diff(duration);
+
+    if let Ok(mut recorder) = PATH_JITTER.lock() {
+        recorder.record(jitter);
+    }
+}
+
+/// Returns a summary of the path step jitter recorded so far.
+pub fn path_jitter_summary() -> JitterSummary {
+    PATH_JITTER
+        .lock()
+        .map(|recorder| recorder.summary())
+        .unwrap_or_default()
+}
+
+/// Discards the path step jitter recorded so far.
+pub fn reset_path_jitter() {
+    if let Ok(mut recorder) = PATH_JITTER.lock() {
+        *recorder = JitterRecorder::new();
+    }
+}
+
+/// Summary statistics of recorded timing jitter.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct JitterSummary {
+    /// Number of recorded sleeps.
+    pub samples: u64,
+    /// Mean absolute jitter in microseconds.
+    pub mean_us: f64,
+    /// Upper bound of the jitter of 99% of the sleeps in microseconds.
+    pub p99_us: u64,
+    /// Largest recorded jitter in microseconds.
+    pub max_us: u64,
+}
+
+/// Accumulates jitter samples in a fixed-size histogram.
+struct JitterRecorder {
+    samples: u64,
+    total_us: u64,
+    max_us: u64,
+    buckets: [u64; BUCKETS],
+}
+
+impl JitterRecorder {
+    const fn new() -> Self {
+        Self {
+            samples: 0,
+            total_us: 0,
+            max_us: 0,
+            buckets: [0; BUCKETS],
+        }
+    }
+
+    fn record(&mut self, jitter: Duration) {
+        let us = u64::try_from(jitter.as_micros()).unwrap_or(u64::MAX);
+        self.samples += 1;
+        self.total_us = self.total_us.saturating_add(us);
+        self.max_us = self.max_us.max(us);
+        let bucket =
+            usize::try_from(us / BUCKET_WIDTH_US).map_or(BUCKETS - 1, |b| b.min(BUCKETS - 1));
+        self.buckets[bucket] += 1;
+    }
+
+    fn summary(&self) -> JitterSummary {
+        if self.samples == 0 {
+            return JitterSummary::default();
+        }
+
+        // Walk the histogram until 99% of the samples are covered
+        let threshold = self.samples.div_ceil(100) * 99;
+        let mut covered = 0;
+        let mut p99_us = self.max_us;
+        for (i, count) in self.buckets.iter().enumerate() {
+            covered += count;
+            if covered >= threshold.min(self.samples) {
+                p99_us = ((i as u64 + 1) * BUCKET_WIDTH_US).min(self.max_us);
+                break;
+            }
+        }
+
+        JitterSummary {
+            samples: self.samples,
+            mean_us: self.total_us as f64 / self.samples as f64,
+            p99_us,
+            max_us: self.max_us,
+        }
+    }
+}
+
+/// Distribution a delay in milliseconds is drawn from.
+#[derive(Debug, Clone, PartialEq, Deserialize, JsonSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum DelayDistribution {
+    /// Every delay in the inclusive range is equally likely.
+    Uniform { min: u64, max: u64 },
+    /// Normal distribution, negative samples are clamped to zero.
+    Gaussian { mean: f64, std_dev: f64 },
+    /// Log-normal distribution given by its median and the standard deviation of its logarithm.
+    ///
+    /// Most delays land near the median with an occasional much longer one, like a human's.
+    Lognormal { median: f64, sigma: f64 },
+}
+
+impl DelayDistribution {
+    /// Checks the distribution's parameters.
+    pub fn validate(&self) -> Result<()> {
+        match *self {
+            DelayDistribution::Uniform { min, max } => ensure!(
+                min <= max,
+                "Uniform delay minimum {} is above its maximum {}",
+                min,
+                max
+            ),
+            DelayDistribution::Gaussian { mean, std_dev } => ensure!(
+                mean.is_finite() && std_dev.is_finite() && std_dev >= 0.0,
+                "Gaussian delay needs a finite mean and a non-negative std_dev"
+            ),
+            DelayDistribution::Lognormal { median, sigma } => ensure!(
+                median.is_finite() && median > 0.0 && sigma.is_finite() && sigma >= 0.0,
+                "Log-normal delay needs a positive median and a non-negative sigma"
+            ),
+        }
+        Ok(())
+    }
+
+    /// Draws a delay.
+    pub fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> Result<Duration> {
+        let ms = match *self {
+            DelayDistribution::Uniform { min, max } => rng.random_range(min..=max) as f64,
+            DelayDistribution::Gaussian { mean, std_dev } => {
+                Normal::new(mean, std_dev)?.sample(rng)
+            }
+            DelayDistribution::Lognormal { median, sigma } => {
+                LogNormal::new(median.ln(), sigma)?.sample(rng)
+            }
+        };
+        Ok(Duration::from_secs_f64(ms.max(0.0) / 1_000.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn samples_ms(dist: &DelayDistribution, n: usize) -> Vec<f64> {
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut samples: Vec<f64> = (0..n)
+            .map(|_| dist.sample(&mut rng).unwrap().as_secs_f64() * 1_000.0)
+            .collect();
+        samples.sort_by(f64::total_cmp);
+        samples
+    }
+
+    #[test]
+    fn uniform_stays_within_bounds() {
+        let samples = samples_ms(&DelayDistribution::Uniform { min: 200, max: 400 }, 1_000);
+        assert!(samples[0] >= 200.0);
+        assert!(samples[samples.len() - 1] <= 400.0);
+    }
+
+    #[test]
+    fn gaussian_centers_on_mean_and_never_goes_negative() {
+        let samples = samples_ms(
+            &DelayDistribution::Gaussian {
+                mean: 50.0,
+                std_dev: 40.0,
+            },
+            10_000,
+        );
+        assert!(samples[0] >= 0.0);
+        let median = samples[samples.len() / 2];
+        assert!((median - 50.0).abs() < 3.0, "median {}", median);
+    }
+
+    #[test]
+    fn lognormal_centers_on_median_with_a_long_tail() {
+        let samples = samples_ms(
+            &DelayDistribution::Lognormal {
+                median: 400.0,
+                sigma: 0.4,
+            },
+            10_000,
+        );
+        let median = samples[samples.len() / 2];
+        assert!((median - 400.0).abs() < 15.0, "median {}", median);
+
+        // The right tail reaches further from the median than the left one
+        let p01 = samples[samples.len() / 100];
+        let p99 = samples[samples.len() * 99 / 100];
+        assert!(p99 - median > median - p01);
+    }
+
+    #[test]
+    fn validate_rejects_bad_parameters() {
+        assert!(DelayDistribution::Uniform { min: 5, max: 1 }
+            .validate()
+            .is_err());
+        assert!(DelayDistribution::Gaussian {
+            mean: 10.0,
+            std_dev: -1.0
+        }
+        .validate()
+        .is_err());
+        assert!(DelayDistribution::Lognormal {
+            median: 0.0,
+            sigma: 0.4
+        }
+        .validate()
+        .is_err());
+    }
+
+    #[test]
+    fn deserializes_tagged_distributions() {
+        let dist: DelayDistribution =
+            serde_json::from_str(r#"{"type": "lognormal", "median": 400, "sigma": 0.4}"#).unwrap();
+        assert_eq!(
+            dist,
+            DelayDistribution::Lognormal {
+                median: 400.0,
+                sigma: 0.4
+            }
+        );
+    }
+}
@@ -0,0 +1,103 @@
+//! Uniform color offsets learned at runtime from near-matching color signatures.
+//!
+//! Day/night tinting or a changed brightness setting shifts every color of the game window by
+//! about the same amount, so RGB and palette targets stop matching although everything is still
+//! where it was. When a color signature isn't found, the frame is searched for the signature's
+//! colors shifted by one per-channel offset. If enough pixels clearly agree on an offset, it's
+//! applied to every RGB and palette target for the rest of the run.
+use crate::vision::{self, ColorTarget, PixelColor, ScanOptions};
+
+use anyhow::Result;
+use image::RgbaImage;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// Fewest pixels that must agree on an offset before it's learned.
+const MIN_PIXELS: usize = 30;
+/// Number of most frequent offsets that are scored.
+const CANDIDATES: usize = 8;
+/// Factor by which the best offset must outscore any clearly different one, so that a scattering
+/// of unrelated pixels doesn't produce an offset.
+const DOMINANCE: usize = 3;
+
+/// Largest per-channel offset that's learned, 0 disables learning.
+static MAX_OFFSET: AtomicU8 = AtomicU8::new(24);
+
+/// Sets the largest per-channel offset that's learned, 0 disables learning.
+pub fn set_max_offset(max: u8) {
+    MAX_OFFSET.store(max, Ordering::Relaxed);
+}
+
+/// Counts the pixels of `frame` matching any of `colors` shifted by `offset`.
+fn count_matches(frame: &RgbaImage, colors: &[[u8; 3]], offset: [i16; 3]) -> usize {
+    let tolerance = vision::color_tolerance();
+    let shifted: Vec<PixelColor> = colors
+        .iter()
+        .map(|rgb| PixelColor::shifted(*rgb, offset))
+        .collect();
+    frame
+        .pixels()
+        .filter(|pixel| {
+            let color = PixelColor::new(pixel[0], pixel[1], pixel[2]);
+            shifted
+                .iter()
+                .any(|target| color.is_match(target, tolerance))
+        })
+        .count()
+}
+
+/// Looks for the target's colors shifted by a uniform offset within the scanned region.
+///
+/// Returns the offset when enough pixels clearly agree on one that differs from the offset
+/// already in use.
+pub fn learn(target: &ColorTarget, scan: &ScanOptions) -> Result<Option<[i16; 3]>> {
+    let max = i16::from(MAX_OFFSET.load(Ordering::Relaxed));
+    let colors = target.rgb_colors();
+    if max == 0 || colors.is_empty() {
+        return Ok(None);
+    }
+
+    let frame = vision::capture_screen_rgba()?;
+    let (frame, _) = vision::crop_to_region(frame, scan.search_region.as_ref());
+
+    // Every pixel votes for the offsets that would turn a target color into it
+    let mut votes: HashMap<[i16; 3], usize> = HashMap::new();
+    for pixel in frame.pixels() {
+        for rgb in colors {
+            let offset = [0, 1, 2].map(|i| i16::from(pixel[i]) - i16::from(rgb[i]));
+            if offset.iter().all(|channel| channel.abs() <= max) {
+                *votes.entry(offset).or_default() += 1;
+            }
+        }
+    }
+    let mut candidates: Vec<([i16; 3], usize)> = votes.into_iter().collect();
+    candidates.sort_by_key(|(_, votes)| std::cmp::Reverse(*votes));
+    candidates.truncate(CANDIDATES);
+
+    // Pixels of a tinted target spread over neighboring offsets, so score each candidate by every
+    // pixel it matches within the tolerance
+    let mut scored: Vec<([i16; 3], usize)> = candidates
+        .into_iter()
+        .map(|(offset, _)| (offset, count_matches(&frame, colors, offset)))
+        .collect();
+    scored.sort_by_key(|(_, score)| std::cmp::Reverse(*score));
+    let Some(&(best, score)) = scored.first() else {
+        return Ok(None);
+    };
+
+    let tolerance = i16::from(vision::color_tolerance());
+    let runner_up = scored
+        .iter()
+        .filter(|(offset, _)| {
+            offset
+                .iter()
+                .zip(best)
+                .any(|(channel, best)| (channel - best).abs() > 2 * tolerance)
+        })
+        .map(|(_, score)| *score)
+        .max()
+        .unwrap_or(0);
+    let learned =
+        score >= MIN_PIXELS && score >= DOMINANCE * runner_up && best != vision::color_offset();
+    Ok(learned.then_some(best))
+}
@@ -0,0 +1,185 @@
+//! Hand tremor and micro-corrections layered onto cursor paths.
+//!
+//! Paths from the movement model are perfectly smooth between their samples, while a real hand
+//! shakes slightly at around 8-12 Hz and now and then makes a small correction that kinks the
+//! path. This stage displaces every sample of a path perpendicular to the direction of travel by
+//! a tremor oscillation plus decaying correction kinks, leaving both ends of the path in place.
+use crate::windmouse::Point;
+
+use anyhow::{ensure, Result};
+use std::f64::consts::TAU;
+use std::time::Duration;
+
+/// Parameters of the tremor applied to cursor paths.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Tremor {
+    /// Peak perpendicular displacement of the oscillation in pixels.
+    pub amplitude: f64,
+    /// Oscillation frequency in Hz.
+    pub frequency: f64,
+    /// Average number of micro-corrections per second of movement.
+    pub correction_rate: f64,
+}
+
+impl Tremor {
+    /// Size range in pixels of the sideways kink a micro-correction adds.
+    const CORRECTION_PX: std::ops::RangeInclusive<f64> = 1.0..=3.0;
+    /// Fraction of a kink left after each following sample as the hand corrects it.
+    const CORRECTION_DECAY: f64 = 0.6;
+    /// Number of samples over which the displacement fades in and out at the path's ends.
+    const FADE_SAMPLES: usize = 4;
+
+    /// Checks that the parameters describe a usable tremor.
+    pub fn validate(&self) -> Result<()> {
+        ensure!(
+            self.amplitude >= 0.0,
+            "Tremor amplitude must not be negative, got {}",
+            self.amplitude
+        );
+        ensure!(
+            self.frequency > 0.0,
+            "Tremor frequency must be positive, got {}",
+            self.frequency
+        );
+        ensure!(
+            self.correction_rate >= 0.0,
+            "Micro-correction rate must not be negative, got {}",
+            self.correction_rate
+        );
+        Ok(())
+    }
+
+    /// Returns `path` with tremor and micro-corrections applied, given the time between samples.
+    ///
+    /// The first and last points are kept exactly, so the cursor still lands on the target.
+    pub fn apply(&self, path: &[Point], step: Duration) -> Vec<Point> {
+        let n = path.len();
+        if n < 3 || (self.amplitude == 0.0 && self.correction_rate == 0.0) {
+            return path.to_vec();
+        }
+
+        let dt = step.as_secs_f64();
+        let phase = rand::random_range(0.0..TAU);
+        let mut kink = 0.0;
+        let mut shaken = Vec::with_capacity(n);
+        shaken.push(path[0]);
+
+        for i in 1..n - 1 {
+            let point = path[i];
+            let (dx, dy) = (
+                f64::from(path[i + 1].x - path[i - 1].x),
+                f64::from(path[i + 1].y - path[i - 1].y),
+            );
+            let length = f64::hypot(dx, dy);
+
+            // The amplitude wanders a little so the shake isn't a pure sine
+            let t = i as f64 * dt;
+            let shake = self.amplitude
+                * (TAU * self.frequency * t + phase).sin()
+                * rand::random_range(0.7..1.3);
+            kink *= Tremor::CORRECTION_DECAY;
+            if rand::random::<f64>() < self.correction_rate * dt {
+                let size = rand::random_range(Tremor::CORRECTION_PX);
+                kink += if rand::random() { size } else { -size };
+            }
+
+            if length == 0.0 {
+                shaken.push(point);
+                continue;
+            }
+            let fade = (i.min(n - 1 - i) as f64 / Tremor::FADE_SAMPLES as f64).min(1.0);
+            let offset = (shake + kink) * fade;
+            shaken.push(Point::new(
+                point.x + (-dy / length * offset).round() as i32,
+                point.y + (dx / length * offset).round() as i32,
+            ));
+        }
+
+        shaken.push(path[n - 1]);
+        shaken.dedup();
+        shaken
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STEP: Duration = Duration::from_millis(10);
+
+    /// Horizontal path one pixel per sample, so displacements show up as the y coordinates.
+    fn straight_path(len: i32) -> Vec<Point> {
+        (0..len).map(|x| Point::new(x, 0)).collect()
+    }
+
+    fn tremor(amplitude: f64, frequency: f64, correction_rate: f64) -> Tremor {
+        Tremor {
+            amplitude,
+            frequency,
+            correction_rate,
+        }
+    }
+
+    #[test]
+    fn keeps_path_ends_in_place() {
+        let path = straight_path(200);
+        for _ in 0..50 {
+            let shaken = tremor(3.0, 10.0, 5.0).apply(&path, STEP);
+            assert_eq!(shaken.first(), path.first());
+            assert_eq!(shaken.last(), path.last());
+        }
+    }
+
+    #[test]
+    fn leaves_path_unchanged_without_tremor() {
+        let path = straight_path(200);
+        assert_eq!(tremor(0.0, 10.0, 0.0).apply(&path, STEP), path);
+    }
+
+    #[test]
+    fn displacement_is_centered_with_expected_spread() {
+        let path = straight_path(1000);
+        let offsets: Vec<f64> = (0..20)
+            .flat_map(|_| tremor(4.0, 10.0, 0.0).apply(&path, STEP))
+            .map(|point| f64::from(point.y))
+            .collect();
+
+        let mean = offsets.iter().sum::<f64>() / offsets.len() as f64;
+        let rms = (offsets.iter().map(|y| y * y).sum::<f64>() / offsets.len() as f64).sqrt();
+        // A sine with a peak of 4 has an RMS of about 2.8
+        assert!(mean.abs() < 0.3, "mean displacement {}", mean);
+        assert!((2.3..3.5).contains(&rms), "RMS displacement {}", rms);
+    }
+
+    #[test]
+    fn oscillates_at_the_given_frequency() {
+        // 1000 samples 10 ms apart are 10 s, a 10 Hz oscillation crosses zero 200 times in that
+        let path = straight_path(1000);
+        let shaken = tremor(4.0, 10.0, 0.0).apply(&path, STEP);
+        let signs: Vec<i32> = shaken
+            .iter()
+            .map(|point| point.y.signum())
+            .filter(|sign| *sign != 0)
+            .collect();
+        let crossings = signs.windows(2).filter(|pair| pair[0] != pair[1]).count();
+        assert!((180..=220).contains(&crossings), "{} crossings", crossings);
+    }
+
+    #[test]
+    fn corrections_occur_at_the_given_rate() {
+        // Without the oscillation the displacement only grows when a new correction kinks the path
+        let path = straight_path(1000);
+        let runs = 20;
+        let onsets: usize = (0..runs)
+            .map(|_| {
+                let shaken = tremor(0.0, 10.0, 2.0).apply(&path, STEP);
+                shaken
+                    .windows(2)
+                    .filter(|pair| pair[1].y.abs() > pair[0].y.abs())
+                    .count()
+            })
+            .sum();
+        let per_run = onsets as f64 / f64::from(runs);
+        assert!((15.0..=25.0).contains(&per_run), "{} corrections", per_run);
+    }
+}
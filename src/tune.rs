@@ -0,0 +1,188 @@
+//! Automatic tuning of detection and movement settings for the `tune` subcommand.
+//!
+//! A tuning session runs the script in short trials, one for every combination of color
+//! tolerance, tempo and path deviation. Each trial is scored from its run report by the hit rate
+//! of its targets, with misclicks and errors counting against it, and the best settings are
+//! printed as command-line flags. Trials only drive real input with an explicit `--live`, tuning
+//! otherwise needs recorded frames or a dry run.
+use crate::config::{BotConfig, TuneConfig};
+use crate::crash;
+use crate::report::RunReport;
+use crate::rundir::RunDir;
+
+use anyhow::{ensure, Context, Result};
+use log::warn;
+use std::cmp::Ordering;
+
+/// Settings tried in one trial.
+#[derive(Debug, Clone, Copy)]
+struct Settings {
+    tolerance: u8,
+    tempo: f64,
+    deviation: f64,
+}
+
+impl Settings {
+    /// Applies the settings to the configuration of a trial run.
+    fn apply(&self, config: &mut BotConfig) {
+        config.color_tolerance = self.tolerance;
+        config.tempo = Some(self.tempo);
+        config.path_deviation = self.deviation;
+    }
+}
+
+/// Outcome of one trial.
+struct Trial {
+    settings: Settings,
+    report: RunReport,
+}
+
+impl Trial {
+    /// Hit rate minus the share of misclicks per attempt, a trial that ended in an error ranks
+    /// below every trial that didn't.
+    fn score(&self) -> f64 {
+        let targets = self.report.total_targets();
+        let attempts = (targets.found + targets.missed).max(1) as f64;
+        let mut score = targets.hit_rate().unwrap_or(1.0) - targets.misclicks as f64 / attempts;
+        if self.report.error.is_some() {
+            score -= 2.0;
+        }
+        score
+    }
+
+    fn iterations_per_hour(&self) -> f64 {
+        if self.report.elapsed_secs > 0.0 {
+            self.report.iterations as f64 * 3_600.0 / self.report.elapsed_secs
+        } else {
+            0.0
+        }
+    }
+
+    /// Orders trials best first by score, breaking ties by throughput.
+    fn rank(&self, other: &Trial) -> Ordering {
+        other.score().total_cmp(&self.score()).then(
+            other
+                .iterations_per_hour()
+                .total_cmp(&self.iterations_per_hour()),
+        )
+    }
+}
+
+/// Returns every combination of the listed settings.
+fn grid(config: &TuneConfig) -> Vec<Settings> {
+    let mut grid = Vec::new();
+    for &tolerance in &config.tolerances {
+        for &tempo in &config.tempos {
+            for &deviation in &config.deviations {
+                grid.push(Settings {
+                    tolerance,
+                    tempo,
+                    deviation,
+                });
+            }
+        }
+    }
+    grid
+}
+
+/// Runs one trial with `run_trial` and reads back its report.
+fn run_one(
+    config: &TuneConfig,
+    settings: Settings,
+    run_trial: &impl Fn(BotConfig, &RunDir) -> Result<()>,
+) -> Result<RunReport> {
+    let mut bot = config.bot.clone();
+    bot.runtime = config.trial_secs;
+    settings.apply(&mut bot);
+
+    let root = bot.run_root.clone().unwrap_or_else(RunDir::default_root);
+    let run_dir = RunDir::create(&root, bot.keep_runs as usize, &bot.script)?;
+    crash::install_hook(&run_dir, &bot);
+
+    let result = run_trial(bot, &run_dir);
+    match RunReport::load(&RunReport::path(&run_dir)?) {
+        Ok(report) => {
+            if let Err(e) = result {
+                warn!("Trial ended early: {:#}", e);
+            }
+            Ok(report)
+        }
+        // Without a report the run failed during setup, which no other trial will get past
+        Err(e) => Err(result.err().unwrap_or(e.into())),
+    }
+}
+
+/// Runs every trial of the tuning session and prints the ranked results.
+///
+/// `run_trial` runs the bot for the given configuration and writes its report to the run
+/// directory.
+pub fn run(config: TuneConfig, run_trial: impl Fn(BotConfig, &RunDir) -> Result<()>) -> Result<()> {
+    ensure!(
+        config.live || config.bot.frames.is_some() || config.bot.dry_run,
+        "Trials drive the real mouse and keyboard, tune against --frames or with --dry-run, or \
+         pass --live to tune on the game"
+    );
+    ensure!(config.trial_secs > 0, "--trial-secs must be positive");
+    ensure!(
+        config
+            .tempos
+            .iter()
+            .chain(&config.deviations)
+            .all(|v| *v > 0.0),
+        "Tempos and deviations must be positive"
+    );
+    let grid = grid(&config);
+    ensure!(
+        !grid.is_empty(),
+        "Nothing to tune, every setting needs a value"
+    );
+
+    println!(
+        "Running {} trials of {} seconds each",
+        grid.len(),
+        config.trial_secs
+    );
+    let mut trials = Vec::with_capacity(grid.len());
+    for (i, settings) in grid.into_iter().enumerate() {
+        println!(
+            "Trial {}: tolerance {}, tempo {}, deviation {}",
+            i + 1,
+            settings.tolerance,
+            settings.tempo,
+            settings.deviation
+        );
+        let report =
+            run_one(&config, settings, &run_trial).context(format!("Trial {} failed", i + 1))?;
+        trials.push(Trial { settings, report });
+    }
+    trials.sort_by(Trial::rank);
+
+    println!(
+        "\n{:>9} {:>6} {:>9} {:>8} {:>9} {:>10} {:>7}  error",
+        "tolerance", "tempo", "deviation", "hit rate", "misclicks", "iter/hour", "score"
+    );
+    for trial in &trials {
+        let targets = trial.report.total_targets();
+        println!(
+            "{:>9} {:>6.2} {:>9.2} {:>8} {:>9} {:>10.1} {:>7.3}  {}",
+            trial.settings.tolerance,
+            trial.settings.tempo,
+            trial.settings.deviation,
+            targets
+                .hit_rate()
+                .map_or_else(|| "-".to_string(), |rate| format!("{:.1}%", rate * 100.0)),
+            targets.misclicks,
+            trial.iterations_per_hour(),
+            trial.score(),
+            trial.report.error.as_deref().unwrap_or("-")
+        );
+    }
+
+    let best = trials[0].settings;
+    println!(
+        "\nRecommended: --color-tolerance {} --tempo {} --path-deviation {}",
+        best.tolerance, best.tempo, best.deviation
+    );
+
+    Ok(())
+}
@@ -0,0 +1,130 @@
+//! Turbo mode, every throughput setting at its fastest with a check on detection latency.
+//!
+//! Turbo lifts the pacing limits that trade speed for a lighter load: the minimum sleep after
+//! each event, the capture rate cap and the CPU target. Humanized delays and mouse movements are
+//! left alone, so turbo only removes time the bot spends on itself. Whether that's enough is
+//! checked against a latency budget with the self-profiler: the time an event spends capturing,
+//! scanning, clustering and planning, before the input it sends, must stay under the budget on
+//! average. Events over it are reported along with the stage that takes the longest.
+use crate::backend::BackendKind;
+use crate::config::BotConfig;
+use crate::profile::EventProfile;
+use crate::vision::CaptureSource;
+
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Event whose detection took longer than the turbo budget on average.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BudgetOverrun {
+    pub event: String,
+    /// Mean time in ms from the start of the event until it knew where to send input.
+    pub latency_ms: f64,
+    /// Stage that took the longest of that time.
+    pub stage: String,
+    /// Mean time in ms spent in that stage.
+    pub stage_ms: f64,
+}
+
+/// Switches the pacing settings to their fastest values.
+pub fn apply(config: &mut BotConfig) {
+    config.min_cycle_sleep = 0;
+    config.max_capture_fps = 0;
+    config.max_cpu_percent = None;
+    config.infer_regions = true;
+    info!("Turbo: no cycle sleep, capture rate cap or CPU target, search regions are inferred");
+    if config.capture == CaptureSource::Portal {
+        warn!(
+            "Turbo: portal frames pass through GStreamer and a pipe before they're scanned, X11 \
+             capture is faster where it shows the game"
+        );
+    }
+    if matches!(config.backend, BackendKind::Xdotool | BackendKind::Ydotool) {
+        warn!(
+            "Turbo: the {:?} backend starts a process for every action, the native and uinput \
+             backends are faster",
+            config.backend
+        );
+    }
+}
+
+/// Returns the events whose mean detection latency is above `budget_ms`, slowest first.
+pub fn check_budget(
+    profiles: &BTreeMap<String, EventProfile>,
+    budget_ms: f64,
+) -> Vec<BudgetOverrun> {
+    let mut overruns: Vec<BudgetOverrun> = profiles
+        .iter()
+        .filter_map(|(id, profile)| {
+            let mean = &profile.mean;
+            let stages = [
+                ("capture", mean.capture_ms),
+                ("scan", mean.scan_ms),
+                ("cluster", mean.cluster_ms),
+                ("plan", mean.plan_ms),
+            ];
+            let latency_ms = mean.search_ms();
+            let (stage, stage_ms) = stages.into_iter().max_by(|a, b| a.1.total_cmp(&b.1))?;
+            (latency_ms > budget_ms).then(|| BudgetOverrun {
+                event: id.clone(),
+                latency_ms,
+                stage: stage.to_string(),
+                stage_ms,
+            })
+        })
+        .collect();
+    overruns.sort_by(|a, b| b.latency_ms.total_cmp(&a.latency_ms));
+    overruns
+}
+
+/// Logs the events over the budget, or that every event stayed under it.
+pub fn log_overruns(overruns: &[BudgetOverrun], budget_ms: f64) {
+    if overruns.is_empty() {
+        info!(
+            "Turbo: every event found its target within {} ms on average",
+            budget_ms
+        );
+    }
+    for overrun in overruns {
+        warn!(
+            "Turbo: event '{}' took {:.1} ms on average before sending input, over the {} ms \
+             budget, most of it in {} ({:.1} ms)",
+            overrun.event, overrun.latency_ms, budget_ms, overrun.stage, overrun.stage_ms
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::profile::PhaseTimes;
+
+    fn profile(capture_ms: f64, scan_ms: f64, execute_ms: f64) -> EventProfile {
+        let mean = PhaseTimes {
+            capture_ms,
+            scan_ms,
+            execute_ms,
+            sleep_ms: 900.0,
+            ..Default::default()
+        };
+        EventProfile {
+            runs: 1,
+            total: mean.clone(),
+            mean,
+        }
+    }
+
+    #[test]
+    fn overruns_name_the_slowest_stage_and_ignore_input_and_sleep() {
+        let profiles = BTreeMap::from([
+            ("fast".to_string(), profile(4.0, 10.0, 400.0)),
+            ("slow".to_string(), profile(8.0, 52.0, 10.0)),
+        ]);
+        let overruns = check_budget(&profiles, 30.0);
+        assert_eq!(overruns.len(), 1);
+        assert_eq!(overruns[0].event, "slow");
+        assert_eq!(overruns[0].stage, "scan");
+        assert!((overruns[0].latency_ms - 60.0).abs() < 1e-9);
+    }
+}
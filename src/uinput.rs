@@ -0,0 +1,335 @@
+//! Input backend that injects events through a uinput virtual device.
+//!
+//! The virtual device is an absolute pointer, like the tablet that virtual machines expose, plus a
+//! keyboard. Events are injected at the evdev level below the display server, so the backend works
+//! the same on X11 and Wayland and doesn't depend on xdotool. Writing to `/dev/uinput` usually
+//! needs a udev rule, see `colorbot doctor`. The display server can't be asked where the cursor
+//! is from below it, so the backend keeps track of where it moved it.
+use crate::backend::{self, Capabilities, InputBackend, MouseButton};
+use crate::display;
+use crate::windmouse::Point;
+
+use anyhow::{bail, Context, Result};
+use evdev::uinput::VirtualDevice;
+use evdev::{
+    AbsInfo, AbsoluteAxisCode, AttributeSet, EventType, InputEvent, KeyCode, UinputAbsSetup,
+};
+use std::time::Duration;
+
+/// Path of the uinput character device.
+pub const UINPUT_PATH: &str = "/dev/uinput";
+
+/// Time given to the display server to pick up the new device before it's used.
+pub const DEVICE_SETTLE_TIME: Duration = Duration::from_millis(300);
+
+/// Gap between the press and release of a single tap.
+const TAP_HOLD_TIME: Duration = Duration::from_millis(15);
+
+/// Backend that drives a uinput virtual absolute pointer and keyboard.
+pub struct UinputBackend {
+    device: VirtualDevice,
+    swapped_buttons: bool,
+}
+
+/// Translates a single xdotool key name into an evdev key code.
+pub(crate) fn parse_key(name: &str) -> Result<KeyCode> {
+    let mut chars = name.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        if let Some((key, false)) = char_key(c) {
+            return Ok(key);
+        }
+    }
+
+    let key = match name.to_ascii_lowercase().as_str() {
+        "ctrl" | "control" | "control_l" => KeyCode::KEY_LEFTCTRL,
+        "control_r" => KeyCode::KEY_RIGHTCTRL,
+        "shift" | "shift_l" => KeyCode::KEY_LEFTSHIFT,
+        "shift_r" => KeyCode::KEY_RIGHTSHIFT,
+        "alt" | "alt_l" => KeyCode::KEY_LEFTALT,
+        "alt_r" => KeyCode::KEY_RIGHTALT,
+        "super" | "super_l" | "meta" => KeyCode::KEY_LEFTMETA,
+        "super_r" => KeyCode::KEY_RIGHTMETA,
+        "escape" | "esc" => KeyCode::KEY_ESC,
+        "return" | "enter" => KeyCode::KEY_ENTER,
+        "space" => KeyCode::KEY_SPACE,
+        "tab" => KeyCode::KEY_TAB,
+        "backspace" => KeyCode::KEY_BACKSPACE,
+        "delete" => KeyCode::KEY_DELETE,
+        "insert" => KeyCode::KEY_INSERT,
+        "home" => KeyCode::KEY_HOME,
+        "end" => KeyCode::KEY_END,
+        "prior" | "page_up" => KeyCode::KEY_PAGEUP,
+        "next" | "page_down" => KeyCode::KEY_PAGEDOWN,
+        "up" => KeyCode::KEY_UP,
+        "down" => KeyCode::KEY_DOWN,
+        "left" => KeyCode::KEY_LEFT,
+        "right" => KeyCode::KEY_RIGHT,
+        "f1" => KeyCode::KEY_F1,
+        "f2" => KeyCode::KEY_F2,
+        "f3" => KeyCode::KEY_F3,
+        "f4" => KeyCode::KEY_F4,
+        "f5" => KeyCode::KEY_F5,
+        "f6" => KeyCode::KEY_F6,
+        "f7" => KeyCode::KEY_F7,
+        "f8" => KeyCode::KEY_F8,
+        "f9" => KeyCode::KEY_F9,
+        "f10" => KeyCode::KEY_F10,
+        "f11" => KeyCode::KEY_F11,
+        "f12" => KeyCode::KEY_F12,
+        _ => bail!("Unsupported key name '{}' for the uinput backend", name),
+    };
+
+    Ok(key)
+}
+
+/// Maps a character to its key code on a US layout and whether shift is needed.
+pub(crate) fn char_key(c: char) -> Option<(KeyCode, bool)> {
+    const LETTERS: [KeyCode; 26] = [
+        KeyCode::KEY_A,
+        KeyCode::KEY_B,
+        KeyCode::KEY_C,
+        KeyCode::KEY_D,
+        KeyCode::KEY_E,
+        KeyCode::KEY_F,
+        KeyCode::KEY_G,
+        KeyCode::KEY_H,
+        KeyCode::KEY_I,
+        KeyCode::KEY_J,
+        KeyCode::KEY_K,
+        KeyCode::KEY_L,
+        KeyCode::KEY_M,
+        KeyCode::KEY_N,
+        KeyCode::KEY_O,
+        KeyCode::KEY_P,
+        KeyCode::KEY_Q,
+        KeyCode::KEY_R,
+        KeyCode::KEY_S,
+        KeyCode::KEY_T,
+        KeyCode::KEY_U,
+        KeyCode::KEY_V,
+        KeyCode::KEY_W,
+        KeyCode::KEY_X,
+        KeyCode::KEY_Y,
+        KeyCode::KEY_Z,
+    ];
+    // Digits in order 0-9, paired with the symbol shift produces on them
+    const DIGITS: [(KeyCode, char); 10] = [
+        (KeyCode::KEY_0, ')'),
+        (KeyCode::KEY_1, '!'),
+        (KeyCode::KEY_2, '@'),
+        (KeyCode::KEY_3, '#'),
+        (KeyCode::KEY_4, '$'),
+        (KeyCode::KEY_5, '%'),
+        (KeyCode::KEY_6, '^'),
+        (KeyCode::KEY_7, '&'),
+        (KeyCode::KEY_8, '*'),
+        (KeyCode::KEY_9, '('),
+    ];
+    const PUNCTUATION: [(KeyCode, char, char); 11] = [
+        (KeyCode::KEY_MINUS, '-', '_'),
+        (KeyCode::KEY_EQUAL, '=', '+'),
+        (KeyCode::KEY_LEFTBRACE, '[', '{'),
+        (KeyCode::KEY_RIGHTBRACE, ']', '}'),
+        (KeyCode::KEY_BACKSLASH, '\\', '|'),
+        (KeyCode::KEY_SEMICOLON, ';', ':'),
+        (KeyCode::KEY_APOSTROPHE, '\'', '"'),
+        (KeyCode::KEY_GRAVE, '`', '~'),
+        (KeyCode::KEY_COMMA, ',', '<'),
+        (KeyCode::KEY_DOT, '.', '>'),
+        (KeyCode::KEY_SLASH, '/', '?'),
+    ];
+
+    if c.is_ascii_lowercase() {
+        return Some((LETTERS[usize::from(c as u8 - b'a')], false));
+    }
+    if c.is_ascii_uppercase() {
+        return Some((LETTERS[usize::from(c as u8 - b'A')], true));
+    }
+    if let Some(d) = c.to_digit(10) {
+        return Some((DIGITS[d as usize].0, false));
+    }
+    if let Some((key, _)) = DIGITS.iter().find(|(_, shifted)| *shifted == c) {
+        return Some((*key, true));
+    }
+    if let Some((key, plain, _)) = PUNCTUATION
+        .iter()
+        .find(|(_, plain, shifted)| *plain == c || *shifted == c)
+    {
+        return Some((*key, *plain != c));
+    }
+
+    match c {
+        ' ' => Some((KeyCode::KEY_SPACE, false)),
+        '\n' => Some((KeyCode::KEY_ENTER, false)),
+        '\t' => Some((KeyCode::KEY_TAB, false)),
+        _ => None,
+    }
+}
+
+impl UinputBackend {
+    /// Creates the virtual device with absolute axes spanning the screen and moves the cursor to
+    /// the middle of it, where it starts tracking it.
+    pub fn probe(swapped_buttons: bool) -> Result<Self> {
+        // The absolute axes are mapped onto the whole screen, or the monitor shared through the
+        // portal on Wayland
+        let (width, height) = display::screen_size()?;
+        let (width, height) = (i32::try_from(width)?, i32::try_from(height)?);
+
+        let mut keys = AttributeSet::<KeyCode>::new();
+        for code in 1..=KeyCode::KEY_F12.code() {
+            keys.insert(KeyCode::new(code));
+        }
+        for button in [KeyCode::BTN_LEFT, KeyCode::BTN_RIGHT, KeyCode::BTN_MIDDLE] {
+            keys.insert(button);
+        }
+        let x_axis = UinputAbsSetup::new(
+            AbsoluteAxisCode::ABS_X,
+            AbsInfo::new(0, 0, width - 1, 0, 0, 1),
+        );
+        let y_axis = UinputAbsSetup::new(
+            AbsoluteAxisCode::ABS_Y,
+            AbsInfo::new(0, 0, height - 1, 0, 0, 1),
+        );
+
+        let device = VirtualDevice::builder()
+            .context(format!(
+                "Failed to open {}, run 'colorbot doctor' for setup help",
+                UINPUT_PATH
+            ))?
+            .name("colorbot virtual pointer")
+            .with_keys(&keys)?
+            .with_absolute_axis(&x_axis)?
+            .with_absolute_axis(&y_axis)?
+            .build()
+            .context("Failed to create the uinput virtual device")?;
+        std::thread::sleep(DEVICE_SETTLE_TIME);
+
+        let mut uinput = Self {
+            device,
+            swapped_buttons,
+        };
+        uinput.move_cursor(Point::new(width / 2, height / 2))?;
+        Ok(uinput)
+    }
+
+    /// Sends a press or release of a key or button.
+    fn set_key(&mut self, key: KeyCode, pressed: bool) -> Result<()> {
+        self.device
+            .emit(&[InputEvent::new(
+                EventType::KEY.0,
+                key.code(),
+                i32::from(pressed),
+            )])
+            .context("Failed to write to the uinput device")
+    }
+
+    /// Holds the modifiers, taps the last key and releases the modifiers in reverse order.
+    fn chord(&mut self, keys: &[KeyCode]) -> Result<()> {
+        let (last, modifiers) = keys.split_last().context("Empty key chord")?;
+        for modifier in modifiers {
+            self.set_key(*modifier, true)?;
+        }
+        self.set_key(*last, true)?;
+        std::thread::sleep(TAP_HOLD_TIME);
+        self.set_key(*last, false)?;
+        for modifier in modifiers.iter().rev() {
+            self.set_key(*modifier, false)?;
+        }
+        Ok(())
+    }
+}
+
+impl InputBackend for UinputBackend {
+    fn name(&self) -> &'static str {
+        "uinput"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        // The device only reports absolute positions
+        Capabilities {
+            scroll: false,
+            drag: true,
+            key_chords: true,
+            relative_moves: false,
+            sub_ms_timing: true,
+            gamepad: false,
+        }
+    }
+
+    fn move_cursor(&mut self, point: Point) -> Result<()> {
+        self.device
+            .emit(&[
+                InputEvent::new(EventType::ABSOLUTE.0, AbsoluteAxisCode::ABS_X.0, point.x),
+                InputEvent::new(EventType::ABSOLUTE.0, AbsoluteAxisCode::ABS_Y.0, point.y),
+            ])
+            .context("Failed to write to the uinput device")?;
+        backend::set_injected_cursor(point);
+        Ok(())
+    }
+
+    fn click(&mut self, button: MouseButton) -> Result<()> {
+        // Physical buttons pass through the pointer mapping like those of a real mouse
+        let physical = match button.physical(self.swapped_buttons) {
+            MouseButton::Left => KeyCode::BTN_LEFT,
+            MouseButton::Middle => KeyCode::BTN_MIDDLE,
+            MouseButton::Right => KeyCode::BTN_RIGHT,
+        };
+        self.chord(&[physical])
+    }
+
+    fn set_button(&mut self, button: MouseButton, pressed: bool) -> Result<()> {
+        let physical = match button.physical(self.swapped_buttons) {
+            MouseButton::Left => KeyCode::BTN_LEFT,
+            MouseButton::Middle => KeyCode::BTN_MIDDLE,
+            MouseButton::Right => KeyCode::BTN_RIGHT,
+        };
+        self.set_key(physical, pressed)
+    }
+
+    fn key(&mut self, keycode: &str) -> Result<()> {
+        let keys = keycode
+            .split('+')
+            .map(parse_key)
+            .collect::<Result<Vec<_>>>()?;
+        self.chord(&keys)
+    }
+
+    fn hold_key(&mut self, keycode: &str, duration: Duration) -> Result<()> {
+        let key = parse_key(keycode)?;
+        self.set_key(key, true)?;
+        std::thread::sleep(duration);
+        self.set_key(key, false)
+    }
+
+    fn text(&mut self, text: &str) -> Result<()> {
+        for c in text.chars() {
+            // Don't quote the character, the text may be a secret
+            let (key, shift) = char_key(c).context("Unsupported character in typed text")?;
+            if shift {
+                self.chord(&[KeyCode::KEY_LEFTSHIFT, key])?;
+            } else {
+                self.chord(&[key])?;
+            }
+        }
+        Ok(())
+    }
+
+    fn release_all(&mut self) -> Result<()> {
+        for key in [
+            KeyCode::KEY_LEFTSHIFT,
+            KeyCode::KEY_RIGHTSHIFT,
+            KeyCode::KEY_LEFTCTRL,
+            KeyCode::KEY_RIGHTCTRL,
+            KeyCode::KEY_LEFTALT,
+            KeyCode::KEY_RIGHTALT,
+            KeyCode::KEY_LEFTMETA,
+            KeyCode::KEY_RIGHTMETA,
+            KeyCode::BTN_LEFT,
+            KeyCode::BTN_RIGHT,
+            KeyCode::BTN_MIDDLE,
+        ] {
+            self.set_key(key, false)?;
+        }
+        Ok(())
+    }
+}
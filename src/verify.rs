@@ -0,0 +1,221 @@
+//! Verification of candidate targets before committing to a click.
+//!
+//! When a target matches several clusters, the candidates are tried in order and each one is
+//! verified after the cursor is moved onto it. A failed verification moves on to the next-best
+//! cluster instead of clicking. For example, identical-looking objects can often only be told
+//! apart by the tooltip the game shows while hovering them.
+//!
+//! A click can also be confirmed after the fact by watching the pixels around the clicked point
+//! change, which works for any click that has a visible effect without knowing what it looks like.
+use crate::backend::{InputBackend, MouseButton};
+use crate::controls::{self, MouseSettings};
+use crate::signature::Signature;
+use crate::vision::{self, Blob, Region};
+use crate::windmouse::Point;
+
+use anyhow::{bail, ensure, Context, Result};
+use image::RgbaImage;
+use log::debug;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use std::time::{Duration, Instant};
+
+/// Time between captures while waiting for a click's effect.
+const EFFECT_POLL_INTERVAL: Duration = Duration::from_millis(50);
+/// Smallest per-channel difference for a watched pixel to count as changed, well above capture
+/// noise.
+const EFFECT_CHANGE_THRESHOLD: u8 = 16;
+
+/// Tooltip expected while hovering the right target.
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct HoverCheck {
+    /// Signature of the tooltip, usually restricted to a search region.
+    pub tooltip: Signature,
+
+    /// Time in ms to wait for the tooltip after hovering a candidate.
+    #[serde(default = "default_tooltip_wait")]
+    pub wait: u64,
+}
+
+fn default_tooltip_wait() -> u64 {
+    250
+}
+
+impl HoverCheck {
+    /// Checks the tooltip signature's parameters.
+    pub fn validate(&self) -> Result<()> {
+        self.tooltip.validate().context("Invalid hover tooltip")
+    }
+
+    /// Waits briefly and reports whether the tooltip appeared for the hovered candidate.
+    pub fn verify(&self) -> Result<bool> {
+        std::thread::sleep(Duration::from_millis(self.wait));
+        self.tooltip.is_present()
+    }
+}
+
+/// Visible change expected around the clicked point shortly after a click.
+#[derive(Debug, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct EffectCheck {
+    /// Distance in pixels from the clicked point to the edges of the watched square.
+    #[serde(default = "default_effect_radius")]
+    #[schemars(range(min = 1))]
+    pub radius: u32,
+
+    /// Time in ms to wait for the pixels to change after the click.
+    #[serde(default = "default_effect_timeout")]
+    pub timeout: u64,
+
+    /// Fraction of the watched pixels that must change for the click to count.
+    #[serde(default = "default_min_changed")]
+    pub min_changed: f64,
+}
+
+fn default_effect_radius() -> u32 {
+    12
+}
+
+fn default_effect_timeout() -> u64 {
+    1000
+}
+
+fn default_min_changed() -> f64 {
+    0.1
+}
+
+impl EffectCheck {
+    /// Checks that the watched area and thresholds are usable.
+    pub fn validate(&self) -> Result<()> {
+        ensure!(
+            self.radius >= 1,
+            "confirm_effect needs a radius of at least 1"
+        );
+        ensure!(self.timeout > 0, "confirm_effect needs a positive timeout");
+        ensure!(
+            self.min_changed > 0.0 && self.min_changed <= 1.0,
+            "confirm_effect needs min_changed in (0, 1], got {}",
+            self.min_changed
+        );
+        Ok(())
+    }
+
+    /// Captures the pixels around `point` before clicking it.
+    pub fn watch(&self, point: Point) -> Result<EffectWatch<'_>> {
+        let region = Region {
+            x: point.x - self.radius as i32,
+            y: point.y - self.radius as i32,
+            width: 2 * self.radius + 1,
+            height: 2 * self.radius + 1,
+        };
+        let (before, _) = vision::crop_to_region(vision::capture_screen_rgba()?, Some(&region));
+        Ok(EffectWatch {
+            check: self,
+            point,
+            region,
+            before,
+        })
+    }
+}
+
+/// Pixels around a point captured before it was clicked.
+pub struct EffectWatch<'a> {
+    check: &'a EffectCheck,
+    point: Point,
+    region: Region,
+    before: RgbaImage,
+}
+
+impl EffectWatch<'_> {
+    /// Returns the fraction of the watched pixels that differ from before the click.
+    fn changed(&self, after: &RgbaImage) -> f64 {
+        let total = self.before.pixels().len();
+        if total == 0 || after.dimensions() != self.before.dimensions() {
+            return 0.0;
+        }
+        let changed = self
+            .before
+            .pixels()
+            .zip(after.pixels())
+            .filter(|(before, after)| {
+                (0..3).any(|i| before[i].abs_diff(after[i]) > EFFECT_CHANGE_THRESHOLD)
+            })
+            .count();
+        changed as f64 / total as f64
+    }
+
+    /// Polls until enough of the watched pixels changed, failing when the timeout passes first.
+    pub fn confirm(self) -> Result<()> {
+        let deadline = Instant::now() + Duration::from_millis(self.check.timeout);
+        let mut changed = 0.0;
+        while Instant::now() < deadline {
+            std::thread::sleep(EFFECT_POLL_INTERVAL);
+            let (after, _) =
+                vision::crop_to_region(vision::capture_screen_rgba()?, Some(&self.region));
+            changed = self.changed(&after);
+            if changed >= self.check.min_changed {
+                debug!(
+                    "Click at {} took effect, {:.0}% of the pixels around it changed",
+                    self.point,
+                    changed * 100.0
+                );
+                return Ok(());
+            }
+        }
+        bail!(
+            "Click at {} had no visible effect, only {:.0}% of the pixels around it changed \
+             within {} ms",
+            self.point,
+            changed * 100.0,
+            self.check.timeout
+        );
+    }
+}
+
+/// Clicks `button` at the cursor `clicks` times and, when a check is given, confirms the click had
+/// a visible effect.
+pub fn click_confirmed(
+    input: &mut dyn InputBackend,
+    button: MouseButton,
+    clicks: u32,
+    effect: Option<&EffectCheck>,
+) -> Result<()> {
+    let watch = effect
+        .map(|check| check.watch(controls::get_mouse_pos()))
+        .transpose()
+        .context("Failed to capture the pixels around the target")?;
+    controls::click(input, button, clicks)?;
+    match watch {
+        Some(watch) => watch.confirm(),
+        None => Ok(()),
+    }
+}
+
+/// Moves onto candidate blobs in order until `verify` accepts one, leaving the cursor on it.
+///
+/// At most `max_candidates` blobs are tried before the target is declared missed.
+pub fn try_candidates(
+    input: &mut dyn InputBackend,
+    blobs: &[Blob],
+    max_candidates: usize,
+    mouse: &MouseSettings,
+    mut verify: impl FnMut() -> Result<bool>,
+) -> Result<()> {
+    let tried = blobs.len().min(max_candidates);
+    for (i, blob) in blobs.iter().take(max_candidates).enumerate() {
+        let hit = vision::pick_in_cluster(&blob.points)?;
+        controls::move_to_target(input, hit, mouse)?;
+
+        if verify()? {
+            debug!("Candidate {}/{} passed verification", i + 1, tried);
+            return Ok(());
+        }
+        debug!(
+            "Candidate {}/{} failed verification, trying the next one",
+            i + 1,
+            tried
+        );
+    }
+
+    bail!("None of the {} candidates passed verification", tried);
+}
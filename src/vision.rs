@@ -2,29 +2,243 @@
 //!
 //! This module provides functions for finding pixels by color, computing convex hulls,
 //! point-in-polygon tests, and selecting points within colored shapes with edge distance bias.
+use crate::autoregion;
+use crate::colormatch::ColorMatcher;
+use crate::controls;
+use crate::crash;
+use crate::decisions::{self, DecisionKind};
+use crate::diagnostics;
+use crate::display;
+use crate::error::ColorbotError;
+use crate::filter::{self, Filter};
+use crate::fixtures;
+use crate::indicator;
+use crate::matchcache;
+use crate::pacing;
+use crate::portal;
+use crate::profile::{self, Phase};
+use crate::replay;
 use crate::windmouse::Point;
 
-use anyhow::{bail, ensure, Context, Result};
+use anyhow::{anyhow, bail, ensure, Context, Result};
+use clap::ValueEnum;
 use image::ImageReader;
-use image::{GrayImage, ImageBuffer, Rgba};
+use image::{GrayImage, ImageBuffer, Rgba, RgbaImage};
 use imageproc::template_matching::{find_extremes, MatchTemplateMethod};
+use log::{debug, warn};
+use rand::seq::SliceRandom;
+use schemars::JsonSchema;
 use scrap::{Capturer, Display};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
 use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicI16, AtomicU8, Ordering};
+use std::time::Duration;
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::{ConnectionExt, ImageFormat};
+
+/// Maximum per-channel difference for a pixel to match an RGB target during a run.
+static COLOR_TOLERANCE: AtomicU8 = AtomicU8::new(ColorTarget::TOLERANCE);
+
+/// Sets the per-channel tolerance RGB and palette targets are matched with.
+pub fn set_color_tolerance(tolerance: u8) {
+    COLOR_TOLERANCE.store(tolerance, Ordering::Relaxed);
+}
+
+/// Returns the per-channel tolerance RGB and palette targets are matched with.
+pub fn color_tolerance() -> u8 {
+    COLOR_TOLERANCE.load(Ordering::Relaxed)
+}
+
+/// Per-channel offset added to the colors of RGB and palette targets, learned during a run.
+static COLOR_OFFSET: [AtomicI16; 3] = [AtomicI16::new(0), AtomicI16::new(0), AtomicI16::new(0)];
+
+/// Sets the per-channel offset added to the colors of RGB and palette targets.
+pub fn set_color_offset(offset: [i16; 3]) {
+    for (channel, value) in COLOR_OFFSET.iter().zip(offset) {
+        channel.store(value, Ordering::Relaxed);
+    }
+}
+
+/// Returns the per-channel offset added to the colors of RGB and palette targets.
+pub fn color_offset() -> [i16; 3] {
+    COLOR_OFFSET
+        .each_ref()
+        .map(|channel| channel.load(Ordering::Relaxed))
+}
+
+/// Where screen frames come from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum CaptureSource {
+    /// The X11 root window, which only shows X11 and XWayland windows.
+    X11,
+    /// A monitor shared through the xdg-desktop-portal ScreenCast interface, which also works
+    /// on Wayland.
+    Portal,
+}
+
+/// Whether screen frames are taken through the portal during a run.
+static PORTAL_CAPTURE: AtomicBool = AtomicBool::new(false);
+
+/// Sets where screen frames are taken from.
+pub fn set_capture_source(source: CaptureSource) {
+    PORTAL_CAPTURE.store(source == CaptureSource::Portal, Ordering::Relaxed);
+}
+
+/// Delay between polls while the capturer has no new frame ready.
+const FRAME_POLL_INTERVAL: Duration = Duration::from_millis(2);
+
+/// Rectangular screen area in absolute pixel coordinates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct Region {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Region {
+    /// Creates the smallest region containing both corner points.
+    pub fn from_corners(a: Point, b: Point) -> Self {
+        Self {
+            x: a.x.min(b.x),
+            y: a.y.min(b.y),
+            width: a.x.abs_diff(b.x),
+            height: a.y.abs_diff(b.y),
+        }
+    }
+
+    /// Returns whether the point lies inside the region.
+    pub fn contains(&self, point: Point) -> bool {
+        let x = i64::from(point.x) - i64::from(self.x);
+        let y = i64::from(point.y) - i64::from(self.y);
+        (0..i64::from(self.width)).contains(&x) && (0..i64::from(self.height)).contains(&y)
+    }
+
+    /// Returns the area of the region in pixels.
+    pub fn area(&self) -> u64 {
+        u64::from(self.width) * u64::from(self.height)
+    }
+
+    /// Returns the smallest region containing both regions.
+    pub fn union(&self, other: &Region) -> Region {
+        let x = self.x.min(other.x);
+        let y = self.y.min(other.y);
+        let right = (i64::from(self.x) + i64::from(self.width))
+            .max(i64::from(other.x) + i64::from(other.width));
+        let bottom = (i64::from(self.y) + i64::from(self.height))
+            .max(i64::from(other.y) + i64::from(other.height));
+        Region {
+            x,
+            y,
+            width: u32::try_from(right - i64::from(x)).unwrap_or(u32::MAX),
+            height: u32::try_from(bottom - i64::from(y)).unwrap_or(u32::MAX),
+        }
+    }
+
+    /// Returns the region grown by `margin` pixels on every side.
+    pub fn grown(&self, margin: u32) -> Region {
+        let margin_i = i32::try_from(margin).unwrap_or(i32::MAX);
+        Region {
+            x: self.x.saturating_sub(margin_i),
+            y: self.y.saturating_sub(margin_i),
+            width: self.width.saturating_add(margin.saturating_mul(2)),
+            height: self.height.saturating_add(margin.saturating_mul(2)),
+        }
+    }
+}
 
 /// RGB color representation for pixel matching
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct PixelColor {
     r: u8,
     g: u8,
     b: u8,
 }
 
+impl std::str::FromStr for Region {
+    type Err = anyhow::Error;
+
+    /// Parses a region from the `x,y,width,height` shorthand used on the command line.
+    fn from_str(s: &str) -> Result<Self> {
+        let parts: Vec<&str> = s.split(',').map(str::trim).collect();
+        ensure!(
+            parts.len() == 4,
+            "Expected a region as 'x,y,width,height', got '{}'",
+            s
+        );
+
+        Ok(Self {
+            x: parts[0].parse().context("Invalid region x")?,
+            y: parts[1].parse().context("Invalid region y")?,
+            width: parts[2].parse().context("Invalid region width")?,
+            height: parts[3].parse().context("Invalid region height")?,
+        })
+    }
+}
+
+/// HSV color with hue in degrees [0, 360) and saturation/value in [0, 1].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Hsv {
+    pub h: f64,
+    pub s: f64,
+    pub v: f64,
+}
+
+impl std::fmt::Display for Hsv {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "HSV({:.0}, {:.0}%, {:.0}%)",
+            self.h,
+            self.s * 100.0,
+            self.v * 100.0
+        )
+    }
+}
+
 impl PixelColor {
     /// Creates a new PixelColor with the given RGB values.
     pub fn new(r: u8, g: u8, b: u8) -> Self {
         Self { r, g, b }
     }
 
+    /// Creates a PixelColor from RGB values shifted by a per-channel offset, saturating at the
+    /// ends of the channel range.
+    pub fn shifted(rgb: [u8; 3], offset: [i16; 3]) -> Self {
+        let [r, g, b] = [0, 1, 2].map(|i| (i16::from(rgb[i]) + offset[i]).clamp(0, 255) as u8);
+        Self { r, g, b }
+    }
+
+    /// Returns the color's channels as `[r, g, b]`.
+    pub fn rgb(&self) -> [u8; 3] {
+        [self.r, self.g, self.b]
+    }
+
+    /// Converts this color to the HSV color space.
+    pub fn to_hsv(self) -> Hsv {
+        let r = f64::from(self.r) / 255.0;
+        let g = f64::from(self.g) / 255.0;
+        let b = f64::from(self.b) / 255.0;
+        let max = r.max(g).max(b);
+        let min = r.min(g).min(b);
+        let delta = max - min;
+
+        let h = if delta == 0.0 {
+            0.0
+        } else if max == r {
+            60.0 * ((g - b) / delta).rem_euclid(6.0)
+        } else if max == g {
+            60.0 * ((b - r) / delta + 2.0)
+        } else {
+            60.0 * ((r - g) / delta + 4.0)
+        };
+        let s = if max == 0.0 { 0.0 } else { delta / max };
+
+        Hsv { h, s, v: max }
+    }
+
     /// Checks if this color matches the target color within the given tolerance.
     pub fn is_match(&self, target: &PixelColor, tolerance: u8) -> bool {
         (i16::from(self.r) - i16::from(target.r)).abs() <= i16::from(tolerance)
@@ -33,6 +247,180 @@ impl PixelColor {
     }
 }
 
+impl std::fmt::Display for PixelColor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "RGB({}, {}, {})", self.r, self.g, self.b)
+    }
+}
+
+/// Color specification a pixel must satisfy to count as part of the target.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(untagged)]
+pub enum ColorTarget {
+    /// A single RGB color [r, g, b].
+    Rgb { rgb: [u8; 3] },
+    /// Any of several RGB colors, e.g. the frames of an animated outline or its hover state.
+    Palette { colors: Vec<[u8; 3]> },
+    /// Any color whose hue lies in `hue_range` (degrees, wrapping through 0 when the start is
+    /// greater than the end) with at least the given saturation and value in [0, 1].
+    Hsv {
+        hue_range: [f64; 2],
+        #[serde(default)]
+        sat_min: f64,
+        #[serde(default)]
+        val_min: f64,
+    },
+}
+
+impl ColorTarget {
+    /// Default maximum per-channel difference for a pixel to match an RGB target.
+    pub const TOLERANCE: u8 = 3;
+
+    /// Checks if the given pixel color satisfies this target, comparing it to the colors of an
+    /// RGB or palette target with `matcher`.
+    pub fn matches(&self, color: &PixelColor, matcher: &ColorMatcher) -> bool {
+        let tolerance = COLOR_TOLERANCE.load(Ordering::Relaxed);
+        let offset = color_offset();
+        match self {
+            ColorTarget::Rgb { rgb } => {
+                matcher.matches(color, &PixelColor::shifted(*rgb, offset), tolerance)
+            }
+            ColorTarget::Palette { colors } => colors
+                .iter()
+                .any(|rgb| matcher.matches(color, &PixelColor::shifted(*rgb, offset), tolerance)),
+            ColorTarget::Hsv {
+                hue_range,
+                sat_min,
+                val_min,
+            } => {
+                let hsv = color.to_hsv();
+                let [start, end] = *hue_range;
+                let hue_ok = if start <= end {
+                    hsv.h >= start && hsv.h <= end
+                } else {
+                    hsv.h >= start || hsv.h <= end
+                };
+                hue_ok && hsv.s >= *sat_min && hsv.v >= *val_min
+            }
+        }
+    }
+
+    /// Returns the exact colors of an RGB or palette target, none for an HSV range.
+    pub fn rgb_colors(&self) -> &[[u8; 3]] {
+        match self {
+            ColorTarget::Rgb { rgb } => std::slice::from_ref(rgb),
+            ColorTarget::Palette { colors } => colors,
+            ColorTarget::Hsv { .. } => &[],
+        }
+    }
+
+    /// Checks that the target's parameters are within their valid ranges.
+    pub fn validate(&self) -> Result<()> {
+        match self {
+            ColorTarget::Rgb { .. } => {}
+            ColorTarget::Palette { colors } => {
+                ensure!(!colors.is_empty(), "Color palette must not be empty");
+            }
+            ColorTarget::Hsv {
+                hue_range,
+                sat_min,
+                val_min,
+            } => {
+                ensure!(
+                    hue_range.iter().all(|h| (0.0..=360.0).contains(h)),
+                    "Hue range {:?} must lie within [0, 360]",
+                    hue_range
+                );
+                ensure!(
+                    (0.0..=1.0).contains(sat_min),
+                    "sat_min {} must lie within [0, 1]",
+                    sat_min
+                );
+                ensure!(
+                    (0.0..=1.0).contains(val_min),
+                    "val_min {} must lie within [0, 1]",
+                    val_min
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Builds the tightest HSV range target that matches all of the given colors.
+    ///
+    /// The hue range is the shortest arc covering every hue, so sets of reds that straddle 0°
+    /// produce a wrapping range instead of one spanning the whole color wheel.
+    pub fn hsv_covering(colors: &[PixelColor]) -> Option<Self> {
+        let mut hues: Vec<f64> = colors.iter().map(|c| c.to_hsv().h).collect();
+        hues.sort_by(f64::total_cmp);
+        let (first, last) = (*hues.first()?, *hues.last()?);
+
+        // The arc to leave out is the largest gap between neighboring hues, including the gap
+        // that wraps from the last hue back around to the first
+        let mut hue_range = [first, last];
+        let mut largest_gap = first + 360.0 - last;
+        for pair in hues.windows(2) {
+            let gap = pair[1] - pair[0];
+            if gap > largest_gap {
+                largest_gap = gap;
+                hue_range = [pair[1], pair[0]];
+            }
+        }
+
+        let sat_min = colors.iter().map(|c| c.to_hsv().s).fold(1.0, f64::min);
+        let val_min = colors.iter().map(|c| c.to_hsv().v).fold(1.0, f64::min);
+
+        Some(ColorTarget::Hsv {
+            hue_range,
+            sat_min,
+            val_min,
+        })
+    }
+}
+
+impl std::fmt::Display for ColorTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ColorTarget::Rgb { rgb } => write!(f, "RGB({},{},{})", rgb[0], rgb[1], rgb[2]),
+            ColorTarget::Palette { colors } => write!(f, "palette of {} colors", colors.len()),
+            ColorTarget::Hsv {
+                hue_range,
+                sat_min,
+                val_min,
+            } => write!(
+                f,
+                "HSV hue {:.0}-{:.0}, sat >= {:.2}, val >= {:.2}",
+                hue_range[0], hue_range[1], sat_min, val_min
+            ),
+        }
+    }
+}
+
+/// Reads the color of a single screen pixel without capturing the whole frame.
+pub fn color_at(point: Point) -> Result<PixelColor> {
+    let (conn, screen_num) = x11rb::connect(None).context("Failed to connect to the X server")?;
+    let root = conn.setup().roots[screen_num].root;
+    let image = conn
+        .get_image(
+            ImageFormat::Z_PIXMAP,
+            root,
+            i16::try_from(point.x)?,
+            i16::try_from(point.y)?,
+            1,
+            1,
+            u32::MAX,
+        )?
+        .reply()
+        .context(format!("Failed to read the pixel at {}", point))?;
+
+    // 24/32-bit TrueColor visuals store pixels as BGRX
+    ensure!(
+        image.data.len() >= 3,
+        "Unexpected pixel format returned by the X server"
+    );
+    Ok(PixelColor::new(image.data[2], image.data[1], image.data[0]))
+}
+
 /// Calculates minimum distance from a point to any edge of the polygon.
 fn min_distance_to_edges(point: &Point, polygon: &[Point]) -> f64 {
     let n = polygon.len();
@@ -161,56 +549,869 @@ fn distance_squared(a: &Point, b: &Point) -> i32 {
     dx * dx + dy * dy
 }
 
-/// Captures screen and returns all pixels matching the target color within tolerance.
-fn get_pixels_with_target_color(target_color: &PixelColor) -> Result<Vec<Point>> {
-    // Get the primary display
-    let display = Display::primary()?;
-    let width = display.width();
-    let mut capturer = Capturer::new(display).context("Failed to create Capturer object")?;
-    let mut matches = Vec::new();
-    const TOLERANCE: u8 = 3;
-
-    loop {
-        // Try to capture a frame
-        if let Ok(frame) = capturer.frame() {
-            // Iterate over the pixels
-            for (i, bgra) in frame.chunks(4).enumerate() {
-                let curr_color = PixelColor::new(bgra[2], bgra[1], bgra[0]);
-
-                if curr_color.is_match(target_color, TOLERANCE) {
-                    // Calculate pixel coordinates
-                    let x = i % width;
-                    let y = i / width;
-                    matches.push(Point::new(i32::try_from(x)?, i32::try_from(y)?));
+/// How many matching pixels a scan collects before it stops.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ResultMode {
+    /// Collect every matching pixel.
+    #[default]
+    All,
+    /// Stop at the first matching pixel.
+    FirstMatch,
+    /// Stop after collecting the given number of matching pixels.
+    FirstN(usize),
+    /// Count every matching pixel without collecting their positions.
+    CountOnly,
+}
+
+/// Which blob of matching pixels is clicked when the target matches several separate objects.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum BlobSelection {
+    /// The blob with the most matching pixels.
+    #[default]
+    Largest,
+    /// The blob whose center is closest to the cursor.
+    Nearest,
+    /// A random blob.
+    Random,
+}
+
+impl BlobSelection {
+    /// Blobs smaller than this fraction of the largest one are only tried after the others by
+    /// the nearest and random policies, so stray matching pixels aren't picked over the object.
+    const MIN_SHARE: f64 = 0.1;
+
+    /// Orders blobs sorted largest first by this policy, the preferred one first.
+    fn order(self, blobs: &mut [Blob], cursor: impl FnOnce() -> Point) {
+        let Some(largest) = blobs.first().map(|blob| blob.pixels) else {
+            return;
+        };
+        let significant = blobs
+            .iter()
+            .take_while(|blob| blob.pixels as f64 >= largest as f64 * Self::MIN_SHARE)
+            .count();
+        let candidates = &mut blobs[..significant];
+        match self {
+            BlobSelection::Largest => {}
+            BlobSelection::Nearest => {
+                let cursor = cursor();
+                candidates.sort_by_key(|blob| distance_squared(&centroid(&blob.points), &cursor));
+            }
+            BlobSelection::Random => candidates.shuffle(&mut rand::rng()),
+        }
+    }
+}
+
+/// Returns the mean position of the points.
+fn centroid(points: &[Point]) -> Point {
+    let n = points.len().max(1) as i64;
+    let (x, y) = points.iter().fold((0_i64, 0_i64), |(x, y), p| {
+        (x + i64::from(p.x), y + i64::from(p.y))
+    });
+    Point::new((x / n) as i32, (y / n) as i32)
+}
+
+/// Group of neighboring matching pixels, usually one object on screen.
+#[derive(Debug, Clone)]
+pub struct Blob {
+    /// Matching pixels of the blob, a sample of them when the scan's matches were sampled.
+    pub points: Vec<Point>,
+    /// Smallest screen rectangle containing the blob.
+    pub bounds: Region,
+    /// Number of matching pixels of the blob, estimated from the sample when the scan's matches
+    /// were sampled.
+    pub pixels: usize,
+}
+
+impl Blob {
+    /// Creates a blob from its points, each of which stands for `weight` matching pixels.
+    fn new(points: Vec<Point>, weight: f64) -> Self {
+        let min_x = points.iter().map(|p| p.x).min().unwrap_or_default();
+        let max_x = points.iter().map(|p| p.x).max().unwrap_or_default();
+        let min_y = points.iter().map(|p| p.y).min().unwrap_or_default();
+        let max_y = points.iter().map(|p| p.y).max().unwrap_or_default();
+        Self {
+            bounds: Region {
+                x: min_x,
+                y: min_y,
+                width: min_x.abs_diff(max_x) + 1,
+                height: min_y.abs_diff(max_y) + 1,
+            },
+            pixels: (points.len() as f64 * weight).round() as usize,
+            points,
+        }
+    }
+}
+
+/// Size limits a blob must meet to be clicked, limits that aren't set don't apply.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct BlobLimits {
+    /// Narrowest bounding box width in pixels.
+    #[serde(default)]
+    pub min_width: Option<u32>,
+    /// Widest bounding box width in pixels.
+    #[serde(default)]
+    pub max_width: Option<u32>,
+    /// Lowest bounding box height in pixels.
+    #[serde(default)]
+    pub min_height: Option<u32>,
+    /// Highest bounding box height in pixels.
+    #[serde(default)]
+    pub max_height: Option<u32>,
+    /// Fewest matching pixels.
+    #[serde(default)]
+    pub min_pixels: Option<u32>,
+    /// Most matching pixels.
+    #[serde(default)]
+    pub max_pixels: Option<u32>,
+}
+
+impl BlobLimits {
+    /// Returns whether the blob is within every limit.
+    pub fn allows(&self, blob: &Blob) -> bool {
+        let within = |value: u64, min: Option<u32>, max: Option<u32>| {
+            min.is_none_or(|min| value >= u64::from(min))
+                && max.is_none_or(|max| value <= u64::from(max))
+        };
+        within(u64::from(blob.bounds.width), self.min_width, self.max_width)
+            && within(
+                u64::from(blob.bounds.height),
+                self.min_height,
+                self.max_height,
+            )
+            && within(blob.pixels as u64, self.min_pixels, self.max_pixels)
+    }
+
+    /// Checks that no minimum is above its maximum.
+    pub fn validate(&self) -> Result<()> {
+        let check = |name: &str, min: Option<u32>, max: Option<u32>| -> Result<()> {
+            if let (Some(min), Some(max)) = (min, max) {
+                ensure!(
+                    min <= max,
+                    "min_{} {} is above max_{} {}",
+                    name,
+                    min,
+                    name,
+                    max
+                );
+            }
+            Ok(())
+        };
+        check("width", self.min_width, self.max_width)?;
+        check("height", self.min_height, self.max_height)?;
+        check("pixels", self.min_pixels, self.max_pixels)
+    }
+}
+
+/// Options controlling how a captured frame is scanned for matching pixels.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ScanOptions {
+    /// Check only every Nth pixel along each axis, then refine around the hits at full
+    /// resolution. Trades a little precision on tiny targets for a large speedup on big frames.
+    #[serde(default = "default_scan_stride")]
+    #[schemars(range(min = 1))]
+    pub scan_stride: u32,
+
+    /// Restrict the scan to this screen region instead of the whole frame.
+    #[serde(default)]
+    pub search_region: Option<Region>,
+
+    /// How many matches to collect before the scan stops.
+    #[serde(default)]
+    pub result_mode: ResultMode,
+
+    /// Which blob is clicked when the matches form several separate objects.
+    #[serde(default)]
+    pub blob: BlobSelection,
+
+    /// Size limits of the blobs that may be clicked.
+    #[serde(flatten)]
+    pub blob_limits: BlobLimits,
+
+    /// Colors whose pixels veto matches next to them, such as the highlight of a depleted
+    /// resource sharing the target's hue. Compared like the colors of an RGB target.
+    #[serde(default)]
+    pub exclude_colors: Vec<[u8; 3]>,
+
+    /// Distance in pixels around a pixel of an excluded color within which matches are dropped.
+    #[serde(default = "default_exclude_radius")]
+    pub exclude_radius: u32,
+
+    /// How pixels are compared to the colors of an RGB or palette target.
+    #[serde(default)]
+    pub match_mode: ColorMatcher,
+
+    /// Most matching pixels kept in memory. Targets matching more than this, such as sky or
+    /// water, keep one pixel per cell of a grid instead, coarsened until they fit.
+    #[serde(default = "default_max_points")]
+    #[schemars(range(min = 1))]
+    pub max_points: usize,
+
+    /// Largest share of the scanned pixels in percent the target may match before it's treated
+    /// as a background color and not clicked.
+    #[serde(default = "default_max_match_percent")]
+    #[schemars(range(min = 0, max = 100))]
+    pub max_match_percent: f64,
+}
+
+fn default_scan_stride() -> u32 {
+    1
+}
+
+fn default_max_points() -> usize {
+    250_000
+}
+
+fn default_max_match_percent() -> f64 {
+    25.0
+}
+
+fn default_exclude_radius() -> u32 {
+    3
+}
+
+impl Default for ScanOptions {
+    fn default() -> Self {
+        Self {
+            scan_stride: default_scan_stride(),
+            search_region: None,
+            result_mode: ResultMode::default(),
+            blob: BlobSelection::default(),
+            blob_limits: BlobLimits::default(),
+            exclude_colors: Vec::new(),
+            exclude_radius: default_exclude_radius(),
+            match_mode: ColorMatcher::default(),
+            max_points: default_max_points(),
+            max_match_percent: default_max_match_percent(),
+        }
+    }
+}
+
+impl ScanOptions {
+    /// Checks that the scan parameters are usable.
+    pub fn validate(&self) -> Result<()> {
+        ensure!(self.scan_stride >= 1, "scan_stride must be at least 1");
+        ensure!(self.max_points >= 1, "max_points must be at least 1");
+        ensure!(
+            self.max_match_percent > 0.0 && self.max_match_percent <= 100.0,
+            "max_match_percent must be above 0 and at most 100"
+        );
+        self.match_mode.validate()?;
+        self.blob_limits.validate()?;
+        if let Some(region) = &self.search_region {
+            ensure!(
+                region.width > 0 && region.height > 0,
+                "search_region must not be empty"
+            );
+        }
+        if let ResultMode::FirstN(n) = self.result_mode {
+            ensure!(n > 0, "first_n must collect at least one match");
+        }
+        Ok(())
+    }
+}
+
+/// Matching pixels found by a scan.
+#[derive(Debug, Default)]
+pub struct ScanResult {
+    /// Positions of the collected matches in absolute screen coordinates.
+    ///
+    /// Empty when scanning with [`ResultMode::CountOnly`], and the first match in every cell of
+    /// a grid when there were more than [`ScanOptions::max_points`] of them.
+    pub points: Vec<Point>,
+    /// Number of matching pixels found before the scan stopped.
+    pub count: usize,
+    /// Number of pixels in the scanned frame.
+    pub pixels: usize,
+    /// Width of the grid cells matches are kept in, 0 or 1 while every match is kept.
+    cell_size: u32,
+    /// Cells holding a kept match, only tracked once the cells are larger than a pixel.
+    cells: HashSet<(i32, i32)>,
+}
+
+impl ScanResult {
+    /// Records a match and returns whether the scan should stop.
+    fn record(&mut self, point: Point, options: &ScanOptions) -> bool {
+        self.count += 1;
+        match options.result_mode {
+            ResultMode::CountOnly => false,
+            ResultMode::All => {
+                self.keep(point, options.max_points);
+                false
+            }
+            ResultMode::FirstMatch => {
+                self.points.push(point);
+                true
+            }
+            ResultMode::FirstN(n) => {
+                self.keep(point, options.max_points);
+                self.count >= n
+            }
+        }
+    }
+
+    /// Keeps the match unless its grid cell already holds one, doubling the cell size whenever
+    /// more than `max_points` are kept.
+    ///
+    /// Unlike a random sample, every cell touched by a match keeps one, so thin shapes and small
+    /// objects survive and kept neighbors are never more than two cells apart.
+    fn keep(&mut self, point: Point, max_points: usize) {
+        let size = self.cell_size.max(1);
+        if size > 1 && !self.cells.insert(Self::cell(point, size)) {
+            return;
+        }
+        self.points.push(point);
+        while self.points.len() > max_points {
+            self.cell_size = self.cell_size.max(1) * 2;
+            let size = self.cell_size;
+            self.cells.clear();
+            let cells = &mut self.cells;
+            self.points.retain(|p| cells.insert(Self::cell(*p, size)));
+        }
+    }
+
+    /// Returns the grid cell of `point` for cells `size` pixels wide.
+    fn cell(point: Point, size: u32) -> (i32, i32) {
+        let size = i32::try_from(size).unwrap_or(i32::MAX);
+        (point.x.div_euclid(size), point.y.div_euclid(size))
+    }
+
+    /// Width in pixels of the grid cells kept matches were thinned to, 1 unless there were more
+    /// matches than could be kept.
+    fn sample_spacing(&self) -> u32 {
+        self.cell_size.max(1)
+    }
+
+    /// Share of the scanned pixels in percent that matched.
+    fn match_percent(&self) -> f64 {
+        self.count as f64 * 100.0 / self.pixels.max(1) as f64
+    }
+}
+
+/// Scans a frame whose top-left pixel sits at `origin` on screen for pixels matching the target.
+///
+/// The frame is walked row by row. With a stride above 1 only a coarse grid is checked first.
+/// Every grid hit is then refined by checking all pixels within one stride of it, so the matched
+/// shape keeps its full-resolution outline even though most of the empty frame was skipped.
+fn scan_frame(
+    frame: &RgbaImage,
+    origin: Point,
+    target: &ColorTarget,
+    options: &ScanOptions,
+) -> Result<ScanResult> {
+    let _phase = profile::phase(Phase::Scan);
+    let (width, height) = frame.dimensions();
+    let stride = options.scan_stride.max(1);
+    let excluded = exclusion_mask(frame, options);
+    let indicator = indicator::region().map(|region| {
+        let corner = display::from_screen(Point::new(region.x, region.y));
+        Region {
+            x: corner.x,
+            y: corner.y,
+            ..region
+        }
+    });
+    let is_match = |x: u32, y: u32| {
+        let covered = indicator.is_some_and(|region| {
+            region.contains(Point::new(
+                origin.x.saturating_add_unsigned(x),
+                origin.y.saturating_add_unsigned(y),
+            ))
+        });
+        if covered {
+            return false;
+        }
+        let pixel = frame.get_pixel(x, y);
+        target.matches(
+            &PixelColor::new(pixel[0], pixel[1], pixel[2]),
+            &options.match_mode,
+        ) && !excluded
+            .as_ref()
+            .is_some_and(|mask| mask[y as usize * width as usize + x as usize])
+    };
+    let to_screen = |x: u32, y: u32| -> Result<Point> {
+        Ok(Point::new(
+            origin.x + i32::try_from(x)?,
+            origin.y + i32::try_from(y)?,
+        ))
+    };
+
+    let mut result = ScanResult {
+        pixels: width as usize * height as usize,
+        ..Default::default()
+    };
+    if stride == 1 {
+        for y in 0..height {
+            for x in 0..width {
+                if is_match(x, y) && result.record(to_screen(x, y)?, options) {
+                    return Ok(result);
                 }
             }
-            break; // Exit after one frame
         }
+        return Ok(result);
     }
-    Ok(matches)
+
+    let mut visited = vec![false; width as usize * height as usize];
+    for gy in (0..height).step_by(stride as usize) {
+        for gx in (0..width).step_by(stride as usize) {
+            if !is_match(gx, gy) {
+                continue;
+            }
+
+            // Refine the neighborhood around the coarse hit at full resolution
+            for y in gy.saturating_sub(stride - 1)..(gy + stride).min(height) {
+                for x in gx.saturating_sub(stride - 1)..(gx + stride).min(width) {
+                    let idx = y as usize * width as usize + x as usize;
+                    if visited[idx] {
+                        continue;
+                    }
+                    visited[idx] = true;
+                    if is_match(x, y) && result.record(to_screen(x, y)?, options) {
+                        return Ok(result);
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(result)
 }
 
-/// Finds a point inside the shape formed by pixels matching the target color, biased away from edges.
-pub fn find_point_in_shape(target_color: &PixelColor) -> Result<Point> {
-    let boundary_points = get_pixels_with_target_color(target_color)?;
+/// Marks the pixels of the frame within the exclude radius of a pixel of an excluded color, row
+/// by row. Returns `None` without excluded colors.
+fn exclusion_mask(frame: &RgbaImage, options: &ScanOptions) -> Option<Vec<bool>> {
+    if options.exclude_colors.is_empty() {
+        return None;
+    }
+    let excluded = ColorTarget::Palette {
+        colors: options.exclude_colors.clone(),
+    };
+    let (width, height) = (frame.width() as usize, frame.height() as usize);
+    let mask: Vec<bool> = frame
+        .pixels()
+        .map(|pixel| {
+            excluded.matches(
+                &PixelColor::new(pixel[0], pixel[1], pixel[2]),
+                &options.match_mode,
+            )
+        })
+        .collect();
 
+    // Grow the excluded pixels into squares, along the rows first and then along the columns
+    let radius = options.exclude_radius as usize;
+    let grow = |mask: &[bool], len: usize, lines: usize, index: &dyn Fn(usize, usize) -> usize| {
+        let mut grown = vec![false; mask.len()];
+        for line in 0..lines {
+            // Position of the last excluded pixel seen and the next one ahead along the line
+            let mut last: Option<usize> = None;
+            let mut next = (0..len).find(|&i| mask[index(line, i)]);
+            for i in 0..len {
+                if next.is_some_and(|next| next < i) {
+                    last = next;
+                    next = (i..len).find(|&j| mask[index(line, j)]);
+                }
+                grown[index(line, i)] = last.is_some_and(|last| i - last <= radius)
+                    || next.is_some_and(|next| next - i <= radius);
+            }
+        }
+        grown
+    };
+    let rows = grow(&mask, width, height, &|y, x| y * width + x);
+    Some(grow(&rows, height, width, &|x, y| y * width + x))
+}
+
+/// Crops the frame to the search region, returning the cropped frame and its screen origin.
+///
+/// The region is clipped to the frame bounds. Without a region the frame is returned whole.
+pub fn crop_to_region(frame: RgbaImage, region: Option<&Region>) -> (RgbaImage, Point) {
+    let Some(region) = region else {
+        return (frame, Point::new(0, 0));
+    };
+
+    let (width, height) = frame.dimensions();
+    let x0 = u32::try_from(region.x.max(0)).unwrap_or(0).min(width);
+    let y0 = u32::try_from(region.y.max(0)).unwrap_or(0).min(height);
+    let x1 = (i64::from(region.x) + i64::from(region.width)).clamp(0, i64::from(width)) as u32;
+    let y1 = (i64::from(region.y) + i64::from(region.height)).clamp(0, i64::from(height)) as u32;
+
+    let cropped =
+        image::imageops::crop_imm(&frame, x0, y0, x1.saturating_sub(x0), y1.saturating_sub(y0))
+            .to_image();
+    (cropped, Point::new(x0 as i32, y0 as i32))
+}
+
+/// Algorithm of a perceptual hash.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema, ValueEnum,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum HashAlgorithm {
+    /// Average hash, robust against small shifts and blur.
+    Average,
+    /// Difference hash, more sensitive to changes in the structure of the screen.
+    #[default]
+    Difference,
+}
+
+/// Computes the 64-bit average hash of a frame.
+///
+/// The frame is shrunk to 8x8 grayscale pixels and every bit records whether a pixel is brighter
+/// than the mean of all of them.
+pub fn average_hash(frame: &RgbaImage) -> u64 {
+    if frame.width() == 0 || frame.height() == 0 {
+        return 0;
+    }
+    let gray = image::imageops::grayscale(frame);
+    let small = image::imageops::resize(&gray, 8, 8, image::imageops::FilterType::Triangle);
+    let mean = small.pixels().map(|pixel| u32::from(pixel[0])).sum::<u32>() / 64;
+    small.pixels().fold(0, |hash, pixel| {
+        (hash << 1) | u64::from(u32::from(pixel[0]) > mean)
+    })
+}
+
+/// Computes the 64-bit difference hash of a frame.
+///
+/// The frame is shrunk to 9x8 grayscale pixels and every bit records whether a pixel is brighter
+/// than its right neighbor. Small changes such as compression noise or a moving cursor leave the
+/// hash almost unchanged, while a different screen changes many bits.
+pub fn difference_hash(frame: &RgbaImage) -> u64 {
+    if frame.width() == 0 || frame.height() == 0 {
+        return 0;
+    }
+    let gray = image::imageops::grayscale(frame);
+    let small = image::imageops::resize(&gray, 9, 8, image::imageops::FilterType::Triangle);
+    let mut hash = 0;
+    for y in 0..8 {
+        for x in 0..8 {
+            let brighter = small.get_pixel(x, y)[0] > small.get_pixel(x + 1, y)[0];
+            hash = (hash << 1) | u64::from(brighter);
+        }
+    }
+    hash
+}
+
+/// Returns the number of bits in which two hashes differ.
+pub fn hash_distance(a: u64, b: u64) -> u32 {
+    (a ^ b).count_ones()
+}
+
+/// Computes the perceptual hash of a frame with the given algorithm.
+pub fn perceptual_hash(frame: &RgbaImage, algorithm: HashAlgorithm) -> u64 {
+    match algorithm {
+        HashAlgorithm::Average => average_hash(frame),
+        HashAlgorithm::Difference => difference_hash(frame),
+    }
+}
+
+/// Captures the screen and computes the perceptual hash of a region of it.
+pub fn region_hash(region: &Region, algorithm: HashAlgorithm) -> Result<u64> {
+    let frame = capture_screen_rgba()?;
+    fixtures::record(&frame, Some(region));
+    let (frame, _) = crop_to_region(frame, Some(region));
     ensure!(
-        !boundary_points.is_empty(),
-        "No pixels found matching the target color"
+        frame.width() > 0 && frame.height() > 0,
+        "Region {:?} lies outside the screen",
+        region
     );
+    Ok(perceptual_hash(&frame, algorithm))
+}
+
+/// Formats a hash as the 16 hex digits scripts refer to it by.
+pub fn format_hash(hash: u64) -> String {
+    format!("{:016x}", hash)
+}
+
+/// Parses a hash written as up to 16 hex digits.
+pub fn parse_hash(hash: &str) -> Result<u64> {
+    u64::from_str_radix(hash, 16)
+        .context(format!("Invalid hash '{}', expected 16 hex digits", hash))
+}
+
+/// Point picked on a located target along with the target's size on screen.
+#[derive(Debug, Clone, Copy)]
+pub struct TargetHit {
+    /// Point to click.
+    pub point: Point,
+    /// Area in pixels of the target's bounding box.
+    pub area: u64,
+}
+
+/// Captures the screen and scans it for pixels matching the target.
+///
+/// Only the search region is filtered and scanned when one is configured.
+pub fn scan_screen(
+    target: &ColorTarget,
+    filters: &[Filter],
+    options: &ScanOptions,
+) -> Result<ScanResult, ColorbotError> {
+    Ok(capture_and_scan(target, filters, options)?.0)
+}
+
+/// Captures and scans the screen, returning the matches along with the scanned frame.
+fn capture_and_scan(
+    target: &ColorTarget,
+    filters: &[Filter],
+    options: &ScanOptions,
+) -> Result<(ScanResult, RgbaImage)> {
+    let frame = capture_screen_rgba()?;
+    fixtures::record(&frame, options.search_region.as_ref());
+    let (mut frame, origin) = crop_to_region(frame, options.search_region.as_ref());
+    filter::apply_all(&mut frame, filters);
+
+    let result = scan_frame(&frame, origin, target, options)?;
+    decisions::record(DecisionKind::MatchesFound {
+        matches: result.count,
+    });
+    Ok((result, frame))
+}
+
+/// Scans the screen for a target to click, refusing targets that match too much of it.
+///
+/// A target matching more than [`ScanOptions::max_match_percent`] of the scanned pixels is most
+/// likely a background color such as sky or water, and clicking it would hit nothing useful. The
+/// scanned frame is saved to the run directory and a warning points at it.
+fn scan_for_target(
+    target: &ColorTarget,
+    filters: &[Filter],
+    options: &ScanOptions,
+) -> Result<ScanResult> {
+    let (result, frame) = capture_and_scan(target, filters, options)?;
+    let percent = result.match_percent();
+    if options.result_mode == ResultMode::All && percent > options.max_match_percent {
+        let event = decisions::current_event();
+        let screenshot = match diagnostics::save(&format!("too-common-{}", event), &frame) {
+            Ok(Some(path)) => format!(", the scanned area is saved as {}", path.display()),
+            Ok(None) => String::new(),
+            Err(e) => format!(", failed to save the scanned area: {:#}", e),
+        };
+        warn!(
+            "Event '{}': target {} matches {:.0}% of the scanned pixels, more than \
+             max_match_percent ({}%). It's most likely a background color and isn't clicked. \
+             Pick a color unique to the object or restrict the search_region{}",
+            event, target, percent, options.max_match_percent, screenshot
+        );
+        bail!(ColorbotError::TargetTooCommon(percent));
+    }
+    Ok(result)
+}
+
+/// Finds a point inside the shape formed by pixels matching the target color, biased away from edges.
+///
+/// Matches are grouped into blobs of connected pixels first and the point is picked inside the
+/// blob chosen by the scan's [`BlobSelection`] among those within its [`BlobLimits`], so separate
+/// objects of the same color don't produce a click between them. With [`ResultMode::FirstMatch`]
+/// the first matching pixel is returned as is, which suits targets only a pixel or two wide.
+/// `filters` are applied to the captured frame before matching.
+pub fn find_point_in_shape(
+    target: &ColorTarget,
+    filters: &[Filter],
+    options: &ScanOptions,
+) -> Result<TargetHit, ColorbotError> {
+    let (result, blobs) = scan_for_blobs(target, filters, options)?;
+
+    if result.points.is_empty() {
+        return Err(ColorbotError::ColorNotFound);
+    }
+    if options.result_mode == ResultMode::FirstMatch {
+        return Ok(TargetHit {
+            point: result.points[0],
+            area: 1,
+        });
+    }
+    if result.points.len() == 1 {
+        return Err(anyhow!(
+            "Only one pixel found matching the target color, cannot determine shape"
+        )
+        .into());
+    }
+
+    match blobs.first() {
+        Some(blob) => Ok(pick_in_cluster(&blob.points)?),
+        None => Err(ColorbotError::ColorNotFound),
+    }
+}
+
+/// Scans for a target to click and groups the matches into the blobs that may be clicked.
+///
+/// Without a search region of its own, the current event scans the region inferred from its
+/// earlier matches when there is one, and the whole screen if the target isn't found there.
+fn scan_for_blobs(
+    target: &ColorTarget,
+    filters: &[Filter],
+    options: &ScanOptions,
+) -> Result<(ScanResult, Vec<Blob>)> {
+    let found = |result: &ScanResult, blobs: &[Blob]| {
+        !blobs.is_empty()
+            || (options.result_mode == ResultMode::FirstMatch && !result.points.is_empty())
+    };
+    let event = decisions::current_event();
+    let inferred = match options.search_region {
+        Some(_) => None,
+        None => autoregion::region(&event),
+    };
+    if let Some(region) = inferred {
+        let narrowed = ScanOptions {
+            search_region: Some(region),
+            ..options.clone()
+        };
+        let (result, _) = capture_and_scan(target, filters, &narrowed)?;
+        let blobs = order_blobs(&result, &narrowed);
+        if found(&result, &blobs) {
+            if let Some(bounds) = blobs_bounds(&blobs) {
+                autoregion::record(&event, bounds, None);
+            }
+            return Ok((result, blobs));
+        }
+        debug!(
+            "Target of event '{}' isn't in its inferred region {:?}, scanning the whole screen",
+            event, region
+        );
+    }
+
+    let result = scan_for_target(target, filters, options)?;
+    let blobs = order_blobs(&result, options);
+    if options.search_region.is_none() {
+        if let Some(bounds) = blobs_bounds(&blobs) {
+            autoregion::record(&event, bounds, Some(result.pixels as u64));
+        }
+    }
+    Ok((result, blobs))
+}
+
+/// Returns the smallest region containing every blob.
+fn blobs_bounds(blobs: &[Blob]) -> Option<Region> {
+    blobs
+        .iter()
+        .map(|blob| blob.bounds)
+        .reduce(|a, b| a.union(&b))
+}
+
+/// Groups matching pixels into the blobs within the scan's size limits, ordered by its blob
+/// selection.
+fn order_blobs(result: &ScanResult, options: &ScanOptions) -> Vec<Blob> {
+    const CLUSTER_GAP_PX: u32 = 4;
+
+    // Strided scans leave gaps between points, and kept matches of neighboring cells may be up
+    // to two cells apart
+    let gap = (CLUSTER_GAP_PX * options.scan_stride).max(2 * result.sample_spacing());
+    let weight = result.count as f64 / result.points.len().max(1) as f64;
+    let mut blobs: Vec<Blob> = cluster_points(&result.points, gap)
+        .into_iter()
+        .map(|cluster| Blob::new(cluster, weight))
+        .filter(|blob| options.blob_limits.allows(blob))
+        .collect();
+    if blobs.is_empty() {
+        debug!("No blob of matching pixels is within the size limits");
+    }
+    options.blob.order(&mut blobs, controls::get_mouse_pos);
+    blobs
+}
+
+/// Groups matching pixels into clusters of pixels at most `max_gap` apart along each axis.
+///
+/// Clusters are returned largest first.
+fn cluster_points(points: &[Point], max_gap: u32) -> Vec<Vec<Point>> {
+    let _phase = profile::phase(Phase::Cluster);
+    let gap = i32::try_from(max_gap.max(1)).unwrap_or(i32::MAX);
+
+    // Bucket points into gap-sized cells so neighbors are only searched in adjacent cells
+    let mut cells: HashMap<(i32, i32), Vec<usize>> = HashMap::new();
+    for (i, p) in points.iter().enumerate() {
+        cells
+            .entry((p.x.div_euclid(gap), p.y.div_euclid(gap)))
+            .or_default()
+            .push(i);
+    }
+
+    let mut visited = vec![false; points.len()];
+    let mut clusters = Vec::new();
+    for start in 0..points.len() {
+        if visited[start] {
+            continue;
+        }
+        visited[start] = true;
+
+        let mut cluster = Vec::new();
+        let mut pending = vec![start];
+        while let Some(i) = pending.pop() {
+            let p = points[i];
+            cluster.push(p);
+            let (cx, cy) = (p.x.div_euclid(gap), p.y.div_euclid(gap));
+            for nx in cx - 1..=cx + 1 {
+                for ny in cy - 1..=cy + 1 {
+                    for &j in cells.get(&(nx, ny)).into_iter().flatten() {
+                        let q = points[j];
+                        if !visited[j] && (p.x - q.x).abs() <= gap && (p.y - q.y).abs() <= gap {
+                            visited[j] = true;
+                            pending.push(j);
+                        }
+                    }
+                }
+            }
+        }
+        clusters.push(cluster);
+    }
+
+    clusters.sort_by_key(|cluster| std::cmp::Reverse(cluster.len()));
+    clusters
+}
+
+/// Captures the screen and returns the blobs of pixels matching the target within the scan's
+/// size limits, preferred first by the scan's blob selection.
+pub fn find_blobs(
+    target: &ColorTarget,
+    filters: &[Filter],
+    options: &ScanOptions,
+) -> Result<Vec<Blob>, ColorbotError> {
+    let (_, blobs) = scan_for_blobs(target, filters, options)?;
+    if blobs.is_empty() {
+        return Err(ColorbotError::ColorNotFound);
+    }
+
+    Ok(blobs)
+}
+
+/// Picks a point inside a single cluster, biased away from its edges.
+///
+/// Clusters without an interior, such as lines and single pixels, yield a random member.
+pub fn pick_in_cluster(cluster: &[Point]) -> Result<TargetHit> {
+    let _phase = profile::phase(Phase::Plan);
     ensure!(
-        boundary_points.len() > 1,
-        "Only one pixel found matching the target color, cannot determine shape"
+        !cluster.is_empty(),
+        "Cannot pick a point in an empty cluster"
     );
+    if convex_hull(cluster).len() >= 3 {
+        return point_in_shape(cluster);
+    }
 
+    Ok(TargetHit {
+        point: cluster[rand::random_range(0..cluster.len())],
+        area: bounding_box_area(cluster),
+    })
+}
+
+/// Returns the area of the points' bounding box in pixels.
+fn bounding_box_area(points: &[Point]) -> u64 {
+    let min_x = points.iter().map(|p| p.x).min().unwrap_or_default();
+    let max_x = points.iter().map(|p| p.x).max().unwrap_or_default();
+    let min_y = points.iter().map(|p| p.y).min().unwrap_or_default();
+    let max_y = points.iter().map(|p| p.y).max().unwrap_or_default();
+    u64::from(min_x.abs_diff(max_x) + 1) * u64::from(min_y.abs_diff(max_y) + 1)
+}
+
+/// Picks a random point inside the convex hull of the points, biased away from its edges.
+fn point_in_shape(boundary_points: &[Point]) -> Result<TargetHit> {
+    let _phase = profile::phase(Phase::Plan);
     // Find bounding box
     let min_x = boundary_points.iter().map(|p| p.x).min().unwrap();
     let max_x = boundary_points.iter().map(|p| p.x).max().unwrap();
     let min_y = boundary_points.iter().map(|p| p.y).min().unwrap();
     let max_y = boundary_points.iter().map(|p| p.y).max().unwrap();
+    let area = bounding_box_area(boundary_points);
 
     // Create convex hull from boundary points to form a proper polygon
-    let polygon = convex_hull(&boundary_points);
+    let polygon = convex_hull(boundary_points);
 
     ensure!(
         polygon.len() >= 3,
@@ -235,7 +1436,10 @@ pub fn find_point_in_shape(target_color: &PixelColor) -> Result<Point> {
 
             // If we found a point with good distance from edges, return it
             if dist >= MIN_EDGE_DISTANCE {
-                return Ok(candidate);
+                return Ok(TargetHit {
+                    point: candidate,
+                    area,
+                });
             }
 
             // Keep track of the best candidate (furthest from edges)
@@ -247,7 +1451,10 @@ pub fn find_point_in_shape(target_color: &PixelColor) -> Result<Point> {
     }
 
     if let Some(candidate) = best_candidate {
-        Ok(candidate)
+        Ok(TargetHit {
+            point: candidate,
+            area,
+        })
     } else {
         bail!(
             "Failed to find a point inside the shape after {} attempts",
@@ -256,47 +1463,145 @@ pub fn find_point_in_shape(target_color: &PixelColor) -> Result<Point> {
     }
 }
 
-/// Captures the primary display and returns it as a grayscale image.
-fn capture_screen() -> Result<GrayImage> {
-    // Initialize the display capturer for the primary monitor
-    let display =
-        Display::primary().context("Failed to identify or access the primary display monitor")?;
-
-    let mut capturer = Capturer::new(display).context(
-        "Failed to initialize system capture session. Check OS screen recording permissions.",
-    )?;
+/// Captures the selected display and returns it as an RGBA image.
+///
+/// Returns the replayed screenshot instead while a replay is active.
+pub fn capture_screen_rgba() -> Result<RgbaImage, ColorbotError> {
+    if let Some(frame) = replay::capture() {
+        return Ok(frame?);
+    }
+    pacing::wait_for_capture_slot();
+    let _phase = profile::phase(Phase::Capture);
 
-    let width = capturer.width();
-    let height = capturer.height();
+    let frame = if PORTAL_CAPTURE.load(Ordering::Relaxed) {
+        portal::capture().context("Failed to capture the screen through the portal")
+    } else {
+        CAPTURER.with_borrow_mut(|slot| {
+            let mut capturer = match slot.take() {
+                Some(capturer) => capturer,
+                None => ScreenCapturer::new()?,
+            };
+            let frame = capturer.capture()?;
+            // A session that failed is dropped and set up again on the next capture
+            *slot = Some(capturer);
+            Ok(frame)
+        })
+    }
+    .map_err(ColorbotError::CaptureFailed)?;
 
-    // Loop until a valid display frame is ready
-    let frame_buffer = loop {
-        if let Ok(frame) = capturer.frame() {
-            break frame.to_vec();
+    // Scripts relative to a window only see the window
+    let frame = match display::window_area() {
+        Some(area) => {
+            if area.x < 0
+                || area.y < 0
+                || area.x as u32 + area.width > frame.width()
+                || area.y as u32 + area.height > frame.height()
+            {
+                return Err(ColorbotError::CaptureFailed(anyhow!(
+                    "The followed window doesn't fit into the captured frame"
+                )));
+            }
+            image::imageops::crop_imm(
+                &frame,
+                area.x as u32,
+                area.y as u32,
+                area.width,
+                area.height,
+            )
+            .to_image()
         }
+        None => frame,
     };
 
-    // Convert raw scrap buffer from BGRA to RGBA channels
-    let mut rgba_raw = Vec::with_capacity(frame_buffer.len());
-    for chunk in frame_buffer.chunks_exact(4) {
-        rgba_raw.push(chunk[2]); // R
-        rgba_raw.push(chunk[1]); // G
-        rgba_raw.push(chunk[0]); // B
-        rgba_raw.push(chunk[3]); // A
+    crash::record_frame(&frame);
+
+    Ok(frame)
+}
+
+/// Capture session of the selected display.
+///
+/// Setting up a session takes far longer than capturing a frame, so one is kept open and reused
+/// for every capture.
+pub struct ScreenCapturer {
+    capturer: Capturer,
+}
+
+impl ScreenCapturer {
+    /// Opens a capture session of the display chosen with `--display`, or the primary one.
+    pub fn new() -> Result<Self> {
+        let display = match display::selected() {
+            Some(monitor) => Display::all()
+                .context("Failed to list the display monitors")?
+                .into_iter()
+                .nth(monitor.index)
+                .context(format!("Display {} is no longer connected", monitor.index))?,
+            None => Display::primary()
+                .context("Failed to identify or access the primary display monitor")?,
+        };
+        let capturer = Capturer::new(display).context(
+            "Failed to initialize system capture session. Check OS screen recording permissions.",
+        )?;
+        Ok(Self { capturer })
     }
 
-    // Wrap raw byte buffer into an ImageBuffer container
-    let src_rgba: ImageBuffer<Rgba<u8>, Vec<u8>> =
-        ImageBuffer::from_raw(width as u32, height as u32, rgba_raw).context(
+    /// Captures the next frame as an RGBA image.
+    pub fn capture(&mut self) -> Result<RgbaImage> {
+        let width = self.capturer.width();
+        let height = self.capturer.height();
+
+        // Wait for a valid display frame without spinning on the capturer
+        let frame_buffer = loop {
+            match self.capturer.frame() {
+                Ok(frame) => break frame.to_vec(),
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                    std::thread::sleep(FRAME_POLL_INTERVAL);
+                }
+                Err(e) => return Err(e).context("Failed to capture a screen frame"),
+            }
+        };
+
+        // Convert raw scrap buffer from BGRA to RGBA channels
+        let mut rgba_raw = Vec::with_capacity(frame_buffer.len());
+        for chunk in frame_buffer.chunks_exact(4) {
+            rgba_raw.push(chunk[2]); // R
+            rgba_raw.push(chunk[1]); // G
+            rgba_raw.push(chunk[0]); // B
+            rgba_raw.push(chunk[3]); // A
+        }
+
+        // Wrap raw byte buffer into an ImageBuffer container
+        let src_rgba: ImageBuffer<Rgba<u8>, Vec<u8>> = ImageBuffer::from_raw(
+            width as u32,
+            height as u32,
+            rgba_raw,
+        )
+        .context(
             "Captured screen byte buffer dimensions did not match required resolution constraints",
         )?;
+        Ok(src_rgba)
+    }
+}
 
-    // Convert to grayscale for template matching
-    Ok(image::DynamicImage::ImageRgba8(src_rgba).to_luma8())
+thread_local! {
+    /// Capture session reused by every X11 capture on this thread, the capturer can't be shared
+    /// between threads.
+    static CAPTURER: RefCell<Option<ScreenCapturer>> = const { RefCell::new(None) };
+}
+
+/// Opens the capture session used by the following captures, replacing any open one.
+///
+/// Does nothing when capturing through the portal.
+pub fn open_capturer() -> Result<()> {
+    if PORTAL_CAPTURE.load(Ordering::Relaxed) {
+        return portal::open();
+    }
+    let capturer = ScreenCapturer::new()?;
+    CAPTURER.with_borrow_mut(|slot| *slot = Some(capturer));
+    Ok(())
 }
 
 /// Generates a random point within the rectangle defined by the origin and dimensions.
-fn get_rand_point_in_rect(origin: Point, width: u32, height: u32) -> Result<Point> {
+pub fn get_rand_point_in_rect(origin: Point, width: u32, height: u32) -> Result<Point> {
     // Generate random offsets within 0.0 (inclusive) and dimension (exclusive)
     let random_x = i32::try_from(rand::random_range(0..width))?;
     let random_y = i32::try_from(rand::random_range(0..height))?;
@@ -310,23 +1615,62 @@ fn get_rand_point_in_rect(origin: Point, width: u32, height: u32) -> Result<Poin
     Ok(point)
 }
 
-/// Finds the location of the target image on the screen using template matching.
-pub fn find_image_on_screen(target_image: &Path) -> Result<Point> {
-    // Capture the screen using our separate function
-    let src = capture_screen().context("Could not extract a valid desktop screenshot frame")?;
+/// Largest average difference per pixel between a template and a match on screen.
+pub const MAX_TEMPLATE_PIXEL_VARIANCE: f32 = 15.0;
 
-    // Load template image converted to grayscale
-    let temp_dynamic = ImageReader::open(target_image)
+/// Loads a template image as grayscale after applying the filters.
+pub fn load_template(path: &Path, filters: &[Filter]) -> Result<GrayImage> {
+    let temp_dynamic = ImageReader::open(path)
         .context(format!(
             "Failed to locate or open {} asset from disk",
-            target_image.display()
+            path.display()
         ))?
         .decode()
         .context(format!(
             "Failed to parse and decode target {} format structure",
-            target_image.display()
+            path.display()
         ))?;
-    let temp = temp_dynamic.to_luma8();
+    let mut temp_rgba = temp_dynamic.to_rgba8();
+    filter::apply_all(&mut temp_rgba, filters);
+    Ok(image::DynamicImage::ImageRgba8(temp_rgba).to_luma8())
+}
+
+/// Finds the location of the target image on the screen using template matching.
+///
+/// `filters` are applied to both the captured frame and the template before matching so that
+/// they stay comparable.
+pub fn find_image_on_screen(
+    target_image: &Path,
+    filters: &[Filter],
+) -> Result<TargetHit, ColorbotError> {
+    locate_image_on_screen(target_image, filters)?
+        .ok_or_else(|| ColorbotError::ImageNotFound(target_image.to_path_buf()))
+}
+
+/// Like [`find_image_on_screen`], but reports a missing match as `None` instead of an error.
+pub fn locate_image_on_screen(
+    target_image: &Path,
+    filters: &[Filter],
+) -> Result<Option<TargetHit>, ColorbotError> {
+    Ok(match_template_on_screen(target_image, filters)?)
+}
+
+fn match_template_on_screen(target_image: &Path, filters: &[Filter]) -> Result<Option<TargetHit>> {
+    // Capture the screen and convert to grayscale for template matching
+    let mut src_rgba =
+        capture_screen_rgba().context("Could not extract a valid desktop screenshot frame")?;
+    fixtures::record(&src_rgba, None);
+    let _phase = profile::phase(Phase::Scan);
+    filter::apply_all(&mut src_rgba, filters);
+    if let Some((origin, width, height)) = matchcache::lookup(target_image, filters, &src_rgba) {
+        debug!("Reusing the cached match of {}", target_image.display());
+        return Ok(Some(TargetHit {
+            point: get_rand_point_in_rect(origin, width, height)?,
+            area: u64::from(width) * u64::from(height),
+        }));
+    }
+    let src = image::imageops::grayscale(&src_rgba);
+    let temp = load_template(target_image, filters)?;
 
     // Run template matching
     let result_image = imageproc::template_matching::match_template_parallel(
@@ -341,11 +1685,10 @@ pub fn find_image_on_screen(target_image: &Path) -> Result<Point> {
     let confidence_score = extremes.min_value;
 
     // Dynamically calculate a confidence threshold based on template size and a variance factor
-    const MAX_PIXEL_VARIANCE: f32 = 15.0;
     let temp_width = temp.width();
     let temp_height = temp.height();
     let total_pixels = (temp_width * temp_height) as f32;
-    let dynamic_threshold = total_pixels * MAX_PIXEL_VARIANCE.powi(2);
+    let dynamic_threshold = total_pixels * MAX_TEMPLATE_PIXEL_VARIANCE.powi(2);
 
     // Check if the confidence score is below the dynamic threshold to determine if a valid match
     // was found
@@ -356,12 +1699,346 @@ pub fn find_image_on_screen(target_image: &Path) -> Result<Point> {
             i32::try_from(best_match_pos.0)?,
             i32::try_from(best_match_pos.1)?,
         );
-        Ok(get_rand_point_in_rect(origin, temp_width, temp_height)?)
+        matchcache::store(
+            target_image,
+            filters,
+            &src_rgba,
+            origin,
+            temp_width,
+            temp_height,
+        );
+        Ok(Some(TargetHit {
+            point: get_rand_point_in_rect(origin, temp_width, temp_height)?,
+            area: u64::from(temp_width) * u64::from(temp_height),
+        }))
     } else {
-        bail!(
-            "No match found for template image. Best match confidence score {} exceeded threshold {}",
+        debug!(
+            "No match for {}, best match confidence score {} exceeded threshold {}",
+            target_image.display(),
             confidence_score,
             dynamic_threshold
         );
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hsv_target(hue_range: [f64; 2], sat_min: f64, val_min: f64) -> ColorTarget {
+        ColorTarget::Hsv {
+            hue_range,
+            sat_min,
+            val_min,
+        }
+    }
+
+    #[test]
+    fn region_contains_points_inside_its_bounds() {
+        let region = Region {
+            x: 10,
+            y: 20,
+            width: 5,
+            height: 5,
+        };
+        assert!(region.contains(Point::new(10, 20)));
+        assert!(region.contains(Point::new(14, 24)));
+        assert!(!region.contains(Point::new(15, 24)));
+        assert!(!region.contains(Point::new(9, 22)));
+    }
+
+    #[test]
+    fn to_hsv_converts_primary_colors() {
+        assert_eq!(
+            PixelColor::new(255, 0, 0).to_hsv(),
+            Hsv {
+                h: 0.0,
+                s: 1.0,
+                v: 1.0
+            }
+        );
+        assert_eq!(PixelColor::new(0, 255, 0).to_hsv().h, 120.0);
+        assert_eq!(PixelColor::new(0, 0, 255).to_hsv().h, 240.0);
+        assert_eq!(PixelColor::new(0, 0, 0).to_hsv().s, 0.0);
+    }
+
+    #[test]
+    fn hsv_target_matches_within_range() {
+        let purple = hsv_target([260.0, 300.0], 0.3, 0.2);
+        assert!(purple.matches(&PixelColor::new(128, 0, 255), &ColorMatcher::Rgb));
+        assert!(purple.matches(&PixelColor::new(90, 40, 120), &ColorMatcher::Rgb));
+        assert!(!purple.matches(&PixelColor::new(0, 0, 255), &ColorMatcher::Rgb));
+        assert!(!purple.matches(&PixelColor::new(120, 110, 125), &ColorMatcher::Rgb));
+    }
+
+    #[test]
+    fn hsv_target_hue_range_wraps_through_zero() {
+        let red = hsv_target([340.0, 20.0], 0.5, 0.5);
+        assert!(red.matches(&PixelColor::new(255, 0, 0), &ColorMatcher::Rgb));
+        assert!(red.matches(&PixelColor::new(255, 0, 40), &ColorMatcher::Rgb));
+        assert!(red.matches(&PixelColor::new(255, 40, 0), &ColorMatcher::Rgb));
+        assert!(!red.matches(&PixelColor::new(0, 255, 0), &ColorMatcher::Rgb));
+    }
+
+    #[test]
+    fn hsv_target_deserializes_with_default_minimums() {
+        let target: ColorTarget = serde_json::from_str(r#"{"hue_range": [10, 50]}"#).unwrap();
+        assert!(matches!(
+            target,
+            ColorTarget::Hsv {
+                sat_min: 0.0,
+                val_min: 0.0,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn validate_rejects_out_of_range_hsv() {
+        assert!(hsv_target([0.0, 360.0], 0.0, 1.0).validate().is_ok());
+        assert!(hsv_target([-1.0, 20.0], 0.0, 0.0).validate().is_err());
+        assert!(hsv_target([0.0, 20.0], 1.5, 0.0).validate().is_err());
+        assert!(hsv_target([0.0, 20.0], 0.0, -0.1).validate().is_err());
+    }
+
+    #[test]
+    fn hsv_covering_uses_shortest_arc() {
+        let reds = [
+            PixelColor::new(255, 0, 40),
+            PixelColor::new(255, 30, 0),
+            PixelColor::new(200, 0, 0),
+        ];
+        let target = ColorTarget::hsv_covering(&reds).unwrap();
+        for color in &reds {
+            assert!(target.matches(color, &ColorMatcher::Rgb));
+        }
+        assert!(!target.matches(&PixelColor::new(0, 255, 255), &ColorMatcher::Rgb));
+    }
+
+    /// Filled square of points with its top left corner at (x, y).
+    fn square(x: i32, y: i32, size: i32) -> Vec<Point> {
+        (x..x + size)
+            .flat_map(|px| (y..y + size).map(move |py| Point::new(px, py)))
+            .collect()
+    }
+
+    #[test]
+    fn clicks_inside_one_of_two_separate_blobs() {
+        let mut points = square(0, 0, 30);
+        points.extend(square(200, 0, 20));
+        let clusters = cluster_points(&points, 4);
+        assert_eq!(clusters.len(), 2);
+        for _ in 0..50 {
+            let hit = pick_in_cluster(&clusters[0]).unwrap();
+            assert!((0..30).contains(&hit.point.x), "clicked {}", hit.point);
+        }
+    }
+
+    #[test]
+    fn sampled_matches_stay_within_cap_and_keep_blobs_together() {
+        let options = ScanOptions {
+            max_points: 500,
+            ..Default::default()
+        };
+        let mut result = ScanResult::default();
+        let mut points = square(0, 0, 60);
+        points.extend(square(200, 0, 40));
+        for point in points {
+            result.record(point, &options);
+        }
+        assert_eq!(result.count, 5200);
+        // One match per 4x4 cell
+        assert_eq!(result.points.len(), 325);
+
+        let blobs = order_blobs(&result, &options);
+        assert_eq!(blobs.len(), 2);
+        assert!(blobs[0].points.iter().all(|p| p.x < 60));
+        assert!(blobs[0].bounds.width > 56, "{}", blobs[0].bounds.width);
+        assert!(
+            (3200..4000).contains(&blobs[0].pixels),
+            "{}",
+            blobs[0].pixels
+        );
+
+        let limited = ScanOptions {
+            blob_limits: BlobLimits {
+                max_width: Some(50),
+                ..Default::default()
+            },
+            ..options
+        };
+        let blobs = order_blobs(&result, &limited);
+        assert_eq!(blobs.len(), 1);
+        assert_eq!(blobs[0].bounds.x, 200);
+    }
+
+    #[test]
+    fn sampled_matches_keep_close_blobs_apart() {
+        let options = ScanOptions {
+            max_points: 2_000,
+            ..Default::default()
+        };
+        let mut result = ScanResult::default();
+        // Squares 12 px apart, which a gap widened with the sampling rate would merge
+        let mut points = square(0, 0, 100);
+        points.extend(square(112, 0, 100));
+        // A line one pixel high isn't lost to the sampling
+        points.extend((0..212).map(|x| Point::new(x, 150)));
+        for point in points {
+            result.record(point, &options);
+        }
+        assert_eq!(result.points.len(), 625 + 625 + 53);
+
+        let blobs = order_blobs(&result, &options);
+        assert_eq!(blobs.len(), 3);
+        assert!(blobs[..2].iter().all(|blob| blob.bounds.height > 96));
+        assert!(blobs[..2].iter().any(|blob| blob.bounds.x >= 112));
+        assert_eq!(blobs[2].bounds.y, 150);
+    }
+
+    #[test]
+    fn blob_selection_orders_clusters() {
+        let mut points = square(0, 0, 30);
+        points.extend(square(200, 0, 20));
+        // A few stray pixels right at the cursor aren't preferred over real objects
+        points.extend(square(400, 0, 2));
+        let cursor = || Point::new(390, 10);
+        let blobs = || -> Vec<Blob> {
+            cluster_points(&points, 4)
+                .into_iter()
+                .map(|cluster| Blob::new(cluster, 1.0))
+                .collect()
+        };
+
+        let mut largest = blobs();
+        BlobSelection::Largest.order(&mut largest, cursor);
+        assert_eq!(largest[0].pixels, 900);
+
+        let mut nearest = blobs();
+        BlobSelection::Nearest.order(&mut nearest, cursor);
+        assert_eq!(nearest[0].pixels, 400);
+        assert_eq!(nearest[2].pixels, 4);
+
+        for _ in 0..20 {
+            let mut random = blobs();
+            BlobSelection::Random.order(&mut random, cursor);
+            assert_ne!(random[0].pixels, 4);
+        }
+    }
+
+    #[test]
+    fn strided_scan_refines_hits_to_the_full_shape() {
+        // Only two pixels of the shape lie on the grid of a stride of 4
+        let frame = RgbaImage::from_fn(40, 40, |x, y| match (x, y) {
+            (11..=17, 5..=9) => Rgba([255, 0, 0, 255]),
+            _ => Rgba([0, 0, 0, 255]),
+        });
+        let target = ColorTarget::Rgb { rgb: [255, 0, 0] };
+        let full = scan_frame(&frame, Point::new(0, 0), &target, &ScanOptions::default()).unwrap();
+        let options = ScanOptions {
+            scan_stride: 4,
+            ..Default::default()
+        };
+        let strided = scan_frame(&frame, Point::new(0, 0), &target, &options).unwrap();
+        assert_eq!(strided.count, 35);
+        let mut points = strided.points;
+        points.sort_by_key(|p| (p.y, p.x));
+        assert_eq!(points, full.points);
+    }
+
+    #[test]
+    fn cropped_scans_report_screen_positions() {
+        let frame = RgbaImage::from_fn(20, 10, |x, y| match (x, y) {
+            (2, 1) | (17, 1) => Rgba([255, 0, 0, 255]),
+            _ => Rgba([0, 0, 0, 255]),
+        });
+        let (whole, origin) = crop_to_region(frame.clone(), None);
+        assert_eq!((whole.dimensions(), origin), ((20, 10), Point::new(0, 0)));
+
+        // Clipped to the frame on the right and at the top
+        let region = Region {
+            x: 15,
+            y: -2,
+            width: 10,
+            height: 5,
+        };
+        let (cropped, origin) = crop_to_region(frame, Some(&region));
+        assert_eq!((cropped.dimensions(), origin), ((5, 3), Point::new(15, 0)));
+        let target = ColorTarget::Rgb { rgb: [255, 0, 0] };
+        let result = scan_frame(&cropped, origin, &target, &ScanOptions::default()).unwrap();
+        assert_eq!(result.points, vec![Point::new(17, 1)]);
+        assert_eq!(result.pixels, 15);
+    }
+
+    #[test]
+    fn result_modes_stop_early_or_only_count() {
+        let frame = RgbaImage::from_fn(10, 2, |x, y| match (x, y) {
+            (1 | 3 | 5, 0) | (2 | 4, 1) => Rgba([255, 0, 0, 255]),
+            _ => Rgba([0, 0, 0, 255]),
+        });
+        let target = ColorTarget::Rgb { rgb: [255, 0, 0] };
+        let scan = |result_mode| {
+            let options = ScanOptions {
+                result_mode,
+                ..Default::default()
+            };
+            scan_frame(&frame, Point::new(0, 0), &target, &options).unwrap()
+        };
+
+        let first = scan(ResultMode::FirstMatch);
+        assert_eq!((first.count, first.points), (1, vec![Point::new(1, 0)]));
+        let first_n = scan(ResultMode::FirstN(3));
+        assert_eq!(first_n.count, 3);
+        assert_eq!(
+            first_n.points,
+            vec![Point::new(1, 0), Point::new(3, 0), Point::new(5, 0)]
+        );
+        let counted = scan(ResultMode::CountOnly);
+        assert_eq!(counted.count, 5);
+        assert!(counted.points.is_empty());
+        assert_eq!(scan(ResultMode::All).points.len(), 5);
+    }
+
+    #[test]
+    fn excluded_colors_veto_matches_next_to_them() {
+        // Cyan outline on the left is a live resource, the one on the right surrounds a gray
+        // depleted one
+        let frame = RgbaImage::from_fn(60, 20, |x, y| match (x, y) {
+            (32..=37, 7..=12) => Rgba([90, 90, 90, 255]),
+            (5..=14, 5..=14) | (30..=39, 5..=14) => Rgba([0, 255, 255, 255]),
+            _ => Rgba([0, 0, 0, 255]),
+        });
+        let target = ColorTarget::Rgb { rgb: [0, 255, 255] };
+        let options = ScanOptions {
+            exclude_colors: vec![[90, 90, 90]],
+            exclude_radius: 2,
+            ..Default::default()
+        };
+        let result = scan_frame(&frame, Point::new(0, 0), &target, &options).unwrap();
+        assert_eq!(result.count, 100);
+        assert!(result.points.iter().all(|p| p.x < 15));
+    }
+
+    #[test]
+    fn perceptual_hashes_tolerate_noise() {
+        let frame = RgbaImage::from_fn(64, 64, |x, y| {
+            let value = if (x / 16 + y / 16) % 2 == 0 { 220 } else { 30 };
+            Rgba([value, value, value, 255])
+        });
+        let mut noisy = frame.clone();
+        noisy.put_pixel(10, 10, Rgba([128, 0, 0, 255]));
+        let inverted = RgbaImage::from_fn(64, 64, |x, y| {
+            let value = 250 - frame.get_pixel(x, y)[0];
+            Rgba([value, value, value, 255])
+        });
+
+        for algorithm in [HashAlgorithm::Average, HashAlgorithm::Difference] {
+            let hash = perceptual_hash(&frame, algorithm);
+            assert!(hash_distance(hash, perceptual_hash(&noisy, algorithm)) <= 2);
+            assert!(hash_distance(hash, perceptual_hash(&inverted, algorithm)) > 16);
+            assert_eq!(parse_hash(&format_hash(hash)).unwrap(), hash);
+        }
+        assert!(parse_hash("not a hash").is_err());
     }
 }
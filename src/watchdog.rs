@@ -0,0 +1,164 @@
+//! Watchdog that detects a run that stopped making progress.
+//!
+//! A background thread checks how long ago the last event completed. Once that exceeds the
+//! configured limit it logs a stuck-run incident and kills any supervised child processes, which
+//! unblocks an event waiting on a hung xdotool. The event loop then either runs the recovery
+//! script or aborts. An event that only returns after a further grace period isn't recovered
+//! from, the run ends with an error instead. The process is never exited from here, since it may
+//! be another program running the bot.
+use crate::process;
+
+use anyhow::{Context, Result};
+use log::{error, warn};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// How often the watchdog thread checks for progress.
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Time the event loop gets to react to an incident before the run is given up on.
+const ABORT_GRACE: Duration = Duration::from_secs(60);
+
+/// State shared between the event loop and the watchdog thread.
+struct State {
+    last_progress: Mutex<(Instant, Option<String>)>,
+    suspended: AtomicBool,
+    stuck: AtomicBool,
+    aborted: AtomicBool,
+    stop: AtomicBool,
+}
+
+/// Handle to the watchdog thread, which stops when the handle is dropped.
+///
+/// A watchdog without a timeout never declares the run stuck.
+pub struct Watchdog {
+    state: Arc<State>,
+    thread: Option<JoinHandle<()>>,
+    incidents: u64,
+}
+
+impl Watchdog {
+    /// Starts a watchdog that declares the run stuck when no event completes within `timeout`.
+    pub fn start(timeout: Option<Duration>) -> Result<Self> {
+        let state = Arc::new(State {
+            last_progress: Mutex::new((Instant::now(), None)),
+            suspended: AtomicBool::new(false),
+            stuck: AtomicBool::new(false),
+            aborted: AtomicBool::new(false),
+            stop: AtomicBool::new(false),
+        });
+
+        let thread = match timeout {
+            Some(timeout) => {
+                let thread_state = Arc::clone(&state);
+                Some(
+                    thread::Builder::new()
+                        .name("watchdog".to_string())
+                        .spawn(move || watch(&thread_state, timeout))
+                        .context("Failed to start the watchdog thread")?,
+                )
+            }
+            None => None,
+        };
+
+        Ok(Self {
+            state,
+            thread,
+            incidents: 0,
+        })
+    }
+
+    /// Records that the named event is starting.
+    pub fn event_started(&self, id: &str) {
+        if let Ok(mut progress) = self.state.last_progress.lock() {
+            progress.1 = Some(id.to_string());
+        }
+    }
+
+    /// Records that an event completed, resetting the stuck timer.
+    pub fn event_finished(&self) {
+        if let Ok(mut progress) = self.state.last_progress.lock() {
+            *progress = (Instant::now(), None);
+        }
+    }
+
+    /// Stops counting time towards the timeout, for a pause that may last any length of time.
+    pub fn suspend(&self) {
+        self.state.suspended.store(true, Ordering::SeqCst);
+    }
+
+    /// Starts counting time towards the timeout again, from zero.
+    pub fn resume(&self) {
+        self.event_finished();
+        self.state.suspended.store(false, Ordering::SeqCst);
+    }
+
+    /// Returns whether the run was declared stuck since the last call, clearing the incident.
+    pub fn take_incident(&mut self) -> bool {
+        let stuck = self.state.stuck.swap(false, Ordering::SeqCst);
+        if stuck {
+            self.incidents += 1;
+            self.event_finished();
+        }
+        stuck
+    }
+
+    /// Whether the event loop didn't react to an incident within the grace period, after which
+    /// the run is given up on rather than recovered.
+    pub fn aborted(&self) -> bool {
+        self.state.aborted.load(Ordering::SeqCst)
+    }
+
+    /// Number of stuck-run incidents handled by the event loop.
+    pub fn incidents(&self) -> u64 {
+        self.incidents
+    }
+}
+
+impl Drop for Watchdog {
+    fn drop(&mut self) {
+        self.state.stop.store(true, Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Body of the watchdog thread.
+fn watch(state: &State, timeout: Duration) {
+    while !state.stop.load(Ordering::SeqCst) {
+        thread::sleep(CHECK_INTERVAL);
+        if state.suspended.load(Ordering::SeqCst) {
+            continue;
+        }
+
+        let Ok(progress) = state.last_progress.lock() else {
+            return;
+        };
+        let (since, event) = (progress.0.elapsed(), progress.1.clone());
+        drop(progress);
+
+        if since >= timeout + ABORT_GRACE {
+            error!(
+                "Run still stuck {:?} after the incident, it ends with an error once the stuck \
+                 event returns",
+                ABORT_GRACE
+            );
+            state.aborted.store(true, Ordering::SeqCst);
+            return;
+        }
+        if since >= timeout && !state.stuck.swap(true, Ordering::SeqCst) {
+            error!(
+                "Stuck run: no event completed for {:?} (running: {})",
+                since,
+                event.as_deref().unwrap_or("none")
+            );
+            let killed = process::kill_all();
+            if killed > 0 {
+                warn!("Killed {} hung child process(es)", killed);
+            }
+        }
+    }
+}
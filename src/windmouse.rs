@@ -4,13 +4,22 @@
 //! with gravity, wind forces, and velocity constraints to simulate natural mouse movement.
 //! Credit to Ben Land for the [original algorithm](https://ben.land/post/2021/04/25/windmouse-human-mouse-movement/).
 //! This is a Rust implementation of the code found in Ben's blog post.
+use crate::backend::InputBackend;
+use crate::display;
+use crate::forbidden;
+use crate::homing::Homing;
+use crate::movement::{self, MouseAlgorithm, MovementStrategy};
+use crate::timing;
+use crate::tremor::Tremor;
+
 use anyhow::{Context, Result};
-use enigo::{Coordinate, Enigo, Mouse, Settings};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
 use std::fmt::Display;
 use std::time::Duration;
 
 /// Starting and ending coordinates for mouse movement
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct Point {
     pub x: i32,
     pub y: i32,
@@ -47,9 +56,15 @@ pub struct WindMouseParams {
     pub distance_threshold: f64,
 }
 
+impl Default for WindMouseParams {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl WindMouseParams {
     /// Creates new parameters with randomized values for natural mouse movement.
-    fn new() -> Self {
+    pub fn new() -> Self {
         // Constants used here were taken from DreamBot source code
         // https://dreambot.org/forums/index.php?/topic/21147-windmouse-custom-mouse-movement-algorithm/
         let gravity = rand::random_range(4.0..20.0);
@@ -64,22 +79,53 @@ impl WindMouseParams {
             distance_threshold,
         }
     }
+
+    /// Creates parameters for a short, slow corrective movement onto a small target.
+    pub fn precise() -> Self {
+        Self {
+            gravity: rand::random_range(3.0..6.0),
+            wind: rand::random_range(0.5..2.0),
+            max_velocity: rand::random_range(2.0..4.0),
+            distance_threshold: rand::random_range(5.0..10.0),
+        }
+    }
 }
 
-/// Wind mouse controller that owns the Enigo instance
+impl MovementStrategy for WindMouseParams {
+    fn path(&self, start: Point, dest: Point) -> Vec<Point> {
+        WindMouse::wind_mouse(start, dest, *self)
+    }
+}
+
+/// Mouse controller that emits cursor paths through an input backend
 pub struct WindMouse {
-    enigo: Enigo,
+    poll_interval: Duration,
+    tempo: f64,
+    deviation: f64,
+    algorithm: MouseAlgorithm,
+    tremor: Option<Tremor>,
+    homing: Option<Homing>,
+}
+
+impl Default for WindMouse {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl WindMouse {
-    const MOUSE_POLL_INTERVAL_MS: Duration = Duration::from_millis(8);
+    const MIN_POLL_INTERVAL: Duration = Duration::from_millis(8);
+    const MAX_POLL_INTERVAL: Duration = Duration::from_millis(16);
 
-    /// Moves mouse cursor from start to destination using wind mouse algorithm.
-    fn wind_mouse(&mut self, start: Point, dest: Point, params: WindMouseParams) -> Result<()> {
+    /// Generates the cursor path from start to destination using wind mouse algorithm.
+    ///
+    /// The path starts at `start` and every following point differs from the one before it.
+    fn wind_mouse(start: Point, dest: Point, params: WindMouseParams) -> Vec<Point> {
         let sqrt3 = 3.0_f64.sqrt();
         let sqrt5 = 5.0_f64.sqrt();
 
         let mut current = start;
+        let mut path = vec![start];
         let mut velocity = (0.0, 0.0);
         let mut wind = (0.0, 0.0);
         let mut max_velocity = params.max_velocity;
@@ -139,31 +185,124 @@ impl WindMouse {
 
             if next.x != current.x || next.y != current.y {
                 current = next;
-
-                // Apply the mouse poll interval to control update frequency
-                std::thread::sleep(WindMouse::MOUSE_POLL_INTERVAL_MS);
-                self.enigo
-                    .move_mouse(current.x, current.y, Coordinate::Abs)?;
+                path.push(current);
             }
         }
 
+        path
+    }
+
+    /// Moves the cursor along a path from start to destination, applying tremor on the way and
+    /// homing at the end.
+    fn follow_path(
+        &mut self,
+        input: &mut dyn InputBackend,
+        start: Point,
+        dest: Point,
+        strategy: &dyn MovementStrategy,
+    ) -> Result<()> {
+        let step = self.poll_interval.mul_f64(self.tempo);
+        let mut path = forbidden::route(start, dest, &mut |from, to| strategy.path(from, to))?;
+        if let Some(tremor) = &self.tremor {
+            path = tremor.apply(&path, step);
+        }
+        let steps = match &self.homing {
+            Some(homing) => homing.apply(&path),
+            None => path.into_iter().map(|point| (point, 1.0)).collect(),
+        };
+        let precise_timing = input.capabilities().sub_ms_timing;
+
+        for (point, slowdown) in steps.into_iter().skip(1) {
+            // Apply the mouse poll interval to control update frequency, backends that
+            // deliver input promptly get a hybrid sleep to keep the spacing even
+            timing::path_step_sleep(step.mul_f64(slowdown), precise_timing);
+            input.move_cursor(display::to_screen(point))?;
+        }
+
         Ok(())
     }
 
-    /// Creates a new WindMouse instance with initialized Enigo controller.
-    pub fn new() -> Result<Self> {
-        let enigo = Enigo::new(&Settings::default()).context("failed to init enigo")?;
+    /// Creates a new WindMouse instance.
+    pub fn new() -> Self {
+        Self {
+            poll_interval: WindMouse::MIN_POLL_INTERVAL,
+            tempo: 1.0,
+            deviation: 1.0,
+            algorithm: MouseAlgorithm::default(),
+            tremor: None,
+            homing: None,
+        }
+    }
 
-        Ok(Self { enigo })
+    /// Sets the delay between intermediate cursor updates.
+    ///
+    /// The interval is clamped so that slow compositors don't drag movements out and fast ones
+    /// don't flood the display server with updates it can't reflect yet.
+    pub fn with_poll_interval(mut self, interval: Duration) -> Self {
+        self.poll_interval =
+            interval.clamp(WindMouse::MIN_POLL_INTERVAL, WindMouse::MAX_POLL_INTERVAL);
+        self
     }
 
-    /// Moves the mouse cursor from start to destination using human-like movement.
-    pub fn move_to(&mut self, start: Point, dest: Point) -> Result<()> {
-        let params = WindMouseParams::new();
+    /// Stretches the delay between cursor updates, and with it the movement duration, by `tempo`.
+    ///
+    /// Applied on top of the clamped poll interval.
+    pub fn with_tempo(mut self, tempo: f64) -> Self {
+        self.tempo = tempo;
+        self
+    }
+
+    /// Scales the wind strength, and with it how far paths stray from a straight line, by
+    /// `deviation`.
+    pub fn with_deviation(mut self, deviation: f64) -> Self {
+        self.deviation = deviation;
+        self
+    }
 
-        self.wind_mouse(start, dest, params)
+    /// Generates paths with `algorithm` instead of the WindMouse physics.
+    pub fn with_algorithm(mut self, algorithm: MouseAlgorithm) -> Self {
+        self.algorithm = algorithm;
+        self
+    }
+
+    /// Adds hand tremor and micro-corrections to every path.
+    pub fn with_tremor(mut self, tremor: Tremor) -> Self {
+        self.tremor = Some(tremor);
+        self
+    }
+
+    /// Slows paths down over their end and lets the cursor wander around the destination before
+    /// settling on it.
+    pub fn with_homing(mut self, homing: Homing) -> Self {
+        self.homing = Some(homing);
+        self
+    }
+
+    /// Moves the mouse cursor from start to destination using human-like movement.
+    pub fn move_to(
+        &mut self,
+        input: &mut dyn InputBackend,
+        start: Point,
+        dest: Point,
+    ) -> Result<()> {
+        let strategy = movement::strategy(self.algorithm, self.deviation);
+        self.follow_path(input, start, dest, strategy.as_ref())
             .context(format!("failed to move to destination {}", dest))?;
 
         Ok(())
     }
+
+    /// Moves the mouse cursor from start to destination slowly and with little wind.
+    ///
+    /// Meant for the final corrective step onto a small target after a fast approach.
+    pub fn move_precisely_to(
+        &mut self,
+        input: &mut dyn InputBackend,
+        start: Point,
+        dest: Point,
+    ) -> Result<()> {
+        let strategy = movement::precise_strategy(self.algorithm, self.deviation);
+        self.follow_path(input, start, dest, strategy.as_ref())
+            .context(format!("failed to move precisely to destination {}", dest))
+    }
 }
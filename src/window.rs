@@ -0,0 +1,285 @@
+//! X11 window lookup for translating between screen and window coordinates.
+use crate::windmouse::Point;
+
+use anyhow::{bail, Context, Result};
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::{
+    AtomEnum, ClientMessageEvent, ConfigureWindowAux, ConnectionExt, EventMask, MapState,
+    StackMode, Window,
+};
+use x11rb::rust_connection::RustConnection;
+
+/// Location and size of a top-level window on screen.
+#[derive(Debug, Clone)]
+pub struct WindowInfo {
+    /// X11 id of the window, which stays the same while it's open.
+    pub id: Window,
+    pub title: String,
+    pub origin: Point,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl WindowInfo {
+    /// Converts an absolute screen point into coordinates relative to this window.
+    pub fn to_relative(&self, point: Point) -> Point {
+        Point::new(point.x - self.origin.x, point.y - self.origin.y)
+    }
+}
+
+/// Reads the size of the screen in pixels.
+pub fn screen_size() -> Result<(u16, u16)> {
+    let (conn, screen_num) = x11rb::connect(None)
+        .context("Failed to connect to the X server to read the screen size")?;
+    let screen = &conn.setup().roots[screen_num];
+    Ok((screen.width_in_pixels, screen.height_in_pixels))
+}
+
+/// Reads the window title, preferring the UTF-8 EWMH name over the legacy WM_NAME.
+fn window_title(conn: &RustConnection, window: Window) -> Result<Option<String>> {
+    let net_wm_name = conn.intern_atom(false, b"_NET_WM_NAME")?.reply()?.atom;
+    let utf8_string = conn.intern_atom(false, b"UTF8_STRING")?.reply()?.atom;
+
+    for (property, kind) in [
+        (net_wm_name, utf8_string),
+        (AtomEnum::WM_NAME.into(), AtomEnum::STRING.into()),
+    ] {
+        let reply = conn
+            .get_property(false, window, property, kind, 0, u32::MAX)?
+            .reply()?;
+        if !reply.value.is_empty() {
+            return Ok(Some(String::from_utf8_lossy(&reply.value).into_owned()));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Reads the instance and class names from the WM_CLASS of a window.
+fn window_class(conn: &RustConnection, window: Window) -> Result<Option<(String, String)>> {
+    let reply = conn
+        .get_property(
+            false,
+            window,
+            AtomEnum::WM_CLASS,
+            AtomEnum::STRING,
+            0,
+            u32::MAX,
+        )?
+        .reply()?;
+    let value = String::from_utf8_lossy(&reply.value);
+    let mut names = value.split('\0');
+    Ok(match (names.next(), names.next()) {
+        (Some(instance), Some(class)) => Some((instance.to_string(), class.to_string())),
+        _ => None,
+    })
+}
+
+/// Properties a window is searched by.
+#[derive(Debug, Default)]
+struct Candidate {
+    /// Whether the window and its ancestors are mapped, unmapped windows such as the group
+    /// leaders of Java clients have no meaningful geometry.
+    viewable: bool,
+    title: Option<String>,
+    /// Instance and class names from WM_CLASS.
+    class: Option<(String, String)>,
+}
+
+impl Candidate {
+    /// Reads the properties of a window.
+    fn read(conn: &RustConnection, window: Window) -> Result<Self> {
+        let attributes = conn.get_window_attributes(window)?.reply()?;
+        Ok(Self {
+            viewable: attributes.map_state == MapState::VIEWABLE,
+            title: window_title(conn, window)?,
+            class: window_class(conn, window)?,
+        })
+    }
+
+    /// Whether the window is shown with a title containing `title` and a WM_CLASS instance or
+    /// class name equal to `class`, ignoring whichever is `None`.
+    fn matches(&self, title: Option<&str>, class: Option<&str>) -> bool {
+        let class_matches = match class {
+            Some(class) => self
+                .class
+                .as_ref()
+                .is_some_and(|(instance, name)| instance == class || name == class),
+            None => true,
+        };
+        // Only a title search needs a title, client windows of some games have none
+        let title_matches = match (title, &self.title) {
+            (Some(title), Some(name)) => name.contains(title),
+            (Some(_), None) => false,
+            (None, _) => class.is_some(),
+        };
+        self.viewable && class_matches && title_matches
+    }
+}
+
+/// Reads the screen geometry of a window.
+fn window_info(
+    conn: &RustConnection,
+    root: Window,
+    window: Window,
+    title: String,
+) -> Result<WindowInfo> {
+    let geometry = conn.get_geometry(window)?.reply()?;
+    let translated = conn.translate_coordinates(window, root, 0, 0)?.reply()?;
+    Ok(WindowInfo {
+        id: window,
+        title,
+        origin: Point::new(i32::from(translated.dst_x), i32::from(translated.dst_y)),
+        width: u32::from(geometry.width),
+        height: u32::from(geometry.height),
+    })
+}
+
+/// Finds the first window whose title contains `title` and returns its screen geometry.
+pub fn find_window(title: &str) -> Result<WindowInfo> {
+    find_window_matching(Some(title), None)
+}
+
+/// Finds the first viewable window whose title contains `title` and whose WM_CLASS instance or
+/// class name equals `class`, ignoring whichever is `None`, and returns its screen geometry.
+pub fn find_window_matching(title: Option<&str>, class: Option<&str>) -> Result<WindowInfo> {
+    let (conn, screen_num) = x11rb::connect(None).context("Failed to connect to the X server")?;
+    let root = conn.setup().roots[screen_num].root;
+
+    // Breadth-first walk so top-level windows are checked before their children
+    let mut pending = std::collections::VecDeque::from([root]);
+    while let Some(window) = pending.pop_front() {
+        let candidate = Candidate::read(&conn, window)?;
+        if candidate.matches(title, class) {
+            // Untitled windows go by their class in messages
+            let label = candidate
+                .title
+                .or(class.map(str::to_string))
+                .unwrap_or_default();
+            return window_info(&conn, root, window, label);
+        }
+        pending.extend(conn.query_tree(window)?.reply()?.children);
+    }
+
+    match (title, class) {
+        (Some(title), Some(class)) => bail!(
+            "No window of class '{}' found with a title containing '{}'",
+            class,
+            title
+        ),
+        (None, Some(class)) => bail!("No window found of class '{}'", class),
+        (title, None) => bail!(
+            "No window found with a title containing '{}'",
+            title.unwrap_or_default()
+        ),
+    }
+}
+
+/// Reads where a window found earlier is now, failing once it was closed.
+pub fn locate(window: &WindowInfo) -> Result<WindowInfo> {
+    let (conn, screen_num) = x11rb::connect(None).context("Failed to connect to the X server")?;
+    let root = conn.setup().roots[screen_num].root;
+    window_info(&conn, root, window.id, window.title.clone())
+        .context(format!("The window '{}' was closed", window.title))
+}
+
+/// Raises and focuses a window unless it already has the focus, returning whether it had to.
+///
+/// Window managers are asked through `_NET_ACTIVE_WINDOW`, which they may refuse, the window is
+/// also restacked above the others for those that ignore it.
+pub fn activate(window: &WindowInfo) -> Result<bool> {
+    let (conn, active) = active_window()?;
+    if active == Some(window.id) {
+        return Ok(false);
+    }
+    let root = conn.query_tree(window.id)?.reply()?.root;
+    let net_active_window = conn
+        .intern_atom(false, b"_NET_ACTIVE_WINDOW")?
+        .reply()?
+        .atom;
+    // Source indication 2 tells the window manager the request comes from a pager-like tool,
+    // which most honor without focus stealing prevention
+    let message = ClientMessageEvent::new(
+        32,
+        window.id,
+        net_active_window,
+        [2, x11rb::CURRENT_TIME, 0, 0, 0],
+    );
+    conn.send_event(
+        false,
+        root,
+        EventMask::SUBSTRUCTURE_REDIRECT | EventMask::SUBSTRUCTURE_NOTIFY,
+        message,
+    )?;
+    conn.configure_window(
+        window.id,
+        &ConfigureWindowAux::new().stack_mode(StackMode::ABOVE),
+    )?;
+    conn.flush()?;
+    Ok(true)
+}
+
+/// Connects to the X server and returns the window that currently has input focus, if any.
+fn active_window() -> Result<(RustConnection, Option<Window>)> {
+    let (conn, screen_num) = x11rb::connect(None).context("Failed to connect to the X server")?;
+    let root = conn.setup().roots[screen_num].root;
+
+    let net_active_window = conn
+        .intern_atom(false, b"_NET_ACTIVE_WINDOW")?
+        .reply()?
+        .atom;
+    let reply = conn
+        .get_property(false, root, net_active_window, AtomEnum::WINDOW, 0, 1)?
+        .reply()?;
+    let window = reply
+        .value32()
+        .and_then(|mut values| values.next())
+        .filter(|window| *window != x11rb::NONE);
+    Ok((conn, window))
+}
+
+/// Returns the title of the window that currently has input focus, if any.
+pub fn active_window_title() -> Result<Option<String>> {
+    match active_window()? {
+        (conn, Some(window)) => window_title(&conn, window),
+        (_, None) => Ok(None),
+    }
+}
+
+/// Returns the instance and class names from the WM_CLASS of the window that currently has
+/// input focus, if any.
+pub fn active_window_class() -> Result<Option<(String, String)>> {
+    let (conn, Some(window)) = active_window()? else {
+        return Ok(None);
+    };
+    window_class(&conn, window)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidate(viewable: bool, title: Option<&str>, class: &str) -> Candidate {
+        Candidate {
+            viewable,
+            title: title.map(str::to_string),
+            class: Some((class.to_lowercase(), class.to_string())),
+        }
+    }
+
+    #[test]
+    fn matches_shown_windows_by_title_and_class() {
+        let client = candidate(true, None, "RuneLite");
+        let leader = candidate(false, None, "RuneLite");
+        let titled = candidate(true, Some("RuneLite - Zezima"), "RuneLite");
+
+        assert!(client.matches(None, Some("RuneLite")));
+        assert!(client.matches(None, Some("runelite")));
+        assert!(!leader.matches(None, Some("RuneLite")));
+        assert!(!client.matches(Some("Zezima"), Some("RuneLite")));
+        assert!(titled.matches(Some("Zezima"), Some("RuneLite")));
+        assert!(titled.matches(Some("Zezima"), None));
+        assert!(!titled.matches(Some("Zezima"), Some("Firefox")));
+        assert!(!client.matches(None, None));
+    }
+}
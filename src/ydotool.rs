@@ -0,0 +1,188 @@
+//! Input backend that runs ydotool for every action.
+//!
+//! ydotool injects input through a uinput device owned by its `ydotoold` daemon, so it works on
+//! Wayland where only the daemon, not the bot, may write to `/dev/uinput`. Keys are sent as evdev
+//! key codes and take the same names as with the uinput backend. Like the uinput backend it
+//! can't read where the cursor is, so it keeps track of where it moved it.
+use crate::backend::{self, Capabilities, InputBackend, MouseButton};
+use crate::display;
+use crate::error::{self, ColorbotError};
+use crate::process;
+use crate::uinput;
+use crate::windmouse::Point;
+
+use anyhow::{bail, Context, Result};
+use evdev::KeyCode;
+use std::process::Command;
+use std::time::Duration;
+
+/// Longest time a single ydotool invocation may take before it's killed.
+const YDOTOOL_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Flags of `ydotool click` pressing and releasing a button.
+const BUTTON_DOWN: u8 = 0x40;
+const BUTTON_UP: u8 = 0x80;
+
+/// Backend that sends every action through the ydotool client.
+pub struct YdotoolBackend {
+    swapped_buttons: bool,
+}
+
+impl YdotoolBackend {
+    /// Checks that ydotool reaches its daemon by moving the cursor to the middle of the screen,
+    /// where it starts tracking it.
+    pub fn probe(swapped_buttons: bool) -> Result<Self> {
+        let (width, height) = display::screen_size()?;
+        let mut ydotool = Self { swapped_buttons };
+        let center = Point::new(i32::try_from(width / 2)?, i32::try_from(height / 2)?);
+        match ydotool.move_cursor(center) {
+            Ok(()) => Ok(ydotool),
+            Err(e) if matches!(error::kind(&e), Some(ColorbotError::ToolMissing(_))) => Err(e),
+            Err(e) => Err(e.context("ydotool can't reach ydotoold, start the daemon first")),
+        }
+    }
+
+    /// Executes ydotool with the given arguments.
+    fn run(&self, args: &[&str]) -> Result<()> {
+        let output = process::run(
+            Command::new("ydotool").args(args),
+            "ydotool",
+            YDOTOOL_TIMEOUT,
+        )
+        .map_err(|e| process::missing_tool(e, "ydotool"))?;
+        if !output.status.success() {
+            bail!(
+                "ydotool exited with {}: {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        Ok(())
+    }
+
+    /// Returns the button number `ydotool click` takes for the physical button.
+    fn button_code(&self, button: MouseButton) -> u8 {
+        match button.physical(self.swapped_buttons) {
+            MouseButton::Left => 0x00,
+            MouseButton::Right => 0x01,
+            MouseButton::Middle => 0x02,
+        }
+    }
+
+    /// Presses and releases keys, `true` pressing and `false` releasing.
+    fn send_keys(&self, keys: &[(KeyCode, bool)]) -> Result<()> {
+        let keys: Vec<String> = keys
+            .iter()
+            .map(|(key, pressed)| format!("{}:{}", key.code(), u8::from(*pressed)))
+            .collect();
+        let mut args = vec!["key"];
+        args.extend(keys.iter().map(String::as_str));
+        self.run(&args)
+    }
+}
+
+impl InputBackend for YdotoolBackend {
+    fn name(&self) -> &'static str {
+        "ydotool"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        // Every action spawns a process, so timing is only as precise as process startup
+        Capabilities {
+            scroll: false,
+            drag: true,
+            key_chords: true,
+            relative_moves: false,
+            sub_ms_timing: false,
+            gamepad: false,
+        }
+    }
+
+    fn move_cursor(&mut self, point: Point) -> Result<()> {
+        self.run(&[
+            "mousemove",
+            "--absolute",
+            "-x",
+            &point.x.to_string(),
+            "-y",
+            &point.y.to_string(),
+        ])
+        .context("Failed to move the cursor with ydotool")?;
+        backend::set_injected_cursor(point);
+        Ok(())
+    }
+
+    fn click(&mut self, button: MouseButton) -> Result<()> {
+        let code = BUTTON_DOWN | BUTTON_UP | self.button_code(button);
+        self.run(&["click", &format!("{:#04x}", code)])
+            .context(format!("Failed to execute ydotool for {} click", button))
+    }
+
+    fn set_button(&mut self, button: MouseButton, pressed: bool) -> Result<()> {
+        let flag = if pressed { BUTTON_DOWN } else { BUTTON_UP };
+        let code = flag | self.button_code(button);
+        self.run(&["click", &format!("{:#04x}", code)])
+            .context(format!(
+                "Failed to execute ydotool to {} the {} button",
+                if pressed { "press" } else { "release" },
+                button
+            ))
+    }
+
+    fn key(&mut self, keycode: &str) -> Result<()> {
+        let keys = keycode
+            .split('+')
+            .map(uinput::parse_key)
+            .collect::<Result<Vec<_>>>()?;
+        // Modifiers are held until the last key was tapped, then released in reverse order
+        let presses = keys.iter().map(|key| (*key, true));
+        let releases = keys.iter().rev().map(|key| (*key, false));
+        self.send_keys(&presses.chain(releases).collect::<Vec<_>>())
+            .context(format!("Failed to execute ydotool for key '{}'", keycode))
+    }
+
+    fn hold_key(&mut self, keycode: &str, duration: Duration) -> Result<()> {
+        let key = uinput::parse_key(keycode)?;
+        self.send_keys(&[(key, true)])
+            .context(format!("Failed to press '{}' with ydotool", keycode))?;
+        std::thread::sleep(duration);
+        self.send_keys(&[(key, false)])
+            .context(format!("Failed to release '{}' with ydotool", keycode))
+    }
+
+    fn text(&mut self, text: &str) -> Result<()> {
+        // The text may be a secret, so it's passed on stdin where other users can't read it
+        let output = process::run_with_stdin(
+            Command::new("ydotool").args(["type", "--file", "-"]),
+            "ydotool type",
+            YDOTOOL_TIMEOUT,
+            Some(text.as_bytes()),
+        )
+        .context("Failed to execute ydotool to type text")?;
+        if !output.status.success() {
+            bail!("ydotool type exited with {}", output.status);
+        }
+        Ok(())
+    }
+
+    fn release_all(&mut self) -> Result<()> {
+        let modifiers: Vec<(KeyCode, bool)> = [
+            KeyCode::KEY_LEFTSHIFT,
+            KeyCode::KEY_RIGHTSHIFT,
+            KeyCode::KEY_LEFTCTRL,
+            KeyCode::KEY_RIGHTCTRL,
+            KeyCode::KEY_LEFTALT,
+            KeyCode::KEY_RIGHTALT,
+            KeyCode::KEY_LEFTMETA,
+            KeyCode::KEY_RIGHTMETA,
+        ]
+        .into_iter()
+        .map(|key| (key, false))
+        .collect();
+        self.send_keys(&modifiers)?;
+        for button in [MouseButton::Left, MouseButton::Right, MouseButton::Middle] {
+            self.set_button(button, false)?;
+        }
+        Ok(())
+    }
+}